@@ -0,0 +1,50 @@
+//! Record information about the build for `rederr --version --verbose`.
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    for path in [".git/HEAD", ".git/refs"] {
+        // Cargo always reruns the build script if a path doesn’t exist.
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+
+    println!(
+        "cargo:rustc-env=REDERR_GIT_COMMIT={}",
+        git_commit().unwrap_or_else(|| "unknown".to_owned())
+    );
+    println!(
+        "cargo:rustc-env=REDERR_TARGET={}",
+        env::var("TARGET").expect("cargo should set TARGET")
+    );
+    println!("cargo:rustc-env=REDERR_FEATURES={}", features().join(","));
+}
+
+/// Get the abbreviated git commit, if we’re building from a git checkout.
+fn git_commit() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8(output.stdout).ok()?.trim().to_owned())
+    } else {
+        None
+    }
+}
+
+/// Get the names of the enabled cargo features.
+fn features() -> Vec<String> {
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|name| name.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    features
+}
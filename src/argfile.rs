@@ -0,0 +1,208 @@
+//! Expand argument files (`@file`) on the command line.
+//!
+//! An argument file contains one argument per line. Leading and trailing
+//! whitespace is removed from each line, and blank lines and lines starting
+//! with `#` are ignored.
+//!
+//! Only arguments before the command are expanded, so that arguments to the
+//! command that start with `@` are passed through unchanged. Arguments within
+//! an argument file are not expanded further.
+
+use anyhow::Context;
+use bstr::ByteSlice;
+use clap::Command;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+
+/// Expand argument files in `args`.
+///
+/// `args` should include the program name as the first element. `command` is
+/// used to determine which options take values, so that the values aren’t
+/// mistaken for the command to run.
+pub fn expand<I>(command: &Command, args: I) -> anyhow::Result<Vec<OsString>>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut args = args.into_iter();
+    let mut expanded: Vec<OsString> = args.next().into_iter().collect();
+    let mut scanner = Scanner::new(command);
+
+    for arg in args.by_ref() {
+        if scanner.done {
+            expanded.push(arg);
+            break;
+        }
+
+        if let Some(path) = arg.as_bytes().strip_prefix(b"@") {
+            if !scanner.expecting_value {
+                let path = Path::new(OsStr::from_bytes(path));
+                for arg in read(path).with_context(|| {
+                    format!("reading argument file {}", path.display())
+                })? {
+                    scanner.scan(&arg);
+                    expanded.push(arg);
+                }
+                continue;
+            }
+        }
+
+        scanner.scan(&arg);
+        expanded.push(arg);
+    }
+
+    expanded.extend(args);
+    Ok(expanded)
+}
+
+/// Read arguments from an argument file.
+fn read(path: &Path) -> anyhow::Result<Vec<OsString>> {
+    Ok(fs::read(path)?
+        .lines()
+        .map(<[u8]>::trim_ascii)
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .map(|line| OsString::from_vec(line.to_vec()))
+        .collect())
+}
+
+/// Tracks where we are in the command line.
+struct Scanner<'a> {
+    /// The command definition used to look up options.
+    command: &'a Command,
+
+    /// Whether the next argument is the value of an option.
+    expecting_value: bool,
+
+    /// Whether we’ve reached the command to run (or a tool), after which
+    /// nothing should be expanded.
+    done: bool,
+}
+
+impl<'a> Scanner<'a> {
+    /// Create a new scanner for the start of the command line.
+    const fn new(command: &'a Command) -> Self {
+        Self {
+            command,
+            expecting_value: false,
+            done: false,
+        }
+    }
+
+    /// Update state based on the next argument.
+    fn scan(&mut self, arg: &OsString) {
+        let arg = arg.as_bytes();
+        if self.expecting_value {
+            self.expecting_value = false;
+        } else if arg == b"--" || !arg.starts_with(b"-") || arg == b"-" {
+            self.done = true;
+        } else if let Some(long) = arg.strip_prefix(b"--") {
+            if !long.contains(&b'=') {
+                self.expecting_value = self.long_takes_value(long);
+            }
+        } else {
+            // A cluster of short options, e.g. `-cs`. If one of them takes a
+            // value, then the value is either the rest of the argument or the
+            // next argument.
+            let mut rest = &arg[1..];
+            while let Some((c, tail)) = rest.split_first() {
+                if self.short_takes_value(char::from(*c)) {
+                    self.expecting_value = tail.is_empty();
+                    break;
+                }
+                rest = tail;
+            }
+        }
+    }
+
+    /// Does the long option `name` take a value?
+    fn long_takes_value(&self, name: &[u8]) -> bool {
+        self.command.get_arguments().any(|arg| {
+            arg.get_long().map(str::as_bytes) == Some(name)
+                && arg.get_action().takes_values()
+        })
+    }
+
+    /// Does the short option `c` take a value?
+    fn short_takes_value(&self, c: char) -> bool {
+        self.command.get_arguments().any(|arg| {
+            arg.get_short() == Some(c) && arg.get_action().takes_values()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::Params;
+    use assert2::{check, let_assert};
+    use clap::CommandFactory;
+    use std::env;
+    use std::path::PathBuf;
+
+    /// Write an argument file to a temporary location.
+    fn args_file(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir()
+            .join(format!("rederr-argfile-{}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Expand a command line.
+    fn expand_args(args: &[&str]) -> anyhow::Result<Vec<OsString>> {
+        expand(&Params::command(), args.iter().map(OsString::from))
+    }
+
+    #[test]
+    fn no_files() {
+        let_assert!(Ok(args) = expand_args(&["rederr", "-s", "ls", "-l"]));
+        check!(args == ["rederr", "-s", "ls", "-l"]);
+    }
+
+    #[test]
+    fn file_with_comments() {
+        let path = args_file(
+            "comments",
+            "# Options\n--run-timeout\n  1h 30m  \n\n-s\n# Command\nls\n",
+        );
+        let arg = format!("@{}", path.display());
+        let result = expand_args(&["rederr", &arg, "-l"]);
+        fs::remove_file(path).unwrap();
+
+        let_assert!(Ok(args) = result);
+        check!(args == ["rederr", "--run-timeout", "1h 30m", "-s", "ls", "-l"]);
+    }
+
+    #[test]
+    fn file_after_command() {
+        let_assert!(Ok(args) = expand_args(&["rederr", "curl", "-d", "@x"]));
+        check!(args == ["rederr", "curl", "-d", "@x"]);
+    }
+
+    #[test]
+    fn file_as_option_value() {
+        let_assert!(
+            Ok(args) = expand_args(&["rederr", "--buffer-size", "@x", "ls"])
+        );
+        check!(args == ["rederr", "--buffer-size", "@x", "ls"]);
+    }
+
+    #[test]
+    fn file_missing() {
+        let_assert!(
+            Err(error) = expand_args(&["rederr", "@/nonexistent/args", "ls"])
+        );
+        check!(error.to_string().contains("/nonexistent/args"));
+    }
+
+    #[test]
+    fn scan_short_cluster_with_value() {
+        let command = Params::command();
+        let mut scanner = Scanner::new(&command);
+        scanner.scan(&"-cs".into());
+        check!(!scanner.expecting_value);
+        check!(!scanner.done);
+        scanner.scan(&"ls".into());
+        check!(scanner.done);
+    }
+}
@@ -0,0 +1,248 @@
+//! Run a command with tokio (see the `tokio` feature).
+//!
+//! [`AsyncRunner`] produces the same [`Event`]s as [`Events`](crate::Events),
+//! but it uses [`tokio::process`] rather than a `poll()` loop so that it
+//! doesn’t block the runtime. The command is run by a task spawned on the
+//! current runtime, which sends events through a channel.
+
+use crate::runner::{Event, TimeoutKind};
+use crate::timeout::Timeout;
+use futures_core::Stream;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use std::future;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::pin::Pin;
+use std::process::ExitStatus;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, ChildStderr, ChildStdout, Command};
+use tokio::sync::mpsc;
+
+/// Number of events to hold before we stop reading the command’s output.
+const CHANNEL_SIZE: usize = 16;
+
+/// A command running on a tokio runtime.
+///
+/// This is a [`Stream`] of what happens while the command runs. It ends after
+/// [`Event::Exit`] or an error.
+///
+/// If this is dropped before the command exits, the command is killed.
+#[derive(Debug)]
+pub struct AsyncRunner {
+    /// Receives events from the task running the command.
+    events: mpsc::Receiver<io::Result<Event>>,
+}
+
+impl AsyncRunner {
+    /// Start `command` with its output piped, and spawn a task to watch it.
+    pub(crate) fn start(
+        mut command: Command,
+        idle_timeout: Option<Duration>,
+        run_timeout: Option<Duration>,
+        kill_after: Option<Duration>,
+        buffer_size: usize,
+    ) -> io::Result<Self> {
+        let mut child = command.kill_on_drop(true).spawn()?;
+        let task = Task {
+            out: child.stdout.take(),
+            err: child.stderr.take(),
+            child,
+            run_timeout: Timeout::from(run_timeout).start(),
+            idle_timeout: Timeout::from(idle_timeout).start(),
+            idle: idle_timeout,
+            kill_timeout: Timeout::Never,
+            kill_after,
+            timed_out: false,
+            buffer_size,
+        };
+
+        let (sender, events) = mpsc::channel(CHANNEL_SIZE);
+        tokio::spawn(task.run(sender));
+        Ok(Self { events })
+    }
+}
+
+impl Stream for AsyncRunner {
+    type Item = io::Result<Event>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+/// Runs the command and reports what happens.
+#[derive(Debug)]
+struct Task {
+    /// The command.
+    child: Child,
+
+    /// The command’s stdout, until it’s closed.
+    out: Option<ChildStdout>,
+
+    /// The command’s stderr, until it’s closed.
+    err: Option<ChildStderr>,
+
+    /// Timeout for the entire run.
+    run_timeout: Timeout,
+
+    /// Timeout for the command to produce output.
+    idle_timeout: Timeout,
+
+    /// The length of the idle timeout, so it can be restarted.
+    idle: Option<Duration>,
+
+    /// Timeout for the command to exit after it was sent `SIGTERM`.
+    kill_timeout: Timeout,
+
+    /// The length of the kill timeout, so it can be started.
+    kill_after: Option<Duration>,
+
+    /// Whether a timeout expired.
+    timed_out: bool,
+
+    /// Size of the buffers used to read the command’s output.
+    buffer_size: usize,
+}
+
+impl Task {
+    /// Watch the command until it exits, sending events to `sender`.
+    ///
+    /// This stops early if the receiver is dropped, which kills the command.
+    async fn run(mut self, sender: mpsc::Sender<io::Result<Event>>) {
+        let mut out_buffer = vec![0; self.buffer_size];
+        let mut err_buffer = vec![0; self.buffer_size];
+        loop {
+            let result = tokio::select! {
+                result = self.step(&mut out_buffer, &mut err_buffer) => result,
+                () = sender.closed() => return,
+            };
+            let last = matches!(result, Ok(Some(Event::Exit(_))) | Err(_));
+            if let Some(event) = result.transpose() {
+                if sender.send(event).await.is_err() || last {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Wait for something to happen, and return the resulting event, if any.
+    async fn step(
+        &mut self,
+        out_buffer: &mut [u8],
+        err_buffer: &mut [u8],
+    ) -> io::Result<Option<Event>> {
+        let (kind, timeout) = self.timeout();
+        let expire = async {
+            match timeout.timeout() {
+                Some(remaining) => tokio::time::sleep(remaining).await,
+                None => future::pending().await,
+            }
+        };
+        let closed = self.out.is_none() && self.err.is_none();
+
+        tokio::select! {
+            result = read(&mut self.out, out_buffer) => {
+                let count = result?;
+                if count == 0 {
+                    self.out = None;
+                    self.check_closed();
+                    return Ok(None);
+                }
+                self.restart_idle_timeout();
+                Ok(Some(Event::Stdout(out_buffer[..count].to_vec())))
+            }
+            result = read(&mut self.err, err_buffer) => {
+                let count = result?;
+                if count == 0 {
+                    self.err = None;
+                    self.check_closed();
+                    return Ok(None);
+                }
+                self.restart_idle_timeout();
+                Ok(Some(Event::Stderr(err_buffer[..count].to_vec())))
+            }
+            status = self.child.wait(), if closed => {
+                Ok(Some(Event::Exit(exit_code(status?))))
+            }
+            () = expire => self.expired(kind),
+        }
+    }
+
+    /// Get the timeout that will expire first, and which timeout it is.
+    ///
+    /// The kind is `None` for the timeout to kill the command.
+    fn timeout(&self) -> (Option<TimeoutKind>, Timeout) {
+        let mut first = (Some(TimeoutKind::Run), &self.run_timeout);
+        for (kind, timeout) in [
+            (Some(TimeoutKind::Idle), &self.idle_timeout),
+            (None, &self.kill_timeout),
+        ] {
+            if timeout < first.1 {
+                first = (kind, timeout);
+            }
+        }
+        (first.0, first.1.clone())
+    }
+
+    /// Handle a timeout expiring.
+    fn expired(
+        &mut self,
+        kind: Option<TimeoutKind>,
+    ) -> io::Result<Option<Event>> {
+        let Some(kind) = kind else {
+            self.kill_timeout = Timeout::Never;
+            self.child.start_kill()?;
+            return Ok(None);
+        };
+
+        self.timed_out = true;
+        self.run_timeout = Timeout::Never;
+        self.idle_timeout = Timeout::Never;
+        self.kill_timeout = Timeout::from(self.kill_after).start();
+        if let Some(id) = self.child.id() {
+            let pid = id.try_into().expect("child PID should fit in i32");
+            kill(Pid::from_raw(pid), Signal::SIGTERM)?;
+        }
+        Ok(Some(Event::TimedOut(kind)))
+    }
+
+    /// Restart the idle timeout, unless a timeout already expired.
+    fn restart_idle_timeout(&mut self) {
+        if !self.timed_out {
+            self.idle_timeout = Timeout::from(self.idle).start();
+        }
+    }
+
+    /// Stop the idle timeout if both output streams are closed.
+    const fn check_closed(&mut self) {
+        if self.out.is_none() && self.err.is_none() {
+            // There’s no more output to wait for.
+            self.idle_timeout = Timeout::Never;
+        }
+    }
+}
+
+/// Read from `stream` into `buffer`, or wait forever if it’s closed.
+async fn read<R: AsyncRead + Unpin>(
+    stream: &mut Option<R>,
+    buffer: &mut [u8],
+) -> io::Result<usize> {
+    match stream {
+        Some(stream) => stream.read(buffer).await,
+        None => future::pending().await,
+    }
+}
+
+/// Get the exit code for `status`, or 128 + the signal number if the command
+/// was killed by a signal.
+fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .unwrap_or_else(|| status.signal().unwrap_or(0).saturating_add(128))
+}
@@ -0,0 +1,217 @@
+//! Hold output until our stdout or stderr can take it (see `--max-buffer`).
+//!
+//! Normally output is written as soon as it’s read, which blocks if whatever
+//! is reading our output is slow. While that write is blocked, `rederr` can’t
+//! read the child’s other stream or handle timeouts and signals.
+//!
+//! With `--max-buffer`, output is queued instead, and written when `poll()`
+//! says our stdout or stderr is writable. Like our stdin, they’re left
+//! blocking since the file description may be shared with other processes, so
+//! each write is limited to [`PIPE_BUF`] bytes, which a writable pipe can
+//! always take without blocking.
+//!
+//! Once `--max-buffer` bytes are waiting for stdout or stderr, the child’s
+//! output that goes there isn’t read until some of it is written. (The last
+//! read can go over the limit by up to the size of the read buffer.) The
+//! child blocks, as it would writing to a full pipe, but `rederr` still reads
+//! its other stream and handles timeouts and signals.
+
+use crate::params::Stream;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+
+/// Most that can be written to a writable pipe without blocking.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub const PIPE_BUF: usize = 4096;
+
+/// Most that can be written to a writable pipe without blocking.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub const PIPE_BUF: usize = 512;
+
+/// Output waiting to be written to our stdout and stderr.
+#[derive(Debug)]
+pub struct Backlog {
+    /// Output waiting for our stdout.
+    out: Queue,
+
+    /// Output waiting for our stderr.
+    err: Queue,
+
+    /// Whether the child’s stderr goes to our stderr rather than our stdout.
+    separate: bool,
+
+    /// Most output to queue for each destination before we stop reading the
+    /// child’s output that goes there.
+    max: usize,
+}
+
+/// Output waiting to be written to one of our streams.
+#[derive(Debug)]
+struct Queue {
+    /// A duplicate of our stdout or stderr, so that writes aren’t buffered.
+    file: File,
+
+    /// The output.
+    bytes: VecDeque<u8>,
+}
+
+impl Backlog {
+    /// Start queuing output, up to `max` bytes for each of our streams.
+    ///
+    /// `separate` is whether the child’s stderr goes to our stderr (see
+    /// `--separate`).
+    pub fn new(max: usize, separate: bool) -> io::Result<Self> {
+        Ok(Self {
+            out: Queue::new(io::stdout().as_fd())?,
+            err: Queue::new(io::stderr().as_fd())?,
+            separate,
+            max,
+        })
+    }
+
+    /// Which of our streams output from the child’s `stream` goes to.
+    pub const fn destination(&self, stream: Stream) -> Stream {
+        if self.separate {
+            stream
+        } else {
+            Stream::Out
+        }
+    }
+
+    /// Queue output from the child’s `stream`.
+    pub fn push(&mut self, stream: Stream, bytes: &[u8]) {
+        let destination = self.destination(stream);
+        self.queue_mut(destination).bytes.extend(bytes);
+    }
+
+    /// Is output waiting to be written to our `destination`?
+    pub fn is_waiting(&self, destination: Stream) -> bool {
+        !self.queue(destination).bytes.is_empty()
+    }
+
+    /// Is any output waiting to be written?
+    pub fn is_empty(&self) -> bool {
+        self.out.bytes.is_empty() && self.err.bytes.is_empty()
+    }
+
+    /// Is too much output waiting for where the child’s `stream` goes to read
+    /// more of it?
+    pub fn is_full(&self, stream: Stream) -> bool {
+        self.queue(self.destination(stream)).bytes.len() >= self.max
+    }
+
+    /// Our `destination`, to poll for writability.
+    pub fn fd(&self, destination: Stream) -> BorrowedFd<'_> {
+        self.queue(destination).file.as_fd()
+    }
+
+    /// Write what we can to our `destination`, which is writable.
+    pub fn write(&mut self, destination: Stream) -> io::Result<()> {
+        let queue = self.queue_mut(destination);
+        let (bytes, _) = queue.bytes.as_slices();
+        let len = bytes.len().min(PIPE_BUF);
+        match queue.file.write(&bytes[..len]) {
+            Ok(count) => {
+                queue.bytes.drain(..count);
+                Ok(())
+            }
+            // Our output might have been made non-blocking by another process.
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                Ok(())
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// If too much output is waiting for where the child’s `stream` goes,
+    /// write all of it, blocking if necessary.
+    ///
+    /// This keeps the backlog under `max` when output is pushed faster than
+    /// the child produces it (see `--quiet-on-success`).
+    pub fn make_room(&mut self, stream: Stream) -> io::Result<()> {
+        if !self.is_full(stream) {
+            return Ok(());
+        }
+        let queue = self.queue_mut(self.destination(stream));
+        let (front, back) = queue.bytes.as_slices();
+        queue.file.write_all(front)?;
+        queue.file.write_all(back)?;
+        queue.bytes.clear();
+        Ok(())
+    }
+
+    /// Write everything that’s waiting, blocking if necessary.
+    pub fn drain(&mut self) -> io::Result<()> {
+        for queue in [&mut self.out, &mut self.err] {
+            let (front, back) = queue.bytes.as_slices();
+            queue.file.write_all(front)?;
+            queue.file.write_all(back)?;
+            queue.bytes.clear();
+        }
+        Ok(())
+    }
+
+    /// The queue for our `destination`.
+    const fn queue(&self, destination: Stream) -> &Queue {
+        match destination {
+            Stream::Out => &self.out,
+            Stream::Err => &self.err,
+        }
+    }
+
+    /// The queue for our `destination`.
+    const fn queue_mut(&mut self, destination: Stream) -> &mut Queue {
+        match destination {
+            Stream::Out => &mut self.out,
+            Stream::Err => &mut self.err,
+        }
+    }
+}
+
+impl Queue {
+    /// Create an empty queue for `fd`.
+    fn new(fd: BorrowedFd<'_>) -> io::Result<Self> {
+        Ok(Self {
+            file: File::from(fd.try_clone_to_owned()?),
+            bytes: VecDeque::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    #[test]
+    fn combined_destination() {
+        let mut backlog = Backlog::new(4, false).unwrap();
+        backlog.push(Stream::Out, b"ab");
+        backlog.push(Stream::Err, b"c");
+        check!(backlog.is_waiting(Stream::Out));
+        check!(!backlog.is_waiting(Stream::Err));
+        check!(!backlog.is_full(Stream::Err));
+
+        backlog.push(Stream::Err, b"d");
+        check!(backlog.is_full(Stream::Out));
+        check!(backlog.is_full(Stream::Err));
+    }
+
+    #[test]
+    fn separate_destinations() {
+        let mut backlog = Backlog::new(2, true).unwrap();
+        backlog.push(Stream::Err, b"abc");
+        check!(!backlog.is_waiting(Stream::Out));
+        check!(backlog.is_waiting(Stream::Err));
+        check!(!backlog.is_full(Stream::Out));
+        check!(backlog.is_full(Stream::Err));
+        check!(!backlog.is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+//! Run the child in its own cgroup (see `--cgroup`).
+//!
+//! A transient cgroup v2 is created for each run under `--cgroup-parent`, or
+//! under the cgroup `rederr` is in. The child moves itself into the cgroup
+//! before it execs the command (see [`crate::pre_exec`]), so that everything
+//! the command starts is in the cgroup too. That makes it possible
+//! to limit the memory and CPU the command and its workers use together, and
+//! to report how much they used.
+//!
+//! The cgroup is removed when the run is over, unless processes the command
+//! started are still in it.
+//!
+//! Limits and peak memory usage need the `memory` and `cpu` controllers, which
+//! can only be enabled for the new cgroup if the parent cgroup has no
+//! processes of its own. That usually isn’t true of the cgroup `rederr` is in,
+//! so use `--cgroup-parent` to pass a cgroup delegated to the user, e.g. with
+//! systemd’s `Delegate=yes`. CPU usage is available regardless.
+
+use crate::params::Params;
+use std::fs::{self, File, OpenOptions};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// The period for `--cgroup-cpu-max`, in microseconds. This is the default.
+#[cfg(target_os = "linux")]
+const CPU_PERIOD: u32 = 100_000;
+
+/// Number of cgroups created so far, to give each run a unique cgroup.
+#[cfg(target_os = "linux")]
+static CREATED: AtomicU32 = AtomicU32::new(0);
+
+/// A transient cgroup for the child.
+#[derive(Debug)]
+pub struct Cgroup {
+    /// The cgroup directory.
+    path: PathBuf,
+}
+
+/// What everything in a cgroup used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// The most memory in use at once, if the memory controller is enabled
+    /// and the kernel reports it.
+    pub memory_peak: Option<u64>,
+
+    /// Total CPU time.
+    pub cpu_time: Option<Duration>,
+}
+
+impl Cgroup {
+    /// Create a cgroup for the child with the limits in `params`.
+    #[cfg(target_os = "linux")]
+    pub fn create(params: &Params) -> anyhow::Result<Self> {
+        use anyhow::Context;
+
+        let parent = match &params.cgroup_parent {
+            Some(parent) => parent.clone(),
+            None => own_cgroup().context("finding rederr’s cgroup")?,
+        };
+        enable(&parent, "memory", params.cgroup_memory_max.is_some())?;
+        enable(&parent, "cpu", params.cgroup_cpu_max.is_some())?;
+
+        let count = CREATED.fetch_add(1, Ordering::Relaxed);
+        let path =
+            parent.join(format!("rederr-{}-{count}", std::process::id()));
+        fs::create_dir(&path)
+            .with_context(|| format!("creating cgroup {}", path.display()))?;
+        let cgroup = Self { path };
+
+        if let Some(bytes) = params.cgroup_memory_max {
+            cgroup.write("memory.max", &bytes.to_string())?;
+        }
+        if let Some(cpus) = params.cgroup_cpu_max {
+            // Clamped to a u32, so the cast can’t truncate much.
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let quota = (cpus * f64::from(CPU_PERIOD))
+                .round()
+                .clamp(1_000.0, f64::from(u32::MAX))
+                as u32;
+            cgroup.write("cpu.max", &format!("{quota} {CPU_PERIOD}"))?;
+        }
+        Ok(cgroup)
+    }
+
+    /// cgroups are only supported on Linux.
+    #[cfg(not(target_os = "linux"))]
+    pub fn create(_params: &Params) -> anyhow::Result<Self> {
+        anyhow::bail!("--cgroup is only supported on Linux")
+    }
+
+    /// Open the file to write a PID to to move it into the cgroup. Writing
+    /// “0” moves the process doing the writing.
+    pub fn procs(&self) -> anyhow::Result<File> {
+        use anyhow::Context;
+
+        let path = self.path.join("cgroup.procs");
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("opening {}", path.display()))
+    }
+
+    /// Get what everything in the cgroup has used so far.
+    pub fn usage(&self) -> Usage {
+        let read = |name| fs::read_to_string(self.path.join(name)).ok();
+        Usage {
+            memory_peak: read("memory.peak")
+                .and_then(|peak| peak.trim().parse().ok()),
+            cpu_time: read("cpu.stat").and_then(|stat| {
+                stat.lines()
+                    .find_map(|line| line.strip_prefix("usage_usec "))
+                    .and_then(|usec| usec.trim().parse().ok())
+                    .map(Duration::from_micros)
+            }),
+        }
+    }
+
+    /// Write `value` to the cgroup’s `name` file.
+    #[cfg(target_os = "linux")]
+    fn write(&self, name: &str, value: &str) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let path = self.path.join(name);
+        fs::write(&path, value)
+            .with_context(|| format!("writing {value:?} to {}", path.display()))
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // This fails if processes are still in the cgroup. They’d outlive the
+        // limits if they were moved out, so leave it.
+        let _ = fs::remove_dir(&self.path);
+    }
+}
+
+impl Usage {
+    /// Summarize the usage for `-v`, e.g. "used 1.2s of CPU time".
+    pub fn summary(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(cpu_time) = self.cpu_time {
+            parts.push(format!("{cpu_time:.3?} of CPU time"));
+        }
+        if let Some(peak) = self.memory_peak {
+            parts.push(format!("{peak} bytes of memory at peak"));
+        }
+        (!parts.is_empty()).then(|| format!("used {}", parts.join(" and ")))
+    }
+}
+
+/// Enable `controller` for cgroups under `parent`.
+///
+/// If it can’t be enabled, this returns an error if it’s `required`, and
+/// otherwise does nothing.
+#[cfg(target_os = "linux")]
+fn enable(
+    parent: &Path,
+    controller: &str,
+    required: bool,
+) -> anyhow::Result<()> {
+    use anyhow::bail;
+
+    let has = |name: &str| {
+        fs::read_to_string(parent.join(name)).is_ok_and(|controllers| {
+            controllers
+                .split_whitespace()
+                .any(|name| name == controller)
+        })
+    };
+    if has("cgroup.subtree_control") {
+        return Ok(());
+    }
+    if !has("cgroup.controllers") {
+        if required {
+            bail!(
+                "the {controller} controller is not available in cgroup {}",
+                parent.display()
+            );
+        }
+        return Ok(());
+    }
+
+    let control = parent.join("cgroup.subtree_control");
+    match fs::write(&control, format!("+{controller}")) {
+        Err(error) if required => bail!(
+            "enabling the {controller} controller in {}: {error} (the parent \
+            cgroup must not contain processes; see --cgroup-parent)",
+            control.display()
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Find the cgroup `rederr` is in.
+#[cfg(target_os = "linux")]
+fn own_cgroup() -> anyhow::Result<PathBuf> {
+    use anyhow::anyhow;
+
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+    let mount = mountinfo
+        .lines()
+        .find_map(|line| {
+            let (fields, filesystem) = line.split_once(" - ")?;
+            filesystem.starts_with("cgroup2 ").then_some(())?;
+            fields.split(' ').nth(4)
+        })
+        .ok_or_else(|| anyhow!("cgroup v2 is not mounted"))?;
+
+    let cgroups = fs::read_to_string("/proc/self/cgroup")?;
+    let path = cgroups
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .ok_or_else(|| anyhow!("not in a cgroup v2"))?;
+    // Normalize so that the root cgroup doesn’t have a trailing slash.
+    Ok(Path::new(mount)
+        .join(path.trim_start_matches('/'))
+        .components()
+        .collect())
+}
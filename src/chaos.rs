@@ -0,0 +1,109 @@
+//! Inject failures into the event loop to test its robustness.
+//!
+//! `--chaos SEED` is only available when `rederr` is built with the `chaos`
+//! feature. It randomly interrupts `poll()`, shortens reads, and delays
+//! handling hangups. The random numbers are generated from the seed, so a
+//! failing run can be reproduced.
+
+/// Random failure injection.
+///
+/// When disabled, this never injects anything.
+#[derive(Clone, Debug)]
+pub struct Chaos {
+    /// State for the random number generator, or `None` if disabled.
+    state: Option<u64>,
+}
+
+impl Chaos {
+    /// Inject failures based on `seed`, or never inject failures if `seed` is
+    /// `None`.
+    pub const fn new(seed: Option<u64>) -> Self {
+        // xorshift gets stuck at 0.
+        Self {
+            state: match seed {
+                Some(0) => Some(1),
+                seed => seed,
+            },
+        }
+    }
+
+    /// Should `poll()` pretend it was interrupted by a signal (EINTR)?
+    pub fn interrupt(&mut self) -> bool {
+        self.one_in(4)
+    }
+
+    /// How much to read into a buffer of length `len`.
+    ///
+    /// Sometimes returns a length less than `len`, but never 0 unless `len`
+    /// is 0.
+    pub fn read_len(&mut self, len: usize) -> usize {
+        if len > 1 && self.one_in(3) {
+            let range =
+                u64::try_from(len.saturating_sub(1)).unwrap_or(u64::MAX);
+            self.next()
+                .and_then(|value| value.checked_rem(range))
+                .and_then(|short| usize::try_from(short.saturating_add(1)).ok())
+                .unwrap_or(len)
+        } else {
+            len
+        }
+    }
+
+    /// Should handling a hangup be put off until the next `poll()`?
+    pub fn delay_hangup(&mut self) -> bool {
+        self.one_in(2)
+    }
+
+    /// Return true with a probability of 1 in `n`.
+    fn one_in(&mut self, n: u64) -> bool {
+        self.next()
+            .is_some_and(|value| value.checked_rem(n) == Some(0))
+    }
+
+    /// Get the next random number, or `None` if disabled (xorshift64*).
+    fn next(&mut self) -> Option<u64> {
+        let mut x = self.state?;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = Some(x);
+        Some(x.wrapping_mul(0x2545_f491_4f6c_dd1d))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    #[test]
+    fn disabled() {
+        let mut chaos = Chaos::new(None);
+        for _ in 0..100 {
+            check!(!chaos.interrupt());
+            check!(!chaos.delay_hangup());
+            check!(chaos.read_len(1024) == 1024);
+        }
+    }
+
+    #[test]
+    fn reproducible() {
+        let mut a = Chaos::new(Some(42));
+        let mut b = Chaos::new(Some(42));
+        for _ in 0..100 {
+            check!(a.interrupt() == b.interrupt());
+            check!(a.read_len(1024) == b.read_len(1024));
+        }
+    }
+
+    #[test]
+    fn read_len_in_range() {
+        let mut chaos = Chaos::new(Some(0));
+        let lengths: Vec<usize> =
+            (0..1000).map(|_| chaos.read_len(16)).collect();
+        check!(lengths.iter().all(|len| (1..=16).contains(len)));
+        check!(lengths.iter().any(|len| *len < 16));
+        check!(chaos.read_len(1) == 1);
+        check!(chaos.read_len(0) == 0);
+    }
+}
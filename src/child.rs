@@ -0,0 +1,281 @@
+//! Track the state of the child process.
+//!
+//! We use `waitpid()` directly rather than [`std::process::Child::wait()`] so
+//! that we can find out when the child is stopped or continued, and so that we
+//! can check on the child without blocking.
+
+use nix::errno::Errno;
+use nix::sys::signal::{kill, killpg, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use std::process;
+
+/// A change in the state of the child.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// The child was stopped by a signal.
+    Stopped(Signal),
+
+    /// The child was continued after being stopped.
+    Continued,
+
+    /// The child exited. Contains the exit code, or 128 + the signal number if
+    /// it was killed by a signal.
+    Exited(i32),
+}
+
+/// Which processes signals for the child are sent to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scope {
+    /// Just the child.
+    #[default]
+    Child,
+
+    /// The child’s process group.
+    ///
+    /// The child must have been started in its own process group, e.g. with
+    /// [`std::os::unix::process::CommandExt::process_group()`]. Signals are
+    /// sent to the group even after the child exits, since processes it
+    /// started might still be running.
+    Group,
+
+    /// The child and its descendants, found by walking `/proc`.
+    ///
+    /// This is only supported on Linux; elsewhere, it’s the same as
+    /// [`Scope::Child`]. Descendants whose parent exited are reparented, so
+    /// they can’t be found.
+    Tree,
+}
+
+/// The child process.
+#[derive(Debug)]
+pub struct Child {
+    /// The child’s process ID.
+    pid: Pid,
+
+    /// Which processes to send signals to.
+    scope: Scope,
+
+    /// The child’s exit code, once it has exited.
+    exit_code: Option<i32>,
+
+    /// The signal that killed the child, if it was killed by one.
+    killed_by: Option<Signal>,
+}
+
+impl Child {
+    /// Track `child`.
+    ///
+    /// Don’t call [`process::Child::wait()`] or similar on `child` after this.
+    ///
+    /// # Panics
+    ///
+    /// This panics if the child’s PID doesn’t fit in an `i32`, which shouldn’t
+    /// be possible.
+    #[must_use]
+    pub fn new(child: &process::Child) -> Self {
+        Self {
+            pid: Pid::from_raw(
+                child.id().try_into().expect("child PID should fit in i32"),
+            ),
+            scope: Scope::Child,
+            exit_code: None,
+            killed_by: None,
+        }
+    }
+
+    /// Send signals to `scope` rather than just the child.
+    #[must_use]
+    pub const fn with_scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Has the child exited?
+    #[must_use]
+    pub const fn has_exited(&self) -> bool {
+        self.exit_code.is_some()
+    }
+
+    /// Get the child’s exit code, if it has exited.
+    ///
+    /// If it was killed by a signal, this is 128 + the signal number.
+    #[must_use]
+    pub const fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Get the signal that killed the child, if it was killed by one.
+    #[must_use]
+    pub const fn killed_by(&self) -> Option<Signal> {
+        self.killed_by
+    }
+
+    /// Check for a change in the child’s state without blocking.
+    ///
+    /// Call this repeatedly until it returns `Ok(None)` to get all changes.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if `waitpid()` fails.
+    pub fn check(&mut self) -> nix::Result<Option<Change>> {
+        if self.exit_code.is_some() {
+            return Ok(None);
+        }
+
+        let flags = WaitPidFlag::WNOHANG
+            | WaitPidFlag::WUNTRACED
+            | WaitPidFlag::WCONTINUED;
+        Ok(self.handle(waitpid(self.pid, Some(flags))?))
+    }
+
+    /// Wait for the child to exit and return its exit code.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if `waitpid()` fails.
+    pub fn wait(&mut self) -> nix::Result<i32> {
+        loop {
+            if let Some(code) = self.exit_code {
+                return Ok(code);
+            }
+
+            match waitpid(self.pid, None) {
+                Err(Errno::EINTR) => {}
+                result => {
+                    self.handle(result?);
+                }
+            }
+        }
+    }
+
+    /// Send `signal` to the child, unless it has already exited.
+    ///
+    /// With [`Scope::Group`], this is sent to the child’s process group if any
+    /// of it is left. With [`Scope::Tree`], it’s also sent to the child’s
+    /// descendants.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if the signal couldn’t be sent.
+    pub fn signal(&self, signal: Signal) -> nix::Result<()> {
+        match self.scope {
+            Scope::Group => match killpg(self.pid, signal) {
+                // Everything in the group has exited.
+                Err(Errno::ESRCH) if self.exit_code.is_some() => {}
+                result => result?,
+            },
+            _ if self.exit_code.is_some() => {}
+            Scope::Child => kill(self.pid, signal)?,
+            Scope::Tree => {
+                // Find descendants first, since they’re reparented if their
+                // parent exits. Then signal the child so that it doesn’t start
+                // anything more, then its descendants from the top down.
+                let descendants = descendants(self.pid);
+                kill(self.pid, signal)?;
+                for pid in descendants {
+                    match kill(pid, signal) {
+                        // It exited since we found it.
+                        Err(Errno::ESRCH) => {}
+                        result => result?,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Update our state based on the result of `waitpid()`.
+    const fn handle(&mut self, status: WaitStatus) -> Option<Change> {
+        match status {
+            WaitStatus::Exited(_, code) => {
+                self.exit_code = Some(code);
+                Some(Change::Exited(code))
+            }
+            WaitStatus::Signaled(_, signal, _) => {
+                let code = (signal as i32).saturating_add(128);
+                self.exit_code = Some(code);
+                self.killed_by = Some(signal);
+                Some(Change::Exited(code))
+            }
+            WaitStatus::Stopped(_, signal) => Some(Change::Stopped(signal)),
+            WaitStatus::Continued(_) => Some(Change::Continued),
+            _ => None,
+        }
+    }
+}
+
+/// Find the descendants of `pid`, parents before children.
+///
+/// Processes that can’t be read, e.g. because they just exited, are skipped.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn descendants(pid: Pid) -> Vec<Pid> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let processes: Vec<(Pid, Pid)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            let stat =
+                std::fs::read_to_string(entry.path().join("stat")).ok()?;
+            Some((Pid::from_raw(pid), parent_from_stat(&stat)?))
+        })
+        .collect();
+
+    let mut found = vec![pid];
+    let mut i = 0;
+    while let Some(&parent) = found.get(i) {
+        found.extend(
+            processes
+                .iter()
+                .filter(|(_, ppid)| *ppid == parent)
+                .map(|(pid, _)| *pid),
+        );
+        i = i.saturating_add(1);
+    }
+    found.remove(0);
+    found
+}
+
+/// Descendants can only be found on Linux.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const fn descendants(_pid: Pid) -> Vec<Pid> {
+    Vec::new()
+}
+
+/// Get the parent PID from the contents of `/proc/PID/stat`.
+///
+/// That’s the fourth field, after the command name in parentheses, which might
+/// contain spaces or parentheses itself.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn parent_from_stat(stat: &str) -> Option<Pid> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    let ppid = rest.split_whitespace().nth(1)?.parse().ok()?;
+    Some(Pid::from_raw(ppid))
+}
+
+#[cfg(test)]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    #[test]
+    fn parent_from_stat_odd_name() {
+        let stat = "1234 (a) b (c) S 99 1234 1234 0 -1 4194304";
+        check!(parent_from_stat(stat) == Some(Pid::from_raw(99)));
+    }
+
+    #[test]
+    fn descendants_of_this_process() {
+        let mut child =
+            process::Command::new("sleep").arg("10").spawn().unwrap();
+        let found = descendants(Pid::this());
+        let _ = child.kill();
+        let _ = child.wait();
+
+        let pid = i32::try_from(child.id()).unwrap();
+        check!(found.contains(&Pid::from_raw(pid)));
+    }
+}
@@ -0,0 +1,199 @@
+//! Collapse lines overwritten with carriage returns (see `--collapse-cr`).
+//!
+//! Progress bars redraw a line by outputting `\r` and then the new version of
+//! the line. That looks fine on a terminal, but a log ends up with every
+//! version. This holds on to each line until it’s finished, dropping whatever
+//! comes before a `\r` so that only the final version is logged.
+//!
+//! The prefix of a line (its tag, sequence number, and timestamp) is kept, so
+//! it shows when the line was started. `\r\n` line endings are left alone.
+
+use crate::params::Stream;
+use std::io;
+
+/// Holds the unfinished line from each of the child’s streams.
+#[derive(Debug, Default)]
+pub struct Collapse {
+    /// Unfinished line from stdout.
+    out: Line,
+
+    /// Unfinished line from stderr.
+    err: Line,
+}
+
+/// An unfinished line.
+#[derive(Debug, Default)]
+struct Line {
+    /// The line so far.
+    bytes: Vec<u8>,
+
+    /// Length of the prefix at the start of the line.
+    prefix: usize,
+
+    /// Whether the last byte was `\r`. It’s held back until we know whether
+    /// it’s part of a `\r\n`.
+    cr: bool,
+}
+
+impl Collapse {
+    /// Add rendered output from `stream`, and pass any lines that it finishes
+    /// to `write`.
+    ///
+    /// `prefix` is whether `bytes` is the prefix of a line rather than output.
+    pub fn push<F>(
+        &mut self,
+        stream: Stream,
+        bytes: &[u8],
+        prefix: bool,
+        mut write: F,
+    ) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        let line = self.line(stream);
+        if prefix {
+            line.bytes.extend_from_slice(bytes);
+            line.prefix = line.bytes.len();
+            return Ok(());
+        }
+
+        for &byte in bytes {
+            if line.cr {
+                line.cr = false;
+                if byte == b'\n' {
+                    line.bytes.push(b'\r');
+                } else {
+                    line.bytes.truncate(line.prefix);
+                }
+            }
+
+            if byte == b'\r' {
+                line.cr = true;
+                continue;
+            }
+
+            line.bytes.push(byte);
+            if byte == b'\n' {
+                write(&line.bytes)?;
+                line.clear();
+            }
+        }
+        Ok(())
+    }
+
+    /// Pass the unfinished line from `stream`, if any, to `write`.
+    pub fn flush<F>(&mut self, stream: Stream, write: F) -> io::Result<()>
+    where
+        F: FnOnce(&[u8]) -> io::Result<()>,
+    {
+        let line = self.line(stream);
+        let result = if line.bytes.is_empty() {
+            Ok(())
+        } else {
+            write(&line.bytes)
+        };
+        line.clear();
+        result
+    }
+
+    /// Get the unfinished line for `stream`.
+    const fn line(&mut self, stream: Stream) -> &mut Line {
+        match stream {
+            Stream::Out => &mut self.out,
+            Stream::Err => &mut self.err,
+        }
+    }
+}
+
+impl Line {
+    /// Start a new line, keeping the buffer.
+    fn clear(&mut self) {
+        self.bytes.clear();
+        self.prefix = 0;
+        self.cr = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+    use bstr::ByteSlice;
+
+    /// Push `chunks` of `(prefix, bytes)` from stdout, then flush, and return
+    /// what was written.
+    fn collapse(chunks: &[(bool, &[u8])]) -> Vec<u8> {
+        let mut collapse = Collapse::default();
+        let mut written = Vec::new();
+        for (prefix, bytes) in chunks {
+            collapse
+                .push(Stream::Out, bytes, *prefix, |line| {
+                    written.extend_from_slice(line);
+                    Ok(())
+                })
+                .unwrap();
+        }
+        collapse
+            .flush(Stream::Out, |line| {
+                written.extend_from_slice(line);
+                Ok(())
+            })
+            .unwrap();
+        written
+    }
+
+    #[test]
+    fn no_cr() {
+        check!(collapse(&[(false, b"a\nb")]).as_bstr() == "a\nb");
+    }
+
+    #[test]
+    fn progress() {
+        check!(
+            collapse(&[(false, b"10%\r50%"), (false, b"\r100%\ndone\n")])
+                .as_bstr()
+                == "100%\ndone\n"
+        );
+    }
+
+    #[test]
+    fn keeps_prefix() {
+        check!(
+            collapse(&[(true, b"[out] "), (false, b"10%\r100%\n")]).as_bstr()
+                == "[out] 100%\n"
+        );
+    }
+
+    #[test]
+    fn crlf() {
+        check!(
+            collapse(&[(false, b"a\r"), (false, b"\nb\r\n")]).as_bstr()
+                == "a\r\nb\r\n"
+        );
+    }
+
+    #[test]
+    fn trailing_cr() {
+        check!(collapse(&[(false, b"50%\r")]).as_bstr() == "50%");
+    }
+
+    #[test]
+    fn streams_separate() {
+        let mut collapse = Collapse::default();
+        let mut written = Vec::new();
+        let mut write = |line: &[u8]| {
+            written.extend_from_slice(line);
+            Ok(())
+        };
+        collapse
+            .push(Stream::Out, b"out\r", false, &mut write)
+            .unwrap();
+        collapse
+            .push(Stream::Err, b"err\n", false, &mut write)
+            .unwrap();
+        collapse
+            .push(Stream::Out, b"OUT\n", false, &mut write)
+            .unwrap();
+        check!(written.as_bstr() == "err\nOUT\n");
+    }
+}
@@ -0,0 +1,29 @@
+//! Generate shell completions for `rederr`.
+//!
+//! The completions are generated from [`Params`], so they stay in sync with
+//! `--help`.
+
+use crate::params::{Params, Shell};
+use clap::CommandFactory;
+use std::io::{self, Write};
+
+/// Write completions for `shell` to `out`.
+pub fn render(shell: Shell, out: &mut dyn Write) -> io::Result<()> {
+    let shell = match shell {
+        Shell::Bash => clap_complete::Shell::Bash,
+        Shell::Elvish => clap_complete::Shell::Elvish,
+        Shell::Fish => clap_complete::Shell::Fish,
+        Shell::PowerShell => clap_complete::Shell::PowerShell,
+        Shell::Zsh => clap_complete::Shell::Zsh,
+    };
+
+    // clap_complete panics if writing fails, e.g. with a closed pipe.
+    let mut buffer = Vec::new();
+    clap_complete::generate(
+        shell,
+        &mut Params::command(),
+        "rederr",
+        &mut buffer,
+    );
+    out.write_all(&buffer)
+}
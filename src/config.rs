@@ -0,0 +1,324 @@
+//! Load default options from a configuration file (see `--config` and
+//! `--profile`).
+//!
+//! The file is TOML. Top-level keys are long option names
+//! that apply to every run, and `[profile.NAME]` tables hold options that only
+//! apply with `--profile NAME`:
+//!
+//! ```toml
+//! idle-timeout = "10m"
+//! separate = true
+//! highlight = ["error", "warning"]
+//!
+//! [profile.cron]
+//! run-timeout = "1h"
+//! log-dir = "/var/log/rederr"
+//! ```
+//!
+//! Values are strings, numbers, booleans, or arrays of strings and numbers. `true` passes a flag and `false` leaves it out; a number passes a
+//! counted flag like `verbose` that many times. Arrays pass the option once
+//! for each element.
+//!
+//! The file is `$XDG_CONFIG_HOME/rederr/config.toml`, which is usually
+//! `~/.config/rederr/config.toml`, unless `--config` is passed.
+//!
+//! The options are inserted before the command line, so options on the
+//! command line override them. `REDERR_*` environment variables also override
+//! them. Options that can be repeated, like
+//! `--highlight`, are combined instead.
+
+use anyhow::{anyhow, bail, Context};
+use clap::{ArgAction, Command};
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Options from a configuration file.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    /// The file the configuration was loaded from, if any.
+    path: Option<PathBuf>,
+
+    /// Options that apply to every run.
+    defaults: Table,
+
+    /// Named sets of options for `--profile`.
+    profiles: BTreeMap<String, Table>,
+}
+
+impl Config {
+    /// Load the configuration from `path`, or from the default location if
+    /// `path` is `None`.
+    ///
+    /// It’s not an error for the file at the default location not to exist.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_owned(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(Self {
+                path: Some(path.clone()),
+                ..Self::parse(&contents)
+                    .with_context(|| format!("in config {}", path.display()))?
+            }),
+            Err(error)
+                if !required && error.kind() == io::ErrorKind::NotFound =>
+            {
+                Ok(Self::default())
+            }
+            Err(error) => Err(error)
+                .with_context(|| format!("reading config {}", path.display())),
+        }
+    }
+
+    /// Parse a configuration file.
+    fn parse(contents: &str) -> anyhow::Result<Self> {
+        let mut defaults: Table = contents.parse()?;
+        let profiles = match defaults.remove("profile") {
+            Some(Value::Table(profiles)) => profiles
+                .into_iter()
+                .map(|(name, options)| match options {
+                    Value::Table(options) => Ok((name, options)),
+                    _ => bail!("expected [profile.{name}] to be a table"),
+                })
+                .collect::<anyhow::Result<_>>()?,
+            Some(_) => bail!("expected [profile.NAME] tables"),
+            None => BTreeMap::new(),
+        };
+        Ok(Self {
+            path: None,
+            defaults,
+            profiles,
+        })
+    }
+
+    /// Get the arguments for the defaults and `profile`, using `command` to
+    /// check that the options exist.
+    pub fn args(
+        &self,
+        command: &Command,
+        profile: Option<&str>,
+    ) -> anyhow::Result<Vec<OsString>> {
+        self.collect_args(command, profile)
+            .with_context(|| match &self.path {
+                Some(path) => format!("in config {}", path.display()),
+                None => "no config file found".to_owned(),
+            })
+    }
+
+    /// Get the arguments for [`Config::args()`] without context.
+    fn collect_args(
+        &self,
+        command: &Command,
+        profile: Option<&str>,
+    ) -> anyhow::Result<Vec<OsString>> {
+        let mut args = Vec::new();
+        for (key, value) in &self.defaults {
+            push_args(&mut args, command, key, value)?;
+        }
+        if let Some(name) = profile {
+            let options = self
+                .profiles
+                .get(name)
+                .ok_or_else(|| anyhow!("no such profile {name:?}"))?;
+            for (key, value) in options {
+                push_args(&mut args, command, key, value)
+                    .with_context(|| format!("in profile {name:?}"))?;
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Insert `options` from the configuration file into the command line `args`,
+/// so that the command line overrides them.
+///
+/// `args` should include the program name as the first element.
+pub fn merge(args: &[OsString], options: &[OsString]) -> Vec<OsString> {
+    let index = args.len().min(1);
+    let mut merged = args[..index].to_vec();
+    merged.extend_from_slice(options);
+    merged.extend_from_slice(&args[index..]);
+    merged
+}
+
+/// The default location of the configuration file.
+fn default_path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME")
+                .filter(|dir| !dir.is_empty())
+                .map(|home| Path::new(&home).join(".config"))
+        })?;
+    Some(config.join("rederr").join("config.toml"))
+}
+
+/// Add the arguments for the option `key` set to `value`.
+fn push_args(
+    args: &mut Vec<OsString>,
+    command: &Command,
+    key: &str,
+    value: &Value,
+) -> anyhow::Result<()> {
+    let long = key.replace('_', "-");
+    let arg = command
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(long.as_str()))
+        .filter(|_| !["config", "profile"].contains(&long.as_str()))
+        .ok_or_else(|| anyhow!("unknown option {key:?}"))?;
+    if arg
+        .get_env()
+        .is_some_and(|name| env::var_os(name).is_some())
+    {
+        // The environment variable overrides the configuration file.
+        return Ok(());
+    }
+    let flag = format!("--{long}");
+
+    match (value, arg.get_action()) {
+        (Value::Boolean(true), action) if !action.takes_values() => {
+            args.push(flag.into());
+        }
+        (Value::Boolean(false), action) if !action.takes_values() => {}
+        (Value::Integer(count), ArgAction::Count) => {
+            let count = usize::try_from(*count)
+                .with_context(|| format!("{key}: expected a count"))?;
+            args.extend((0..count).map(|_| OsString::from(&flag)));
+        }
+        (_, action) if !action.takes_values() => {
+            bail!("{key}: expected true or false");
+        }
+        (Value::Array(values), _) => {
+            for value in values {
+                args.push(format!("{flag}={}", scalar(key, value)?).into());
+            }
+        }
+        (value, _) => {
+            args.push(format!("{flag}={}", scalar(key, value)?).into());
+        }
+    }
+    Ok(())
+}
+
+/// Format a string or number value for the option `key`.
+fn scalar(key: &str, value: &Value) -> anyhow::Result<String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Integer(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        _ => bail!("{key}: expected a value"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::Params;
+    use assert2::{check, let_assert};
+    use clap::CommandFactory;
+
+    /// Parse `contents` and get the arguments for `profile`.
+    fn args(
+        contents: &str,
+        profile: Option<&str>,
+    ) -> anyhow::Result<Vec<String>> {
+        let config = Config::parse(contents)?;
+        Ok(config
+            .collect_args(&Params::command(), profile)?
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn defaults_and_profile() {
+        let contents = "\
+            # Comment\n\
+            idle-timeout = \"10m\" # 10 minutes\n\
+            separate = true\n\
+            tag = false\n\
+            highlight = [\n\
+                'a b',\n\
+                \"c\\\"d\",\n\
+            ]\n\
+            \n\
+            [profile.cron]\n\
+            run_timeout = '1h'\n\
+            verbose = 2\n\
+            [profile.\"other\"]\n\
+            buffer-size = 1_024\n";
+
+        let_assert!(Ok(defaults) = args(contents, None));
+        check!(
+            defaults
+                == [
+                    "--highlight=a b",
+                    "--highlight=c\"d",
+                    "--idle-timeout=10m",
+                    "--separate",
+                ]
+        );
+
+        let_assert!(Ok(cron) = args(contents, Some("cron")));
+        check!(cron[4..] == ["--run-timeout=1h", "--verbose", "--verbose"]);
+
+        let_assert!(Ok(other) = args(contents, Some("other")));
+        check!(other[4..] == ["--buffer-size=1024"]);
+    }
+
+    #[test]
+    fn unknown_profile() {
+        let_assert!(Err(error) = args("", Some("cron")));
+        check!(error.to_string() == "no such profile \"cron\"");
+    }
+
+    #[test]
+    fn unknown_option() {
+        let_assert!(Err(error) = args("no-such-option = 1", None));
+        check!(error.to_string() == "unknown option \"no-such-option\"");
+
+        let_assert!(Err(error) = args("[cron]\nverbose = 1", None));
+        check!(error.to_string() == "unknown option \"cron\"");
+    }
+
+    #[test]
+    fn wrong_type() {
+        let_assert!(Err(error) = args("separate = \"yes\"", None));
+        check!(error.to_string() == "separate: expected true or false");
+
+        let_assert!(Err(error) = args("idle-timeout = true", None));
+        check!(error.to_string() == "idle-timeout: expected a value");
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for contents in [
+            "separate",
+            "separate = true false",
+            "idle-timeout = \"10m",
+            "idle-timeout = 10m'",
+            "[profile.a]\n[profile.a]",
+            "profile = 1",
+            "[profile]\na = 1",
+            "a b = 1",
+        ] {
+            check!(Config::parse(contents).is_err(), "{contents:?}");
+        }
+    }
+
+    #[test]
+    fn line_numbers() {
+        let_assert!(Err(error) = Config::parse("\nseparate = true\nbad\n"));
+        check!(error.to_string().contains("line 3"));
+    }
+}
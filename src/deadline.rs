@@ -0,0 +1,223 @@
+//! Wall clock deadlines for the run (see `--deadline`).
+//!
+//! A deadline is a time of day, e.g. "04:30", or a date and time, e.g.
+//! "2024-03-01 04:30:00", in the zone passed to `--tz` or the local zone. A
+//! time of day means the next time the clock reads that time, so a job
+//! started at 23:00 with `--deadline 04:30` must finish by 04:30 the next
+//! morning.
+
+use crate::params::Params;
+use crate::timestamp::{days_from_civil, Timestamp};
+use crate::tz::TimeZone;
+use anyhow::{anyhow, bail};
+use std::time::{Duration, SystemTime};
+
+/// Seconds in a day.
+const DAY: i64 = 86_400;
+
+/// A deadline as passed on the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deadline {
+    /// The date as (year, month, day), or `None` for the next time the time
+    /// of day comes around.
+    date: Option<(i64, i64, i64)>,
+
+    /// Seconds since midnight.
+    time: i64,
+}
+
+impl Deadline {
+    /// Parse "HH:MM[:SS]" or "YYYY-MM-DD HH:MM[:SS]". The date and time may
+    /// also be separated by "T".
+    pub fn parse(input: &str) -> anyhow::Result<Self> {
+        let (date, time) = match input.trim().split_once([' ', 'T']) {
+            Some((date, time)) => (Some(parse_date(date)?), time),
+            None => (None, input.trim()),
+        };
+        Ok(Self {
+            date,
+            time: parse_time(time)?,
+        })
+    }
+
+    /// Get the wall clock time of the deadline in `zone`, given that it’s
+    /// `now`.
+    // Times and dates are limited by parsing, so the arithmetic can’t
+    // overflow.
+    #[allow(clippy::arithmetic_side_effects)]
+    pub fn resolve(&self, now: SystemTime, zone: &TimeZone) -> SystemTime {
+        let now = seconds(now);
+        let at = if let Some((year, month, day)) = self.date {
+            to_utc(days_from_civil(year, month, day) * DAY + self.time, zone)
+        } else {
+            let today = (now + i64::from(zone.offset_at(now))).div_euclid(DAY);
+            let at = to_utc(today * DAY + self.time, zone);
+            if at > now {
+                at
+            } else {
+                to_utc((today + 1) * DAY + self.time, zone)
+            }
+        };
+        SystemTime::UNIX_EPOCH
+            + Duration::from_secs(u64::try_from(at).unwrap_or_default())
+    }
+}
+
+/// Get the wall clock time of `--deadline`, if it was passed.
+///
+/// Returns an error if the deadline has already passed.
+pub fn resolve(params: &Params) -> anyhow::Result<Option<SystemTime>> {
+    let Some(deadline) = &params.deadline else {
+        return Ok(None);
+    };
+    let zone = params.time_zone();
+    let now = SystemTime::now();
+    let at = deadline.resolve(now, zone);
+    if at <= now {
+        bail!(
+            "--deadline {} has already passed",
+            Timestamp::wall(at, zone)
+        );
+    }
+    Ok(Some(at))
+}
+
+/// Parse a date in the form "YYYY-MM-DD".
+fn parse_date(input: &str) -> anyhow::Result<(i64, i64, i64)> {
+    let mut parts = input.splitn(3, '-');
+    let mut next = || -> anyhow::Result<i64> {
+        let part = parts.next().unwrap_or_default();
+        if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+            bail!("expected a date like 2024-03-01, got {input:?}");
+        }
+        Ok(part.parse()?)
+    };
+    let date = (next()?, next()?, next()?);
+
+    // Check that the day exists in the month, e.g. not 2023-02-29.
+    let (year, month, day) = date;
+    if !(1..=9999).contains(&year) || !(1..=12).contains(&month) {
+        bail!("invalid date {input:?}");
+    }
+    let (next_year, next_month) = if month == 12 {
+        (year.saturating_add(1), 1)
+    } else {
+        (year, month.saturating_add(1))
+    };
+    let days = days_from_civil(next_year, next_month, 1)
+        .saturating_sub(days_from_civil(year, month, 1));
+    if !(1..=days).contains(&day) {
+        bail!("invalid date {input:?}");
+    }
+    Ok(date)
+}
+
+/// Parse a time in the form "HH:MM" or "HH:MM:SS" into seconds since
+/// midnight.
+// The parts are checked to be small before they’re combined.
+#[allow(clippy::arithmetic_side_effects)]
+fn parse_time(input: &str) -> anyhow::Result<i64> {
+    let parts = input
+        .split(':')
+        .map(|part| {
+            if part.len() == 2 && part.bytes().all(|b| b.is_ascii_digit()) {
+                part.parse::<i64>().ok()
+            } else {
+                None
+            }
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| anyhow!("expected a time like 04:30, got {input:?}"))?;
+    let (hour, minute, second) = match parts[..] {
+        [hour, minute] => (hour, minute, 0),
+        [hour, minute, second] => (hour, minute, second),
+        _ => bail!("expected a time like 04:30, got {input:?}"),
+    };
+    if hour > 23 || minute > 59 || second > 59 {
+        bail!("invalid time {input:?}");
+    }
+    Ok(hour * 3_600 + minute * 60 + second)
+}
+
+/// Convert a local time in `zone` to UTC, both in seconds since the epoch.
+///
+/// Local times that are skipped or repeated when the offset changes are
+/// resolved with the offset before the change.
+// Offsets are less than a day, so this can’t overflow for parsed times.
+#[allow(clippy::arithmetic_side_effects)]
+fn to_utc(local: i64, zone: &TimeZone) -> i64 {
+    let guess = local - i64::from(zone.offset_at(local));
+    local - i64::from(zone.offset_at(guess))
+}
+
+/// Get seconds since the epoch, clamping times before it to 0.
+fn seconds(time: SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| {
+            i64::try_from(since.as_secs()).unwrap_or(i64::MAX)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::{check, let_assert};
+
+    /// 2024-03-01T12:00:00Z.
+    const NOON: u64 = 1_709_294_400;
+
+    #[allow(clippy::arithmetic_side_effects)]
+    fn at(seconds: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn resolve(input: &str, now: u64, zone: &str) -> SystemTime {
+        let_assert!(Ok(deadline) = Deadline::parse(input));
+        let_assert!(Ok(zone) = TimeZone::load(zone));
+        deadline.resolve(at(now), &zone)
+    }
+
+    #[test]
+    fn time_later_today() {
+        check!(resolve("13:30", NOON, "UTC") == at(NOON + 5_400));
+        check!(resolve("12:00:01", NOON, "UTC") == at(NOON + 1));
+    }
+
+    #[test]
+    fn time_tomorrow() {
+        check!(resolve("04:30", NOON, "UTC") == at(NOON + 16 * 3_600 + 1_800));
+        check!(resolve("12:00", NOON, "UTC") == at(NOON + 86_400));
+    }
+
+    #[test]
+    fn time_in_zone() {
+        // 12:00 UTC is 07:00 in EST.
+        check!(resolve("08:00", NOON, "EST5") == at(NOON + 3_600));
+        check!(resolve("06:00", NOON, "EST5") == at(NOON + 23 * 3_600));
+    }
+
+    #[test]
+    fn date_and_time() {
+        check!(resolve("2024-03-01 12:00", 0, "UTC") == at(NOON));
+        check!(resolve("2024-03-01T07:00:00", 0, "EST5") == at(NOON));
+        check!(resolve("2024-02-29 12:00", NOON, "UTC") == at(NOON - 86_400));
+    }
+
+    #[test]
+    fn invalid() {
+        for input in [
+            "",
+            "4:30",
+            "04:60",
+            "24:00",
+            "04:30:60",
+            "04:30:00:00",
+            "2023-02-29 04:30",
+            "2024-13-01 04:30",
+            "2024-03 04:30",
+            "tomorrow",
+        ] {
+            check!(Deadline::parse(input).is_err(), "{input:?}");
+        }
+    }
+}
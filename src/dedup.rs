@@ -0,0 +1,153 @@
+//! Collapse repeated lines (see `--dedup`).
+//!
+//! Commands that retry or poll in a loop often output the same line over and
+//! over. Like syslog, this outputs the first copy of a line, then a line like
+//! `… last message repeated 41 times` once a different line comes along or the
+//! stream is closed.
+//!
+//! Each stream is deduplicated separately. This works on complete lines, so
+//! output is line buffered.
+
+use crate::params::Stream;
+
+/// Tracks the last line of each of the child’s streams.
+#[derive(Debug, Default)]
+pub struct Dedup {
+    /// The last line from stdout.
+    out: Last,
+
+    /// The last line from stderr.
+    err: Last,
+}
+
+/// The last line from a stream.
+#[derive(Debug, Default)]
+struct Last {
+    /// The line, including its line ending.
+    line: Vec<u8>,
+
+    /// How many times it was repeated after it was output.
+    repeats: u64,
+}
+
+impl Dedup {
+    /// Add complete lines from the child’s `stream`, replacing the contents of
+    /// `out` with what should be output.
+    pub fn push(&mut self, stream: Stream, bytes: &[u8], out: &mut Vec<u8>) {
+        out.clear();
+        let last = self.last(stream);
+        for line in bytes.split_inclusive(|&b| b == b'\n') {
+            if line == last.line {
+                last.repeats = last.repeats.saturating_add(1);
+                continue;
+            }
+            last.repeated(out);
+            last.line.clear();
+            last.line.extend_from_slice(line);
+            out.extend_from_slice(line);
+        }
+    }
+
+    /// Finish `stream`, which has been closed, replacing the contents of `out`
+    /// with what should be output.
+    pub fn finish(&mut self, stream: Stream, out: &mut Vec<u8>) {
+        out.clear();
+        let last = self.last(stream);
+        last.repeated(out);
+        last.line.clear();
+    }
+
+    /// Get the last line for `stream`.
+    const fn last(&mut self, stream: Stream) -> &mut Last {
+        match stream {
+            Stream::Out => &mut self.out,
+            Stream::Err => &mut self.err,
+        }
+    }
+}
+
+impl Last {
+    /// Add a line saying how many times the last line was repeated to `out`,
+    /// if it was repeated, and reset the count.
+    fn repeated(&mut self, out: &mut Vec<u8>) {
+        if self.repeats == 0 {
+            return;
+        }
+        if !self.line.ends_with(b"\n") {
+            out.push(b'\n');
+        }
+        out.extend_from_slice(
+            format!(
+                "… last message repeated {} time{}\n",
+                self.repeats,
+                if self.repeats == 1 { "" } else { "s" },
+            )
+            .as_bytes(),
+        );
+        self.repeats = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+    use bstr::ByteSlice;
+
+    /// Push `chunks` from stdout, then finish, and return everything output.
+    fn dedup(chunks: &[&[u8]]) -> Vec<u8> {
+        let mut dedup = Dedup::default();
+        let mut out = Vec::new();
+        let mut all = Vec::new();
+        for chunk in chunks {
+            dedup.push(Stream::Out, chunk, &mut out);
+            all.extend_from_slice(&out);
+        }
+        dedup.finish(Stream::Out, &mut out);
+        all.extend_from_slice(&out);
+        all
+    }
+
+    #[test]
+    fn no_repeats() {
+        check!(dedup(&[b"a\nb\n", b"a\n"]).as_bstr() == "a\nb\na\n");
+    }
+
+    #[test]
+    fn repeats() {
+        check!(
+            dedup(&[b"a\na\n", b"a\nb\n", b"b\n"]).as_bstr()
+                == "a\n… last message repeated 2 times\nb\n\
+                    … last message repeated 1 time\n"
+        );
+    }
+
+    #[test]
+    fn partial_line() {
+        check!(
+            dedup(&[b"a", b"a"]).as_bstr()
+                == "a\n… last message repeated 1 time\n"
+        );
+    }
+
+    #[test]
+    fn streams_separate() {
+        let mut dedup = Dedup::default();
+        let mut out = Vec::new();
+        dedup.push(Stream::Out, b"a\n", &mut out);
+        dedup.push(Stream::Err, b"a\n", &mut out);
+        check!(out.as_bstr() == "a\n");
+        dedup.push(Stream::Out, b"a\n", &mut out);
+        check!(out.as_bstr() == "");
+    }
+
+    #[test]
+    fn reset_after_finish() {
+        let mut dedup = Dedup::default();
+        let mut out = Vec::new();
+        dedup.push(Stream::Out, b"a\n", &mut out);
+        dedup.finish(Stream::Out, &mut out);
+        dedup.push(Stream::Out, b"a\n", &mut out);
+        check!(out.as_bstr() == "a\n");
+    }
+}
@@ -0,0 +1,194 @@
+//! Show what `rederr` would run without running it (see `--dry-run`).
+
+use crate::deadline;
+use crate::params::Params;
+use crate::retry::Retry;
+use crate::timestamp::Timestamp;
+use clap::parser::ValueSource;
+use clap::{ArgAction, CommandFactory};
+use std::ffi::{OsStr, OsString};
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::process;
+use std::time::Duration;
+
+/// Write the resolved options, the command, and the timeouts to `out`.
+///
+/// `args` is the command line and `all_args` is the command line with the
+/// options from the configuration file added, so that the options can be
+/// listed with where they were set.
+pub fn render(
+    out: &mut dyn Write,
+    params: &Params,
+    args: &[OsString],
+    all_args: &[OsString],
+    command: &process::Command,
+) -> anyhow::Result<()> {
+    writeln!(out, "Options:")?;
+    for option in options(args, all_args)? {
+        writeln!(out, "  {option}")?;
+    }
+
+    let mut line = quote(command.get_program());
+    for arg in command.get_args() {
+        line.push(' ');
+        line.push_str(&quote(arg));
+    }
+    writeln!(out, "Command: {line}")?;
+    if let Some(dir) = command.get_current_dir() {
+        writeln!(out, "Directory: {}", quote(dir.as_os_str()))?;
+    }
+
+    let mut envs = command.get_envs().peekable();
+    if params.clean_env || envs.peek().is_some() {
+        writeln!(out, "Environment:")?;
+        if params.clean_env {
+            writeln!(out, "  (cleared)")?;
+        }
+        for (key, value) in envs {
+            match value {
+                Some(value) => writeln!(
+                    out,
+                    "  {}={}",
+                    key.to_string_lossy(),
+                    quote(value)
+                )?,
+                None => writeln!(out, "  unset {}", key.to_string_lossy())?,
+            }
+        }
+    }
+
+    writeln!(out, "Timeouts:")?;
+    for (option, value) in timeouts(params)? {
+        writeln!(out, "  {option}: {value}")?;
+    }
+    Ok(())
+}
+
+/// List the options that were set, with where they were set if it wasn’t the
+/// command line.
+fn options(
+    args: &[OsString],
+    all_args: &[OsString],
+) -> anyhow::Result<Vec<String>> {
+    let command = Params::command();
+    let cli = command.clone().try_get_matches_from(args)?;
+    let matches = command.clone().try_get_matches_from(all_args)?;
+
+    let mut options = Vec::new();
+    for arg in command.get_arguments() {
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        let id = arg.get_id().as_str();
+        let source = match matches.value_source(id) {
+            None | Some(ValueSource::DefaultValue) => continue,
+            Some(ValueSource::EnvVariable) => format!(
+                " (from {})",
+                arg.get_env().unwrap_or_default().to_string_lossy()
+            ),
+            _ if cli.value_source(id) != Some(ValueSource::CommandLine) => {
+                " (from config)".to_owned()
+            }
+            _ => String::new(),
+        };
+
+        match arg.get_action() {
+            ArgAction::Count => {
+                for _ in 0..matches.get_count(id) {
+                    options.push(format!("--{long}{source}"));
+                }
+            }
+            ArgAction::SetTrue => {
+                if matches.get_flag(id) {
+                    options.push(format!("--{long}{source}"));
+                }
+            }
+            _ => {
+                for value in matches.get_raw(id).into_iter().flatten() {
+                    options.push(format!("--{long}={}{source}", quote(value)));
+                }
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// List the timeouts that apply, with the options that set them.
+fn timeouts(params: &Params) -> anyhow::Result<Vec<(&'static str, String)>> {
+    let mut timeouts = Vec::new();
+    let mut add = |option, timeout: Option<Duration>| {
+        if let Some(timeout) = timeout {
+            timeouts.push((option, format!("{timeout:?}")));
+        }
+    };
+    add("--run-timeout", params.run_timeout);
+    add("--idle-timeout", params.idle_timeout);
+    add("--start-timeout", params.start_timeout);
+    add("--run-warn", params.run_warn);
+    add("--idle-warn", params.idle_warn);
+    add("--kill-after", params.kill_after);
+    add("--linger-timeout", Some(params.linger_timeout));
+    add("--watchdog", params.watchdog);
+    add(
+        "--lock-timeout",
+        params.lockfile.is_some().then(|| params.lock_wait()),
+    );
+
+    if let Some(at) = deadline::resolve(params)? {
+        let at = Timestamp::wall(at, params.time_zone());
+        timeouts.push(("--deadline", at.to_string()));
+    }
+    if let Some(jitter) = params.jitter {
+        timeouts.push(("--jitter", format!("up to {jitter:?}")));
+    }
+    if !params.warn_at.is_empty() {
+        let warnings: Vec<_> =
+            params.warn_at.iter().map(|at| format!("{at:?}")).collect();
+        timeouts.push(("--warn-at", warnings.join(", ")));
+    }
+    if params.retries > 0 {
+        let mut retry = Retry::with_jitter(
+            params.retries,
+            params.retry_delay,
+            params.retry_backoff,
+            None,
+        );
+        let delays: Vec<_> = std::iter::from_fn(|| retry.next_delay())
+            .map(|delay| format!("{delay:?}"))
+            .collect();
+        let mut delays = delays.join(", ");
+        if params.retry_jitter {
+            delays.push_str(" (each randomly reduced by up to half)");
+        }
+        timeouts.push(("--retry-delay", delays));
+    }
+    Ok(timeouts)
+}
+
+/// Quote `arg` for a POSIX shell if it needs it.
+fn quote(arg: &OsStr) -> String {
+    let bytes = arg.as_bytes();
+    let safe = |byte: &u8| {
+        byte.is_ascii_alphanumeric() || b"%+,-./:=@_".contains(byte)
+    };
+    if !bytes.is_empty() && bytes.iter().all(safe) {
+        return arg.to_string_lossy().into_owned();
+    }
+    format!("'{}'", arg.to_string_lossy().replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    #[test]
+    fn quote_args() {
+        check!(quote(OsStr::new("--run-timeout=1s")) == "--run-timeout=1s");
+        check!(quote(OsStr::new("")) == "''");
+        check!(quote(OsStr::new("a b")) == "'a b'");
+        check!(quote(OsStr::new("it's")) == r"'it'\''s'");
+        check!(quote(OsStr::new("$HOME")) == "'$HOME'");
+    }
+}
@@ -0,0 +1,147 @@
+//! Control the child’s environment.
+//!
+//! The environment is built in this order, so later steps override earlier
+//! ones:
+//!
+//!  1. Start with `rederr`’s environment, or nothing with `--clean-env`.
+//!  2. Remove variables named with `--unset-env`.
+//!  3. Set variables from each `--env-file`, in order.
+//!  4. Set variables from each `--env`, in order.
+//!
+//! An environment file contains one `KEY=VALUE` assignment per line. Leading
+//! and trailing whitespace is removed from each line, and blank lines and
+//! lines starting with `#` are ignored. A line may start with `export `, and a
+//! value may be surrounded by matching single or double quotes, which are
+//! removed. Nothing else is interpreted.
+
+use crate::params::Params;
+use anyhow::{anyhow, Context};
+use bstr::ByteSlice;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::process::Command;
+
+/// Configure the environment `command` will run with.
+pub fn configure(command: &mut Command, params: &Params) -> anyhow::Result<()> {
+    if params.clean_env {
+        command.env_clear();
+    }
+    for key in &params.unset_env {
+        command.env_remove(key);
+    }
+    for path in &params.env_file {
+        for (key, value) in read_file(path)? {
+            command.env(key, value);
+        }
+    }
+    for (key, value) in &params.env {
+        command.env(key, value);
+    }
+    Ok(())
+}
+
+/// Parse a `KEY=VALUE` assignment.
+pub fn parse_assignment(input: &[u8]) -> anyhow::Result<(OsString, OsString)> {
+    let (key, value) = input
+        .split_once_str("=")
+        .ok_or_else(|| anyhow!("expected KEY=VALUE"))?;
+    if key.is_empty() {
+        return Err(anyhow!("variable name is empty"));
+    }
+    Ok((os_string(key), os_string(value)))
+}
+
+/// Read the assignments in an environment file.
+fn read_file(path: &Path) -> anyhow::Result<Vec<(OsString, OsString)>> {
+    let contents = fs::read(path).with_context(|| {
+        format!("reading environment file {}", path.display())
+    })?;
+    parse_file(&contents)
+        .with_context(|| format!("in environment file {}", path.display()))
+}
+
+/// Parse the contents of an environment file.
+fn parse_file(contents: &[u8]) -> anyhow::Result<Vec<(OsString, OsString)>> {
+    let mut assignments = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim_ascii();
+        if line.is_empty() || line.starts_with(b"#") {
+            continue;
+        }
+        let line = line
+            .strip_prefix(b"export ")
+            .unwrap_or(line)
+            .trim_ascii_start();
+        let (key, value) = parse_assignment(line)
+            .with_context(|| format!("line {}", number.saturating_add(1)))?;
+        assignments.push((key, unquote(value)));
+    }
+    Ok(assignments)
+}
+
+/// Remove matching single or double quotes from around `value`.
+fn unquote(value: OsString) -> OsString {
+    let bytes = value.as_bytes();
+    for quote in [b'"', b'\''] {
+        if let [first, inner @ .., last] = bytes {
+            if *first == quote && *last == quote {
+                return os_string(inner);
+            }
+        }
+    }
+    value
+}
+
+/// Convert bytes to an `OsString`.
+fn os_string(bytes: &[u8]) -> OsString {
+    OsStr::from_bytes(bytes).to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::{check, let_assert};
+
+    /// Make a `(key, value)` pair for comparison.
+    fn pair(key: &str, value: &str) -> (OsString, OsString) {
+        (key.into(), value.into())
+    }
+
+    #[test]
+    fn assignment() {
+        let_assert!(Ok(parsed) = parse_assignment(b"A=b=c"));
+        check!(parsed == pair("A", "b=c"));
+        let_assert!(Ok(parsed) = parse_assignment(b"A="));
+        check!(parsed == pair("A", ""));
+    }
+
+    #[test]
+    fn assignment_invalid() {
+        check!(parse_assignment(b"A").is_err());
+        check!(parse_assignment(b"=a").is_err());
+    }
+
+    #[test]
+    fn file() {
+        let contents =
+            b"# comment\n\n  A=1  \nexport B='2 3'\nC=\"4\"\nD=\"5'\n";
+        let_assert!(Ok(parsed) = parse_file(contents));
+        check!(
+            parsed
+                == [
+                    pair("A", "1"),
+                    pair("B", "2 3"),
+                    pair("C", "4"),
+                    pair("D", "\"5'"),
+                ]
+        );
+    }
+
+    #[test]
+    fn file_invalid_line() {
+        let_assert!(Err(error) = parse_file(b"A=1\nB\n"));
+        check!(format!("{error:#}") == "line 2: expected KEY=VALUE");
+    }
+}
@@ -0,0 +1,17 @@
+//! Exit codes for `rederr`.
+//!
+//! These follow the conventions of GNU `timeout`. Otherwise, `rederr` exits
+//! with the same code as the command, or 128 + N if the command was killed by
+//! signal N.
+
+/// A timeout expired. This can be changed with `--timeout-exit-code`.
+pub const TIMEOUT: u8 = 124;
+
+/// `rederr` itself encountered an error.
+pub const ERROR: i32 = 125;
+
+/// The command was found but could not be executed.
+pub const CANNOT_EXECUTE: i32 = 126;
+
+/// The command could not be found.
+pub const NOT_FOUND: i32 = 127;
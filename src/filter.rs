@@ -0,0 +1,387 @@
+//! Only output lines that match (or don’t match) regular expressions (see
+//! `--grep` and `--exclude`), and discard streams entirely (see `--no-stdout`
+//! and `--no-stderr`).
+//!
+//! Each sink can also have its own patterns (see `--sink-grep` and
+//! `--sink-exclude`), so that, say, the terminal only shows errors while the
+//! log gets everything. Those decide which sinks get each line that’s kept.
+//!
+//! Patterns work on complete lines, so output is line buffered when there are
+//! patterns. A partial line that gets too long is checked on its own, as is
+//! the final partial line when a stream is closed.
+//!
+//! Discarded output is still read from the child so that it doesn’t block.
+
+use crate::line_buffer;
+use crate::params::{FilterStreams, SinkFilter, SinkKind, Stream};
+use clap::ValueEnum;
+use regex::bytes::Regex;
+use std::io;
+
+/// Decides which lines of the child’s output to keep.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    /// Lines must match one of these to be kept, unless it’s empty.
+    grep: Vec<Regex>,
+
+    /// Lines that match any of these are dropped.
+    exclude: Vec<Regex>,
+
+    /// Which of the child’s streams to filter.
+    streams: FilterStreams,
+
+    /// Streams to discard entirely.
+    discard: Vec<Stream>,
+}
+
+impl Filter {
+    /// Create a filter, or return `None` if there are no patterns and no
+    /// streams to discard.
+    pub fn new(
+        grep: &[Regex],
+        exclude: &[Regex],
+        streams: FilterStreams,
+        discard: &[Stream],
+    ) -> Option<Self> {
+        if grep.is_empty() && exclude.is_empty() && discard.is_empty() {
+            return None;
+        }
+        Some(Self {
+            grep: grep.to_vec(),
+            exclude: exclude.to_vec(),
+            streams,
+            discard: discard.to_vec(),
+        })
+    }
+
+    /// Pass the lines in `bytes` from the child’s `stream` that should be kept
+    /// to `write`.
+    ///
+    /// Lines from a stream that isn’t being filtered are all kept.
+    pub fn write<F>(
+        &self,
+        stream: Stream,
+        bytes: &[u8],
+        mut write: F,
+    ) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        if self.discard.contains(&stream) {
+            return Ok(());
+        }
+        if !self.streams.includes(stream) {
+            return write(bytes);
+        }
+        for line in bytes.split_inclusive(|&b| b == b'\n') {
+            if self.keep(line) {
+                write(line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Should `line` be kept?
+    fn keep(&self, line: &[u8]) -> bool {
+        let line = line_buffer::strip_ending(line);
+        (self.grep.is_empty() || self.grep.iter().any(|re| re.is_match(line)))
+            && !self.exclude.iter().any(|re| re.is_match(line))
+    }
+}
+
+/// Which sinks get a line of output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)] // One flag per sink.
+pub struct Sinks {
+    /// Our stdout and stderr.
+    pub terminal: bool,
+
+    /// The log file.
+    pub log: bool,
+
+    /// The recording.
+    pub record: bool,
+
+    /// The output sent with notifications.
+    pub notify: bool,
+}
+
+impl Sinks {
+    /// Every sink.
+    pub const ALL: Self = Self {
+        terminal: true,
+        log: true,
+        record: true,
+        notify: true,
+    };
+
+    /// Stop sending to `sink`.
+    const fn remove(&mut self, sink: SinkKind) {
+        match sink {
+            SinkKind::Terminal => self.terminal = false,
+            SinkKind::Log => self.log = false,
+            SinkKind::Record => self.record = false,
+            SinkKind::Notify => self.notify = false,
+        }
+    }
+}
+
+/// Decides which sinks get each line of the child’s output (see
+/// `--sink-grep` and `--sink-exclude`).
+#[derive(Clone, Debug)]
+pub struct SinkFilters {
+    /// The patterns for each sink that has any.
+    filters: Vec<(SinkKind, Filter)>,
+
+    /// Which of the child’s streams to filter.
+    streams: FilterStreams,
+}
+
+impl SinkFilters {
+    /// Group patterns by sink, or return `None` if there aren’t any.
+    pub fn new(
+        grep: &[SinkFilter],
+        exclude: &[SinkFilter],
+        streams: FilterStreams,
+    ) -> Option<Self> {
+        let patterns = |filters: &[SinkFilter], sink| -> Vec<Regex> {
+            filters
+                .iter()
+                .filter(|filter| filter.sink == sink)
+                .map(|filter| filter.regex.clone())
+                .collect()
+        };
+        let filters: Vec<_> = SinkKind::value_variants()
+            .iter()
+            .filter_map(|&sink| {
+                let grep = patterns(grep, sink);
+                let exclude = patterns(exclude, sink);
+                Filter::new(&grep, &exclude, FilterStreams::Both, &[])
+                    .map(|filter| (sink, filter))
+            })
+            .collect();
+        (!filters.is_empty()).then_some(Self { filters, streams })
+    }
+
+    /// Split `bytes` from the child’s `stream` into runs of lines that go to
+    /// the same sinks, and pass each run to `write` with its sinks.
+    pub fn write<F>(
+        &self,
+        stream: Stream,
+        bytes: &[u8],
+        mut write: F,
+    ) -> io::Result<()>
+    where
+        F: FnMut(Sinks, &[u8]) -> io::Result<()>,
+    {
+        if !self.streams.includes(stream) {
+            return write(Sinks::ALL, bytes);
+        }
+
+        let (mut start, mut end) = (0, 0);
+        let mut current = None;
+        for line in bytes.split_inclusive(|&b| b == b'\n') {
+            let sinks = self.sinks(line);
+            if let Some(previous) =
+                current.filter(|&previous| previous != sinks)
+            {
+                write(previous, &bytes[start..end])?;
+                start = end;
+            }
+            current = Some(sinks);
+            end = end.saturating_add(line.len());
+        }
+        match current {
+            Some(sinks) => write(sinks, &bytes[start..]),
+            None => Ok(()),
+        }
+    }
+
+    /// Which sinks should get `line`?
+    fn sinks(&self, line: &[u8]) -> Sinks {
+        let mut sinks = Sinks::ALL;
+        for (sink, filter) in &self.filters {
+            if !filter.keep(line) {
+                sinks.remove(*sink);
+            }
+        }
+        sinks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::{check, let_assert};
+    use bstr::ByteSlice;
+
+    /// Filter `bytes` from `stream`, and return what was kept.
+    fn filter(
+        grep: &[&str],
+        exclude: &[&str],
+        streams: FilterStreams,
+        stream: Stream,
+        bytes: &[u8],
+    ) -> Vec<u8> {
+        let regexes = |patterns: &[&str]| -> Vec<Regex> {
+            patterns.iter().map(|p| Regex::new(p).unwrap()).collect()
+        };
+        let_assert!(
+            Some(filter) =
+                Filter::new(&regexes(grep), &regexes(exclude), streams, &[])
+        );
+        write(&filter, stream, bytes)
+    }
+
+    /// Pass `bytes` from `stream` through `filter`, and return what was kept.
+    fn write(filter: &Filter, stream: Stream, bytes: &[u8]) -> Vec<u8> {
+        let mut kept = Vec::new();
+        filter
+            .write(stream, bytes, |line| {
+                kept.extend_from_slice(line);
+                Ok(())
+            })
+            .unwrap();
+        kept
+    }
+
+    #[test]
+    fn no_patterns() {
+        check!(Filter::new(&[], &[], FilterStreams::Both, &[]).is_none());
+    }
+
+    #[test]
+    fn discard() {
+        let_assert!(
+            Some(filter) =
+                Filter::new(&[], &[], FilterStreams::Both, &[Stream::Out])
+        );
+        check!(write(&filter, Stream::Out, b"a\nb").as_bstr() == "");
+        check!(write(&filter, Stream::Err, b"a\nb").as_bstr() == "a\nb");
+    }
+
+    #[test]
+    fn grep() {
+        check!(
+            filter(
+                &["a", "c"],
+                &[],
+                FilterStreams::Both,
+                Stream::Out,
+                b"a\nb\nc\n"
+            )
+            .as_bstr()
+                == "a\nc\n"
+        );
+    }
+
+    #[test]
+    fn exclude() {
+        check!(
+            filter(&[], &["b"], FilterStreams::Both, Stream::Err, b"a\nb\nc")
+                .as_bstr()
+                == "a\nc"
+        );
+    }
+
+    #[test]
+    fn grep_and_exclude() {
+        check!(
+            filter(
+                &["^x"],
+                &["y$"],
+                FilterStreams::Both,
+                Stream::Out,
+                b"xa\nxy\r\nya\n"
+            )
+            .as_bstr()
+                == "xa\n"
+        );
+    }
+
+    #[test]
+    fn other_stream_untouched() {
+        check!(
+            filter(&["a"], &[], FilterStreams::Out, Stream::Err, b"a\nb\n")
+                .as_bstr()
+                == "a\nb\n"
+        );
+        check!(
+            filter(&["a"], &[], FilterStreams::Err, Stream::Err, b"a\nb\n")
+                .as_bstr()
+                == "a\n"
+        );
+    }
+
+    /// Split `bytes` from `stream` into runs for each set of sinks.
+    fn runs(
+        filters: &SinkFilters,
+        stream: Stream,
+        bytes: &[u8],
+    ) -> Vec<(Sinks, Vec<u8>)> {
+        let mut runs = Vec::new();
+        filters
+            .write(stream, bytes, |sinks, run| {
+                runs.push((sinks, run.to_vec()));
+                Ok(())
+            })
+            .unwrap();
+        runs
+    }
+
+    /// A pattern for `sink`.
+    fn sink_filter(sink: SinkKind, pattern: &str) -> SinkFilter {
+        SinkFilter {
+            sink,
+            regex: Regex::new(pattern).unwrap(),
+        }
+    }
+
+    #[test]
+    fn sink_filters_none() {
+        check!(SinkFilters::new(&[], &[], FilterStreams::Both).is_none());
+    }
+
+    #[test]
+    fn sink_filters_runs() {
+        let_assert!(
+            Some(filters) = SinkFilters::new(
+                &[sink_filter(SinkKind::Terminal, "^err")],
+                &[sink_filter(SinkKind::Notify, "")],
+                FilterStreams::Both,
+            )
+        );
+        let not_shown = Sinks {
+            terminal: false,
+            notify: false,
+            ..Sinks::ALL
+        };
+        let no_notify = Sinks {
+            notify: false,
+            ..Sinks::ALL
+        };
+        check!(
+            runs(&filters, Stream::Out, b"a\nb\nerr\nc")
+                == [
+                    (not_shown, b"a\nb\n".to_vec()),
+                    (no_notify, b"err\n".to_vec()),
+                    (not_shown, b"c".to_vec()),
+                ]
+        );
+    }
+
+    #[test]
+    fn sink_filters_other_stream_untouched() {
+        let_assert!(
+            Some(filters) = SinkFilters::new(
+                &[sink_filter(SinkKind::Log, "a")],
+                &[],
+                FilterStreams::Out,
+            )
+        );
+        check!(
+            runs(&filters, Stream::Err, b"b\nc\n")
+                == [(Sinks::ALL, b"b\nc\n".to_vec())]
+        );
+    }
+}
@@ -0,0 +1,943 @@
+//! Format the child’s output.
+//!
+//! [`Formatter`] turns chunks of bytes read from the child into [`Segment`]s
+//! that say where to write what, and how to style it. It doesn’t do any I/O, so
+//! it can be tested on its own. [`render()`] writes a segment to an output
+//! stream.
+
+use crate::highlight::{self, Highlight};
+use crate::params::Stream;
+use crate::timestamp::{Clock, Delay, Timestamp, Timing};
+use std::io;
+use std::time::{Duration, Instant};
+use termcolor::{ColorSpec, WriteColor};
+
+/// How to style a [`Segment`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    /// Output as is.
+    Plain,
+
+    /// Style as the child’s stdout (see `--stdout-color`).
+    Output,
+
+    /// Highlight as an error.
+    Error,
+
+    /// De-emphasize, e.g. for markers added by `rederr`.
+    Dim,
+}
+
+/// A piece of formatted output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment<'a> {
+    /// The stream the output came from.
+    pub stream: Stream,
+
+    /// How to style the output.
+    pub style: Style,
+
+    /// The output itself.
+    pub bytes: &'a [u8],
+
+    /// Line sequence number to output before the bytes, if any.
+    pub seq: Option<u64>,
+
+    /// Timestamp to output before the bytes, if any.
+    pub timestamp: Option<Timestamp>,
+
+    /// Time since the previous line to output before the bytes, if any (see
+    /// `--timing`).
+    pub delay: Option<Delay>,
+
+    /// Whether this is the start of a line added by `rederr` (its tag,
+    /// sequence number, and timestamp) rather than output.
+    pub prefix: bool,
+}
+
+/// Tags to put at the start of lines. Empty tags are left out.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Tags<'a> {
+    /// Tag for lines from the child’s stdout.
+    pub out: &'a [u8],
+
+    /// Tag for lines from the child’s stderr.
+    pub err: &'a [u8],
+
+    /// Tag for messages from `rederr` itself.
+    pub notice: &'a [u8],
+}
+
+/// Formats chunks of output from the child.
+///
+/// This keeps track of where lines start in each stream so that tags, sequence
+/// numbers, and timestamps can be added to the start of every line, even when
+/// a line is split across reads.
+#[derive(Debug)]
+pub struct Formatter<'a> {
+    /// State for the child’s stdout.
+    out: LineState<'a>,
+
+    /// State for the child’s stderr.
+    err: LineState<'a>,
+
+    /// Tag for messages from `rederr` itself.
+    notice_tag: &'a [u8],
+
+    /// Produces sequence numbers and timestamps for the start of lines.
+    starts: LineStarts,
+}
+
+/// Produces sequence numbers and timestamps for the start of lines.
+#[derive(Debug)]
+struct LineStarts {
+    /// The next line sequence number, or `None` for no sequence numbers.
+    next_seq: Option<u64>,
+
+    /// Produces timestamps.
+    clock: Clock,
+
+    /// Measures the time between lines, if `--timing` was passed.
+    timing: Option<Timing>,
+}
+
+impl LineStarts {
+    /// Should anything be output at the start of a line?
+    const fn is_enabled(&self) -> bool {
+        self.next_seq.is_some()
+            || self.clock.is_enabled()
+            || self.timing.is_some()
+    }
+
+    /// Start a new line, returning its sequence number, timestamp, and the
+    /// time since the previous line.
+    fn start(&mut self) -> (Option<u64>, Option<Timestamp>, Option<Delay>) {
+        let seq = self.next_seq;
+        self.next_seq = seq.map(|seq| seq.saturating_add(1));
+        let delay = self
+            .timing
+            .as_mut()
+            .map(|timing| timing.delay(Instant::now()));
+        (seq, self.clock.stamp(), delay)
+    }
+}
+
+/// Where we are in the output of one stream.
+#[derive(Debug)]
+struct LineState<'a> {
+    /// Tag to output at the start of every line. Empty for no tag.
+    tag: &'a [u8],
+
+    /// Whether the next byte of output starts a new line.
+    at_line_start: bool,
+}
+
+impl<'a> Formatter<'a> {
+    /// Create a formatter that prefixes lines with `tags` and, optionally,
+    /// sequence numbers (starting from 1) and timestamps from `clock`.
+    pub fn new(tags: Tags<'a>, seq: bool, clock: Clock) -> Self {
+        Self {
+            out: LineState {
+                tag: tags.out,
+                at_line_start: true,
+            },
+            err: LineState {
+                tag: tags.err,
+                at_line_start: true,
+            },
+            notice_tag: tags.notice,
+            starts: LineStarts {
+                next_seq: seq.then_some(1),
+                clock,
+                timing: None,
+            },
+        }
+    }
+
+    /// Prefix lines with the time since the previous line, marking delays
+    /// longer than `slow` as slow (see `--timing`).
+    #[must_use]
+    pub fn with_timing(mut self, slow: Option<Duration>) -> Self {
+        self.starts.timing = Some(Timing::new(slow));
+        self
+    }
+
+    /// Are both streams at the start of a line?
+    pub const fn at_line_start(&self) -> bool {
+        self.out.at_line_start && self.err.at_line_start
+    }
+
+    /// Does this leave the child’s output unchanged? If so, and nothing else
+    /// is written between its lines, the output can be spliced (see
+    /// `--no-splice`).
+    pub const fn is_identity(&self) -> bool {
+        self.out.tag.is_empty()
+            && self.err.tag.is_empty()
+            && !self.starts.is_enabled()
+    }
+
+    /// Should messages from `rederr` be included in the output?
+    pub const fn includes_notices(&self) -> bool {
+        !self.notice_tag.is_empty()
+    }
+
+    /// Format a message from `rederr` itself as a line of the child’s stderr.
+    ///
+    /// If the child’s stderr is in the middle of a line, this ends the line
+    /// first so that the message starts on its own line.
+    pub fn notice<'b>(
+        &'b mut self,
+        message: &'b [u8],
+    ) -> impl Iterator<Item = Segment<'b>> {
+        let newline = (!self.err.at_line_start).then_some(&b"\n"[..]);
+        self.err.at_line_start = true;
+        let start = self.starts.start();
+
+        newline
+            .into_iter()
+            .map(|bytes| (bytes, (None, None, None), false))
+            .chain([
+                (self.notice_tag, start, true),
+                (message, (None, None, None), false),
+                (b"\n", (None, None, None), false),
+            ])
+            .map(|(bytes, (seq, timestamp, delay), prefix)| Segment {
+                stream: Stream::Err,
+                style: Style::Error,
+                bytes,
+                seq,
+                timestamp,
+                delay,
+                prefix,
+            })
+    }
+
+    /// Format a status line from `rederr` itself as a dim line of stderr (see
+    /// `--heartbeat`).
+    ///
+    /// If the child’s stderr is in the middle of a line, this ends the line
+    /// first. Like markers, status lines don’t get tags, sequence numbers, or
+    /// timestamps.
+    pub fn heartbeat<'b>(
+        &'b mut self,
+        message: &'b [u8],
+    ) -> impl Iterator<Item = Segment<'b>> {
+        let newline =
+            (!self.err.at_line_start).then_some((Style::Error, &b"\n"[..]));
+        self.err.at_line_start = true;
+
+        newline
+            .into_iter()
+            .chain([(Style::Dim, message), (Style::Plain, b"\n")])
+            .map(|(style, bytes)| Segment {
+                stream: Stream::Err,
+                style,
+                bytes,
+                seq: None,
+                timestamp: None,
+                delay: None,
+                prefix: false,
+            })
+    }
+
+    /// Format a marker from `rederr` itself as a dim line of stdout (see
+    /// `--gap-marker`).
+    ///
+    /// Partial lines on either stream are ended first so that the marker is on
+    /// its own line. Markers don’t get tags, sequence numbers, or timestamps,
+    /// since they aren’t output.
+    pub fn marker<'b>(
+        &'b mut self,
+        message: &'b [u8],
+    ) -> impl Iterator<Item = Segment<'b>> {
+        let err_newline = (!self.err.at_line_start).then_some((
+            Stream::Err,
+            Style::Error,
+            &b"\n"[..],
+        ));
+        let out_newline = (!self.out.at_line_start).then_some((
+            Stream::Out,
+            Style::Plain,
+            &b"\n"[..],
+        ));
+        self.err.at_line_start = true;
+        self.out.at_line_start = true;
+
+        err_newline
+            .into_iter()
+            .chain(out_newline)
+            .chain([
+                (Stream::Out, Style::Dim, message),
+                (Stream::Out, Style::Plain, b"\n"),
+            ])
+            .map(|(stream, style, bytes)| Segment {
+                stream,
+                style,
+                bytes,
+                seq: None,
+                timestamp: None,
+                delay: None,
+                prefix: false,
+            })
+    }
+
+    /// Format a chunk of output from the child’s `stream`.
+    ///
+    /// This is called for every chunk of output, so it shouldn’t allocate.
+    pub const fn format<'b>(
+        &'b mut self,
+        stream: Stream,
+        chunk: &'b [u8],
+    ) -> Segments<'b> {
+        let (style, state) = match stream {
+            Stream::Out => (Style::Output, &mut self.out),
+            Stream::Err => (Style::Error, &mut self.err),
+        };
+
+        Segments {
+            stream,
+            style,
+            tag: state.tag,
+            at_line_start: &mut state.at_line_start,
+            starts: &mut self.starts,
+            rest: chunk,
+        }
+    }
+}
+
+/// Iterator over the [`Segment`]s in a chunk of output.
+///
+/// Returned by [`Formatter::format()`].
+#[derive(Debug)]
+pub struct Segments<'a> {
+    /// The stream the chunk came from.
+    stream: Stream,
+
+    /// How to style the chunk.
+    style: Style,
+
+    /// Tag to output at the start of every line. Empty for no tag.
+    tag: &'a [u8],
+
+    /// Whether the next byte of output starts a new line.
+    at_line_start: &'a mut bool,
+
+    /// Produces sequence numbers and timestamps for the start of lines.
+    starts: &'a mut LineStarts,
+
+    /// The part of the chunk that hasn’t been returned yet.
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = Segment<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+
+        let prefixed = !self.tag.is_empty() || self.starts.is_enabled();
+        if prefixed && *self.at_line_start {
+            *self.at_line_start = false;
+            let (seq, timestamp, delay) = self.starts.start();
+            return Some(Segment {
+                stream: self.stream,
+                style: self.style,
+                bytes: self.tag,
+                seq,
+                timestamp,
+                delay,
+                prefix: true,
+            });
+        }
+
+        let end = if prefixed {
+            self.rest
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(self.rest.len(), |i| i.saturating_add(1))
+        } else {
+            // No need to split into lines.
+            self.rest.len()
+        };
+        let (line, rest) = self.rest.split_at(end);
+        *self.at_line_start = line.ends_with(b"\n");
+        self.rest = rest;
+
+        Some(Segment {
+            stream: self.stream,
+            style: self.style,
+            bytes: line,
+            seq: None,
+            timestamp: None,
+            delay: None,
+            prefix: false,
+        })
+    }
+}
+
+/// Colors for the child’s output.
+#[derive(Clone, Debug, Default)]
+pub struct Colors {
+    /// Color for [`Style::Output`]. Usually this is empty.
+    pub out: ColorSpec,
+
+    /// Color for [`Style::Error`].
+    pub err: ColorSpec,
+
+    /// Rules to highlight parts of the child’s output (see `--highlight`).
+    pub highlights: Vec<Highlight>,
+
+    /// Color for the time since the previous line when it’s longer than
+    /// `--slow-threshold`.
+    pub slow: ColorSpec,
+}
+
+/// Write `segment` to `out`, using `colors` for its style.
+///
+/// Colors are always reset at the end of the segment so that they can’t leak
+/// into other output.
+pub fn render(
+    out: &mut dyn WriteColor,
+    segment: &Segment,
+    colors: &Colors,
+) -> io::Result<()> {
+    let mut dim = ColorSpec::new();
+    let color = match segment.style {
+        Style::Plain => None,
+        Style::Output => Some(&colors.out),
+        Style::Error => Some(&colors.err),
+        Style::Dim => Some(&*dim.set_dimmed(true)),
+    }
+    .filter(|color| !color.is_none());
+
+    if let Some(color) = color {
+        out.set_color(color)?;
+    }
+    if let Some(seq) = segment.seq {
+        write!(out, "{seq:6} ")?;
+    }
+    if let Some(timestamp) = segment.timestamp {
+        write!(out, "{timestamp} ")?;
+    }
+    if let Some(delay) = segment.delay {
+        if delay.slow && !colors.slow.is_none() {
+            out.set_color(&colors.slow)?;
+            write!(out, "{delay}")?;
+            out.reset()?;
+            if let Some(color) = color {
+                out.set_color(color)?;
+            }
+            write!(out, " ")?;
+        } else {
+            write!(out, "{delay} ")?;
+        }
+    }
+    let output = matches!(segment.style, Style::Output | Style::Error);
+    if output && !segment.prefix && !colors.highlights.is_empty() {
+        highlight::write(out, segment.bytes, color, &colors.highlights)?;
+    } else {
+        out.write_all(segment.bytes)?;
+    }
+    if color.is_some() {
+        out.reset()?;
+    }
+
+    out.flush() // If there wasn’t a newline.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::TimestampFormat;
+    use crate::tz::TimeZone;
+    use assert2::check;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use std::io::Read;
+    use std::time::Duration;
+    use termcolor::{Ansi, Color};
+
+    /// The escape character that starts ANSI escape sequences.
+    const ESC: u8 = 0x1b;
+
+    /// Colors to use for output: plain stdout and red stderr.
+    fn colors() -> Colors {
+        let mut colors = Colors::default();
+        colors.err.set_fg(Some(Color::Red)).set_intense(true);
+        colors
+    }
+
+    /// A clock that doesn’t produce timestamps.
+    fn no_clock() -> Clock {
+        Clock::new(None, TimeZone::default())
+    }
+
+    /// Short tags for testing.
+    const fn tags() -> Tags<'static> {
+        Tags {
+            out: b"O:",
+            err: b"E:",
+            notice: b"R:",
+        }
+    }
+
+    /// Format and render chunks to a single ANSI terminal.
+    fn render_all(chunks: &[(Stream, Vec<u8>)]) -> Vec<u8> {
+        render_tagged(chunks, b"", b"")
+    }
+
+    /// Format and render chunks with tags to a single ANSI terminal.
+    fn render_tagged(
+        chunks: &[(Stream, Vec<u8>)],
+        out_tag: &[u8],
+        err_tag: &[u8],
+    ) -> Vec<u8> {
+        let mut formatter = Formatter::new(
+            Tags {
+                out: out_tag,
+                err: err_tag,
+                notice: b"",
+            },
+            false,
+            no_clock(),
+        );
+        let mut out = Ansi::new(Vec::new());
+        for (stream, chunk) in chunks {
+            for segment in formatter.format(*stream, chunk) {
+                render(&mut out, &segment, &colors()).unwrap();
+            }
+        }
+        out.into_inner()
+    }
+
+    /// Format chunks into `(stream, bytes)` pairs without rendering them.
+    fn segments(
+        formatter: &mut Formatter,
+        chunks: &[(Stream, &[u8])],
+    ) -> Vec<(Stream, Vec<u8>)> {
+        chunks
+            .iter()
+            .flat_map(|(stream, chunk)| {
+                formatter
+                    .format(*stream, chunk)
+                    .map(|segment| (segment.stream, segment.bytes.to_vec()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Find the length of the SGR escape sequence at the start of `bytes`.
+    fn escape_len(bytes: &[u8]) -> Option<usize> {
+        let rest = bytes.strip_prefix(&[ESC, b'['])?;
+        let params = rest
+            .iter()
+            .take_while(|b| b.is_ascii_digit() || **b == b';')
+            .count();
+        (rest.get(params) == Some(&b'm')).then_some(params.saturating_add(3))
+    }
+
+    /// Remove SGR escape sequences and stray escape characters.
+    fn strip(bytes: &[u8]) -> Vec<u8> {
+        let mut stripped = Vec::with_capacity(bytes.len());
+        let mut rest = bytes;
+        while let Some((&byte, tail)) = rest.split_first() {
+            if byte == ESC {
+                rest = &rest[escape_len(rest).unwrap_or(1)..];
+            } else {
+                stripped.push(byte);
+                rest = tail;
+            }
+        }
+        stripped
+    }
+
+    /// Are all escape sequences in `bytes` complete?
+    fn escapes_complete(bytes: &[u8]) -> bool {
+        bytes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b == ESC)
+            .all(|(i, _)| escape_len(&bytes[i..]).is_some())
+    }
+
+    /// Generate chunks of output without escape characters.
+    fn chunks() -> impl Strategy<Value = Vec<(Stream, Vec<u8>)>> {
+        let stream = prop_oneof![Just(Stream::Out), Just(Stream::Err)];
+        let byte = any::<u8>().prop_filter("no escapes", |b| *b != ESC);
+        vec((stream, vec(byte, 0..64)), 0..16)
+    }
+
+    #[test]
+    fn format_empty() {
+        let mut formatter = Formatter::new(tags(), false, no_clock());
+        check!(formatter.format(Stream::Err, b"").count() == 0);
+    }
+
+    #[test]
+    fn format_err() {
+        let mut formatter = Formatter::new(Tags::default(), false, no_clock());
+        check!(
+            formatter.format(Stream::Err, b"abc").collect::<Vec<_>>()
+                == [Segment {
+                    stream: Stream::Err,
+                    style: Style::Error,
+                    bytes: b"abc",
+                    seq: None,
+                    timestamp: None,
+                    delay: None,
+                    prefix: false,
+                }]
+        );
+    }
+
+    #[test]
+    fn format_tags_lines() {
+        let mut formatter = Formatter::new(tags(), false, no_clock());
+        check!(
+            segments(&mut formatter, &[(Stream::Out, b"a\nb\n")])
+                == [
+                    (Stream::Out, b"O:".to_vec()),
+                    (Stream::Out, b"a\n".to_vec()),
+                    (Stream::Out, b"O:".to_vec()),
+                    (Stream::Out, b"b\n".to_vec()),
+                ]
+        );
+    }
+
+    #[test]
+    fn format_tags_split_lines() {
+        let mut formatter = Formatter::new(tags(), false, no_clock());
+        check!(
+            segments(
+                &mut formatter,
+                &[
+                    (Stream::Out, b"a"),
+                    (Stream::Err, b"b\n"),
+                    (Stream::Out, b"c\nd"),
+                ]
+            ) == [
+                (Stream::Out, b"O:".to_vec()),
+                (Stream::Out, b"a".to_vec()),
+                (Stream::Err, b"E:".to_vec()),
+                (Stream::Err, b"b\n".to_vec()),
+                (Stream::Out, b"c\n".to_vec()),
+                (Stream::Out, b"O:".to_vec()),
+                (Stream::Out, b"d".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn format_timestamps() {
+        let mut formatter = Formatter::new(
+            Tags::default(),
+            false,
+            Clock::new(Some(TimestampFormat::Delta), TimeZone::default()),
+        );
+        let segments: Vec<_> = formatter.format(Stream::Out, b"a\nb").collect();
+
+        check!(segments.len() == 4);
+        for (i, segment) in segments.iter().enumerate() {
+            check!(segment.timestamp.is_some() == (i % 2 == 0));
+        }
+        check!(segments[0].bytes == b"");
+        check!(segments[1].bytes == b"a\n");
+        check!(segments[3].bytes == b"b");
+    }
+
+    #[test]
+    fn format_seq() {
+        let mut formatter = Formatter::new(Tags::default(), true, no_clock());
+        let mut seqs: Vec<_> = formatter
+            .format(Stream::Out, b"a\nb")
+            .filter_map(|segment| segment.seq)
+            .collect();
+        seqs.extend(formatter.notice(b"oops").filter_map(|s| s.seq));
+        seqs.extend(
+            formatter
+                .format(Stream::Err, b"c\n")
+                .filter_map(|segment| segment.seq),
+        );
+        check!(seqs == [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn render_prefix() {
+        let segment = Segment {
+            stream: Stream::Out,
+            style: Style::Plain,
+            bytes: b"[out] ",
+            seq: Some(12),
+            timestamp: Some(Timestamp::Offset(Duration::from_millis(1_500))),
+            delay: None,
+            prefix: true,
+        };
+        let mut out = Ansi::new(Vec::new());
+        render(&mut out, &segment, &colors()).unwrap();
+        check!(out.into_inner() == b"    12 00:00:01.500 [out] ");
+    }
+
+    #[test]
+    fn render_delay() {
+        let segment = Segment {
+            stream: Stream::Out,
+            style: Style::Plain,
+            bytes: b"",
+            seq: None,
+            timestamp: None,
+            delay: Some(Delay {
+                elapsed: Duration::from_millis(2_500),
+                slow: false,
+            }),
+            prefix: true,
+        };
+        let mut out = Ansi::new(Vec::new());
+        render(&mut out, &segment, &colors()).unwrap();
+        check!(out.into_inner() == b"   2.500s ");
+    }
+
+    #[test]
+    fn render_delay_slow() {
+        let segment = Segment {
+            stream: Stream::Err,
+            style: Style::Error,
+            bytes: b"E:",
+            seq: None,
+            timestamp: None,
+            delay: Some(Delay {
+                elapsed: Duration::from_millis(2_500),
+                slow: true,
+            }),
+            prefix: true,
+        };
+        let mut colors = colors();
+        colors.slow.set_fg(Some(Color::Yellow));
+        let mut out = Ansi::new(Vec::new());
+        render(&mut out, &segment, &colors).unwrap();
+        check!(
+            out.into_inner()
+                == b"\x1b[0m\x1b[38;5;9m\x1b[0m\x1b[33m   2.500s\x1b[0m\
+                    \x1b[0m\x1b[38;5;9m E:\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn format_timing() {
+        let mut formatter =
+            Formatter::new(tags(), false, no_clock()).with_timing(None);
+        let delays: Vec<_> = formatter
+            .format(Stream::Out, b"a\nb")
+            .map(|segment| segment.delay.is_some())
+            .collect();
+        check!(delays == [true, false, true, false]);
+    }
+
+    #[test]
+    fn notice_ends_line() {
+        let mut formatter = Formatter::new(tags(), false, no_clock());
+        let mut output = segments(&mut formatter, &[(Stream::Err, b"a")]);
+        output.extend(
+            formatter
+                .notice(b"oops")
+                .map(|segment| (segment.stream, segment.bytes.to_vec())),
+        );
+        output.extend(segments(&mut formatter, &[(Stream::Err, b"b")]));
+        check!(
+            output
+                == [
+                    (Stream::Err, b"E:".to_vec()),
+                    (Stream::Err, b"a".to_vec()),
+                    (Stream::Err, b"\n".to_vec()),
+                    (Stream::Err, b"R:".to_vec()),
+                    (Stream::Err, b"oops".to_vec()),
+                    (Stream::Err, b"\n".to_vec()),
+                    (Stream::Err, b"E:".to_vec()),
+                    (Stream::Err, b"b".to_vec()),
+                ]
+        );
+    }
+
+    #[test]
+    fn marker_ends_lines() {
+        let mut formatter = Formatter::new(tags(), false, no_clock());
+        let mut output = segments(
+            &mut formatter,
+            &[(Stream::Out, b"a"), (Stream::Err, b"b")],
+        );
+        output.extend(
+            formatter
+                .marker(b"gap")
+                .map(|segment| (segment.stream, segment.bytes.to_vec())),
+        );
+        output.extend(segments(&mut formatter, &[(Stream::Out, b"c")]));
+        check!(
+            output
+                == [
+                    (Stream::Out, b"O:".to_vec()),
+                    (Stream::Out, b"a".to_vec()),
+                    (Stream::Err, b"E:".to_vec()),
+                    (Stream::Err, b"b".to_vec()),
+                    (Stream::Err, b"\n".to_vec()),
+                    (Stream::Out, b"\n".to_vec()),
+                    (Stream::Out, b"gap".to_vec()),
+                    (Stream::Out, b"\n".to_vec()),
+                    (Stream::Out, b"O:".to_vec()),
+                    (Stream::Out, b"c".to_vec()),
+                ]
+        );
+    }
+
+    #[test]
+    fn heartbeat_ends_line() {
+        let mut formatter = Formatter::new(tags(), false, no_clock());
+        let mut output = segments(
+            &mut formatter,
+            &[(Stream::Out, b"a"), (Stream::Err, b"b")],
+        );
+        output.extend(
+            formatter
+                .heartbeat(b"alive")
+                .map(|segment| (segment.stream, segment.bytes.to_vec())),
+        );
+        output.extend(segments(&mut formatter, &[(Stream::Err, b"c")]));
+        check!(
+            output
+                == [
+                    (Stream::Out, b"O:".to_vec()),
+                    (Stream::Out, b"a".to_vec()),
+                    (Stream::Err, b"E:".to_vec()),
+                    (Stream::Err, b"b".to_vec()),
+                    (Stream::Err, b"\n".to_vec()),
+                    (Stream::Err, b"alive".to_vec()),
+                    (Stream::Err, b"\n".to_vec()),
+                    (Stream::Err, b"E:".to_vec()),
+                    (Stream::Err, b"c".to_vec()),
+                ]
+        );
+    }
+
+    #[test]
+    fn render_dim() {
+        let mut out = Ansi::new(Vec::new());
+        let segment = Segment {
+            stream: Stream::Out,
+            style: Style::Dim,
+            bytes: b"gap",
+            seq: None,
+            timestamp: None,
+            delay: None,
+            prefix: false,
+        };
+        render(&mut out, &segment, &colors()).unwrap();
+        check!(out.into_inner() == b"\x1b[0m\x1b[2mgap\x1b[0m");
+    }
+
+    #[test]
+    fn render_out_plain() {
+        let output = render_all(&[(Stream::Out, b"abc".to_vec())]);
+        check!(output == b"abc");
+    }
+
+    #[test]
+    fn render_out_color() {
+        let mut colors = colors();
+        colors.out.set_fg(Some(Color::Blue)).set_dimmed(true);
+        let mut formatter = Formatter::new(tags(), false, no_clock());
+        let mut out = Ansi::new(Vec::new());
+        for segment in formatter.format(Stream::Out, b"abc") {
+            render(&mut out, &segment, &colors).unwrap();
+        }
+        check!(
+            out.into_inner()
+                == b"\x1b[0m\x1b[2m\x1b[34mO:\x1b[0m\x1b[0m\x1b[2m\x1b[34mabc\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn render_err() {
+        let output = render_all(&[(Stream::Err, b"abc".to_vec())]);
+        check!(output == b"\x1b[0m\x1b[38;5;9mabc\x1b[0m");
+    }
+
+    #[test]
+    fn steady_state_no_allocations() {
+        // Simulate reading and formatting a lot of output.
+        let input = b"line of output\n".repeat(10_000);
+        let colors = colors();
+        let mut reader = &input[..];
+        let mut buffer = vec![0; 1024];
+        let mut out = Ansi::new(io::sink());
+        let mut formatter = Formatter::new(
+            tags(),
+            true,
+            Clock::new(Some(TimestampFormat::Elapsed), TimeZone::default()),
+        );
+
+        let info = allocation_counter::measure(|| {
+            let streams = [Stream::Out, Stream::Err].into_iter().cycle();
+            for stream in streams {
+                let count = reader.read(&mut buffer).unwrap();
+                if count == 0 {
+                    break;
+                }
+                for segment in formatter.format(stream, &buffer[..count]) {
+                    render(&mut out, &segment, &colors).unwrap();
+                }
+            }
+        });
+
+        check!(reader.is_empty());
+        check!(info.count_total == 0);
+    }
+
+    proptest! {
+        #[test]
+        fn preserves_bytes(chunks in chunks()) {
+            let expected: Vec<u8> = chunks
+                .iter()
+                .flat_map(|(_, chunk)| chunk.iter().copied())
+                .collect();
+            prop_assert_eq!(strip(&render_all(&chunks)), expected);
+        }
+
+        #[test]
+        fn tags_every_line(chunks in chunks()) {
+            // Each stream is tagged independently, so check them separately.
+            for stream in [Stream::Out, Stream::Err] {
+                let chunks: Vec<_> = chunks
+                    .iter()
+                    .filter(|(s, _)| *s == stream)
+                    .cloned()
+                    .collect();
+                let expected: Vec<u8> = chunks
+                    .iter()
+                    .flat_map(|(_, chunk)| chunk.iter().copied())
+                    .collect::<Vec<u8>>()
+                    .split_inclusive(|b| *b == b'\n')
+                    .flat_map(|line| [&b"[tag] "[..], line].concat())
+                    .collect();
+                let output = render_tagged(&chunks, b"[tag] ", b"[tag] ");
+                prop_assert_eq!(strip(&output), expected);
+            }
+        }
+
+        #[test]
+        fn escapes_not_split(chunks in chunks()) {
+            for chunk in chunks {
+                let output = render_all(std::slice::from_ref(&chunk));
+                prop_assert!(escapes_complete(&output));
+                if chunk.0 == Stream::Err && !chunk.1.is_empty() {
+                    prop_assert!(output.ends_with(b"\x1b[0m"));
+                }
+            }
+        }
+
+        #[test]
+        fn strip_idempotent(bytes in vec(any::<u8>(), 0..256)) {
+            let stripped = strip(&bytes);
+            prop_assert_eq!(strip(&stripped), stripped);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Mark long silences in the output (see `--gap-marker`).
+//!
+//! When reading a log after the fact, it’s hard to tell where the command
+//! stalled without timestamps on every line. A marker line like
+//! `‹ 2m 14s of silence ›` makes bursts and stalls stand out.
+
+use std::time::{Duration, Instant};
+
+/// Notices when the child has been quiet for too long.
+#[derive(Debug)]
+pub struct GapMarker {
+    /// Silences longer than this are marked.
+    threshold: Duration,
+
+    /// When the child last produced output, or when it started.
+    last: Instant,
+}
+
+impl GapMarker {
+    /// Start watching for silences longer than `threshold`.
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            last: Instant::now(),
+        }
+    }
+
+    /// Record that the child produced output.
+    ///
+    /// Returns how long the child was quiet before this, if it was longer
+    /// than the threshold.
+    pub fn output(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        let silence = now.saturating_duration_since(self.last);
+        self.last = now;
+        (silence > self.threshold).then_some(silence)
+    }
+}
+
+/// Get the marker line (without a newline) for a silence.
+pub fn message(silence: Duration) -> String {
+    format!("‹ {} of silence ›", describe(silence))
+}
+
+/// Describe a duration like "2m 14s", or like "500ms" if it’s under a second.
+// Truncating division is how we split out the units.
+#[allow(clippy::integer_division)]
+pub fn describe(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs == 0 {
+        return format!("{}ms", duration.subsec_millis());
+    }
+
+    let parts = [
+        (secs / 3_600, "h"),
+        (secs % 3_600 / 60, "m"),
+        (secs % 60, "s"),
+    ];
+    parts
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, unit)| format!("{count}{unit}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    #[test]
+    fn describe_units() {
+        check!(describe(Duration::from_millis(500)) == "500ms");
+        check!(describe(Duration::from_millis(45_999)) == "45s");
+        check!(describe(Duration::from_secs(134)) == "2m 14s");
+        check!(describe(Duration::from_hours(1)) == "1h");
+        check!(describe(Duration::from_secs(3_605)) == "1h 5s");
+    }
+
+    #[test]
+    fn message_format() {
+        check!(message(Duration::from_secs(134)) == "‹ 2m 14s of silence ›");
+    }
+
+    #[test]
+    fn only_long_silences() {
+        let mut gaps = GapMarker::new(Duration::from_mins(1));
+        check!(gaps.output() == None);
+
+        let mut gaps = GapMarker::new(Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(1));
+        check!(gaps.output() >= Some(Duration::from_millis(1)));
+    }
+}
@@ -0,0 +1,95 @@
+//! Generate output for testing.
+//!
+//! `rederr generate-output` is a controllable command to run under `rederr`
+//! in tests, or when reproducing a bug. Lines look like `out 1` or `err 2`,
+//! numbered from 1 across both streams.
+
+use crate::params::GenerateParams;
+use std::io::{self, Write};
+use std::thread;
+use std::time::Instant;
+
+/// Invalid UTF-8 to include in lines with `--invalid-utf8`.
+const INVALID_UTF8: &[u8] = b" \xe2\x28\xa1";
+
+/// Generate output as specified by `params`.
+pub fn run(params: &GenerateParams) -> io::Result<()> {
+    let start = Instant::now();
+    let mut out = io::stdout().lock();
+    let mut err = io::stderr().lock();
+
+    for index in 0..params.lines {
+        if let Some(interval) = params.rate {
+            // Schedule relative to the start so that delays don’t accumulate.
+            if let Some(when) =
+                start.checked_add(interval.saturating_mul(index))
+            {
+                thread::sleep(when.saturating_duration_since(Instant::now()));
+            }
+        }
+
+        let number = index.saturating_add(1);
+        if is_stderr(number, params.stderr_ratio) {
+            write_line(&mut err, "err", number, params.invalid_utf8)?;
+        } else {
+            write_line(&mut out, "out", number, params.invalid_utf8)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Should line `number` go to stderr?
+///
+/// This spreads stderr lines evenly, so that exactly `floor(lines * ratio)`
+/// lines go to stderr.
+fn is_stderr(number: u32, ratio: f64) -> bool {
+    let previous = number.saturating_sub(1);
+    (f64::from(number) * ratio).floor() > (f64::from(previous) * ratio).floor()
+}
+
+/// Write a single line and flush it.
+///
+/// The line is written all at once, since stderr isn’t buffered and `rederr`
+/// might otherwise read it in pieces.
+fn write_line(
+    out: &mut dyn Write,
+    name: &str,
+    number: u32,
+    invalid_utf8: bool,
+) -> io::Result<()> {
+    let mut line = format!("{name} {number}").into_bytes();
+    if invalid_utf8 {
+        line.extend_from_slice(INVALID_UTF8);
+    }
+    line.push(b'\n');
+    out.write_all(&line)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    /// Get the line numbers that go to stderr.
+    fn stderr_lines(lines: u32, ratio: f64) -> Vec<u32> {
+        (1..=lines).filter(|&n| is_stderr(n, ratio)).collect()
+    }
+
+    #[test]
+    fn stderr_ratio_zero() {
+        check!(stderr_lines(10, 0.0) == []);
+    }
+
+    #[test]
+    fn stderr_ratio_one() {
+        check!(stderr_lines(3, 1.0) == [1, 2, 3]);
+    }
+
+    #[test]
+    fn stderr_ratio_even() {
+        check!(stderr_lines(10, 0.1) == [10]);
+        check!(stderr_lines(6, 0.5) == [2, 4, 6]);
+    }
+}
@@ -0,0 +1,196 @@
+//! Only keep the first and last lines of each stream (see `--head` and
+//! `--tail`).
+//!
+//! The first lines are output as they come. After that, the most recent lines
+//! are held in a ring buffer until the stream is closed, then a marker like
+//! `‹ 1234 lines of stdout omitted ›` is output, followed by the held lines.
+//!
+//! This works on complete lines, so output is line buffered. A partial line
+//! that gets too long counts as a line.
+
+use crate::params::Stream;
+use std::collections::VecDeque;
+
+/// Keeps the first and last lines of each of the child’s streams.
+#[derive(Debug)]
+pub struct HeadTail {
+    /// Number of lines to output from the start of each stream.
+    head: u64,
+
+    /// Number of lines to output from the end of each stream.
+    tail: usize,
+
+    /// State for the child’s stdout.
+    out: State,
+
+    /// State for the child’s stderr.
+    err: State,
+}
+
+/// Where we are in one stream.
+#[derive(Debug, Default)]
+struct State {
+    /// Number of lines seen so far.
+    lines: u64,
+
+    /// The last lines, up to `tail` of them.
+    held: VecDeque<Vec<u8>>,
+
+    /// Number of lines dropped from between the head and the tail.
+    omitted: u64,
+}
+
+/// What’s left of a stream once it’s closed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Rest {
+    /// Number of lines dropped from between the head and the tail.
+    pub omitted: u64,
+
+    /// The last lines.
+    pub tail: Vec<Vec<u8>>,
+}
+
+impl HeadTail {
+    /// Keep `head` lines from the start of each stream and `tail` lines from
+    /// the end, or return `None` if neither was set.
+    pub fn new(head: Option<u64>, tail: Option<usize>) -> Option<Self> {
+        if head.is_none() && tail.is_none() {
+            return None;
+        }
+        Some(Self {
+            head: head.unwrap_or(0),
+            tail: tail.unwrap_or(0),
+            out: State::default(),
+            err: State::default(),
+        })
+    }
+
+    /// Add complete lines from the child’s `stream`, and return the part that
+    /// should be output now.
+    pub fn push<'a>(&mut self, stream: Stream, bytes: &'a [u8]) -> &'a [u8] {
+        let (head, tail) = (self.head, self.tail);
+        let state = self.state(stream);
+
+        let mut now: usize = 0;
+        for line in bytes.split_inclusive(|&b| b == b'\n') {
+            state.lines = state.lines.saturating_add(1);
+            if state.lines <= head {
+                now = now.saturating_add(line.len());
+                continue;
+            }
+
+            if tail == 0 {
+                state.omitted = state.omitted.saturating_add(1);
+                continue;
+            }
+            if state.held.len() == tail {
+                state.held.pop_front();
+                state.omitted = state.omitted.saturating_add(1);
+            }
+            state.held.push_back(line.to_vec());
+        }
+
+        bytes.split_at(now).0
+    }
+
+    /// Get what was held back from `stream`, which has been closed, and start
+    /// over for the next run.
+    pub fn finish(&mut self, stream: Stream) -> Rest {
+        let state = std::mem::take(self.state(stream));
+        Rest {
+            omitted: state.omitted,
+            tail: state.held.into(),
+        }
+    }
+
+    /// Get the state for `stream`.
+    const fn state(&mut self, stream: Stream) -> &mut State {
+        match stream {
+            Stream::Out => &mut self.out,
+            Stream::Err => &mut self.err,
+        }
+    }
+}
+
+/// Get the marker line (without a newline) for lines omitted from `stream`.
+pub fn message(stream: Stream, omitted: u64) -> String {
+    let name = match stream {
+        Stream::Out => "stdout",
+        Stream::Err => "stderr",
+    };
+    format!(
+        "‹ {omitted} line{} of {name} omitted ›",
+        if omitted == 1 { "" } else { "s" },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::{check, let_assert};
+    use bstr::ByteSlice;
+
+    /// Keep `head` and `tail` lines.
+    fn head_tail(head: Option<u64>, tail: Option<usize>) -> HeadTail {
+        let_assert!(Some(head_tail) = HeadTail::new(head, tail));
+        head_tail
+    }
+
+    /// Build a [`Rest`].
+    fn rest(omitted: u64, tail: &[&str]) -> Rest {
+        Rest {
+            omitted,
+            tail: tail.iter().map(|line| line.as_bytes().to_vec()).collect(),
+        }
+    }
+
+    #[test]
+    fn neither() {
+        check!(HeadTail::new(None, None).is_none());
+    }
+
+    #[test]
+    fn head_and_tail() {
+        let mut head_tail = head_tail(Some(2), Some(2));
+        check!(head_tail.push(Stream::Out, b"1\n").as_bstr() == "1\n");
+        check!(head_tail.push(Stream::Out, b"2\n3\n4\n").as_bstr() == "2\n");
+        check!(head_tail.push(Stream::Out, b"5\n6").as_bstr() == "");
+        check!(head_tail.finish(Stream::Out) == rest(2, &["5\n", "6"]));
+    }
+
+    #[test]
+    fn short() {
+        let mut head_tail = head_tail(Some(2), Some(2));
+        check!(head_tail.push(Stream::Out, b"1\n2\n3\n").as_bstr() == "1\n2\n");
+        check!(head_tail.finish(Stream::Out) == rest(0, &["3\n"]));
+    }
+
+    #[test]
+    fn head_only() {
+        let mut head_tail = head_tail(Some(1), None);
+        check!(head_tail.push(Stream::Err, b"1\n2\n3\n").as_bstr() == "1\n");
+        check!(head_tail.finish(Stream::Err) == rest(2, &[]));
+    }
+
+    #[test]
+    fn tail_only() {
+        let mut head_tail = head_tail(None, Some(1));
+        check!(head_tail.push(Stream::Err, b"1\n2\n3\n").as_bstr() == "");
+        check!(head_tail.finish(Stream::Err) == rest(2, &["3\n"]));
+    }
+
+    #[test]
+    fn streams_separate_and_reset() {
+        let mut head_tail = head_tail(Some(1), Some(0));
+        check!(head_tail.push(Stream::Out, b"a\nb\n").as_bstr() == "a\n");
+        check!(head_tail.push(Stream::Err, b"c\n").as_bstr() == "c\n");
+        check!(head_tail.finish(Stream::Out) == rest(1, &[]));
+        check!(head_tail.push(Stream::Out, b"d\n").as_bstr() == "d\n");
+    }
+
+    #[test]
+    fn marker() {
+        check!(message(Stream::Out, 1) == "‹ 1 line of stdout omitted ›");
+        check!(message(Stream::Err, 5) == "‹ 5 lines of stderr omitted ›");
+    }
+}
@@ -0,0 +1,116 @@
+//! Render the child’s output as a hex dump (see `--hex`).
+//!
+//! Each stream gets its own offset. Rows are the same as `hexdump -C`:
+//!
+//! ```text
+//! 00000000  68 65 6c 6c 6f 0a                                 |hello.|
+//! ```
+//!
+//! Output isn’t held back to fill rows, so a chunk that isn’t a multiple of 16
+//! bytes ends with a short row, and the next row starts at an unaligned offset.
+
+use crate::params::Stream;
+use std::io::Write;
+
+/// Number of bytes in each row.
+const ROW_LEN: usize = 16;
+
+/// Number of bytes in each group of hex in a row.
+const GROUP_LEN: usize = 8;
+
+/// Renders output as hex, keeping track of the offset in each stream.
+#[derive(Debug, Default)]
+pub struct HexDump {
+    /// Number of bytes of stdout dumped so far.
+    out: u64,
+
+    /// Number of bytes of stderr dumped so far.
+    err: u64,
+}
+
+impl HexDump {
+    /// Render a chunk from the child’s `stream` as rows of hex, replacing the
+    /// contents of `out`.
+    pub fn dump(&mut self, stream: Stream, bytes: &[u8], out: &mut Vec<u8>) {
+        let offset = match stream {
+            Stream::Out => &mut self.out,
+            Stream::Err => &mut self.err,
+        };
+
+        out.clear();
+        for row in bytes.chunks(ROW_LEN) {
+            // Writing to a Vec can’t fail.
+            let _ = write!(out, "{offset:08x} ");
+            for i in 0..ROW_LEN {
+                if i == GROUP_LEN {
+                    out.push(b' ');
+                }
+                let _ = match row.get(i) {
+                    Some(byte) => write!(out, " {byte:02x}"),
+                    None => write!(out, "   "),
+                };
+            }
+            out.extend_from_slice(b"  |");
+            out.extend(row.iter().map(|&byte| match byte {
+                0x20..=0x7e => byte,
+                _ => b'.',
+            }));
+            out.extend_from_slice(b"|\n");
+
+            *offset = offset.saturating_add(row.len() as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+    use bstr::ByteSlice;
+
+    #[test]
+    fn short_row() {
+        let mut out = Vec::new();
+        HexDump::default().dump(Stream::Out, b"hello\n", &mut out);
+        check!(
+            out.as_bstr()
+                == "00000000  68 65 6c 6c 6f 0a                                 \
+                    |hello.|\n"
+        );
+    }
+
+    #[test]
+    fn rows() {
+        let mut out = Vec::new();
+        HexDump::default().dump(
+            Stream::Out,
+            b"0123456789abcdef\x00\x7f",
+            &mut out,
+        );
+        check!(
+            out.as_bstr()
+                == "00000000  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  \
+                    |0123456789abcdef|\n\
+                    00000010  00 7f                                             \
+                    |..|\n"
+        );
+    }
+
+    #[test]
+    fn offsets_per_stream() {
+        let mut hex = HexDump::default();
+        let mut out = Vec::new();
+        hex.dump(Stream::Out, b"abc", &mut out);
+        hex.dump(Stream::Err, b"d", &mut out);
+        check!(out.starts_with(b"00000000 "));
+        hex.dump(Stream::Out, b"e", &mut out);
+        check!(out.starts_with(b"00000003 "));
+    }
+
+    #[test]
+    fn empty() {
+        let mut out = b"old".to_vec();
+        HexDump::default().dump(Stream::Out, b"", &mut out);
+        check!(out.is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+//! Highlight parts of the output that match regular expressions (see
+//! `--highlight`).
+//!
+//! Each rule is a regular expression and a style. Rules apply to both streams.
+//! If more than one rule matches at the same place, the first one passed wins.
+//! Matches can’t span lines, since output is line buffered when there are
+//! rules, but they also can’t span chunks of a line that was too long to hold.
+
+use crate::params::TextStyle;
+use anyhow::{anyhow, Context};
+use clap::ValueEnum;
+use regex::bytes::{Match, Regex};
+use std::io;
+use termcolor::{ColorSpec, WriteColor};
+
+/// A rule to highlight matches of a regular expression.
+#[derive(Clone, Debug)]
+pub struct Highlight {
+    /// What to highlight.
+    regex: Regex,
+
+    /// How to highlight it. This is combined with the stream’s color.
+    color: ColorSpec,
+}
+
+/// Parse a rule like "ERROR|FATAL=bold,red".
+///
+/// The style is a comma-separated list of colors (e.g. "red" or "0,128,255")
+/// and text styles (e.g. "bold").
+pub fn parse(input: &str) -> anyhow::Result<Highlight> {
+    let (pattern, style) = input
+        .rsplit_once('=')
+        .ok_or_else(|| anyhow!("expected REGEX=STYLE"))?;
+    let regex = Regex::new(pattern).context("invalid regular expression")?;
+
+    let mut color = ColorSpec::new();
+    for name in style.split(',').map(str::trim) {
+        match TextStyle::from_str(name, true) {
+            Ok(TextStyle::Bold) => color.set_bold(true),
+            Ok(TextStyle::Dim) => color.set_dimmed(true),
+            Ok(TextStyle::Italic) => color.set_italic(true),
+            Ok(TextStyle::Underline) => color.set_underline(true),
+            Err(_) => color
+                .set_fg(Some(name.parse().map_err(|_| {
+                    anyhow!("unknown color or style {name:?}")
+                })?)),
+        };
+    }
+    if color.is_none() {
+        return Err(anyhow!("expected a color or style after ="));
+    }
+
+    Ok(Highlight { regex, color })
+}
+
+/// Write `bytes` to `out`, highlighting matches of `rules`.
+///
+/// `base` is the color that `out` is already set to, if any. It’s restored
+/// after each match.
+pub fn write(
+    out: &mut dyn WriteColor,
+    bytes: &[u8],
+    base: Option<&ColorSpec>,
+    rules: &[Highlight],
+) -> io::Result<()> {
+    let mut start = 0;
+    while let Some((rule, found)) = next_match(bytes, start, rules) {
+        out.write_all(&bytes[start..found.start()])?;
+        out.set_color(&combine(base, &rule.color))?;
+        out.write_all(found.as_bytes())?;
+        match base {
+            Some(base) => out.set_color(base)?,
+            None => out.reset()?,
+        }
+        start = found.end();
+    }
+    out.write_all(&bytes[start..])
+}
+
+/// Find the earliest non-empty match of any rule at or after `start`.
+fn next_match<'a, 'b>(
+    bytes: &'b [u8],
+    start: usize,
+    rules: &'a [Highlight],
+) -> Option<(&'a Highlight, Match<'b>)> {
+    rules
+        .iter()
+        .filter_map(|rule| {
+            let mut at = start;
+            loop {
+                let found = rule.regex.find_at(bytes, at)?;
+                if !found.is_empty() {
+                    return Some((rule, found));
+                }
+                at = found.end().checked_add(1)?;
+                if at > bytes.len() {
+                    return None;
+                }
+            }
+        })
+        .min_by_key(|(_, found)| found.start())
+}
+
+/// Apply `highlight` on top of `base`.
+fn combine(base: Option<&ColorSpec>, highlight: &ColorSpec) -> ColorSpec {
+    let Some(base) = base else {
+        return highlight.clone();
+    };
+    let mut color = base.clone();
+    if let Some(fg) = highlight.fg() {
+        color.set_fg(Some(*fg));
+    }
+    color
+        .set_bold(base.bold() || highlight.bold())
+        .set_dimmed(base.dimmed() || highlight.dimmed())
+        .set_italic(base.italic() || highlight.italic())
+        .set_underline(base.underline() || highlight.underline());
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::{check, let_assert};
+    use bstr::ByteSlice;
+    use termcolor::{Ansi, Color};
+
+    /// Highlight `bytes` with `rules` on top of `base`, and return the result
+    /// rendered for an ANSI terminal.
+    fn highlight(
+        bytes: &[u8],
+        base: Option<&ColorSpec>,
+        rules: &[&str],
+    ) -> Vec<u8> {
+        let rules: Vec<_> =
+            rules.iter().map(|rule| parse(rule).unwrap()).collect();
+        let mut out = Ansi::new(Vec::new());
+        write(&mut out, bytes, base, &rules).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn parse_style() {
+        let_assert!(Ok(rule) = parse("a=b=bold, Red"));
+        check!(rule.regex.as_str() == "a=b");
+        let mut expected = ColorSpec::new();
+        expected.set_fg(Some(Color::Red)).set_bold(true);
+        check!(rule.color == expected);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        check!(parse("ERROR").is_err());
+        check!(parse("ERROR=").is_err());
+        check!(parse("ERROR=mauve").is_err());
+        check!(parse("(=red").is_err());
+    }
+
+    #[test]
+    fn no_match() {
+        check!(
+            highlight(b"fine\n", None, &["ERROR=red"]).as_bstr() == "fine\n"
+        );
+    }
+
+    #[test]
+    fn plain_base() {
+        check!(
+            highlight(b"an ERROR and a WARN\n", None, &["ERROR=red", "WARN=yellow"])
+                .as_bstr()
+                == "an \x1b[0m\x1b[31mERROR\x1b[0m and a \x1b[0m\x1b[33mWARN\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn colored_base() {
+        let mut base = ColorSpec::new();
+        base.set_fg(Some(Color::Red));
+        check!(
+            highlight(b"a WARN\n", Some(&base), &["WARN=bold"]).as_bstr()
+                == "a \x1b[0m\x1b[1m\x1b[31mWARN\x1b[0m\x1b[31m\n"
+        );
+    }
+
+    #[test]
+    fn first_rule_wins() {
+        check!(
+            highlight(b"ERROR\n", None, &["ERR=red", "ERROR=yellow"]).as_bstr()
+                == "\x1b[0m\x1b[31mERR\x1b[0mOR\n"
+        );
+    }
+
+    #[test]
+    fn empty_matches_skipped() {
+        check!(
+            highlight(b"baab\n", None, &["a*=red"]).as_bstr()
+                == "b\x1b[0m\x1b[31maa\x1b[0mb\n"
+        );
+    }
+}
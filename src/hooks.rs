@@ -0,0 +1,128 @@
+//! Callbacks for events while running a command (see
+//! [`Runner::run_with()`](crate::Runner::run_with)).
+//!
+//! This is an alternative to iterating over [`Events`](crate::Events) for
+//! embedders that just want to send output somewhere, like a database or a
+//! socket. Each hook is called from the `poll()` loop as soon as the event
+//! happens.
+
+use crate::runner::{Event, TimeoutKind};
+use std::fmt;
+use std::io;
+
+/// A hook for a chunk of output.
+type OutputHook<'a> = Box<dyn FnMut(&[u8]) -> io::Result<()> + 'a>;
+
+/// Callbacks for events while running a command.
+///
+/// Hooks that aren’t set do nothing. If a hook returns an error, the command
+/// is killed and [`Runner::run_with()`](crate::Runner::run_with) returns the
+/// error.
+///
+/// ```rust
+/// use rederr::{Hooks, Runner};
+///
+/// let mut lines = Vec::new();
+/// let code = Runner::new("tests/fixtures/fail.sh")
+///     .run_with(
+///         Hooks::new()
+///             .on_stdout(|bytes| {
+///                 lines.push(bytes.to_vec());
+///                 Ok(())
+///             })
+///             .on_exit(|code| {
+///                 println!("exited with {code}");
+///                 Ok(())
+///             }),
+///     )
+///     .unwrap();
+/// assert_eq!(code, 3);
+/// assert_eq!(lines, [b"out\n"]);
+/// ```
+#[derive(Default)]
+pub struct Hooks<'a> {
+    /// Called with each chunk of output from the command’s stdout.
+    stdout: Option<OutputHook<'a>>,
+
+    /// Called with each chunk of output from the command’s stderr.
+    stderr: Option<OutputHook<'a>>,
+
+    /// Called when a timeout expires.
+    timeout: Option<Box<dyn FnMut(TimeoutKind) -> io::Result<()> + 'a>>,
+
+    /// Called with the exit code when the command exits.
+    exit: Option<Box<dyn FnMut(i32) -> io::Result<()> + 'a>>,
+}
+
+impl<'a> Hooks<'a> {
+    /// Create hooks that do nothing.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `hook` with each chunk of output from the command’s stdout.
+    #[must_use]
+    pub fn on_stdout<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(&[u8]) -> io::Result<()> + 'a,
+    {
+        self.stdout = Some(Box::new(hook));
+        self
+    }
+
+    /// Call `hook` with each chunk of output from the command’s stderr.
+    #[must_use]
+    pub fn on_stderr<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(&[u8]) -> io::Result<()> + 'a,
+    {
+        self.stderr = Some(Box::new(hook));
+        self
+    }
+
+    /// Call `hook` when a timeout expires, after the command is sent
+    /// `SIGTERM`.
+    #[must_use]
+    pub fn on_timeout<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(TimeoutKind) -> io::Result<()> + 'a,
+    {
+        self.timeout = Some(Box::new(hook));
+        self
+    }
+
+    /// Call `hook` with the exit code once the command has exited and its
+    /// output is closed. The exit code is 128 + the signal number if the
+    /// command was killed by a signal.
+    #[must_use]
+    pub fn on_exit<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(i32) -> io::Result<()> + 'a,
+    {
+        self.exit = Some(Box::new(hook));
+        self
+    }
+
+    /// Call the hook for `event`, if there is one.
+    pub(crate) fn call(&mut self, event: &Event) -> io::Result<()> {
+        match event {
+            Event::Stdout(bytes) => self.stdout.as_mut().map(|f| f(bytes)),
+            Event::Stderr(bytes) => self.stderr.as_mut().map(|f| f(bytes)),
+            Event::TimedOut(kind) => self.timeout.as_mut().map(|f| f(*kind)),
+            Event::Exit(code) => self.exit.as_mut().map(|f| f(*code)),
+        }
+        .unwrap_or(Ok(()))
+    }
+}
+
+impl fmt::Debug for Hooks<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("stdout", &self.stdout.is_some())
+            .field("stderr", &self.stderr.is_some())
+            .field("timeout", &self.timeout.is_some())
+            .field("exit", &self.exit.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,103 @@
+//! Run a command and capture its stdout and stderr separately, without
+//! blocking.
+//!
+//! This is the core of the `rederr` executable, for use without shelling out
+//! to it. Use [`Runner`] to configure and start a command, then iterate over
+//! the [`Event`]s it produces:
+//!
+//! ```rust
+//! use rederr::{Event, Runner, TimeoutKind};
+//! use std::time::Duration;
+//!
+//! let runner = Runner::new("sleep")
+//!     .arg("10")
+//!     .idle_timeout(Duration::from_millis(100));
+//!
+//! let events: Vec<Event> = runner.run().unwrap().map(Result::unwrap).collect();
+//! assert_eq!(
+//!     events,
+//!     [Event::TimedOut(TimeoutKind::Idle), Event::Exit(128 + 15)],
+//! );
+//! ```
+//!
+//! To have functions called as things happen instead, pass [`Hooks`] to
+//! [`Runner::run_with()`].
+//!
+//! With the `tokio` feature, [`Runner::run_async()`] starts the command on the
+//! current tokio runtime and returns an `AsyncRunner`, which is a `Stream` of
+//! the same events.
+//!
+//! The `rederr` executable parses its command line into [`params::Params`] and
+//! passes them to [`session::run()`], which is the same loop with every option
+//! available.
+
+// Lint configuration in Cargo.toml isn’t supported by cargo-geiger.
+#![deny(unsafe_code)]
+
+pub mod child;
+pub mod exit_code;
+pub mod params;
+pub mod poller;
+pub mod session;
+pub mod signals;
+pub mod timeout;
+
+mod backlog;
+mod cgroup;
+mod chaos;
+mod collapse;
+mod curl;
+mod deadline;
+mod dedup;
+mod dry_run;
+mod environment;
+mod filter;
+mod format;
+mod gap;
+mod head_tail;
+mod hex;
+mod highlight;
+mod limit;
+mod limits;
+mod line_buffer;
+mod lock;
+mod mail;
+mod oom;
+mod patterns;
+mod ping;
+mod pre_exec;
+mod priority;
+mod pty;
+mod random;
+mod read_buffer;
+mod record;
+mod report;
+mod retry;
+mod rusage;
+mod sanitize;
+mod sd_notify;
+mod splice;
+mod spool;
+mod stats;
+mod status;
+mod status_line;
+mod stdin;
+mod subreaper;
+mod tail;
+mod timestamp;
+mod tz;
+mod unbuffer;
+mod utf8;
+mod watchdog;
+mod webhook;
+
+mod hooks;
+pub use hooks::Hooks;
+
+mod runner;
+pub use runner::{Event, Events, Runner, TimeoutKind};
+
+#[cfg(feature = "tokio")]
+mod async_runner;
+#[cfg(feature = "tokio")]
+pub use async_runner::AsyncRunner;
@@ -0,0 +1,199 @@
+//! Stop outputting a stream after a limit (see `--max-output-bytes` and
+//! `--max-output-lines`).
+//!
+//! Limits apply to each of the child’s streams separately, and count output
+//! after `--grep` and similar options. Output over the limit is still read from
+//! the child so that it doesn’t block; it’s just dropped.
+
+use crate::params::Stream;
+use bstr::ByteSlice;
+
+/// Limits on how much of each stream to output.
+#[derive(Debug)]
+pub struct Limit {
+    /// Maximum number of bytes to output from each stream.
+    max_bytes: Option<u64>,
+
+    /// Maximum number of lines to output from each stream.
+    max_lines: Option<u64>,
+
+    /// How much of stdout has been output.
+    out: Count,
+
+    /// How much of stderr has been output.
+    err: Count,
+}
+
+/// How much of a stream has been output.
+#[derive(Debug, Default)]
+struct Count {
+    /// Bytes output.
+    bytes: u64,
+
+    /// Lines output.
+    lines: u64,
+
+    /// Whether the stream went over a limit.
+    exceeded: bool,
+
+    /// Whether `exceeded` has been reported with [`Limit::take_exceeded()`].
+    reported: bool,
+}
+
+impl Limit {
+    /// Create limits, or return `None` if there aren’t any.
+    pub fn new(max_bytes: Option<u64>, max_lines: Option<u64>) -> Option<Self> {
+        if max_bytes.is_none() && max_lines.is_none() {
+            return None;
+        }
+        Some(Self {
+            max_bytes,
+            max_lines,
+            out: Count::default(),
+            err: Count::default(),
+        })
+    }
+
+    /// Get the part of a chunk from the child’s `stream` that’s within the
+    /// limits.
+    pub fn allow<'a>(&mut self, stream: Stream, bytes: &'a [u8]) -> &'a [u8] {
+        let (max_bytes, max_lines) = (self.max_bytes, self.max_lines);
+        let count = self.count(stream);
+        if count.exceeded {
+            return &[];
+        }
+
+        let mut allowed = bytes.len();
+        if let Some(max_bytes) = max_bytes {
+            let left = max_bytes.saturating_sub(count.bytes);
+            allowed = allowed.min(usize::try_from(left).unwrap_or(usize::MAX));
+        }
+        if let Some(max_lines) = max_lines {
+            let left = max_lines.saturating_sub(count.lines);
+            // Allow through the last newline we can output, plus anything
+            // after it if there’s a line left.
+            let end = bytes
+                .split_inclusive(|&b| b == b'\n')
+                .take(usize::try_from(left).unwrap_or(usize::MAX))
+                .map(<[u8]>::len)
+                .sum();
+            allowed = allowed.min(end);
+        }
+
+        let (allowed, dropped) = bytes.split_at(allowed);
+        count.bytes = count.bytes.saturating_add(allowed.len() as u64);
+        count.lines = count
+            .lines
+            .saturating_add(allowed.find_iter(b"\n").count() as u64);
+        if !dropped.is_empty() {
+            count.exceeded = true;
+        }
+        allowed
+    }
+
+    /// Get a message about a stream that went over a limit, if one did and it
+    /// hasn’t been reported yet.
+    pub fn take_exceeded(&mut self) -> Option<String> {
+        for (stream, name) in [(Stream::Out, "stdout"), (Stream::Err, "stderr")]
+        {
+            let count = self.count(stream);
+            if count.exceeded && !count.reported {
+                count.reported = true;
+                return Some(format!(
+                    "Command {name} truncated after {} byte{} and {} line{}",
+                    count.bytes,
+                    if count.bytes == 1 { "" } else { "s" },
+                    count.lines,
+                    if count.lines == 1 { "" } else { "s" },
+                ));
+            }
+        }
+        None
+    }
+
+    /// Get the count for `stream`.
+    const fn count(&mut self, stream: Stream) -> &mut Count {
+        match stream {
+            Stream::Out => &mut self.out,
+            Stream::Err => &mut self.err,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::{check, let_assert};
+
+    /// Create limits.
+    fn limit(max_bytes: Option<u64>, max_lines: Option<u64>) -> Limit {
+        let_assert!(Some(limit) = Limit::new(max_bytes, max_lines));
+        limit
+    }
+
+    #[test]
+    fn no_limits() {
+        check!(Limit::new(None, None).is_none());
+    }
+
+    #[test]
+    fn max_bytes() {
+        let mut limit = limit(Some(5), None);
+        check!(limit.allow(Stream::Out, b"abc").as_bstr() == "abc");
+        check!(limit.take_exceeded() == None);
+        check!(limit.allow(Stream::Out, b"d\nef").as_bstr() == "d\n");
+        check!(limit.allow(Stream::Out, b"g").as_bstr() == "");
+        check!(
+            limit.take_exceeded().as_deref()
+                == Some("Command stdout truncated after 5 bytes and 1 line")
+        );
+        check!(limit.take_exceeded() == None);
+    }
+
+    #[test]
+    fn max_bytes_exact() {
+        let mut limit = limit(Some(3), None);
+        check!(limit.allow(Stream::Out, b"abc").as_bstr() == "abc");
+        check!(limit.take_exceeded() == None);
+        check!(limit.allow(Stream::Out, b"").as_bstr() == "");
+        check!(limit.take_exceeded() == None);
+        check!(limit.allow(Stream::Out, b"d").as_bstr() == "");
+        check!(limit.take_exceeded().is_some());
+    }
+
+    #[test]
+    fn max_lines() {
+        let mut limit = limit(None, Some(2));
+        check!(limit.allow(Stream::Err, b"a\nb").as_bstr() == "a\nb");
+        check!(limit.allow(Stream::Err, b"c\nd\n").as_bstr() == "c\n");
+        check!(
+            limit.take_exceeded().as_deref()
+                == Some("Command stderr truncated after 5 bytes and 2 lines")
+        );
+        check!(limit.allow(Stream::Err, b"e\n").as_bstr() == "");
+    }
+
+    #[test]
+    fn max_lines_exact() {
+        let mut limit = limit(None, Some(1));
+        check!(limit.allow(Stream::Err, b"a\n").as_bstr() == "a\n");
+        check!(limit.take_exceeded() == None);
+        check!(limit.allow(Stream::Err, b"b").as_bstr() == "");
+        check!(limit.take_exceeded().is_some());
+    }
+
+    #[test]
+    fn both_limits() {
+        let mut bytes_first = limit(Some(3), Some(1));
+        check!(bytes_first.allow(Stream::Out, b"abcd\n").as_bstr() == "abc");
+        let mut lines_first = limit(Some(10), Some(1));
+        check!(lines_first.allow(Stream::Out, b"a\nb\n").as_bstr() == "a\n");
+    }
+
+    #[test]
+    fn streams_separate() {
+        let mut limit = limit(Some(1), None);
+        check!(limit.allow(Stream::Out, b"ab").as_bstr() == "a");
+        check!(limit.allow(Stream::Err, b"cd").as_bstr() == "c");
+    }
+}
@@ -0,0 +1,44 @@
+//! Limit the resources the child can use (see `--limit-cpu`, `--limit-mem`,
+//! `--limit-fsize`, and `--limit-nofile`).
+//!
+//! Limits are set with `setrlimit()` in the child before it execs the command
+//! (see [`crate::pre_exec`]). Each limit is set as both the soft and the hard
+//! limit, so the command can’t raise it again.
+//!
+//! The CPU time limit is soft, with a hard limit a second later, so that the
+//! command gets `SIGXCPU` rather than `SIGKILL` when it reaches it.
+
+use nix::sys::resource::{rlim_t, Resource};
+
+/// A resource, and its soft and hard limits.
+pub type Limit = (Resource, rlim_t, rlim_t);
+
+/// Get the limits set in `params`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn limits(params: &crate::params::Params) -> Vec<Limit> {
+    let mut limits = Vec::new();
+    if let Some(cpu) = params.limit_cpu {
+        // The limit is in whole seconds, so round up.
+        let seconds = cpu
+            .as_secs()
+            .saturating_add(u64::from(cpu.subsec_nanos() > 0))
+            .max(1);
+        let hard = seconds.saturating_add(1);
+        limits.push((Resource::RLIMIT_CPU, rlim(seconds), rlim(hard)));
+    }
+    let mut push = |resource, value: Option<u64>| {
+        if let Some(value) = value {
+            limits.push((resource, rlim(value), rlim(value)));
+        }
+    };
+    push(Resource::RLIMIT_AS, params.limit_mem);
+    push(Resource::RLIMIT_FSIZE, params.limit_fsize);
+    push(Resource::RLIMIT_NOFILE, params.limit_nofile);
+    limits
+}
+
+/// Convert `value` to a limit, treating values that are too large as no limit.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn rlim(value: u64) -> rlim_t {
+    rlim_t::try_from(value).unwrap_or(nix::sys::resource::RLIM_INFINITY)
+}
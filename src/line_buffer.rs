@@ -0,0 +1,186 @@
+//! Hold back partial lines until they’re complete (see `--line-buffered`).
+//!
+//! The child’s stdout and stderr are read separately, so if it writes part of
+//! a line to one and then something to the other, the output gets spliced
+//! together mid-line. Buffering each stream until it has a complete line
+//! avoids that, at the cost of delaying output that doesn’t end in a newline.
+
+use crate::params::Stream;
+use std::io;
+
+/// Partial lines longer than this are output anyway.
+///
+/// This keeps a command that never outputs a newline from using unbounded
+/// memory.
+pub const MAX_PARTIAL_LINE: usize = 64 * 1024;
+
+/// Remove the `\n` or `\r\n` from the end of `line`, if there is one.
+///
+/// This is used before matching lines against regular expressions, so that
+/// `$` matches the end of the line.
+pub fn strip_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Partial lines from each of the child’s streams.
+#[derive(Debug)]
+pub struct LineBuffer {
+    /// Partial line from stdout.
+    out: Vec<u8>,
+
+    /// Partial line from stderr.
+    err: Vec<u8>,
+
+    /// Maximum length of a partial line before it’s output anyway.
+    max_partial: usize,
+}
+
+impl LineBuffer {
+    /// Create an empty buffer that holds partial lines up to `max_partial`
+    /// bytes long.
+    pub const fn new(max_partial: usize) -> Self {
+        Self {
+            out: Vec::new(),
+            err: Vec::new(),
+            max_partial,
+        }
+    }
+
+    /// Add a chunk of output from the child’s `stream`.
+    ///
+    /// Complete lines are passed to `write`, along with the partial line if
+    /// it’s grown too long.
+    pub fn push<F>(
+        &mut self,
+        stream: Stream,
+        chunk: &[u8],
+        mut write: F,
+    ) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        let max_partial = self.max_partial;
+        let partial = self.partial(stream);
+
+        let rest = match chunk.iter().rposition(|&b| b == b'\n') {
+            Some(i) => {
+                let (lines, rest) = chunk.split_at(i.saturating_add(1));
+                if partial.is_empty() {
+                    write(lines)?;
+                } else {
+                    partial.extend_from_slice(lines);
+                    write(partial)?;
+                    partial.clear();
+                }
+                rest
+            }
+            None => chunk,
+        };
+
+        partial.extend_from_slice(rest);
+        if partial.len() >= max_partial {
+            write(partial)?;
+            partial.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Pass the partial line from `stream`, if any, to `write`.
+    pub fn flush<F>(&mut self, stream: Stream, write: F) -> io::Result<()>
+    where
+        F: FnOnce(&[u8]) -> io::Result<()>,
+    {
+        let partial = self.partial(stream);
+        if !partial.is_empty() {
+            write(partial)?;
+            partial.clear();
+        }
+        Ok(())
+    }
+
+    /// Get the partial line for `stream`.
+    const fn partial(&mut self, stream: Stream) -> &mut Vec<u8> {
+        match stream {
+            Stream::Out => &mut self.out,
+            Stream::Err => &mut self.err,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    /// Push chunks and return what was written.
+    fn push_all(
+        buffer: &mut LineBuffer,
+        chunks: &[(Stream, &[u8])],
+    ) -> Vec<(Stream, Vec<u8>)> {
+        let mut written = Vec::new();
+        for (stream, chunk) in chunks {
+            buffer
+                .push(*stream, chunk, |bytes| {
+                    written.push((*stream, bytes.to_vec()));
+                    Ok(())
+                })
+                .unwrap();
+        }
+        written
+    }
+
+    #[test]
+    fn holds_partial_lines() {
+        let mut buffer = LineBuffer::new(MAX_PARTIAL_LINE);
+        let written = push_all(
+            &mut buffer,
+            &[
+                (Stream::Out, b"a"),
+                (Stream::Err, b"b\nc"),
+                (Stream::Out, b"d\ne\nf"),
+            ],
+        );
+        check!(
+            written
+                == [
+                    (Stream::Err, b"b\n".to_vec()),
+                    (Stream::Out, b"ad\ne\n".to_vec()),
+                ]
+        );
+
+        let mut flushed = Vec::new();
+        for stream in [Stream::Out, Stream::Err] {
+            buffer
+                .flush(stream, |bytes| {
+                    flushed.push(bytes.to_vec());
+                    Ok(())
+                })
+                .unwrap();
+        }
+        check!(flushed == [b"f".to_vec(), b"c".to_vec()]);
+    }
+
+    #[test]
+    fn strip_endings() {
+        check!(strip_ending(b"a\r\n") == b"a");
+        check!(strip_ending(b"a\n") == b"a");
+        check!(strip_ending(b"a\r") == b"a");
+        check!(strip_ending(b"a") == b"a");
+    }
+
+    #[test]
+    fn long_partial_line() {
+        let mut buffer = LineBuffer::new(4);
+        let written = push_all(
+            &mut buffer,
+            &[
+                (Stream::Out, b"ab"),
+                (Stream::Out, b"cd"),
+                (Stream::Out, b"e"),
+            ],
+        );
+        check!(written == [(Stream::Out, b"abcd".to_vec())]);
+    }
+}
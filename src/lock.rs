@@ -0,0 +1,117 @@
+//! Prevent overlapping runs (see `--lockfile`).
+//!
+//! The lock is an exclusive `flock()` on the lock file, so it’s released by the
+//! kernel when `rederr` exits, however it exits. The holder writes its PID to
+//! the file so that `--lock-kill` knows who to terminate.
+
+use anyhow::{anyhow, Context};
+use nix::errno::Errno;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use std::fs::{self, File, TryLockError};
+use std::io::{self, Seek, Write};
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long `--lock-kill` waits for the holder to exit if `--lock-timeout`
+/// wasn’t passed.
+pub const DEFAULT_KILL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often to check whether the lock has been released.
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A held lock. It’s released when this is dropped.
+#[derive(Debug)]
+pub struct Lock {
+    /// The locked file.
+    _file: File,
+}
+
+impl Lock {
+    /// Lock `path`, creating it if necessary.
+    ///
+    /// If the lock is held by another process, this terminates it if
+    /// `kill_holder` is set, and waits up to `timeout` for the lock to be
+    /// released.
+    pub fn acquire(
+        path: &Path,
+        timeout: Duration,
+        kill_holder: bool,
+    ) -> anyhow::Result<Self> {
+        let context = || format!("locking {}", path.display());
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(context)?;
+
+        let deadline = Instant::now().checked_add(timeout);
+        let mut killed = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(error)) => {
+                    return Err(error).with_context(context);
+                }
+            }
+
+            let holder = read_pid(path).with_context(context)?;
+            if let (true, false, Some(pid)) = (kill_holder, killed, holder) {
+                match kill(pid, Signal::SIGTERM) {
+                    // The holder may have just exited.
+                    Ok(()) | Err(Errno::ESRCH) => killed = true,
+                    Err(error) => {
+                        return Err(error).with_context(|| {
+                            format!("terminating lock holder (PID {pid})")
+                        });
+                    }
+                }
+            }
+
+            // If the deadline overflowed, wait forever.
+            let now = Instant::now();
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                let holder = holder.map_or_else(
+                    || "another process".to_owned(),
+                    |pid| format!("process {pid}"),
+                );
+                return Err(anyhow!(
+                    "lock file {} is held by {holder}",
+                    path.display()
+                ));
+            }
+            thread::sleep(
+                deadline
+                    .map_or(RETRY_INTERVAL, |deadline| {
+                        deadline.saturating_duration_since(now)
+                    })
+                    .min(RETRY_INTERVAL),
+            );
+        }
+
+        write_pid(&mut file).with_context(context)?;
+        Ok(Self { _file: file })
+    }
+}
+
+/// Read the PID of the lock holder from the lock file, if it’s there.
+fn read_pid(path: &Path) -> io::Result<Option<Pid>> {
+    Ok(fs::read_to_string(path)?
+        .trim()
+        .parse()
+        .ok()
+        .filter(|pid| *pid > 0)
+        .map(Pid::from_raw))
+}
+
+/// Replace the contents of the lock file with our PID.
+fn write_pid(file: &mut File) -> io::Result<()> {
+    file.set_len(0)?;
+    file.rewind()?;
+    writeln!(file, "{}", process::id())
+}
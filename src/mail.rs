@@ -0,0 +1,322 @@
+//! Email the output when the command finishes (see `--mail-to`).
+//!
+//! This replaces cron’s MAILTO: the output is sent uncolored, only the end of
+//! it is included, and mail is only sent when `--mail-on` says to. It’s sent
+//! with the local sendmail, or through an SMTP server with `curl` if
+//! `--smtp-url` is passed.
+
+use crate::curl;
+use crate::params::{MailOn, Params};
+use crate::report::Report;
+use crate::tail;
+use anyhow::{bail, Context};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::mem;
+use std::path::Path;
+use std::process::{self, Stdio};
+use std::time::Duration;
+
+/// How long to wait for the SMTP server.
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The longest an encoded word in a header may be (see RFC 2047).
+const MAX_ENCODED_WORD: usize = 75;
+
+/// The start of an encoded word in a header.
+const ENCODED_WORD_START: &str = "=?utf-8?q?";
+
+/// The end of an encoded word in a header.
+const ENCODED_WORD_END: &str = "?=";
+
+/// The most encoded text that fits in an encoded word.
+const MAX_ENCODED_TEXT: usize =
+    MAX_ENCODED_WORD - ENCODED_WORD_START.len() - ENCODED_WORD_END.len();
+
+/// How the mail is sent.
+#[derive(Clone, Copy, Debug)]
+enum Transport<'a> {
+    /// Run sendmail at this path.
+    Sendmail(&'a Path),
+
+    /// Send through the SMTP server at this URL.
+    Smtp(&'a str),
+}
+
+/// Sends mail about runs.
+#[derive(Debug)]
+pub struct Mail<'a> {
+    /// Who to send mail to.
+    to: &'a [String],
+
+    /// Who the mail is from, if not the default.
+    from: Option<&'a str>,
+
+    /// When to send mail.
+    on: MailOn,
+
+    /// How to send mail.
+    transport: Transport<'a>,
+}
+
+impl<'a> Mail<'a> {
+    /// Get the mailer configured by `params`, if any.
+    pub fn new(params: &'a Params) -> Option<Self> {
+        if params.mail_to.is_empty() {
+            return None;
+        }
+        Some(Self {
+            to: &params.mail_to,
+            from: params.mail_from.as_deref(),
+            on: params.mail_on,
+            transport: params.smtp_url.as_deref().map_or(
+                Transport::Sendmail(&params.sendmail_path),
+                Transport::Smtp,
+            ),
+        })
+    }
+
+    /// Should mail be sent about `report`? `previous` is whether the last
+    /// run succeeded, if it’s known.
+    pub const fn wanted(
+        &self,
+        report: &Report,
+        previous: Option<bool>,
+    ) -> bool {
+        let succeeded = report.succeeded();
+        match (self.on, previous) {
+            (MailOn::Always, _) => true,
+            (MailOn::Change, Some(previous)) => previous != succeeded,
+            (MailOn::Failure | MailOn::Change, _) => !succeeded,
+        }
+    }
+
+    /// Send mail about `report`.
+    pub fn send(&self, report: &Report) -> anyhow::Result<()> {
+        let from = self
+            .from
+            .map_or_else(|| format!("rederr@{}", report.host), str::to_owned);
+        let message = message(&from, self.to, report);
+        match self.transport {
+            Transport::Sendmail(path) => sendmail(path, message.as_bytes())
+                .with_context(|| {
+                    format!("sending mail with {}", path.display())
+                }),
+            Transport::Smtp(url) => curl::send_mail(
+                url,
+                &from,
+                self.to,
+                message.as_bytes(),
+                SMTP_TIMEOUT,
+            )
+            .context("sending mail with --smtp-url"),
+        }
+    }
+}
+
+/// Compose the message about `report`, including its headers.
+fn message(from: &str, to: &[String], report: &Report) -> String {
+    let mut message = String::new();
+    // Writing to a String can’t fail.
+    let _ = write!(
+        message,
+        "From: {from}\nTo: {}\nSubject: {}\nMIME-Version: 1.0\n\
+        Content-Type: text/plain; charset=utf-8\n\
+        Content-Transfer-Encoding: 8bit\nAuto-Submitted: auto-generated\n\n",
+        to.join(", "),
+        encode_header(&format!(
+            "{}: {} {}",
+            report.host,
+            report.command,
+            report.outcome()
+        )),
+    );
+    let _ = writeln!(
+        message,
+        "{} {} after {}.",
+        report.command,
+        report.outcome(),
+        report.variable("duration").unwrap_or_default(),
+    );
+    if report.truncated {
+        let _ = writeln!(
+            message,
+            "Only the last {} KiB of output are included.",
+            tail::MAX_SIZE_KIB
+        );
+    }
+    if !report.output.is_empty() {
+        message.push('\n');
+        message.push_str(&report.output);
+        if !report.output.ends_with('\n') {
+            message.push('\n');
+        }
+    }
+    message
+}
+
+/// Encode a header value so that it only contains printable ASCII, as
+/// described in RFC 2047.
+///
+/// Values that are already printable ASCII are left alone. Otherwise, the
+/// value is split into encoded words of at most [`MAX_ENCODED_WORD`]
+/// characters, each on its own folded line.
+fn encode_header(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    if value.is_ascii() {
+        return value;
+    }
+
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut encoded = String::new();
+    for c in value.chars() {
+        encoded.clear();
+        let mut bytes = [0; 4];
+        for byte in c.encode_utf8(&mut bytes).bytes() {
+            match byte {
+                b' ' => encoded.push('_'),
+                b'=' | b'?' | b'_' | 0x80.. => {
+                    let _ = write!(encoded, "={byte:02X}");
+                }
+                _ => encoded.push(char::from(byte)),
+            }
+        }
+        // Characters can’t be split between words.
+        if word.len().saturating_add(encoded.len()) > MAX_ENCODED_TEXT {
+            words.push(mem::take(&mut word));
+        }
+        word.push_str(&encoded);
+    }
+    words.push(word);
+
+    words
+        .iter()
+        .map(|word| format!("{ENCODED_WORD_START}{word}{ENCODED_WORD_END}"))
+        .collect::<Vec<_>>()
+        .join("\n ")
+}
+
+/// Send `message` with the sendmail at `path`, taking the recipients from its
+/// headers.
+fn sendmail(path: &Path, message: &[u8]) -> anyhow::Result<()> {
+    let mut child = process::Command::new(path)
+        .args(["-t", "-oi"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // If this fails, sendmail failed, which is checked below.
+        let _ = stdin.write_all(message);
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("sendmail failed: {status}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    /// Get a report for a run.
+    fn report(exit_code: i32, output: &str) -> Report<'static> {
+        Report {
+            command: "backup --full",
+            host: "web1".to_owned(),
+            exit_code,
+            signal: None,
+            timed_out: false,
+            duration: Duration::from_secs(3),
+            output: output.to_owned(),
+            truncated: false,
+        }
+    }
+
+    /// Get a mailer that sends mail `on` the given runs.
+    fn mail(on: MailOn) -> Mail<'static> {
+        Mail {
+            to: &[],
+            from: None,
+            on,
+            transport: Transport::Sendmail(Path::new("sendmail")),
+        }
+    }
+
+    #[test]
+    fn wanted() {
+        let (success, failure) = (report(0, ""), report(1, ""));
+        let failures = mail(MailOn::Failure);
+        check!(!failures.wanted(&success, None));
+        check!(failures.wanted(&failure, Some(false)));
+        let always = mail(MailOn::Always);
+        check!(always.wanted(&success, Some(true)));
+        let changes = mail(MailOn::Change);
+        check!(!changes.wanted(&success, None));
+        check!(changes.wanted(&failure, None));
+        check!(changes.wanted(&success, Some(false)));
+        check!(!changes.wanted(&failure, Some(false)));
+    }
+
+    #[test]
+    fn compose() {
+        let to = ["a@example.com".to_owned(), "b@example.com".to_owned()];
+        check!(
+            message("rederr@web1", &to, &report(2, "no space\n"))
+                == "From: rederr@web1\n\
+                To: a@example.com, b@example.com\n\
+                Subject: web1: backup --full failed with exit code 2\n\
+                MIME-Version: 1.0\n\
+                Content-Type: text/plain; charset=utf-8\n\
+                Content-Transfer-Encoding: 8bit\n\
+                Auto-Submitted: auto-generated\n\
+                \n\
+                backup --full failed with exit code 2 after 3s.\n\
+                \n\
+                no space\n"
+        );
+    }
+
+    #[test]
+    fn compose_truncated() {
+        let mut report = report(1, "x");
+        report.truncated = true;
+        check!(message("f", &[], &report).ends_with(
+            "after 3s.\nOnly the last 10 KiB of output are included.\n\nx\n"
+        ));
+    }
+
+    #[test]
+    fn header_encoding() {
+        check!(encode_header("plain\ttext") == "plain text");
+        check!(
+            encode_header("café = _?") == "=?utf-8?q?caf=C3=A9_=3D_=5F=3F?="
+        );
+    }
+
+    #[test]
+    fn header_encoding_long() {
+        let value = format!("{}é", "a".repeat(62));
+        let encoded = encode_header(&value);
+        check!(
+            encoded
+                == format!(
+                    "=?utf-8?q?{}?=\n =?utf-8?q?=C3=A9?=",
+                    "a".repeat(62)
+                )
+        );
+
+        let encoded = encode_header(&"é".repeat(100));
+        for word in encoded.split("\n ") {
+            check!(word.len() <= MAX_ENCODED_WORD);
+            check!(word.starts_with("=?utf-8?q?=C3=A9"));
+            check!(word.ends_with("=C3=A9?="));
+        }
+        check!(encoded.matches("=C3=A9").count() == 100);
+    }
+}
@@ -0,0 +1,153 @@
+//! Generate a man page for `rederr`.
+//!
+//! Most of the page is generated from [`Params`], so it stays in sync with
+//! `--help`. The sections that clap doesn’t know about are defined here.
+
+use crate::params::Params;
+use clap::CommandFactory;
+use clap_mangen::roff::{bold, italic, roman, Roff};
+use clap_mangen::Man;
+use std::io::{self, Write};
+
+/// Exit codes `rederr` may return, and what they mean.
+const EXIT_STATUS: &[(&str, &str)] = &[
+    ("0", "The command exited successfully."),
+    (
+        "124",
+        "A timeout expired. This can be changed with --timeout-exit-code.",
+    ),
+    ("125", "rederr encountered an error."),
+    ("126", "The command was found but could not be executed."),
+    ("127", "The command could not be found."),
+    (
+        "128+N",
+        "The command was killed by signal N, e.g. 143 for SIGTERM.",
+    ),
+    ("other", "The command exited with that code."),
+];
+
+/// Signals `rederr` handles specially, and what it does with them.
+const SIGNALS: &[(&str, &str)] = &[
+    (
+        "SIGHUP, SIGINT, SIGQUIT, SIGTERM",
+        "Forwarded to the command. rederr exits when the command does. If \
+        --kill-after was passed, the command is killed with SIGKILL if it \
+        doesn’t exit in time.",
+    ),
+    (
+        "SIGCHLD",
+        "Used to notice when the command stops, continues, or exits.",
+    ),
+];
+
+/// Environment variables that affect `rederr`.
+const ENVIRONMENT: &[(&str, &str)] = &[
+    (
+        "CLICOLOR_FORCE",
+        "If set to anything other than \"0\", output color even when not \
+        outputting to a terminal, unless --color=never is passed or NO_COLOR \
+        is set.",
+    ),
+    (
+        "COLUMNS, LINES",
+        "The size of the pseudo-terminals created by --pty, if rederr isn’t \
+        running in a terminal.",
+    ),
+    (
+        "NO_COLOR",
+        "If set, don’t output color unless --color=always is passed.",
+    ),
+    (
+        "NOTIFY_SOCKET, WATCHDOG_USEC, WATCHDOG_PID",
+        "Set by systemd for services. With --sd-notify, rederr sends \
+        notifications to the socket and pings the watchdog, and removes these \
+        from the command’s environment.",
+    ),
+    (
+        "REDERR_*",
+        "Default values for options, e.g. REDERR_IDLE_TIMEOUT=10m for \
+        --idle-timeout=10m. Flags are on unless the variable is empty or a \
+        value like \"0\", \"no\", or \"false\". Options on the command line \
+        override these, and these override the configuration file.",
+    ),
+    (
+        "REDERR_LIBSTDBUF",
+        "Path to libstdbuf.so from coreutils, used by --unbuffer. By default \
+        rederr looks for it in common locations. If it isn’t found, \
+        --unbuffer uses --pty instead.",
+    ),
+    (
+        "TERM",
+        "If unset or set to \"dumb\", don’t output color unless \
+        --color=always is passed.",
+    ),
+    (
+        "TZDIR",
+        "Where to find the time zone database for --tz. Defaults to \
+        /usr/share/zoneinfo.",
+    ),
+    (
+        "XDG_CONFIG_HOME",
+        "Where to find the configuration file, rederr/config.toml, unless \
+        --config is passed. Defaults to ~/.config.",
+    ),
+];
+
+/// Write the man page as roff to `out`.
+pub fn render(out: &mut dyn Write) -> io::Result<()> {
+    let man = Man::new(Params::command());
+    man.render_title(out)?;
+    man.render_name_section(out)?;
+    man.render_synopsis_section(out)?;
+    man.render_description_section(out)?;
+    man.render_options_section(out)?;
+    man.render_subcommands_section(out)?;
+
+    let mut roff = Roff::new();
+    roff.control("SH", ["DURATIONS"]);
+    roff.text([
+        roman("Options that take a "),
+        italic("DURATION"),
+        roman(" accept a plain number of seconds, e.g. "),
+        bold("30"),
+        roman(", or a number with a unit, e.g. "),
+        bold("30ms"),
+        roman(", "),
+        bold("5m"),
+        roman(", or "),
+        bold("2h"),
+        roman(". Multiple values are added together, so "),
+        bold("\"1m 30s\""),
+        roman(
+            " is 90 seconds. Durations may not be negative or more precise \
+            than a millisecond.",
+        ),
+    ]);
+    roff.text([roman(
+        "Timeouts are measured with a monotonic clock, so they aren’t \
+        affected by changes to the system clock, and time the system spends \
+        suspended doesn’t count.",
+    )]);
+
+    roff.control("SH", ["EXIT STATUS"]);
+    render_table(&mut roff, EXIT_STATUS);
+
+    roff.control("SH", ["SIGNALS"]);
+    render_table(&mut roff, SIGNALS);
+
+    roff.control("SH", ["ENVIRONMENT"]);
+    render_table(&mut roff, ENVIRONMENT);
+    roff.to_writer(out)?;
+
+    man.render_version_section(out)?;
+    man.render_authors_section(out)
+}
+
+/// Render a list of terms and their descriptions.
+fn render_table(roff: &mut Roff, table: &[(&str, &str)]) {
+    for (term, description) in table {
+        roff.control("TP", []);
+        roff.text([bold(*term)]);
+        roff.text([roman(*description)]);
+    }
+}
@@ -0,0 +1,41 @@
+//! Make the child more or less likely to be killed when the system runs out of
+//! memory (see `--oom-score-adj`).
+//!
+//! The adjustment is written to `/proc/PID/oom_score_adj` as soon as the child
+//! is started. It’s inherited by processes the child starts, and survives
+//! `exec()`, so it also applies to the command when it’s started through a
+//! wrapper like `sh`. The command runs with the default adjustment for the
+//! moment before it’s written, which is too short for it to use much memory.
+//!
+//! Raising the adjustment is always allowed for our own child, but lowering it
+//! below what it started with requires `CAP_SYS_RESOURCE`.
+
+use std::process;
+
+/// Set the OOM score adjustment of `child` to `score`, from -1000 to 1000.
+///
+/// If that fails, `child` is killed so that it doesn’t run without it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn adjust(child: &mut process::Child, score: i16) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use std::fs;
+    use std::path::PathBuf;
+
+    let path = PathBuf::from(format!("/proc/{}/oom_score_adj", child.id()));
+    fs::write(&path, format!("{score}\n"))
+        .with_context(|| format!("--oom-score-adj: writing {}", path.display()))
+        .inspect_err(|_| kill(child))
+}
+
+/// `oom_score_adj` is only available on Linux.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn adjust(child: &mut process::Child, _score: i16) -> anyhow::Result<()> {
+    kill(child);
+    anyhow::bail!("--oom-score-adj is only supported on Linux")
+}
+
+/// Kill `child` and wait for it, ignoring errors.
+fn kill(child: &mut process::Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
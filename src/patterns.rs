@@ -0,0 +1,264 @@
+//! Watch the child’s output for patterns (see `--fail-pattern`,
+//! `--success-pattern`, `--kill-on-pattern`, and `--ready-pattern`).
+//!
+//! Lots of tools exit with 0 even when they report errors. This watches each
+//! line of output from both streams, including lines dropped by `--grep` and
+//! `--exclude`, and remembers which patterns matched during the run.
+
+use crate::line_buffer;
+use regex::bytes::Regex;
+
+/// Watches the child’s output for patterns.
+#[derive(Clone, Debug)]
+pub struct Patterns {
+    /// The run fails if any line matches one of these.
+    fail: Vec<Regex>,
+
+    /// The run fails unless some line matches one of these, if it isn’t
+    /// empty.
+    success: Vec<Regex>,
+
+    /// The child is terminated if any line matches one of these.
+    kill: Trigger,
+
+    /// The child is ready if any line matches one of these.
+    ready: Trigger,
+
+    /// The first pattern in `fail` that matched during this run.
+    failed: Option<usize>,
+
+    /// Whether a pattern in `success` matched during this run.
+    succeeded: bool,
+}
+
+/// Patterns that trigger something the first time one of them matches in a
+/// run.
+#[derive(Clone, Debug, Default)]
+struct Trigger {
+    /// The patterns.
+    regexes: Vec<Regex>,
+
+    /// The first pattern that matched during this run.
+    matched: Option<usize>,
+
+    /// Whether `matched` has been taken with [`Trigger::take()`].
+    taken: bool,
+}
+
+impl Patterns {
+    /// Start watching for patterns, or return `None` if there are none.
+    pub fn new(
+        fail: &[Regex],
+        success: &[Regex],
+        kill: &[Regex],
+        ready: &[Regex],
+    ) -> Option<Self> {
+        if fail.is_empty()
+            && success.is_empty()
+            && kill.is_empty()
+            && ready.is_empty()
+        {
+            return None;
+        }
+        Some(Self {
+            fail: fail.to_vec(),
+            success: success.to_vec(),
+            kill: Trigger::new(kill),
+            ready: Trigger::new(ready),
+            failed: None,
+            succeeded: false,
+        })
+    }
+
+    /// Check the complete lines in `bytes`.
+    pub fn check(&mut self, bytes: &[u8]) {
+        for line in bytes.split_inclusive(|&b| b == b'\n') {
+            let line = line_buffer::strip_ending(line);
+            if self.failed.is_none() {
+                self.failed = self.fail.iter().position(|re| re.is_match(line));
+            }
+            if !self.succeeded {
+                self.succeeded =
+                    self.success.iter().any(|re| re.is_match(line));
+            }
+            self.kill.check(line);
+            self.ready.check(line);
+        }
+    }
+
+    /// Get the `--kill-on-pattern` that matched, if one did. This only returns
+    /// it once per run.
+    pub fn take_kill(&mut self) -> Option<&str> {
+        self.kill.take()
+    }
+
+    /// Get the `--ready-pattern` that matched, if one did. This only returns
+    /// it once per run.
+    pub fn take_ready(&mut self) -> Option<&str> {
+        self.ready.take()
+    }
+
+    /// Get why the run failed, if it did, and start watching a new run.
+    pub fn finish(&mut self) -> Option<String> {
+        let reason = if let Some(index) = self.failed {
+            Some(format!(
+                "Output matched --fail-pattern {:?}",
+                self.fail[index].as_str(),
+            ))
+        } else if !self.success.is_empty() && !self.succeeded {
+            Some(if let [pattern] = self.success.as_slice() {
+                format!(
+                    "Output did not match --success-pattern {:?}",
+                    pattern.as_str(),
+                )
+            } else {
+                "Output did not match any --success-pattern".to_owned()
+            })
+        } else {
+            None
+        };
+
+        self.failed = None;
+        self.succeeded = false;
+        self.kill.reset();
+        self.ready.reset();
+        reason
+    }
+}
+
+impl Trigger {
+    /// Create a trigger for `regexes`.
+    fn new(regexes: &[Regex]) -> Self {
+        Self {
+            regexes: regexes.to_vec(),
+            ..Self::default()
+        }
+    }
+
+    /// Check a line without its line ending.
+    fn check(&mut self, line: &[u8]) {
+        if self.matched.is_none() {
+            self.matched = self.regexes.iter().position(|re| re.is_match(line));
+        }
+    }
+
+    /// Get the pattern that matched, if one did and it hasn’t been taken yet.
+    fn take(&mut self) -> Option<&str> {
+        let index = self.matched.filter(|_| !self.taken)?;
+        self.taken = true;
+        Some(self.regexes[index].as_str())
+    }
+
+    /// Start watching a new run.
+    const fn reset(&mut self) {
+        self.matched = None;
+        self.taken = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::{check, let_assert};
+
+    /// Compile `patterns`.
+    fn regexes(patterns: &[&str]) -> Vec<Regex> {
+        patterns.iter().map(|p| Regex::new(p).unwrap()).collect()
+    }
+
+    /// Watch for `fail` and `success` patterns.
+    fn patterns(fail: &[&str], success: &[&str]) -> Patterns {
+        let_assert!(
+            Some(patterns) =
+                Patterns::new(&regexes(fail), &regexes(success), &[], &[])
+        );
+        patterns
+    }
+
+    #[test]
+    fn no_patterns() {
+        check!(Patterns::new(&[], &[], &[], &[]).is_none());
+    }
+
+    #[test]
+    fn fail_pattern() {
+        let mut patterns = patterns(&["WARN", "^ERROR$"], &[]);
+        patterns.check(b"fine\nERROR\r\n");
+        patterns.check(b"WARN\n");
+        check!(
+            patterns.finish().as_deref()
+                == Some(r#"Output matched --fail-pattern "^ERROR$""#)
+        );
+        check!(patterns.finish() == None);
+    }
+
+    #[test]
+    fn success_pattern() {
+        let mut patterns = patterns(&[], &["^done$"]);
+        patterns.check(b"not done\n");
+        check!(
+            patterns.finish().as_deref()
+                == Some(r#"Output did not match --success-pattern "^done$""#)
+        );
+
+        patterns.check(b"working\ndone\n");
+        check!(patterns.finish() == None);
+    }
+
+    #[test]
+    fn success_patterns() {
+        let mut patterns = patterns(&[], &["a", "b"]);
+        check!(
+            patterns.finish().as_deref()
+                == Some("Output did not match any --success-pattern")
+        );
+        patterns.check(b"b");
+        check!(patterns.finish() == None);
+    }
+
+    #[test]
+    fn fail_wins() {
+        let mut patterns = patterns(&["bad"], &["good"]);
+        patterns.check(b"good\nbad\n");
+        check!(
+            patterns.finish().as_deref()
+                == Some(r#"Output matched --fail-pattern "bad""#)
+        );
+    }
+
+    #[test]
+    fn kill_pattern() {
+        let_assert!(
+            Some(mut patterns) = Patterns::new(
+                &[],
+                &[],
+                &regexes(&["deadlock", "OutOfMemory"]),
+                &[]
+            )
+        );
+        patterns.check(b"fine\n");
+        check!(patterns.take_kill() == None);
+
+        patterns.check(b"OutOfMemoryError\ndeadlock detected\n");
+        check!(patterns.take_kill() == Some("OutOfMemory"));
+        check!(patterns.take_kill() == None);
+        check!(patterns.finish() == None);
+
+        patterns.check(b"deadlock detected\n");
+        check!(patterns.take_kill() == Some("deadlock"));
+    }
+
+    #[test]
+    fn ready_pattern() {
+        let_assert!(
+            Some(mut patterns) =
+                Patterns::new(&[], &[], &[], &regexes(&["listening on"]))
+        );
+        patterns.check(b"starting\n");
+        check!(patterns.take_ready() == None);
+        patterns.check(b"listening on :8080\n");
+        check!(patterns.take_kill() == None);
+        check!(patterns.take_ready() == Some("listening on"));
+        check!(patterns.take_ready() == None);
+    }
+}
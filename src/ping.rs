@@ -0,0 +1,95 @@
+//! Ping a dead man’s switch monitoring service like Healthchecks.io (see
+//! `--ping-url`).
+//!
+//! "/start" is added to the URL when the command starts. When `rederr` exits,
+//! the URL is requested as is if the command succeeded, or with "/fail" added
+//! if it failed or timed out. The failure includes the end of the output, so
+//! that it shows up in the service’s alert.
+
+use crate::curl::Request;
+use crate::params::Params;
+use anyhow::Context;
+use std::time::Duration;
+
+/// Pings a monitoring service.
+#[derive(Clone, Copy, Debug)]
+pub struct Ping<'a> {
+    /// The URL to ping.
+    url: &'a str,
+
+    /// How long to wait for each request.
+    timeout: Duration,
+
+    /// How many times to retry each request.
+    retries: u32,
+}
+
+impl<'a> Ping<'a> {
+    /// Get the pinger configured by `params`, if any.
+    pub fn new(params: &'a Params) -> Option<Self> {
+        params.ping_url.as_deref().map(|url| Self {
+            url,
+            timeout: params.ping_timeout,
+            retries: params.ping_retries,
+        })
+    }
+
+    /// Report that the command is starting.
+    pub fn start(&self) -> anyhow::Result<()> {
+        self.send("/start", None)
+    }
+
+    /// Report that the command succeeded.
+    pub fn success(&self) -> anyhow::Result<()> {
+        self.send("", None)
+    }
+
+    /// Report that the command failed, with the end of its `output`.
+    pub fn fail(&self, output: &[u8]) -> anyhow::Result<()> {
+        self.send("/fail", Some(output))
+    }
+
+    /// Request the URL with `suffix` added to its path.
+    fn send(&self, suffix: &str, body: Option<&[u8]>) -> anyhow::Result<()> {
+        let url = endpoint(self.url, suffix);
+        Request {
+            url: &url,
+            body: body.map(|body| ("text/plain; charset=utf-8", body)),
+            timeout: self.timeout,
+            retries: self.retries,
+        }
+        .send()
+        .with_context(|| format!("pinging {url}"))
+    }
+}
+
+/// Add `suffix` to the path of `url`, before the query string if there is
+/// one.
+fn endpoint(url: &str, suffix: &str) -> String {
+    let (path, query) = url
+        .split_once('?')
+        .map_or((url, None), |(path, query)| (path, Some(query)));
+    let path = path.trim_end_matches('/');
+    match query {
+        Some(query) => format!("{path}{suffix}?{query}"),
+        None => format!("{path}{suffix}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    #[test]
+    fn endpoints() {
+        let url = "https://hc-ping.com/1234";
+        check!(endpoint(url, "") == url);
+        check!(endpoint(url, "/start") == "https://hc-ping.com/1234/start");
+        check!(endpoint("https://x/1234/", "/fail") == "https://x/1234/fail");
+        check!(
+            endpoint("https://x/1?rid=a/b", "/fail")
+                == "https://x/1/fail?rid=a/b"
+        );
+    }
+}
@@ -0,0 +1,682 @@
+//! Wait for file descriptors to become ready.
+//!
+//! [`Poller`] abstracts over the system calls we can use to wait for the
+//! child’s output, our signal pipe, and stdin forwarding. [`new()`] picks the
+//! best one available at runtime: epoll on Linux, kqueue on macOS and the BSDs,
+//! or `poll()` (through `popol`) everywhere else and if the others fail.
+//!
+//! `poll()` and `epoll_wait()` take a timeout in milliseconds as an `int`, so
+//! they can’t wait longer than about 24.8 days at a time. kqueue takes a
+//! `timespec`, but macOS rejects timeouts over 10⁸ seconds. Each backend caps
+//! the timeout itself, so [`Poller::poll()`] may return early with no events;
+//! callers should check their own timeouts and poll again.
+//!
+//! `poll()` always reports regular files as ready, but epoll refuses to watch
+//! them and kqueue only reports them as ready before EOF. Those backends
+//! report regular files, and anything else they can’t watch, as ready on every
+//! call, just like `poll()`.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::os::fd::BorrowedFd;
+use std::os::unix::fs::FileTypeExt;
+use std::time::Duration;
+
+/// What to wait for on a file descriptor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interest {
+    /// Wait for it to be readable.
+    Read,
+
+    /// Wait for it to be writable.
+    Write,
+}
+
+/// A file descriptor that is ready.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event<K> {
+    /// The key the file descriptor was registered with.
+    pub key: K,
+
+    /// Whether it can be read without blocking.
+    readable: bool,
+
+    /// Whether it can be written without blocking.
+    writable: bool,
+
+    /// Whether the other end was closed.
+    hangup: bool,
+}
+
+impl<K> Event<K> {
+    /// Can the file descriptor be read without blocking?
+    #[must_use]
+    pub const fn is_readable(&self) -> bool {
+        self.readable
+    }
+
+    /// Can the file descriptor be written without blocking?
+    #[must_use]
+    pub const fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    /// Was the other end closed?
+    ///
+    /// There may still be data to read.
+    #[must_use]
+    pub const fn is_hangup(&self) -> bool {
+        self.hangup
+    }
+}
+
+/// Waits for file descriptors identified by keys of type `K` to become ready.
+pub trait Poller<K>: fmt::Debug + Send {
+    /// The name of the system call used, for debugging.
+    fn name(&self) -> &'static str;
+
+    /// Start watching `fd` for `interest`, identified by `key`.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if `fd` couldn’t be watched.
+    fn register(
+        &mut self,
+        key: K,
+        fd: BorrowedFd<'_>,
+        interest: Interest,
+    ) -> io::Result<()>;
+
+    /// Stop watching the file descriptor registered with `key`, if there is
+    /// one.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if the file descriptor couldn’t be removed.
+    fn unregister(&mut self, key: &K) -> io::Result<()>;
+
+    /// Wait up to `timeout` (or forever, if it’s `None`) for registered file
+    /// descriptors to become ready, add them to `events`, and return how many
+    /// there were.
+    ///
+    /// This returns `Ok(0)` if the timeout expired, which may be earlier than
+    /// requested (see the [module documentation](self)).
+    ///
+    /// # Errors
+    ///
+    /// This returns an error of kind [`io::ErrorKind::Interrupted`] if a
+    /// signal was received, or another error if the wait failed.
+    fn poll(
+        &mut self,
+        events: &mut VecDeque<Event<K>>,
+        timeout: Option<Duration>,
+    ) -> io::Result<usize>;
+}
+
+/// Get the best [`Poller`] available.
+#[must_use]
+pub fn new<K>() -> Box<dyn Poller<K>>
+where
+    K: Clone + PartialEq + Eq + fmt::Debug + Send + 'static,
+{
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let Ok(epoll) = Epoll::new() {
+        return Box::new(epoll);
+    }
+
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd",
+    ))]
+    if let Ok(kqueue) = Kqueue::new() {
+        return Box::new(kqueue);
+    }
+
+    Box::new(Popol::new())
+}
+
+/// Can `fd` ever block? Pipes, sockets, and terminals can; regular files
+/// and such are always ready.
+fn can_block(fd: BorrowedFd<'_>) -> io::Result<bool> {
+    let file_type =
+        File::from(fd.try_clone_to_owned()?).metadata()?.file_type();
+    Ok(file_type.is_fifo()
+        || file_type.is_socket()
+        || file_type.is_char_device())
+}
+
+/// An event for a file descriptor that is always ready.
+fn always_ready<K: Clone>(key: &K, interest: Interest) -> Event<K> {
+    Event {
+        key: key.clone(),
+        readable: interest == Interest::Read,
+        writable: interest == Interest::Write,
+        hangup: false,
+    }
+}
+
+/// Uses `poll()` through `popol`. This works everywhere.
+#[derive(Debug)]
+pub struct Popol<K> {
+    /// The file descriptors to poll.
+    sources: popol::Sources<K>,
+
+    /// Buffer for events from `popol`.
+    events: Vec<popol::Event<K>>,
+}
+
+impl<K> Popol<K> {
+    /// Longest timeout `poll()` accepts.
+    const MAX_TIMEOUT: Duration = Duration::from_millis(i32::MAX as u64);
+
+    /// Create a poller with nothing registered.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            sources: popol::Sources::with_capacity(4),
+            events: Vec::with_capacity(4),
+        }
+    }
+}
+
+impl<K> Default for Popol<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> Poller<K> for Popol<K>
+where
+    K: Clone + PartialEq + Eq + fmt::Debug + Send,
+{
+    fn name(&self) -> &'static str {
+        "poll"
+    }
+
+    fn register(
+        &mut self,
+        key: K,
+        fd: BorrowedFd<'_>,
+        interest: Interest,
+    ) -> io::Result<()> {
+        let interest = match interest {
+            Interest::Read => popol::interest::READ,
+            Interest::Write => popol::interest::WRITE,
+        };
+        self.sources.register(key, &fd, interest);
+        Ok(())
+    }
+
+    fn unregister(&mut self, key: &K) -> io::Result<()> {
+        self.sources.unregister(key);
+        Ok(())
+    }
+
+    fn poll(
+        &mut self,
+        events: &mut VecDeque<Event<K>>,
+        timeout: Option<Duration>,
+    ) -> io::Result<usize> {
+        let timeout =
+            timeout.unwrap_or(Self::MAX_TIMEOUT).min(Self::MAX_TIMEOUT);
+        match self.sources.poll(&mut self.events, timeout) {
+            Ok(_) => {}
+            Err(error) if error.kind() == io::ErrorKind::TimedOut => {}
+            Err(error) => return Err(error),
+        }
+
+        let count = self.events.len();
+        events.extend(self.events.drain(..).map(|event| Event {
+            readable: event.is_readable(),
+            writable: event.is_writable(),
+            hangup: event.is_hangup(),
+            key: event.key,
+        }));
+        Ok(count)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use epoll::Epoll;
+
+/// The epoll backend.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod epoll {
+    use super::{always_ready, can_block, Event, Interest, Poller};
+    use nix::errno::Errno;
+    use nix::sys::epoll::{
+        self, EpollCreateFlags, EpollEvent, EpollFlags, EpollTimeout,
+    };
+    use std::collections::VecDeque;
+    use std::fmt;
+    use std::io;
+    use std::os::fd::{BorrowedFd, OwnedFd};
+    use std::time::Duration;
+
+    /// Uses epoll. This is only available on Linux.
+    #[derive(Debug)]
+    pub struct Epoll<K> {
+        /// The epoll instance.
+        instance: epoll::Epoll,
+
+        /// Registered file descriptors. The index of each is passed to epoll
+        /// to identify it; removed ones leave a `None` to be reused.
+        ///
+        /// Each file descriptor is a duplicate, so that it can be removed from
+        /// epoll even if the original was closed.
+        sources: Vec<Option<(K, OwnedFd)>>,
+
+        /// File descriptors epoll can’t watch, which are always ready.
+        ready: Vec<(K, Interest)>,
+
+        /// Buffer for events from epoll.
+        buffer: Vec<EpollEvent>,
+    }
+
+    impl<K> Epoll<K> {
+        /// Create a poller with nothing registered.
+        ///
+        /// # Errors
+        ///
+        /// This returns an error if the epoll instance couldn’t be created.
+        pub fn new() -> io::Result<Self> {
+            Ok(Self {
+                instance: epoll::Epoll::new(EpollCreateFlags::EPOLL_CLOEXEC)?,
+                sources: Vec::with_capacity(4),
+                ready: Vec::new(),
+                buffer: vec![EpollEvent::empty(); 16],
+            })
+        }
+    }
+
+    impl<K> Poller<K> for Epoll<K>
+    where
+        K: Clone + PartialEq + Eq + fmt::Debug + Send,
+    {
+        fn name(&self) -> &'static str {
+            "epoll"
+        }
+
+        fn register(
+            &mut self,
+            key: K,
+            fd: BorrowedFd<'_>,
+            interest: Interest,
+        ) -> io::Result<()> {
+            if !can_block(fd)? {
+                self.ready.push((key, interest));
+                return Ok(());
+            }
+
+            let fd = fd.try_clone_to_owned()?;
+            let index = self
+                .sources
+                .iter()
+                .position(Option::is_none)
+                .unwrap_or(self.sources.len());
+            let flags = match interest {
+                Interest::Read => EpollFlags::EPOLLIN,
+                Interest::Write => EpollFlags::EPOLLOUT,
+            };
+            let data = u64::try_from(index).map_err(io::Error::other)?;
+            match self.instance.add(&fd, EpollEvent::new(flags, data)) {
+                Ok(()) => {}
+                Err(Errno::EPERM) => {
+                    // epoll doesn’t support this kind of file.
+                    self.ready.push((key, interest));
+                    return Ok(());
+                }
+                Err(error) => return Err(error.into()),
+            }
+
+            if index == self.sources.len() {
+                self.sources.push(Some((key, fd)));
+            } else {
+                self.sources[index] = Some((key, fd));
+            }
+            Ok(())
+        }
+
+        fn unregister(&mut self, key: &K) -> io::Result<()> {
+            self.ready.retain(|(ready, _)| ready != key);
+            let source = self.sources.iter_mut().find(|source| {
+                source.as_ref().is_some_and(|(source, _)| source == key)
+            });
+            if let Some((_, fd)) = source.and_then(Option::take) {
+                self.instance.delete(&fd)?;
+            }
+            Ok(())
+        }
+
+        fn poll(
+            &mut self,
+            events: &mut VecDeque<Event<K>>,
+            timeout: Option<Duration>,
+        ) -> io::Result<usize> {
+            let timeout = match timeout {
+                _ if !self.ready.is_empty() => EpollTimeout::ZERO,
+                Some(timeout) => {
+                    EpollTimeout::try_from(timeout).unwrap_or(EpollTimeout::MAX)
+                }
+                None => EpollTimeout::MAX,
+            };
+            let count = self.instance.wait(&mut self.buffer, timeout)?;
+
+            let before = events.len();
+            for event in &self.buffer[..count] {
+                let flags = event.events();
+                let source = usize::try_from(event.data())
+                    .ok()
+                    .and_then(|index| self.sources.get(index))
+                    .and_then(Option::as_ref);
+                if let Some((key, _)) = source {
+                    events.push_back(Event {
+                        key: key.clone(),
+                        readable: flags.intersects(
+                            EpollFlags::EPOLLIN | EpollFlags::EPOLLPRI,
+                        ),
+                        writable: flags.contains(EpollFlags::EPOLLOUT),
+                        hangup: flags.contains(EpollFlags::EPOLLHUP),
+                    });
+                }
+            }
+            events.extend(
+                self.ready
+                    .iter()
+                    .map(|(key, interest)| always_ready(key, *interest)),
+            );
+            Ok(events.len().saturating_sub(before))
+        }
+    }
+}
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+))]
+pub use kqueue::Kqueue;
+
+/// The kqueue backend.
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd",
+))]
+mod kqueue {
+    use super::{always_ready, can_block, Event, Interest, Poller};
+    use nix::errno::Errno;
+    use nix::sys::event::{self, EventFilter, EventFlag, FilterFlag, KEvent};
+    use nix::sys::time::TimeSpec;
+    use std::collections::VecDeque;
+    use std::fmt;
+    use std::io;
+    use std::os::fd::{AsRawFd, BorrowedFd, RawFd};
+    use std::time::Duration;
+
+    /// Uses kqueue. This is available on macOS and the BSDs.
+    #[derive(Debug)]
+    pub struct Kqueue<K> {
+        /// The kqueue instance.
+        instance: event::Kqueue,
+
+        /// Registered file descriptors and the filters they were registered
+        /// with.
+        sources: Vec<(K, RawFd, EventFilter)>,
+
+        /// File descriptors kqueue can’t watch, which are always ready.
+        ready: Vec<(K, Interest)>,
+
+        /// Buffer for events from kqueue.
+        buffer: Vec<KEvent>,
+    }
+
+    impl<K> Kqueue<K> {
+        /// Longest timeout macOS accepts.
+        const MAX_TIMEOUT: Duration = Duration::from_secs(100_000_000);
+
+        /// Create a poller with nothing registered.
+        ///
+        /// # Errors
+        ///
+        /// This returns an error if the kqueue couldn’t be created.
+        pub fn new() -> io::Result<Self> {
+            let empty = KEvent::new(
+                0,
+                EventFilter::EVFILT_READ,
+                EventFlag::empty(),
+                FilterFlag::empty(),
+                0,
+                0,
+            );
+            Ok(Self {
+                instance: event::Kqueue::new()?,
+                sources: Vec::with_capacity(4),
+                ready: Vec::new(),
+                buffer: vec![empty; 16],
+            })
+        }
+
+        /// Add or remove (depending on `flags`) `filter` for `fd`.
+        fn change(
+            &self,
+            fd: RawFd,
+            filter: EventFilter,
+            flags: EventFlag,
+        ) -> nix::Result<()> {
+            let ident = usize::try_from(fd).map_err(|_| Errno::EBADF)?;
+            let change =
+                KEvent::new(ident, filter, flags, FilterFlag::empty(), 0, 0);
+            let zero = *TimeSpec::from_duration(Duration::ZERO).as_ref();
+            self.instance.kevent(&[change], &mut [], Some(zero))?;
+            Ok(())
+        }
+    }
+
+    impl<K> Poller<K> for Kqueue<K>
+    where
+        K: Clone + PartialEq + Eq + fmt::Debug + Send,
+    {
+        fn name(&self) -> &'static str {
+            "kqueue"
+        }
+
+        fn register(
+            &mut self,
+            key: K,
+            fd: BorrowedFd<'_>,
+            interest: Interest,
+        ) -> io::Result<()> {
+            if !can_block(fd)? {
+                self.ready.push((key, interest));
+                return Ok(());
+            }
+
+            let filter = match interest {
+                Interest::Read => EventFilter::EVFILT_READ,
+                Interest::Write => EventFilter::EVFILT_WRITE,
+            };
+            let fd = fd.as_raw_fd();
+            match self.change(fd, filter, EventFlag::EV_ADD) {
+                Ok(()) => self.sources.push((key, fd, filter)),
+                Err(Errno::EINVAL | Errno::ENODEV | Errno::EPERM) => {
+                    // kqueue doesn’t support this kind of file.
+                    self.ready.push((key, interest));
+                }
+                Err(error) => return Err(error.into()),
+            }
+            Ok(())
+        }
+
+        fn unregister(&mut self, key: &K) -> io::Result<()> {
+            self.ready.retain(|(ready, _)| ready != key);
+            let Some(index) =
+                self.sources.iter().position(|(source, _, _)| source == key)
+            else {
+                return Ok(());
+            };
+            let (_, fd, filter) = self.sources.remove(index);
+            match self.change(fd, filter, EventFlag::EV_DELETE) {
+                // The file descriptor was already closed.
+                Ok(()) | Err(Errno::EBADF | Errno::ENOENT) => Ok(()),
+                Err(error) => Err(error.into()),
+            }
+        }
+
+        fn poll(
+            &mut self,
+            events: &mut VecDeque<Event<K>>,
+            timeout: Option<Duration>,
+        ) -> io::Result<usize> {
+            let timeout = match timeout {
+                _ if !self.ready.is_empty() => Some(Duration::ZERO),
+                Some(timeout) => Some(timeout.min(Self::MAX_TIMEOUT)),
+                None => None,
+            };
+            let timeout = timeout
+                .map(|timeout| *TimeSpec::from_duration(timeout).as_ref());
+            let count = self.instance.kevent(&[], &mut self.buffer, timeout)?;
+
+            let before = events.len();
+            for event in &self.buffer[..count] {
+                let Ok(filter) = event.filter() else {
+                    continue;
+                };
+                let source = self.sources.iter().find(|(_, fd, source)| {
+                    usize::try_from(*fd) == Ok(event.ident())
+                        && *source == filter
+                });
+                if let Some((key, _, _)) = source {
+                    events.push_back(Event {
+                        key: key.clone(),
+                        readable: filter == EventFilter::EVFILT_READ,
+                        writable: filter == EventFilter::EVFILT_WRITE,
+                        hangup: event.flags().contains(EventFlag::EV_EOF),
+                    });
+                }
+            }
+            events.extend(
+                self.ready
+                    .iter()
+                    .map(|(key, interest)| always_ready(key, *interest)),
+            );
+            Ok(events.len().saturating_sub(before))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::{check, let_assert};
+    use std::io::Write;
+    use std::os::fd::AsFd;
+    use std::os::unix::net::UnixStream;
+
+    /// Every backend available on this platform.
+    fn backends() -> Vec<Box<dyn Poller<u8>>> {
+        let mut backends: Vec<Box<dyn Poller<u8>>> =
+            vec![Box::new(Popol::new())];
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        backends.push(Box::new(Epoll::new().unwrap()));
+        #[cfg(any(
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd",
+            target_os = "dragonfly",
+            target_os = "netbsd",
+            target_os = "openbsd",
+        ))]
+        backends.push(Box::new(Kqueue::new().unwrap()));
+        backends
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn best_on_linux() {
+        check!(new::<u8>().name() == "epoll");
+    }
+
+    #[test]
+    fn timeout() {
+        for mut poller in backends() {
+            let (read, _write) = UnixStream::pair().unwrap();
+            poller.register(1, read.as_fd(), Interest::Read).unwrap();
+            let mut events = VecDeque::new();
+            let result = poller.poll(&mut events, Some(Duration::ZERO));
+            check!(result.ok() == Some(0), "{}", poller.name());
+            check!(events.is_empty(), "{}", poller.name());
+        }
+    }
+
+    #[test]
+    fn readable_and_hangup() {
+        for mut poller in backends() {
+            let (read, mut write) = UnixStream::pair().unwrap();
+            poller.register(1, read.as_fd(), Interest::Read).unwrap();
+            write.write_all(b"a").unwrap();
+            drop(write);
+
+            let mut events = VecDeque::new();
+            let result = poller.poll(&mut events, None);
+            check!(result.ok() == Some(1), "{}", poller.name());
+            let_assert!(Some(event) = events.pop_front());
+            check!(event.key == 1);
+            check!(event.is_readable(), "{}", poller.name());
+            check!(event.is_hangup(), "{}", poller.name());
+        }
+    }
+
+    #[test]
+    fn writable() {
+        for mut poller in backends() {
+            let (_read, write) = UnixStream::pair().unwrap();
+            poller.register(2, write.as_fd(), Interest::Write).unwrap();
+            let mut events = VecDeque::new();
+            check!(poller.poll(&mut events, None).ok() == Some(1));
+            let_assert!(Some(event) = events.pop_front());
+            check!(event.key == 2);
+            check!(event.is_writable(), "{}", poller.name());
+            check!(!event.is_readable(), "{}", poller.name());
+        }
+    }
+
+    #[test]
+    fn unregister() {
+        for mut poller in backends() {
+            let (read, mut write) = UnixStream::pair().unwrap();
+            poller.register(1, read.as_fd(), Interest::Read).unwrap();
+            poller.unregister(&1).unwrap();
+            poller.unregister(&1).unwrap();
+            write.write_all(b"a").unwrap();
+
+            let mut events = VecDeque::new();
+            poller.register(2, write.as_fd(), Interest::Write).unwrap();
+            check!(poller.poll(&mut events, None).ok() == Some(1));
+            check!(events.pop_front().map(|event| event.key) == Some(2));
+        }
+    }
+
+    #[test]
+    fn regular_file_always_ready() {
+        for mut poller in backends() {
+            let file = File::open("Cargo.toml").unwrap();
+            poller.register(3, file.as_fd(), Interest::Read).unwrap();
+            let mut events = VecDeque::new();
+            check!(poller.poll(&mut events, None).ok() == Some(1));
+            check!(events.pop_front().map(|event| event.key) == Some(3));
+        }
+    }
+}
@@ -0,0 +1,273 @@
+//! Change things about the child that can only be changed by the child itself
+//! (see `--cgroup`, `--parent-death-signal`, `--user`, `--group`, `--nice`,
+//! `--ionice`, and the `--limit-*` options).
+//!
+//! These are changed with calls like `prctl(PR_SET_PDEATHSIG)` and `setuid()`
+//! in the child between `fork()` and `exec()`. Everything that can fail in a
+//! way we can explain is looked up first, in the parent, so that the child
+//! only has to make the calls.
+//!
+//! The child joins the cgroup first, so that nothing it does happens outside
+//! of it. Limits and priorities are set before switching users, so that they
+//! can be raised as root. The parent death signal is set last, since changing
+//! the user or group clears it. If `rederr` died before it was set, the child
+//! signals itself.
+
+use crate::cgroup::Cgroup;
+use crate::limits::Limit;
+use crate::params::Params;
+use crate::priority::Priority;
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem;
+use std::process;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use {
+    crate::params::ReadyExit,
+    nix::sys::signal::Signal,
+    nix::unistd::{Gid, Pid, Uid},
+};
+
+/// How `ioprio_set()` is told to change the calling process.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const IOPRIO_WHO_PROCESS: i32 = 1;
+
+/// The user and groups to run the command as (see `--user` and `--group`).
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Debug)]
+struct Ids {
+    /// The user to switch to, if any.
+    uid: Option<Uid>,
+
+    /// The group to switch to.
+    gid: Gid,
+
+    /// The supplementary groups.
+    groups: Vec<Gid>,
+}
+
+/// What to change in the child before it execs the command.
+#[derive(Debug, Default)]
+pub struct PreExec {
+    /// The `cgroup.procs` file of the cgroup to join, if any.
+    cgroup: Option<File>,
+
+    /// The signal to send the child if `rederr` dies, and our PID.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    parent_death_signal: Option<(Signal, Pid)>,
+
+    /// Resource limits to set.
+    limits: Vec<Limit>,
+
+    /// Priorities to set.
+    priority: Priority,
+
+    /// The user and groups to run the command as, if they should change.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    ids: Option<Ids>,
+
+    /// Environment variables to set for the command.
+    env: Vec<(&'static str, String)>,
+}
+
+impl PreExec {
+    /// Figure out what to change in the child for `params`, and `cgroup` to
+    /// move it into, if any.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn new(
+        params: &Params,
+        cgroup: Option<&Cgroup>,
+    ) -> anyhow::Result<Self> {
+        use anyhow::bail;
+
+        let mut pre_exec = Self {
+            cgroup: cgroup.map(Cgroup::procs).transpose()?,
+            limits: crate::limits::limits(params),
+            priority: Priority::new(params),
+            ..Self::default()
+        };
+        if params.ready_exit != Some(ReadyExit::Leave) {
+            // The command is meant to outlive us with --ready-exit=leave.
+            pre_exec.parent_death_signal = params
+                .parent_death_signal
+                .map(|signal| (signal, Pid::this()));
+        }
+        if params.user.is_some() || params.group.is_some() {
+            if !Uid::effective().is_root() {
+                bail!("--user and --group require running rederr as root");
+            }
+            pre_exec.ids = Some(pre_exec.look_up_ids(params)?);
+        }
+        Ok(pre_exec)
+    }
+
+    /// Figure out what to change in the child for `params`, and `cgroup` to
+    /// move it into, if any. Only `--nice` is supported on other platforms.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn new(
+        params: &Params,
+        cgroup: Option<&Cgroup>,
+    ) -> anyhow::Result<Self> {
+        if params.limit_cpu.is_some()
+            || params.limit_mem.is_some()
+            || params.limit_fsize.is_some()
+            || params.limit_nofile.is_some()
+        {
+            anyhow::bail!("--limit-* options are only supported on Linux");
+        }
+        if params.parent_death_signal.is_some()
+            || params.user.is_some()
+            || params.group.is_some()
+        {
+            anyhow::bail!(
+                "--parent-death-signal, --user, and --group are only supported \
+                on Linux"
+            );
+        }
+        Ok(Self {
+            cgroup: cgroup.map(Cgroup::procs).transpose()?,
+            priority: Priority::new(params)?,
+            ..Self::default()
+        })
+    }
+
+    /// Look up the IDs for `--user` and `--group`, and add the environment
+    /// variables for the user.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn look_up_ids(&mut self, params: &Params) -> anyhow::Result<Ids> {
+        use anyhow::{anyhow, Context};
+        use nix::unistd::{getgrouplist, User};
+        use std::ffi::CString;
+
+        let group = params.group.as_deref().map(look_up_group).transpose()?;
+
+        let Some(name) = &params.user else {
+            return Ok(Ids {
+                uid: None,
+                gid: group.expect("--user or --group was passed"),
+                groups: Vec::new(),
+            });
+        };
+        let user = match name.parse() {
+            Ok(uid) => User::from_uid(Uid::from_raw(uid)),
+            Err(_) => User::from_name(name),
+        }
+        .with_context(|| format!("looking up user {name:?}"))?
+        .ok_or_else(|| anyhow!("no such user {name:?}"))?;
+
+        let gid = group.unwrap_or(user.gid);
+        let groups = CString::new(user.name.as_str())
+            .map_err(anyhow::Error::from)
+            .and_then(|name| Ok(getgrouplist(&name, gid)?))
+            .with_context(|| format!("looking up groups for {name:?}"))?;
+        self.env
+            .push(("HOME", user.dir.to_string_lossy().into_owned()));
+        self.env.push(("USER", user.name.clone()));
+        self.env.push(("LOGNAME", user.name));
+        Ok(Ids {
+            uid: Some(user.uid),
+            gid,
+            groups,
+        })
+    }
+
+    /// Is there nothing to change in the child?
+    const fn is_empty(&self) -> bool {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if self.parent_death_signal.is_some() || self.ids.is_some() {
+            return false;
+        }
+        self.cgroup.is_none()
+            && self.limits.is_empty()
+            && !self.priority.is_set()
+    }
+
+    /// Set up `command` to make the changes in the child.
+    #[allow(unsafe_code)] // The only way to run code between fork and exec.
+    pub fn configure(mut self, command: &mut process::Command) {
+        use nix::sys::resource::setrlimit;
+        use rustix::process::setpriority_process;
+        use std::os::unix::process::CommandExt;
+
+        command.envs(mem::take(&mut self.env));
+        if self.is_empty() {
+            return;
+        }
+
+        let hook = move || -> io::Result<()> {
+            if let Some(mut procs) = self.cgroup.as_ref() {
+                procs.write_all(b"0")?;
+            }
+            for &(resource, soft, hard) in &self.limits {
+                setrlimit(resource, soft, hard)?;
+            }
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            if let Some(priority) = self.priority.io {
+                // SAFETY: ioprio_set() only takes integers.
+                let result = unsafe {
+                    nix::libc::syscall(
+                        nix::libc::SYS_ioprio_set,
+                        IOPRIO_WHO_PROCESS,
+                        0,
+                        priority,
+                    )
+                };
+                if result == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(nice) = self.priority.nice {
+                setpriority_process(None, nice)?;
+            }
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            self.switch_ids_and_set_parent_death_signal()?;
+            Ok(())
+        };
+
+        // SAFETY: `hook` only makes system calls, which are safe to make
+        // between `fork()` and `exec()`. It doesn’t allocate or take locks.
+        unsafe {
+            command.pre_exec(hook);
+        }
+    }
+
+    /// Switch to the user and groups, then set the parent death signal, since
+    /// switching clears it. This runs in the child.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn switch_ids_and_set_parent_death_signal(&self) -> nix::Result<()> {
+        use nix::sys::{prctl, signal};
+        use nix::unistd::{getppid, setgid, setgroups, setuid};
+
+        if let Some(ids) = &self.ids {
+            setgroups(&ids.groups)?;
+            setgid(ids.gid)?;
+            if let Some(uid) = ids.uid {
+                setuid(uid)?;
+            }
+        }
+        if let Some((signal, parent)) = self.parent_death_signal {
+            prctl::set_pdeathsig(signal)?;
+            if getppid() != parent {
+                // We died before the parent death signal was set.
+                signal::raise(signal)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Look up the ID of a group by name or ID (see `--group`).
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn look_up_group(name: &str) -> anyhow::Result<Gid> {
+    use anyhow::{anyhow, Context};
+    use nix::unistd::Group;
+
+    if let Ok(gid) = name.parse() {
+        return Ok(Gid::from_raw(gid));
+    }
+    Group::from_name(name)
+        .with_context(|| format!("looking up group {name:?}"))?
+        .map(|group| group.gid)
+        .ok_or_else(|| anyhow!("no such group {name:?}"))
+}
@@ -0,0 +1,65 @@
+//! Change the child’s scheduling priority (see `--nice` and `--ionice`).
+//!
+//! These are set with `setpriority()` and `ioprio_set()` in the child before
+//! it execs the command (see [`crate::pre_exec`]).
+//!
+//! `ioprio_set()` is only available on Linux, so `--ionice` fails on other
+//! platforms.
+
+use crate::params::Params;
+
+/// The priorities to run the command with.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Priority {
+    /// The niceness to set (see `--nice`).
+    pub nice: Option<i32>,
+
+    /// The I/O priority to pass to `ioprio_set()` (see `--ionice`).
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub io: Option<i32>,
+}
+
+impl Priority {
+    /// Get the priorities set in `params`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn new(params: &Params) -> Self {
+        use crate::params::IoClass;
+
+        /// How far the class is shifted in an I/O priority.
+        const CLASS_SHIFT: u8 = 13;
+
+        Self {
+            nice: params.nice.map(i32::from),
+            io: params.ionice.map(|io| {
+                let (class, default_level) = match io.class {
+                    IoClass::Realtime => (1, 4),
+                    IoClass::BestEffort => (2, 4),
+                    IoClass::Idle => (3, 0),
+                };
+                (class << CLASS_SHIFT)
+                    | i32::from(io.level.unwrap_or(default_level))
+            }),
+        }
+    }
+
+    /// Get the priorities set in `params`. `--ionice` is only supported on
+    /// Linux.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn new(params: &Params) -> anyhow::Result<Self> {
+        if params.ionice.is_some() {
+            anyhow::bail!("--ionice is only supported on Linux");
+        }
+        Ok(Self {
+            nice: params.nice.map(i32::from),
+        })
+    }
+
+    /// Are there any priorities to set?
+    pub const fn is_set(&self) -> bool {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if self.io.is_some() {
+            return true;
+        }
+        self.nice.is_some()
+    }
+}
@@ -0,0 +1,89 @@
+//! Give the child pseudo-terminals for output (see `--pty`).
+//!
+//! Many programs only output color and progress information when their output
+//! is a terminal. With `--pty`, the child’s stdout and stderr are each
+//! connected to their own pseudo-terminal rather than a pipe, so the child
+//! thinks it’s writing to a terminal but we can still tell the streams apart.
+//!
+//! The terminals get the same window size as our terminal, or the size in the
+//! `COLUMNS` and `LINES` environment variables if we aren’t running in one.
+//! When our terminal is resized, we get `SIGWINCH`, and [`resize()`] copies
+//! the new size to the terminals.
+//!
+//! The child’s stdin is left alone, so the pseudo-terminals aren’t its
+//! controlling terminal and input isn’t forwarded. That also means the kernel
+//! doesn’t send the child `SIGWINCH` when they’re resized, so we forward ours.
+
+use anyhow::Context;
+use nix::pty::{openpty, OpenptyResult, Winsize};
+use nix::sys::termios::{tcgetattr, tcsetattr, OutputFlags, SetArg};
+use rustix::termios::{tcgetwinsize, tcsetwinsize};
+use std::env;
+use std::io;
+use std::os::fd::AsFd;
+
+/// Environment variable with the number of columns in the terminal.
+const COLUMNS_VAR: &str = "COLUMNS";
+
+/// Environment variable with the number of lines in the terminal.
+const LINES_VAR: &str = "LINES";
+
+/// Open a pseudo-terminal for one of the child’s output streams.
+///
+/// The child should write to `slave`, and we should read from `master`.
+pub fn open() -> anyhow::Result<OpenptyResult> {
+    let pty = openpty(window_size().as_ref(), None)
+        .context("opening pseudo-terminal")?;
+
+    // Don’t translate "\n" into "\r\n"; we want the child’s output unchanged.
+    let mut termios =
+        tcgetattr(&pty.slave).context("getting terminal attributes")?;
+    termios.output_flags.remove(OutputFlags::ONLCR);
+    tcsetattr(&pty.slave, SetArg::TCSANOW, &termios)
+        .context("setting terminal attributes")?;
+
+    Ok(pty)
+}
+
+/// Get the size of our terminal, or the size from the environment if we
+/// aren’t running in a terminal and it’s set.
+pub fn window_size() -> Option<Winsize> {
+    if let Some(size) = terminal_size() {
+        return Some(Winsize {
+            ws_row: size.ws_row,
+            ws_col: size.ws_col,
+            ws_xpixel: size.ws_xpixel,
+            ws_ypixel: size.ws_ypixel,
+        });
+    }
+    Some(Winsize {
+        ws_row: env_u16(LINES_VAR)?,
+        ws_col: env_u16(COLUMNS_VAR)?,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    })
+}
+
+/// Set the size of the pseudo-terminal with `master` to the size of our
+/// terminal, if we’re running in one.
+pub fn resize<Fd: AsFd>(master: Fd) -> io::Result<()> {
+    match terminal_size() {
+        Some(size) => Ok(tcsetwinsize(master, size)?),
+        None => Ok(()),
+    }
+}
+
+/// Get the size of the terminal on our stdout, stderr, or stdin, whichever
+/// is a terminal first.
+fn terminal_size() -> Option<rustix::termios::Winsize> {
+    tcgetwinsize(io::stdout())
+        .or_else(|_| tcgetwinsize(io::stderr()))
+        .or_else(|_| tcgetwinsize(io::stdin()))
+        .ok()
+        .filter(|size| size.ws_row > 0 && size.ws_col > 0)
+}
+
+/// Parse an environment variable as a `u16`, if it’s set and valid.
+fn env_u16(name: &str) -> Option<u16> {
+    env::var(name).ok()?.trim().parse().ok()
+}
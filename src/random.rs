@@ -0,0 +1,96 @@
+//! Pseudo-random delays for jitter (see `--jitter` and `--retry-jitter`).
+//!
+//! This doesn’t need to be good randomness; it just needs to keep a bunch of
+//! machines from doing the same thing at the same time.
+
+use std::process;
+use std::time::{Duration, SystemTime};
+
+/// A xorshift64* random number generator, like `Chaos`.
+#[derive(Debug)]
+pub struct Random {
+    /// Generator state. Never 0.
+    state: u64,
+}
+
+impl Random {
+    /// Seed a generator from the clock and our PID.
+    pub fn new() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        Self::with_seed(u64::from(nanos) ^ (u64::from(process::id()) << 32))
+    }
+
+    /// Seed a generator with `seed`.
+    pub const fn with_seed(seed: u64) -> Self {
+        // xorshift gets stuck at 0.
+        Self {
+            state: if seed == 0 { 1 } else { seed },
+        }
+    }
+
+    /// Get the next random number.
+    pub const fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Pick a random duration between `min` and `max`, inclusive, to the
+    /// millisecond.
+    pub fn duration(&mut self, min: Duration, max: Duration) -> Duration {
+        let min = u64::try_from(min.as_millis()).unwrap_or(u64::MAX);
+        let max = u64::try_from(max.as_millis()).unwrap_or(u64::MAX);
+        let extra = self
+            .next_u64()
+            .checked_rem(max.saturating_sub(min).saturating_add(1))
+            .unwrap_or(0);
+        Duration::from_millis(min.saturating_add(extra))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    #[test]
+    fn reproducible() {
+        let mut a = Random::with_seed(42);
+        let mut b = Random::with_seed(42);
+        for _ in 0..100 {
+            check!(a.next_u64() == b.next_u64());
+        }
+    }
+
+    #[test]
+    fn zero_seed() {
+        let mut random = Random::with_seed(0);
+        check!(random.next_u64() != 0);
+    }
+
+    #[test]
+    fn duration_in_range() {
+        let mut random = Random::with_seed(1);
+        let min = Duration::from_millis(10);
+        let max = Duration::from_millis(20);
+        let durations: Vec<_> =
+            (0..1000).map(|_| random.duration(min, max)).collect();
+        check!(durations.iter().all(|d| (min..=max).contains(d)));
+        check!(durations.contains(&min));
+        check!(durations.contains(&max));
+    }
+
+    #[test]
+    fn duration_empty_range() {
+        let mut random = Random::with_seed(1);
+        check!(
+            random.duration(Duration::ZERO, Duration::ZERO) == Duration::ZERO
+        );
+    }
+}
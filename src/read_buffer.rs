@@ -0,0 +1,214 @@
+//! Buffer to read the child’s output into, which grows to keep up with it.
+//!
+//! Small reads keep memory use low for commands that don’t output much, but a
+//! command that outputs a lot (e.g. `pg_dump`) needs large reads to keep up:
+//! every read is a system call, and what it returns is written and flushed
+//! separately. So the buffer starts small and doubles whenever several reads
+//! in a row fill it, up to the capacity of the pipe.
+//!
+//! A read can’t return more than the pipe holds, so there’s no point in a
+//! larger buffer, or in a vectored read into more than one buffer.
+
+use std::ops::{Deref, DerefMut};
+use std::os::fd::BorrowedFd;
+
+/// Initial size of the buffer, unless `--buffer-size` was passed.
+pub const INITIAL_SIZE: usize = 1024;
+
+/// Largest the buffer grows if the pipe’s capacity is unknown. This is the
+/// default capacity of a pipe on Linux.
+const DEFAULT_MAX_SIZE: usize = 64 * 1024;
+
+/// Largest the buffer grows regardless of the pipe’s capacity. This is the
+/// default limit on the size of a pipe on Linux.
+const MAX_SIZE: usize = 1024 * 1024;
+
+/// Number of reads in a row that must fill the buffer for it to grow.
+const GROW_AFTER: u8 = 4;
+
+/// A buffer to read the child’s output into.
+#[derive(Debug)]
+pub struct ReadBuffer {
+    /// The buffer.
+    buffer: Vec<u8>,
+
+    /// Largest the buffer will grow.
+    max: usize,
+
+    /// Number of reads in a row that filled the buffer.
+    full_reads: u8,
+}
+
+impl ReadBuffer {
+    /// Create a buffer for reading from `streams`.
+    ///
+    /// It’s `size` bytes if that’s set (see `--buffer-size`). Otherwise, it
+    /// grows up to the largest capacity of `streams` that are pipes.
+    pub fn new(
+        size: Option<usize>,
+        streams: &[Option<BorrowedFd<'_>>],
+    ) -> Self {
+        match size {
+            Some(size) => Self::fixed(size),
+            None => Self::adaptive(
+                streams
+                    .iter()
+                    .flatten()
+                    .filter_map(|fd| pipe_capacity(*fd))
+                    .max(),
+            ),
+        }
+    }
+
+    /// Create a buffer that is always `size` bytes (see `--buffer-size`).
+    pub fn fixed(size: usize) -> Self {
+        Self {
+            buffer: vec![0; size],
+            max: size,
+            full_reads: 0,
+        }
+    }
+
+    /// Create a buffer that grows up to `capacity` bytes, the capacity of the
+    /// pipes it will read from, if that’s known.
+    pub fn adaptive(capacity: Option<usize>) -> Self {
+        let max = capacity.unwrap_or(DEFAULT_MAX_SIZE).clamp(1, MAX_SIZE);
+        Self {
+            buffer: vec![0; INITIAL_SIZE.min(max)],
+            max,
+            full_reads: 0,
+        }
+    }
+
+    /// Record that a read asking for `limit` bytes got `count`, and grow the
+    /// buffer if reads keep filling it.
+    pub fn record(&mut self, count: usize, limit: usize) {
+        if count < limit || limit < self.buffer.len() {
+            self.full_reads = 0;
+            return;
+        }
+
+        self.full_reads = self.full_reads.saturating_add(1);
+        if self.full_reads >= GROW_AFTER && self.buffer.len() < self.max {
+            let size = self.buffer.len().saturating_mul(2).min(self.max);
+            self.buffer.resize(size, 0);
+            self.full_reads = 0;
+        }
+    }
+}
+
+impl Deref for ReadBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl DerefMut for ReadBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer
+    }
+}
+
+/// Get the capacity of `fd` if it’s a pipe.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn pipe_capacity(fd: BorrowedFd<'_>) -> Option<usize> {
+    use nix::fcntl::{fcntl, FcntlArg};
+    use std::os::fd::AsRawFd;
+
+    let size = fcntl(fd.as_raw_fd(), FcntlArg::F_GETPIPE_SZ).ok()?;
+    usize::try_from(size).ok()
+}
+
+/// Get the capacity of `fd` if it’s a pipe. This is only known on Linux.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub const fn pipe_capacity(_fd: BorrowedFd<'_>) -> Option<usize> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    #[test]
+    fn fixed_never_grows() {
+        let mut buffer = ReadBuffer::fixed(16);
+        for _ in 0..10 {
+            buffer.record(16, 16);
+        }
+        check!(buffer.len() == 16);
+    }
+
+    #[test]
+    fn grows_after_full_reads() {
+        let mut buffer = ReadBuffer::adaptive(Some(4096));
+        check!(buffer.len() == INITIAL_SIZE);
+        for _ in 0..GROW_AFTER - 1 {
+            buffer.record(INITIAL_SIZE, INITIAL_SIZE);
+        }
+        check!(buffer.len() == INITIAL_SIZE);
+        buffer.record(INITIAL_SIZE, INITIAL_SIZE);
+        check!(buffer.len() == INITIAL_SIZE * 2);
+    }
+
+    #[test]
+    fn short_read_resets() {
+        let mut buffer = ReadBuffer::adaptive(Some(4096));
+        for _ in 0..GROW_AFTER - 1 {
+            buffer.record(INITIAL_SIZE, INITIAL_SIZE);
+        }
+        buffer.record(10, INITIAL_SIZE);
+        buffer.record(INITIAL_SIZE, INITIAL_SIZE);
+        check!(buffer.len() == INITIAL_SIZE);
+    }
+
+    #[test]
+    fn limited_read_doesnt_count() {
+        // --chaos shortens reads.
+        let mut buffer = ReadBuffer::adaptive(Some(4096));
+        for _ in 0..GROW_AFTER {
+            buffer.record(10, 10);
+        }
+        check!(buffer.len() == INITIAL_SIZE);
+    }
+
+    #[test]
+    fn grows_up_to_capacity() {
+        let mut buffer = ReadBuffer::adaptive(Some(3000));
+        for _ in 0..100 {
+            let len = buffer.len();
+            buffer.record(len, len);
+        }
+        check!(buffer.len() == 3000);
+    }
+
+    #[test]
+    fn small_capacity() {
+        check!(ReadBuffer::adaptive(Some(100)).len() == 100);
+    }
+
+    #[test]
+    fn unknown_capacity() {
+        let mut buffer = ReadBuffer::adaptive(None);
+        for _ in 0..100 {
+            let len = buffer.len();
+            buffer.record(len, len);
+        }
+        check!(buffer.len() == DEFAULT_MAX_SIZE);
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn pipe_capacity_of_pipe() {
+        use std::os::fd::AsFd;
+        use std::os::unix::net::UnixStream;
+
+        let (read, _write) = nix::unistd::pipe().unwrap();
+        check!(pipe_capacity(read.as_fd()).is_some_and(|size| size > 0));
+
+        let (socket, _other) = UnixStream::pair().unwrap();
+        check!(pipe_capacity(socket.as_fd()) == None);
+    }
+}
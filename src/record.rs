@@ -0,0 +1,152 @@
+//! Record the output in asciinema’s cast format (see `--record`).
+//!
+//! A cast file starts with a JSON header, followed by a JSON array for each
+//! chunk of output, e.g. `[1.234567, "o", "text"]`, where the number is the
+//! time in seconds since the recording started. `asciinema play` replays it at
+//! the speed it was recorded.
+//!
+//! The child’s stderr is recorded in color so that the streams can still be
+//! told apart when it’s replayed. Newlines are recorded as "\r\n", since the
+//! output didn’t go through a terminal that would have translated them.
+//!
+//! Each event is written as soon as it happens, so the recording is still
+//! useful if `rederr` is killed.
+
+use crate::format::{self, Colors, Segment};
+use crate::status;
+use anyhow::Context;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem;
+use std::path::Path;
+use std::str;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use termcolor::Ansi;
+
+/// Writes a recording in asciinema’s cast format.
+#[derive(Debug)]
+pub struct Recorder {
+    /// The cast file.
+    file: File,
+
+    /// When the recording started.
+    started: Instant,
+
+    /// Buffer to render segments into.
+    rendered: Vec<u8>,
+
+    /// The start of a UTF-8 character at the end of the last event, which is
+    /// held back until the rest of the character is written.
+    partial: Vec<u8>,
+
+    /// Buffer for the event being written.
+    event: String,
+}
+
+impl Recorder {
+    /// Create (or truncate) the cast file at `path`, and write its header.
+    pub fn create(
+        path: &Path,
+        (width, height): (u16, u16),
+        command: &str,
+    ) -> anyhow::Result<Self> {
+        let mut file = File::create(path).with_context(|| {
+            format!("creating recording {}", path.display())
+        })?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        writeln!(
+            file,
+            "{{\"version\": 2, \"width\": {width}, \"height\": {height}, \
+            \"timestamp\": {timestamp}, \"command\": {}}}",
+            status::string(command),
+        )
+        .with_context(|| format!("writing recording {}", path.display()))?;
+
+        Ok(Self {
+            file,
+            started: Instant::now(),
+            rendered: Vec::new(),
+            partial: Vec::new(),
+            event: String::new(),
+        })
+    }
+
+    /// Record a segment of formatted output.
+    pub fn render(
+        &mut self,
+        segment: &Segment,
+        colors: &Colors,
+    ) -> io::Result<()> {
+        let mut rendered = mem::take(&mut self.rendered);
+        rendered.clear();
+        format::render(&mut Ansi::new(&mut rendered), segment, colors)?;
+        let result = self.output(&rendered);
+        self.rendered = rendered;
+        result
+    }
+
+    /// Record a message from `rederr` itself.
+    pub fn notice(&mut self, message: &str) -> io::Result<()> {
+        self.output(message.as_bytes())?;
+        self.output(b"\n")
+    }
+
+    /// Write an output event for `bytes`.
+    ///
+    /// An incomplete UTF-8 character at the end of `bytes` is held back until
+    /// the next event. Other invalid UTF-8 is replaced.
+    fn output(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.partial.extend_from_slice(bytes);
+        let end = incomplete_start(&self.partial);
+        if end == 0 {
+            return Ok(());
+        }
+
+        let text =
+            String::from_utf8_lossy(&self.partial[..end]).replace('\n', "\r\n");
+        self.event.clear();
+        // Writing to a String can’t fail.
+        let _ = writeln!(
+            self.event,
+            "[{:.6}, \"o\", {}]",
+            self.started.elapsed().as_secs_f64(),
+            status::string(&text),
+        );
+        self.partial.drain(..end);
+        self.file.write_all(self.event.as_bytes())
+    }
+}
+
+/// Find where an incomplete UTF-8 character at the end of `bytes` starts, or
+/// the length of `bytes` if it doesn’t end with one.
+fn incomplete_start(bytes: &[u8]) -> usize {
+    // A UTF-8 character is at most 4 bytes, so only check the last 3.
+    let start = bytes.len().saturating_sub(3);
+    for index in (start..bytes.len()).rev() {
+        if let Err(error) = str::from_utf8(&bytes[index..]) {
+            if error.error_len().is_none() && error.valid_up_to() == 0 {
+                return index;
+            }
+        }
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    #[test]
+    fn incomplete_character() {
+        check!(incomplete_start(b"") == 0);
+        check!(incomplete_start(b"abc") == 3);
+        check!(incomplete_start("aé".as_bytes()) == 3);
+        check!(incomplete_start(&"aé".as_bytes()[..2]) == 1);
+        check!(incomplete_start(&"a€".as_bytes()[..3]) == 1);
+        check!(incomplete_start(b"a\xff") == 2);
+    }
+}
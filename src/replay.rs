@@ -0,0 +1,255 @@
+//! Play back a recording made with `--record` (see `rederr replay`).
+//!
+//! Recordings are in asciinema’s cast format: a JSON header, followed by a
+//! JSON array for each event. Only output events are played; input, markers,
+//! and resizes are ignored. `--record` translates newlines to "\r\n", so
+//! they’re translated back.
+//!
+//! The command’s stderr was recorded in color. If color is turned off, color
+//! and style escape sequences are removed from the output.
+
+use crate::params::ReplayParams;
+use anyhow::{anyhow, bail, Context};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::str::CharIndices;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Play back the recording specified by `params`.
+pub fn run(params: &ReplayParams) -> anyhow::Result<()> {
+    let path = &params.file;
+    let file = File::open(path)
+        .with_context(|| format!("opening recording {}", path.display()))?;
+    play(params, BufReader::new(file))
+        .with_context(|| format!("in recording {}", path.display()))
+}
+
+/// Play back a recording read from `input`.
+fn play<R: BufRead>(params: &ReplayParams, input: R) -> anyhow::Result<()> {
+    let color = params.shows_color();
+    let mut out = io::stdout().lock();
+    let mut lines = input.lines();
+    let header = lines.next().transpose()?.unwrap_or_default();
+    check_header(&header).context("line 1")?;
+
+    let start = Instant::now();
+    for (index, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = parse_event(&line)
+            .with_context(|| format!("line {}", index.saturating_add(2)))?;
+        if event.kind != "o" {
+            continue;
+        }
+
+        if !params.instant {
+            let offset = Duration::try_from_secs_f64(event.time / params.speed)
+                .with_context(|| format!("line {}", index.saturating_add(2)))?;
+            if let Some(when) = start.checked_add(offset) {
+                thread::sleep(when.saturating_duration_since(Instant::now()));
+            }
+        }
+
+        let text = event.data.replace("\r\n", "\n");
+        let text = if color { text } else { strip_colors(&text) };
+        out.write_all(text.as_bytes())?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// An event in a recording.
+#[derive(Debug, PartialEq)]
+struct Event {
+    /// Seconds since the recording started.
+    time: f64,
+
+    /// The type of event, e.g. "o" for output.
+    kind: String,
+
+    /// The event’s data, e.g. the output.
+    data: String,
+}
+
+/// Check that `header` is the header of a version 2 recording.
+fn check_header(header: &str) -> anyhow::Result<()> {
+    let compact: String = header.split_whitespace().collect();
+    let version_2 = compact.contains("\"version\":2,")
+        || compact.ends_with("\"version\":2}");
+    if !compact.starts_with('{') || !version_2 {
+        bail!("expected an asciinema version 2 header");
+    }
+    Ok(())
+}
+
+/// Parse an event, e.g. `[1.5, "o", "text"]`.
+fn parse_event(line: &str) -> anyhow::Result<Event> {
+    let rest = line
+        .trim()
+        .strip_prefix('[')
+        .ok_or_else(|| anyhow!("expected [ at start of event"))?;
+    let (time, rest) = rest
+        .split_once(',')
+        .ok_or_else(|| anyhow!("expected , after time"))?;
+    let time = time.trim().parse().context("invalid time")?;
+    let (kind, rest) = parse_string(rest.trim_start())?;
+    let rest = rest
+        .trim_start()
+        .strip_prefix(',')
+        .ok_or_else(|| anyhow!("expected , after event type"))?;
+    let (data, rest) = parse_string(rest.trim_start())?;
+    if rest.trim() != "]" {
+        bail!("expected ] at end of event");
+    }
+    Ok(Event { time, kind, data })
+}
+
+/// Parse a JSON string from the start of `input`, and return it with the
+/// rest of `input`.
+fn parse_string(input: &str) -> anyhow::Result<(String, &str)> {
+    let rest = input
+        .strip_prefix('"')
+        .ok_or_else(|| anyhow!("expected a string"))?;
+    let mut value = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &rest[index.saturating_add(1)..])),
+            '\\' => value.push(match chars.next().map(|(_, c)| c) {
+                Some('"') => '"',
+                Some('\\') => '\\',
+                Some('/') => '/',
+                Some('b') => '\u{8}',
+                Some('f') => '\u{c}',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('u') => parse_unicode(&mut chars)?,
+                Some(c) => bail!("unsupported escape \\{c}"),
+                None => bail!("unterminated string"),
+            }),
+            c => value.push(c),
+        }
+    }
+    bail!("unterminated string")
+}
+
+/// Parse the rest of a `\uXXXX` escape, including a second escape if it’s
+/// the first half of a surrogate pair.
+fn parse_unicode(chars: &mut CharIndices<'_>) -> anyhow::Result<char> {
+    let first = parse_hex(chars)?;
+    let code = if (0xd800..0xdc00).contains(&first) {
+        let escape: String = chars.by_ref().take(2).map(|(_, c)| c).collect();
+        if escape != "\\u" {
+            bail!("expected second half of surrogate pair");
+        }
+        let low = parse_hex(chars)?;
+        if !(0xdc00..0xe000).contains(&low) {
+            bail!("invalid surrogate pair");
+        }
+        0x1_0000 | ((first & 0x3ff) << 10) | (low & 0x3ff)
+    } else {
+        first
+    };
+    char::from_u32(code).ok_or_else(|| anyhow!("invalid character {code:#x}"))
+}
+
+/// Parse the 4 hex digits of a `\uXXXX` escape.
+fn parse_hex(chars: &mut CharIndices<'_>) -> anyhow::Result<u32> {
+    let digits: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+    if digits.len() != 4 {
+        bail!("unterminated \\u escape");
+    }
+    u32::from_str_radix(&digits, 16)
+        .with_context(|| format!("invalid escape \\u{digits}"))
+}
+
+/// Remove color and style escape sequences (SGR) from `text`.
+fn strip_colors(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find("\x1b[") {
+        stripped.push_str(&rest[..index]);
+        let after = &rest[index.saturating_add(2)..];
+        let end = after
+            .find(|c: char| !(c.is_ascii_digit() || c == ';'))
+            .unwrap_or(after.len());
+        if after[end..].starts_with('m') {
+            rest = &after[end.saturating_add(1)..];
+        } else {
+            // Not an SGR sequence; leave it alone.
+            stripped.push_str("\x1b[");
+            rest = after;
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::{check, let_assert};
+
+    #[test]
+    fn header() {
+        check!(check_header(r#"{"version": 2, "width": 80}"#).is_ok());
+        check!(check_header(r#"{"width":80,"version":2}"#).is_ok());
+        check!(check_header(r#"{"version": 1}"#).is_err());
+        check!(check_header(r#"{"version": 20}"#).is_err());
+        check!(check_header("").is_err());
+    }
+
+    #[test]
+    fn event() {
+        let_assert!(Ok(event) = parse_event(r#"[1.5, "o", "a\r\nb"]"#));
+        check!(
+            event
+                == Event {
+                    time: 1.5,
+                    kind: "o".to_owned(),
+                    data: "a\r\nb".to_owned(),
+                }
+        );
+    }
+
+    #[test]
+    fn event_errors() {
+        for line in [
+            r#"1.5, "o", "a""#,
+            r#"[x, "o", "a"]"#,
+            r#"[1.5, "o"]"#,
+            r#"[1.5, "o", "a"] x"#,
+            r#"[1.5, "o", "a]"#,
+        ] {
+            check!(parse_event(line).is_err(), "{line:?}");
+        }
+    }
+
+    #[test]
+    fn string_escapes() {
+        let_assert!(
+            Ok((value, rest)) =
+                parse_string(r#""\"\\\/\t\u001b[0m\u00e9\ud83d\ude00" x"#)
+        );
+        check!(value == "\"\\/\t\x1b[0mé😀");
+        check!(rest == " x");
+    }
+
+    #[test]
+    fn string_errors() {
+        for input in [r#""a"#, r#""\q""#, r#""\u12""#, r#""\ud83d x""#] {
+            check!(parse_string(input).is_err(), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn strip() {
+        check!(
+            strip_colors("a\x1b[0m\x1b[38;5;9mb\x1b[0m\x1b[2K") == "ab\x1b[2K"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! Describe how a run went, for notifications (see `--webhook` and
+//! `--mail-to`).
+
+use crate::gap;
+use crate::status;
+use nix::sys::signal::Signal;
+use nix::unistd;
+use std::time::Duration;
+
+/// How a run went.
+#[derive(Debug)]
+pub struct Report<'a> {
+    /// The command line that was run.
+    pub command: &'a str,
+
+    /// The name of the host it was run on.
+    pub host: String,
+
+    /// The code `rederr` is exiting with.
+    pub exit_code: i32,
+
+    /// The signal that killed the child, if any.
+    pub signal: Option<Signal>,
+
+    /// Whether a timeout expired.
+    pub timed_out: bool,
+
+    /// How long the run took.
+    pub duration: Duration,
+
+    /// The end of the output.
+    pub output: String,
+
+    /// Whether the start of the output was left out.
+    pub truncated: bool,
+}
+
+impl Report<'_> {
+    /// Did the run succeed?
+    pub const fn succeeded(&self) -> bool {
+        self.exit_code == 0 && !self.timed_out
+    }
+
+    /// Describe the outcome, e.g. "failed with exit code 1".
+    pub fn outcome(&self) -> String {
+        match self.signal {
+            _ if self.timed_out => "timed out".to_owned(),
+            _ if self.exit_code == 0 => "succeeded".to_owned(),
+            Some(signal) => format!("was killed by {signal}"),
+            None => format!("failed with exit code {}", self.exit_code),
+        }
+    }
+
+    /// Render the report as a JSON object.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"command\":{},\"host\":{},\"exit_code\":{},\"signal\":{},\
+            \"timed_out\":{},\"duration_ms\":{},\"output\":{},\
+            \"output_truncated\":{}}}",
+            status::string(self.command),
+            status::string(&self.host),
+            self.exit_code,
+            self.signal.map_or_else(
+                || "null".to_owned(),
+                |signal| status::string(signal.as_str())
+            ),
+            self.timed_out,
+            self.duration.as_millis(),
+            status::string(&self.output),
+            self.truncated,
+        )
+    }
+
+    /// Get the value of a variable for templates, or `None` if there’s no
+    /// such variable.
+    pub fn variable(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "command" => self.command.to_owned(),
+            "host" => self.host.clone(),
+            "exit_code" => self.exit_code.to_string(),
+            "outcome" => self.outcome(),
+            "duration" => gap::describe(self.duration),
+            "duration_ms" => self.duration.as_millis().to_string(),
+            "output" => self.output.clone(),
+            _ => return None,
+        })
+    }
+}
+
+/// Names of the variables available to templates.
+pub const VARIABLES: &[&str] = &[
+    "command",
+    "host",
+    "exit_code",
+    "outcome",
+    "duration",
+    "duration_ms",
+    "output",
+];
+
+/// Get the name of this host, or "localhost" if it can’t be found.
+pub fn hostname() -> String {
+    unistd::gethostname().map_or_else(
+        |_| "localhost".to_owned(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    /// Get a report for a run that failed.
+    fn failed() -> Report<'static> {
+        Report {
+            command: "backup --full",
+            host: "web1".to_owned(),
+            exit_code: 2,
+            signal: None,
+            timed_out: false,
+            duration: Duration::from_millis(134_500),
+            output: "a \"b\"\n".to_owned(),
+            truncated: true,
+        }
+    }
+
+    #[test]
+    fn outcomes() {
+        let mut report = failed();
+        check!(!report.succeeded());
+        check!(report.outcome() == "failed with exit code 2");
+        report.signal = Some(Signal::SIGKILL);
+        check!(report.outcome() == "was killed by SIGKILL");
+        report.timed_out = true;
+        check!(report.outcome() == "timed out");
+        report.exit_code = 0;
+        check!(!report.succeeded());
+        report.timed_out = false;
+        check!(report.succeeded());
+        check!(report.outcome() == "succeeded");
+    }
+
+    #[test]
+    fn to_json() {
+        check!(
+            failed().to_json()
+                == "{\"command\":\"backup --full\",\"host\":\"web1\",\
+                \"exit_code\":2,\"signal\":null,\"timed_out\":false,\
+                \"duration_ms\":134500,\"output\":\"a \\\"b\\\"\\n\",\
+                \"output_truncated\":true}"
+        );
+    }
+
+    #[test]
+    fn variables() {
+        let report = failed();
+        for name in VARIABLES {
+            check!(report.variable(name).is_some(), "{name}");
+        }
+        check!(report.variable("duration").as_deref() == Some("2m 14s"));
+        check!(report.variable("nope").is_none());
+    }
+}
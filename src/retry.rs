@@ -0,0 +1,138 @@
+//! Decide when to run the command again after it fails (see `--retries`).
+
+use crate::params::Params;
+use crate::random::Random;
+use std::time::Duration;
+
+/// Tracks retries and the delay before each one.
+#[derive(Debug)]
+pub struct Retry {
+    /// Retries left.
+    remaining: u32,
+
+    /// Retries so far.
+    count: u32,
+
+    /// Delay before the next retry, before jitter.
+    delay: Duration,
+
+    /// Whether to double the delay after each retry.
+    backoff: bool,
+
+    /// Random number generator for jitter, or `None` if the delay shouldn’t
+    /// be randomized.
+    jitter: Option<Random>,
+}
+
+impl Retry {
+    /// Set up retries as requested in `params`.
+    pub fn new(params: &Params) -> Self {
+        Self::with_jitter(
+            params.retries,
+            params.retry_delay,
+            params.retry_backoff,
+            params.retry_jitter.then(Random::new),
+        )
+    }
+
+    /// Allow `retries` retries, starting with `delay` between them. The delay
+    /// is randomized with `jitter`, if it’s passed.
+    pub const fn with_jitter(
+        retries: u32,
+        delay: Duration,
+        backoff: bool,
+        jitter: Option<Random>,
+    ) -> Self {
+        Self {
+            remaining: retries,
+            count: 0,
+            delay,
+            backoff,
+            jitter,
+        }
+    }
+
+    /// Number of retries so far.
+    pub const fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Number of retries allowed in total.
+    pub const fn limit(&self) -> u32 {
+        self.count.saturating_add(self.remaining)
+    }
+
+    /// Use up a retry and get how long to wait before it, or get `None` if
+    /// there are no retries left.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        self.remaining = self.remaining.checked_sub(1)?;
+        self.count = self.count.saturating_add(1);
+
+        let delay = self.jittered(self.delay);
+        if self.backoff {
+            self.delay = self.delay.saturating_mul(2);
+        }
+        Some(delay)
+    }
+
+    /// Pick a random delay between half of `delay` and all of it, if jitter
+    /// is enabled.
+    fn jittered(&mut self, delay: Duration) -> Duration {
+        match &mut self.jitter {
+            Some(random) => {
+                random.duration(delay.checked_div(2).unwrap_or_default(), delay)
+            }
+            None => delay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    #[test]
+    fn no_retries() {
+        let mut retry =
+            Retry::with_jitter(0, Duration::from_secs(1), true, None);
+        check!(retry.next_delay() == None);
+        check!(retry.count() == 0);
+        check!(retry.limit() == 0);
+    }
+
+    #[test]
+    fn fixed_delay() {
+        let mut retry =
+            Retry::with_jitter(2, Duration::from_secs(1), false, None);
+        check!(retry.next_delay() == Some(Duration::from_secs(1)));
+        check!(retry.next_delay() == Some(Duration::from_secs(1)));
+        check!(retry.next_delay() == None);
+        check!(retry.count() == 2);
+        check!(retry.limit() == 2);
+    }
+
+    #[test]
+    fn backoff() {
+        let mut retry =
+            Retry::with_jitter(3, Duration::from_secs(1), true, None);
+        check!(retry.next_delay() == Some(Duration::from_secs(1)));
+        check!(retry.next_delay() == Some(Duration::from_secs(2)));
+        check!(retry.next_delay() == Some(Duration::from_secs(4)));
+        check!(retry.next_delay() == None);
+    }
+
+    #[test]
+    fn jitter() {
+        let mut retry = Retry::with_jitter(
+            100,
+            Duration::from_secs(1),
+            false,
+            Some(Random::with_seed(0)),
+        );
+        while let Some(delay) = retry.next_delay() {
+            check!(delay >= Duration::from_millis(500));
+            check!(delay <= Duration::from_secs(1));
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Run a command and capture its output without blocking.
+//!
+//! [`Runner`] starts a command with its stdout and stderr piped, and returns
+//! [`Events`], an iterator over chunks of output from either stream as they
+//! arrive, followed by the command’s exit.
+//!
+//! This uses the same run loop as the `rederr` executable, so it has the same
+//! stateful timeouts: an idle timeout that restarts whenever the command
+//! produces output, and a timeout for the entire run. When one expires the
+//! command is sent `SIGTERM`, and, optionally, `SIGKILL` if it doesn’t exit
+//! in time.
+
+use crate::hooks::Hooks;
+use crate::params::{Params, TimeoutAction};
+use crate::session::{Run, SpawnError};
+use crate::signals::SignalPipe;
+use nix::sys::signal::Signal;
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// Configures how to run a command.
+///
+/// ```rust
+/// use rederr::{Event, Runner};
+/// use std::time::Duration;
+///
+/// let runner = Runner::new("sh")
+///     .args(["-c", "echo out; echo err >&2"])
+///     .idle_timeout(Duration::from_secs(10));
+///
+/// let mut stdout = Vec::new();
+/// for event in runner.run().unwrap() {
+///     match event.unwrap() {
+///         Event::Stdout(bytes) => stdout.extend(bytes),
+///         Event::Stderr(bytes) => assert_eq!(bytes, b"err\n"),
+///         Event::TimedOut(kind) => panic!("{kind:?} timeout expired"),
+///         Event::Exit(code) => assert_eq!(code, 0),
+///     }
+/// }
+/// assert_eq!(stdout, b"out\n");
+/// ```
+#[derive(Clone, Debug)]
+pub struct Runner {
+    /// How to run the command, as if it were passed to the `rederr`
+    /// executable.
+    params: Params,
+}
+
+impl Runner {
+    /// Prepare to run `program` with no arguments and no timeouts.
+    #[must_use]
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Self {
+        let mut params = Params::for_program(program.as_ref());
+        // Without --kill-after, the executable leaves the command running when
+        // a timeout expires.
+        params.on_idle_timeout = Some(TimeoutAction::Term);
+        params.on_run_timeout = Some(TimeoutAction::Term);
+        Self { params }
+    }
+
+    /// Add an argument to pass to the command.
+    #[must_use]
+    pub fn arg<S: AsRef<OsStr>>(mut self, arg: S) -> Self {
+        self.params.command.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Add arguments to pass to the command.
+    #[must_use]
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.params
+            .command
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    /// Time out if the command doesn’t produce output for `timeout`.
+    ///
+    /// This restarts every time the command produces output, and stops once
+    /// both of its output streams are closed.
+    #[must_use]
+    pub const fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.params.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Time out if the command runs for longer than `timeout`.
+    #[must_use]
+    pub const fn run_timeout(mut self, timeout: Duration) -> Self {
+        self.params.run_timeout = Some(timeout);
+        self
+    }
+
+    /// Kill the command with `SIGKILL` if it’s still running `timeout` after
+    /// it was sent `SIGTERM` because a timeout expired.
+    #[must_use]
+    pub const fn kill_after(mut self, timeout: Duration) -> Self {
+        self.params.kill_after = Some(timeout);
+        self
+    }
+
+    /// Read up to `size` bytes of output at a time.
+    ///
+    /// This is the largest chunk that [`Event::Stdout`] and [`Event::Stderr`]
+    /// will contain. By default, the buffer starts small and grows when the
+    /// command produces a lot of output.
+    #[must_use]
+    pub fn buffer_size(mut self, size: usize) -> Self {
+        self.params.buffer_size = Some(size.max(1));
+        self
+    }
+
+    /// Start the command, and return an iterator over what happens while it
+    /// runs.
+    ///
+    /// The command inherits our stdin.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if the command couldn’t be started, or if we
+    /// couldn’t start listening for `SIGCHLD`.
+    pub fn run(&self) -> io::Result<Events<'_>> {
+        // Listen for SIGCHLD before starting the child so that we can’t miss
+        // it.
+        let mut signals = SignalPipe::new()?;
+        signals.add(Signal::SIGCHLD)?;
+
+        Ok(Events {
+            run: Run::for_events(&self.params, &signals)
+                .map_err(into_io_error)?,
+            signals,
+            done: false,
+        })
+    }
+
+    /// Run the command, calling `hooks` as things happen, and return its exit
+    /// code.
+    ///
+    /// The exit code is 128 + the signal number if the command was killed by
+    /// a signal. The command inherits our stdin.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if the command couldn’t be started, if there was
+    /// a problem reading its output or waiting for it, or if a hook returned
+    /// an error. The command is killed if it’s still running.
+    pub fn run_with(&self, mut hooks: Hooks<'_>) -> io::Result<i32> {
+        for event in self.run()? {
+            let event = event?;
+            hooks.call(&event)?;
+            if let Event::Exit(code) = event {
+                return Ok(code);
+            }
+        }
+        unreachable!("events should end with Exit or an error")
+    }
+
+    /// Start the command on the current tokio runtime, and return a stream of
+    /// what happens while it runs.
+    ///
+    /// The command inherits our stdin.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if the command couldn’t be started.
+    ///
+    /// # Panics
+    ///
+    /// This panics if it’s called outside of a tokio runtime.
+    #[cfg(feature = "tokio")]
+    pub fn run_async(&self) -> io::Result<crate::AsyncRunner> {
+        let params = &self.params;
+        let mut command = tokio::process::Command::new(
+            params.program().expect("Runner always has a program"),
+        );
+        command
+            .args(params.program_args())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped());
+        crate::AsyncRunner::start(
+            command,
+            params.idle_timeout,
+            params.run_timeout,
+            params.kill_after,
+            params
+                .buffer_size
+                .unwrap_or(crate::read_buffer::INITIAL_SIZE),
+        )
+    }
+}
+
+/// Something that happened while running a command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A chunk of output from the command’s stdout.
+    Stdout(Vec<u8>),
+
+    /// A chunk of output from the command’s stderr.
+    Stderr(Vec<u8>),
+
+    /// A timeout expired, so the command was sent `SIGTERM`.
+    TimedOut(TimeoutKind),
+
+    /// The command exited and its output was closed. Contains the exit code,
+    /// or 128 + the signal number if it was killed by a signal.
+    ///
+    /// This is always the last event.
+    Exit(i32),
+}
+
+/// Which timeout expired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeoutKind {
+    /// The command didn’t produce output for too long.
+    Idle,
+
+    /// The command ran for too long.
+    Run,
+}
+
+/// A running command.
+///
+/// This is an iterator over what happens while the command runs. It ends after
+/// [`Event::Exit`] or an error.
+///
+/// If this is dropped before the command exits, the command is killed.
+pub struct Events<'a> {
+    /// The run, which is stepped until it produces events.
+    run: Run<'a>,
+
+    /// Receives `SIGCHLD`.
+    signals: SignalPipe,
+
+    /// Whether the run is over, so there are no more events.
+    done: bool,
+}
+
+impl Iterator for Events<'_> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.run.take_event() {
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+            if self.run.is_done() {
+                self.done = true;
+                let code = self.run.exit_code().expect("child should be done");
+                return Some(Ok(Event::Exit(code)));
+            }
+            if let Err(error) = self.run.step(&mut self.signals) {
+                self.done = true;
+                return Some(Err(into_io_error(error)));
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Events<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Events")
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Events<'_> {
+    fn drop(&mut self) {
+        self.run.kill();
+    }
+}
+
+/// Get the [`io::Error`] behind an error from the run loop, so that callers
+/// can check its kind.
+fn into_io_error(error: anyhow::Error) -> io::Error {
+    match error.downcast::<SpawnError>() {
+        Ok(spawn) => spawn.error,
+        Err(error) => error.downcast().unwrap_or_else(io::Error::other),
+    }
+}
@@ -0,0 +1,115 @@
+//! Measure the CPU time and memory the child used (see `--stats`).
+//!
+//! `getrusage(RUSAGE_CHILDREN)` reports the total for all of our children that
+//! have been waited for, including their own children that they waited for.
+//! The usage for a run is the difference between that total before and after
+//! the child is reaped, except for the maximum resident set size, which is the
+//! largest of any child so far.
+//!
+//! The maximum resident set size includes memory the child shared with
+//! `rederr` between `fork()` and `exec()`, so it’s never less than what
+//! `rederr` itself was using when it started the child.
+
+use nix::sys::resource::{getrusage, UsageWho};
+use nix::sys::time::TimeVal;
+use std::time::Duration;
+
+/// CPU time and memory used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rusage {
+    /// CPU time spent in user mode.
+    pub user: Duration,
+
+    /// CPU time spent in the kernel.
+    pub system: Duration,
+
+    /// The largest resident set size, in bytes.
+    pub max_rss: u64,
+}
+
+impl Rusage {
+    /// Get the total usage of all children that have been waited for.
+    pub fn children() -> nix::Result<Self> {
+        let usage = getrusage(UsageWho::RUSAGE_CHILDREN)?;
+        let max_rss = u64::try_from(usage.max_rss()).unwrap_or(0);
+        Ok(Self {
+            user: duration(usage.user_time()),
+            system: duration(usage.system_time()),
+            // macOS reports bytes; everything else reports KiB.
+            max_rss: if cfg!(target_os = "macos") {
+                max_rss
+            } else {
+                max_rss.saturating_mul(1024)
+            },
+        })
+    }
+
+    /// Get the usage since `before`.
+    #[must_use]
+    pub const fn since(&self, before: &Self) -> Self {
+        Self {
+            user: self.user.saturating_sub(before.user),
+            system: self.system.saturating_sub(before.system),
+            max_rss: self.max_rss,
+        }
+    }
+}
+
+impl std::fmt::Display for Rusage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.3}s user, {:.3}s system, {} KiB max RSS",
+            self.user.as_secs_f64(),
+            self.system.as_secs_f64(),
+            self.max_rss.div_ceil(1024),
+        )
+    }
+}
+
+/// Convert a `TimeVal` to a `Duration`, treating negative values as 0.
+fn duration(time: TimeVal) -> Duration {
+    let seconds = u64::try_from(time.tv_sec()).unwrap_or(0);
+    let micros = u64::try_from(time.tv_usec()).unwrap_or(0);
+    Duration::from_secs(seconds).saturating_add(Duration::from_micros(micros))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    #[test]
+    fn since() {
+        let before = Rusage {
+            user: Duration::from_millis(100),
+            system: Duration::from_millis(50),
+            max_rss: 1024,
+        };
+        let after = Rusage {
+            user: Duration::from_millis(350),
+            system: Duration::from_millis(60),
+            max_rss: 4096,
+        };
+        check!(
+            after.since(&before)
+                == Rusage {
+                    user: Duration::from_millis(250),
+                    system: Duration::from_millis(10),
+                    max_rss: 4096,
+                }
+        );
+    }
+
+    #[test]
+    fn display() {
+        let usage = Rusage {
+            user: Duration::from_millis(1_234),
+            system: Duration::from_micros(5_600),
+            max_rss: 10_000,
+        };
+        check!(
+            usage.to_string() == "1.234s user, 0.006s system, 10 KiB max RSS"
+        );
+    }
+}
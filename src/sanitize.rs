@@ -0,0 +1,61 @@
+//! Escape control characters in the child’s output (see `--sanitize`).
+//!
+//! Control characters are shown like `cat -v` does, e.g. ESC becomes `^[`, so
+//! that the child can’t change the state of the terminal or hide escape
+//! sequences in logs. Newlines and tabs are left alone, as are bytes outside of
+//! ASCII so that UTF-8 isn’t mangled.
+//!
+//! Each byte is escaped on its own, so it doesn’t matter how the output is
+//! split into chunks.
+
+/// Escape control characters in `bytes`, replacing the contents of `out`.
+pub fn escape(bytes: &[u8], out: &mut Vec<u8>) {
+    out.clear();
+    for &byte in bytes {
+        match byte {
+            b'\n' | b'\t' => out.push(byte),
+            // 0x40 is '@', so this maps 0x00 to "^@", 0x1b to "^[", etc.
+            0x00..=0x1f => out.extend_from_slice(&[b'^', byte | 0x40]),
+            0x7f => out.extend_from_slice(b"^?"),
+            _ => out.push(byte),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+    use bstr::ByteSlice;
+
+    /// Escape `bytes` into a new buffer.
+    fn escaped(bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        escape(bytes, &mut out);
+        out
+    }
+
+    #[test]
+    fn plain() {
+        check!(escaped(b"a b\tc\n").as_bstr() == "a b\tc\n");
+    }
+
+    #[test]
+    fn controls() {
+        check!(
+            escaped(b"\x00\x07\r\x1b[31m\x7f").as_bstr() == "^@^G^M^[[31m^?"
+        );
+    }
+
+    #[test]
+    fn utf8() {
+        check!(escaped("é\u{FFFD}".as_bytes()).as_bstr() == "é\u{FFFD}");
+    }
+
+    #[test]
+    fn reuses_buffer() {
+        let mut out = b"old".to_vec();
+        escape(b"new", &mut out);
+        check!(out.as_bstr() == "new");
+    }
+}
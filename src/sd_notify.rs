@@ -0,0 +1,286 @@
+//! Report the child’s progress to systemd (see `--sd-notify`).
+//!
+//! systemd passes the path of a datagram socket in `$NOTIFY_SOCKET` to
+//! services with `Type=notify`. Messages are newline-separated assignments,
+//! e.g. "READY=1" once the service has started, or "STATUS=..." to show in
+//! `systemctl status`.
+//!
+//! If the service has `WatchdogSec=` set, systemd also passes the interval in
+//! `$WATCHDOG_USEC`, and restarts the service if it doesn’t send "WATCHDOG=1"
+//! within it. Pings are only sent when the child produces output, so a child
+//! that goes quiet for the whole interval is treated as hung.
+
+use crate::params::Stream;
+use crate::status_line;
+use std::env;
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixDatagram;
+use std::process;
+use std::time::{Duration, Instant};
+
+/// How often to update the status.
+pub const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Environment variables systemd uses to talk to the service.
+///
+/// These are removed from the child’s environment, since `rederr` is the one
+/// talking to systemd.
+pub const ENV_VARS: &[&str] =
+    &["NOTIFY_SOCKET", "WATCHDOG_USEC", "WATCHDOG_PID"];
+
+/// The most of a line to include in the status.
+const MAX_LINE: usize = 200;
+
+/// Sends notifications to systemd.
+#[derive(Debug)]
+pub struct Notifier {
+    /// The socket connected to `$NOTIFY_SOCKET`.
+    socket: UnixDatagram,
+
+    /// How often to ping the watchdog, if it’s enabled.
+    watchdog: Option<Duration>,
+
+    /// When the watchdog was last pinged, if it has been.
+    pinged_at: Option<Instant>,
+
+    /// The last non-blank line of output.
+    last_line: String,
+
+    /// The start of the line being output on each of the child’s streams, up
+    /// to [`MAX_LINE`] bytes.
+    partial: [Vec<u8>; 2],
+
+    /// The status that was last sent.
+    status: String,
+}
+
+impl Notifier {
+    /// Connect to the socket in `$NOTIFY_SOCKET`.
+    ///
+    /// Returns `None` if it isn’t set, e.g. because `rederr` isn’t running
+    /// under systemd.
+    pub fn from_env() -> io::Result<Option<Self>> {
+        let Some(path) = env::var_os("NOTIFY_SOCKET").filter(|p| !p.is_empty())
+        else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            socket: connect(&path)?,
+            watchdog: watchdog_interval(),
+            pinged_at: None,
+            last_line: String::new(),
+            partial: [Vec::new(), Vec::new()],
+            status: String::new(),
+        }))
+    }
+
+    /// Tell systemd that the service has started.
+    pub fn ready(&self) -> io::Result<()> {
+        self.send("READY=1")
+    }
+
+    /// Record output from the child’s `stream`, and ping the watchdog if
+    /// it’s due.
+    pub fn output(&mut self, stream: Stream, bytes: &[u8]) -> io::Result<()> {
+        let partial = &mut self.partial[match stream {
+            Stream::Out => 0,
+            Stream::Err => 1,
+        }];
+        let mut lines = bytes.split(|&byte| byte == b'\n');
+        let rest = lines.next_back().unwrap_or_default();
+        for line in lines {
+            push_limited(partial, line);
+            if let Some(line) = describe_line(partial) {
+                self.last_line = line;
+            }
+            partial.clear();
+        }
+        push_limited(partial, rest);
+
+        let Some(every) = self.watchdog else {
+            return Ok(());
+        };
+        if self.pinged_at.is_none_or(|at| at.elapsed() >= every) {
+            self.pinged_at = Some(Instant::now());
+            self.send("WATCHDOG=1")?;
+        }
+        Ok(())
+    }
+
+    /// Update the status with how long the child has been running and its
+    /// last line of output, if it changed.
+    pub fn update_status(&mut self, elapsed: Duration) -> io::Result<()> {
+        let status = status(elapsed, &self.last_line);
+        if status != self.status {
+            self.send(&format!("STATUS={status}"))?;
+            self.status = status;
+        }
+        Ok(())
+    }
+
+    /// Send `message` to systemd.
+    fn send(&self, message: &str) -> io::Result<()> {
+        self.socket.send(message.as_bytes()).map(|_| ())
+    }
+}
+
+/// Connect to the notification socket at `path`, which is in the abstract
+/// namespace if it starts with "@".
+fn connect(path: &OsStr) -> io::Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.as_bytes().strip_prefix(b"@") {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::SocketAddr;
+        socket.connect_addr(&SocketAddr::from_abstract_name(name)?)?;
+        return Ok(socket);
+    }
+    socket.connect(path)?;
+    Ok(socket)
+}
+
+/// Get how often to ping the watchdog, if systemd enabled it for us.
+///
+/// Pings are sent at half the interval systemd expects, as
+/// `sd_watchdog_enabled(3)` recommends.
+fn watchdog_interval() -> Option<Duration> {
+    if let Some(pid) = env::var_os("WATCHDOG_PID") {
+        if pid.to_str()?.parse::<u32>().ok()? != process::id() {
+            return None;
+        }
+    }
+    let usec: u64 = env::var_os("WATCHDOG_USEC")?.to_str()?.parse().ok()?;
+    Duration::from_micros(usec)
+        .checked_div(2)
+        .filter(|every| !every.is_zero())
+}
+
+/// Append `bytes` to `line`, without letting it get longer than
+/// [`MAX_LINE`].
+fn push_limited(line: &mut Vec<u8>, bytes: &[u8]) {
+    let room = MAX_LINE.saturating_sub(line.len());
+    line.extend_from_slice(&bytes[..room.min(bytes.len())]);
+}
+
+/// Clean up a line of output for the status, or return `None` if it’s blank.
+///
+/// Color and style escape sequences are removed, and runs of whitespace and
+/// other control characters are replaced with a single space.
+fn describe_line(line: &[u8]) -> Option<String> {
+    let line = String::from_utf8_lossy(line);
+    let mut cleaned = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.as_str().starts_with('[') {
+            // Skip the "[" and everything up to the end of the sequence.
+            chars.next();
+            chars.by_ref().find(|c| ('@'..='~').contains(c));
+        } else if c.is_control() {
+            cleaned.push(' ');
+        } else {
+            cleaned.push(c);
+        }
+    }
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!cleaned.is_empty()).then_some(cleaned)
+}
+
+/// Get the status, e.g. "12s elapsed, last line: Copying files".
+fn status(elapsed: Duration, last_line: &str) -> String {
+    let elapsed = status_line::seconds(elapsed);
+    if last_line.is_empty() {
+        format!("{elapsed} elapsed, no output yet")
+    } else {
+        format!("{elapsed} elapsed, last line: {last_line}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    /// Create a notifier connected to a socket, and return both.
+    fn notifier() -> (Notifier, UnixDatagram) {
+        let (socket, listener) = UnixDatagram::pair().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let notifier = Notifier {
+            socket,
+            watchdog: Some(Duration::from_mins(1)),
+            pinged_at: None,
+            last_line: String::new(),
+            partial: [Vec::new(), Vec::new()],
+            status: String::new(),
+        };
+        (notifier, listener)
+    }
+
+    /// Get the messages that have been sent to `listener`.
+    fn received(listener: &UnixDatagram) -> Vec<String> {
+        let mut messages = Vec::new();
+        let mut buffer = [0; 1024];
+        while let Ok(count) = listener.recv(&mut buffer) {
+            messages.push(String::from_utf8_lossy(&buffer[..count]).into());
+        }
+        messages
+    }
+
+    #[test]
+    fn last_line() {
+        let (mut notifier, _listener) = notifier();
+        notifier.output(Stream::Out, b"one\ntw").unwrap();
+        check!(notifier.last_line == "one");
+        notifier.output(Stream::Err, b"three\n\n").unwrap();
+        check!(notifier.last_line == "three");
+        notifier.output(Stream::Out, b"o\n").unwrap();
+        check!(notifier.last_line == "two");
+    }
+
+    #[test]
+    fn last_line_cleaned() {
+        let (mut notifier, _listener) = notifier();
+        notifier
+            .output(Stream::Out, b"\x1b[1mbold\x1b[0m\tx\r\n")
+            .unwrap();
+        check!(notifier.last_line == "bold x");
+    }
+
+    #[test]
+    fn last_line_limited() {
+        let (mut notifier, _listener) = notifier();
+        notifier.output(Stream::Out, &[b'a'; 150]).unwrap();
+        notifier.output(Stream::Out, &[b'b'; 150]).unwrap();
+        notifier.output(Stream::Out, b"\n").unwrap();
+        check!(notifier.last_line.len() == MAX_LINE);
+        check!(notifier.last_line.ends_with('b'));
+    }
+
+    #[test]
+    fn watchdog_pings() {
+        let (mut notifier, listener) = notifier();
+        notifier.output(Stream::Out, b"a\n").unwrap();
+        notifier.output(Stream::Out, b"b\n").unwrap();
+        check!(received(&listener) == ["WATCHDOG=1"]);
+    }
+
+    #[test]
+    fn status_changes() {
+        let (mut notifier, listener) = notifier();
+        notifier.watchdog = None;
+        notifier.ready().unwrap();
+        notifier.update_status(Duration::from_millis(500)).unwrap();
+        notifier.update_status(Duration::from_millis(900)).unwrap();
+        notifier.output(Stream::Out, b"working\n").unwrap();
+        notifier.update_status(Duration::from_secs(62)).unwrap();
+        check!(
+            received(&listener)
+                == [
+                    "READY=1",
+                    "STATUS=0s elapsed, no output yet",
+                    "STATUS=1m 2s elapsed, last line: working",
+                ]
+        );
+    }
+}
@@ -0,0 +1,3302 @@
+//! Run a command and write its output, as the `rederr` executable does.
+//!
+//! [`run()`] takes the parsed options and returns the code to exit with, so the
+//! executable only has to parse its arguments and exit. [`crate::Runner`]
+//! shares the run loop, but gets the output back as events instead.
+
+use crate::backlog::Backlog;
+use crate::cgroup::Cgroup;
+use crate::chaos::Chaos;
+use crate::child::{Change, Child};
+use crate::collapse::Collapse;
+use crate::dedup::Dedup;
+use crate::filter::{Filter, SinkFilters, Sinks};
+use crate::format::{Colors, Formatter, Segment};
+use crate::gap::GapMarker;
+use crate::head_tail::HeadTail;
+use crate::hex::HexDump;
+use crate::limit::Limit;
+use crate::line_buffer::LineBuffer;
+use crate::lock::Lock;
+use crate::mail::Mail;
+use crate::params::{
+    Compression, Params, ReadyExit, StdinSource, Stream, TimeoutAction,
+    Utf8Mode,
+};
+use crate::patterns::Patterns;
+use crate::ping::Ping;
+use crate::poller::{self, Event, Interest, Poller};
+use crate::pre_exec::PreExec;
+use crate::random::Random;
+use crate::read_buffer::ReadBuffer;
+use crate::record::Recorder;
+use crate::report::Report;
+use crate::retry::Retry;
+use crate::rusage::Rusage;
+use crate::sd_notify::Notifier;
+use crate::signals::{self, SignalPipe};
+use crate::splice::Splice;
+use crate::spool::Spool;
+use crate::stats::{RunTime, Stats};
+use crate::status::Status;
+use crate::status_line::StatusLine;
+use crate::stdin::StdinPump;
+use crate::subreaper::Orphans;
+use crate::tail::Tail;
+use crate::timeout::Timeout;
+use crate::timestamp::{Clock, Timestamp};
+use crate::tz::TimeZone;
+use crate::utf8::Utf8Filter;
+use crate::watchdog::Watchdog;
+use crate::{
+    deadline, dry_run, environment, exit_code, format, gap, head_tail,
+    line_buffer, oom, pty, read_buffer, report, runner, sanitize, sd_notify,
+    splice, spool, status, status_line, stdin, subreaper, unbuffer, webhook,
+};
+use anyhow::Context;
+use bstr::ByteSlice;
+use nix::errno::Errno;
+use nix::sys::signal::{self, Signal};
+use popol::set_nonblocking;
+use std::collections::VecDeque;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant, SystemTime};
+use termcolor::{
+    Buffer, Color, ColorSpec, NoColor, StandardStream, WriteColor,
+};
+
+/// Key to identify child output stream that when `poll()` returns.
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum PollKey {
+    /// Child output stream.
+    Output(Stream),
+
+    /// Signals received by `rederr`.
+    Signal,
+
+    /// Our stdin, when it’s forwarded to the child (see `--stdin=pipe`).
+    Input,
+
+    /// The child’s stdin, when it’s forwarded from ours.
+    ChildInput,
+
+    /// Our stdout or stderr, when output is waiting to be written to it (see
+    /// `--max-buffer`).
+    Backlog(Stream),
+}
+
+/// Display a message from `rederr` itself, as opposed to output from the child.
+///
+/// This ignores errors writing the message, since there’s nowhere else to
+/// report them.
+macro_rules! notice {
+    ($($arg:tt)*) => {{
+        let _ = writeln!(io::stderr(), $($arg)*);
+    }};
+}
+
+/// Print what would be run instead of running it (see `--dry-run`).
+///
+/// `args` is the command line, and `all_args` is the command line with the
+/// options from the configuration file added, so that the options can be
+/// listed with where they were set.
+///
+/// # Errors
+///
+/// This returns an error if the command couldn’t be prepared, e.g. because
+/// `--user` was passed without running as root, or if the output couldn’t be
+/// written.
+pub fn dry_run(
+    params: &Params,
+    args: &[OsString],
+    all_args: &[OsString],
+) -> anyhow::Result<()> {
+    // Don’t create a cgroup, since nothing will be run in it. The child joins
+    // it before exec, so it wouldn’t show up in the command anyway.
+    let (command, _) = build_command(params, None)?;
+    let mut out = io::stdout().lock();
+    dry_run::render(&mut out, params, args, all_args, &command)?;
+    Ok(())
+}
+
+/// Run the command as the `rederr` executable does, retrying if requested,
+/// and return the code `rederr` should exit with.
+///
+/// Debugging information for `--debug` is written to `debug`, if it’s passed.
+///
+/// # Errors
+///
+/// This returns an error if the command couldn’t be started (a
+/// [`SpawnError`]), or if there was a problem running it or writing its
+/// output.
+pub fn run(
+    params: &Params,
+    debug: Option<Box<dyn Write>>,
+) -> anyhow::Result<i32> {
+    // Held until the run is over.
+    let _lock = params
+        .lockfile
+        .as_deref()
+        .map(|path| Lock::acquire(path, params.lock_wait(), params.lock_kill))
+        .transpose()?;
+
+    // Set up signal handling before starting the child so that we can’t miss
+    // a SIGCHLD.
+    let mut signals = SignalPipe::new()?;
+    signals.add(Signal::SIGCHLD)?;
+    for signal in signals::FORWARD {
+        signals.add(*signal)?;
+    }
+    if uses_pty(params) {
+        signals.add(Signal::SIGWINCH)?;
+    }
+    if params.subreaper {
+        subreaper::enable()?;
+    }
+
+    // --jitter counts against the first run’s --run-timeout.
+    let deadline = deadline::resolve(params)?;
+    let mut run_timeout = start_run_timeout(params, deadline);
+    if let Some(jitter) = params.jitter {
+        if params.run_timeout.is_some_and(|timeout| jitter >= timeout) {
+            anyhow::bail!("--jitter must be shorter than --run-timeout");
+        }
+        let delay = Random::new().duration(Duration::ZERO, jitter);
+        if let Some(signal) = sleep(&mut signals, delay)? {
+            return Ok((signal as i32).saturating_add(128));
+        }
+    }
+
+    // Open log files before starting the child so that it isn’t left running
+    // if they can’t be opened.
+    let mut output = Output::new(params)?;
+    output.debug = debug;
+    if let Some(ping) = Ping::new(params) {
+        if let Err(error) = ping.start() {
+            output.notice(&format!("Warning: {error:#}"));
+        }
+    }
+    let mut retry = Retry::new(params);
+    loop {
+        let mut session = run_once(params, &mut signals, output, run_timeout)?;
+        let code = session.exit_code()?;
+        if session.should_retry(code) {
+            if let Some(delay) = retry.next_delay() {
+                let failure = if session.timed_out {
+                    "Command timed out".to_owned()
+                } else {
+                    format!("Command failed with exit code {code}")
+                };
+                session.output.notice(&format!(
+                    "{failure}; retrying in {delay:?} (retry {} of {})",
+                    retry.count(),
+                    retry.limit(),
+                ));
+                if sleep(&mut signals, delay)?.is_none() {
+                    output = session.into_output();
+                    output.reset(params);
+                    run_timeout = start_run_timeout(params, deadline);
+                    continue;
+                }
+            }
+        }
+
+        session.warn_invalid_utf8();
+        if code != 0 {
+            // --quiet-on-success: the command failed, so show its output.
+            session.output.release()?;
+        }
+        session.finish(code);
+        return Ok(code);
+    }
+}
+
+/// Start the timeout for a run: `--run-timeout` or `deadline`, whichever
+/// expires first.
+fn start_run_timeout(params: &Params, deadline: Option<SystemTime>) -> Timeout {
+    let run_timeout = Timeout::from(params.run_timeout).start();
+    match deadline.map(Timeout::at) {
+        Some(deadline) if deadline < run_timeout => deadline,
+        _ => run_timeout,
+    }
+}
+
+/// Run the child once with `run_timeout`, and return the session after it
+/// exits and its output is closed, or after it’s left running.
+fn run_once<'a>(
+    params: &'a Params,
+    signals: &mut SignalPipe,
+    output: Output<'a>,
+    run_timeout: Timeout,
+) -> anyhow::Result<Session<'a>> {
+    let mut run = Run::start(params, signals, output, run_timeout)?;
+    while !run.is_done() {
+        run.step(signals)?;
+    }
+    Ok(run.finish())
+}
+
+/// A run of the child: the session tracking it, and what we poll while it
+/// runs.
+///
+/// [`run_once()`] steps this until the child exits and its output is closed.
+/// [`crate::Runner`] does the same, but it hands the child’s output back as
+/// [`runner::Event`]s.
+pub(crate) struct Run<'a> {
+    /// The state of the child.
+    session: Session<'a>,
+
+    /// What to poll.
+    poller: Box<dyn Poller<PollKey>>,
+
+    /// Events from `poll()` that haven’t been handled yet.
+    events: VecDeque<Event<PollKey>>,
+
+    /// The child’s stdout, if it was captured.
+    child_out: Option<File>,
+
+    /// The child’s stderr, if it was captured.
+    child_err: Option<File>,
+
+    /// Forwards our stdin to the child, if its stdin was piped (see
+    /// `--stdin=pipe`).
+    stdin: Option<StdinPump>,
+
+    /// Which of our output streams are polled (see `--max-buffer`).
+    backlog_polls: BacklogPolls,
+}
+
+impl<'a> Run<'a> {
+    /// Start the child with `run_timeout`, writing its output to `output`.
+    /// `signals` must already be listening for `SIGCHLD`.
+    fn start(
+        params: &'a Params,
+        signals: &SignalPipe,
+        output: Output<'a>,
+        run_timeout: Timeout,
+    ) -> anyhow::Result<Self> {
+        let cgroup =
+            params.cgroup.then(|| Cgroup::create(params)).transpose()?;
+        let (mut child, child_out, child_err) = spawn(params, cgroup.as_ref())?;
+        let stdin = pump_stdin(&mut child, params)?;
+
+        let mut poller = poller::new();
+        poller
+            .register(PollKey::Signal, signals.as_fd(), Interest::Read)
+            .context("watching for signals")?;
+        let child_out =
+            register(&mut *poller, PollKey::Output(Stream::Out), child_out)?;
+        let child_err =
+            register(&mut *poller, PollKey::Output(Stream::Err), child_err)?;
+        if let Some(stdin) = &stdin {
+            watch_stdin(&mut *poller, stdin, stdin::Wait::Input)
+                .context("forwarding stdin")?;
+        }
+
+        let notifier = params
+            .sd_notify
+            .then(Notifier::from_env)
+            .transpose()
+            .context("connecting to $NOTIFY_SOCKET")?
+            .flatten();
+
+        let session = Session::new(
+            params,
+            output,
+            Child::new(&child).with_scope(params.signal_scope()),
+            &[
+                child_out.as_ref().map(AsFd::as_fd),
+                child_err.as_ref().map(AsFd::as_fd),
+            ],
+            run_timeout,
+            cgroup,
+            notifier,
+        );
+
+        Ok(Self {
+            session,
+            poller,
+            events: VecDeque::with_capacity(4),
+            child_out,
+            child_err,
+            stdin,
+            backlog_polls: BacklogPolls::default(),
+        })
+    }
+
+    /// Start the child for [`crate::Runner`], which gets its output as
+    /// [`runner::Event`]s instead of having it written.
+    ///
+    /// `signals` must already be listening for `SIGCHLD`.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if the child couldn’t be started.
+    pub fn for_events(
+        params: &'a Params,
+        signals: &SignalPipe,
+    ) -> anyhow::Result<Self> {
+        let mut output = Output::new(params)?;
+        output.events = Some(VecDeque::new());
+        // The output has to be read to be handed back.
+        output.splice = Splice::default();
+        let run_timeout = Timeout::from(params.run_timeout).start();
+        Self::start(params, signals, output, run_timeout)
+    }
+
+    /// Are the child’s output streams closed and written, and has it exited?
+    /// (See [`Session::is_done()`].)
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.session.is_done()
+    }
+
+    /// Wait for something to happen, and handle it.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if there was a problem polling, reading the
+    /// child’s output, writing it, or signalling the child.
+    pub fn step(&mut self, signals: &mut SignalPipe) -> anyhow::Result<()> {
+        let session = &mut self.session;
+        let poller = &mut *self.poller;
+        let (kind, timeout) = session.timeout();
+        if let Some(expired) = timeout.check_expired() {
+            return session.expired(kind, &expired);
+        }
+
+        session.output.debug(format_args!(
+            "{}() with timeout {timeout} (run timeout {})",
+            poller.name(),
+            session.run_timeout
+        ));
+
+        let result =
+            poll(poller, &mut self.events, &timeout, &mut session.chaos);
+        session.stats.record_wakeup();
+        if let Some(expired) = result.context("waiting for input")? {
+            session.expired(kind, &expired)?;
+        }
+
+        // stdout and stderr are read separately, so if the child uses both in
+        // the same line they might be spliced together. --line-buffered avoids
+        // that.
+        while let Some(event) = self.events.pop_front() {
+            if session.leaving.is_some() {
+                // Leave the rest for the child.
+                return Ok(());
+            }
+            session.output.debug(format_args!("{event:?}"));
+
+            if let (PollKey::Input | PollKey::ChildInput, Some(stdin)) =
+                (&event.key, &mut self.stdin)
+            {
+                forward_stdin(poller, stdin, &event.key)?;
+                continue;
+            }
+            if let PollKey::Backlog(destination) = event.key {
+                session.output.write_backlog(destination)?;
+                continue;
+            }
+
+            if event.is_readable() {
+                match (&event.key, &mut self.child_out, &mut self.child_err) {
+                    (PollKey::Output(Stream::Out), Some(reader), _) => {
+                        session.read(Stream::Out, reader)?;
+                    }
+                    (PollKey::Output(Stream::Err), _, Some(reader)) => {
+                        session.read(Stream::Err, reader)?;
+                    }
+                    (PollKey::Signal, out, err) => {
+                        for signal in signals.take()? {
+                            session.handle_signal(signal, [out, err])?;
+                        }
+                    }
+                    _ => {} // Stream wasn’t piped, so it isn’t polled.
+                }
+            }
+
+            if session.should_close(&event) {
+                // Remove the stream from poll.
+                poller
+                    .unregister(&event.key)
+                    .context("removing closed stream")?;
+                if let PollKey::Output(stream) = event.key {
+                    session.close_stream(stream)?;
+                }
+            }
+        }
+
+        let streams = [
+            (Stream::Out, &self.child_out),
+            (Stream::Err, &self.child_err),
+        ];
+        self.backlog_polls.update(poller, session, streams)
+    }
+
+    /// Get the next event for [`crate::Runner`], if there is one.
+    pub fn take_event(&mut self) -> Option<runner::Event> {
+        self.session.output.events.as_mut()?.pop_front()
+    }
+
+    /// Get the child’s exit code, if it has exited.
+    ///
+    /// If it was killed by a signal, this is 128 + the signal number.
+    #[must_use]
+    pub const fn exit_code(&self) -> Option<i32> {
+        self.session.child.exit_code()
+    }
+
+    /// Kill the child if it’s still running, and wait for it to exit.
+    ///
+    /// This ignores errors, since it’s used when giving up on the run.
+    pub fn kill(&mut self) {
+        let child = &mut self.session.child;
+        if !child.has_exited() {
+            let _ = child.signal(Signal::SIGKILL);
+            let _ = child.wait();
+        }
+    }
+
+    /// Report statistics about the run (see [`Session::report_stats()`]), and
+    /// return the session.
+    ///
+    /// There’s nothing to report if the child is being left running.
+    fn finish(mut self) -> Session<'a> {
+        if self.session.leaving.is_none() {
+            self.session.report_stats();
+        }
+        self.session
+    }
+}
+
+/// Start forwarding our stdin to the child, if its stdin was piped (see
+/// `--stdin=pipe`).
+fn pump_stdin(
+    child: &mut process::Child,
+    params: &Params,
+) -> anyhow::Result<Option<StdinPump>> {
+    child
+        .stdin
+        .take()
+        .map(|stdin| {
+            let size = params.buffer_size.unwrap_or(read_buffer::INITIAL_SIZE);
+            StdinPump::new(stdin, size)
+        })
+        .transpose()
+        .context("forwarding stdin")
+}
+
+/// Wait `delay` while the child isn’t running.
+///
+/// Returns the signal if we get one that would have been forwarded to the
+/// child, in which case we shouldn’t start it.
+fn sleep(
+    signals: &mut SignalPipe,
+    delay: Duration,
+) -> anyhow::Result<Option<Signal>> {
+    let mut poller = poller::new();
+    let mut events = VecDeque::with_capacity(1);
+    poller
+        .register(PollKey::Signal, signals.as_fd(), Interest::Read)
+        .context("watching for signals")?;
+    let timeout = Timeout::from(delay).start();
+    loop {
+        let mut chaos = Chaos::new(None);
+        if poll(&mut *poller, &mut events, &timeout, &mut chaos)?.is_some() {
+            return Ok(None);
+        }
+        events.clear();
+        if let Some(signal) = signals
+            .take()?
+            .into_iter()
+            .find(|signal| signals::FORWARD.contains(signal))
+        {
+            return Ok(Some(signal));
+        }
+    }
+}
+
+/// Set the child’s working directory and environment.
+fn configure_environment(
+    command: &mut process::Command,
+    params: &Params,
+) -> anyhow::Result<()> {
+    if let Some(dir) = &params.chdir {
+        // Otherwise this looks like the command wasn’t found.
+        let metadata = fs::metadata(dir)
+            .with_context(|| format!("--chdir {}", dir.display()))?;
+        if !metadata.is_dir() {
+            anyhow::bail!("--chdir {}: not a directory", dir.display());
+        }
+        command.current_dir(dir);
+    }
+    if params.sd_notify {
+        // We talk to systemd, so the child shouldn’t.
+        for key in sd_notify::ENV_VARS {
+            command.env_remove(key);
+        }
+    }
+    environment::configure(command, params)?;
+    if let Some(tz) = &params.tz {
+        command.env("TZ", tz.name());
+    }
+    if let Some(locale) = &params.locale {
+        command.env("LC_ALL", locale);
+    }
+    Ok(())
+}
+
+/// Set where the child’s stdin comes from (see `--stdin`).
+fn configure_stdin(
+    command: &mut process::Command,
+    params: &Params,
+) -> anyhow::Result<()> {
+    match &params.stdin {
+        StdinSource::Inherit => {}
+        StdinSource::Pipe => {
+            command.stdin(process::Stdio::piped());
+        }
+        StdinSource::Null => {
+            command.stdin(process::Stdio::null());
+        }
+        StdinSource::File(path) => {
+            command.stdin(File::open(path).with_context(|| {
+                format!("opening {} for stdin", path.display())
+            })?);
+        }
+    }
+    Ok(())
+}
+
+/// Build the command to start the child, including any wrappers, its working
+/// directory, and its environment. `cgroup` is the cgroup to run it in, if
+/// any.
+///
+/// Returns the command and the name of the command inside the wrappers, for
+/// error messages.
+fn build_command(
+    params: &Params,
+    cgroup: Option<&Cgroup>,
+) -> anyhow::Result<(process::Command, OsString)> {
+    let mut program = params.program().expect("clap requires command");
+    let mut args: Vec<&OsStr> = params
+        .program_args()
+        .iter()
+        .map(OsString::as_os_str)
+        .collect();
+    let shell = params.shell_program();
+    if let Some(shell) = &shell {
+        args.splice(..0, [OsStr::new("-c"), program]);
+        program = shell;
+    }
+
+    // The command run through any wrappers below, for error messages.
+    let name = program;
+    if params.ready_exit == Some(ReadyExit::Leave) {
+        // The child might outlive us, so have it ignore SIGPIPE. Ignored
+        // signals stay ignored across exec(), but Rust resets SIGPIPE in the
+        // child, so we have to ask the shell to do it.
+        let trap = OsStr::new(r#"trap "" PIPE && exec "$0" "$@""#);
+        args.splice(..0, [OsStr::new("-c"), trap, program]);
+        program = OsStr::new("sh");
+    }
+
+    let mut command = process::Command::new(program);
+    command.args(args);
+    PreExec::new(params, cgroup)?.configure(&mut command);
+    configure_environment(&mut command, params)?;
+    if params.unbuffer {
+        if let Some(library) = unbuffer::library() {
+            unbuffer::configure(&mut command, &library);
+        }
+    }
+    if params.process_group {
+        command.process_group(0);
+    }
+    Ok((command, name.to_owned()))
+}
+
+/// Should the child’s output go to pseudo-terminals? Without libstdbuf, a
+/// terminal is the only way to get line buffering (see `--unbuffer`).
+fn uses_pty(params: &Params) -> bool {
+    params.pty || params.unbuffer && unbuffer::library().is_none()
+}
+
+/// Start the child.
+///
+/// Returns the child and the streams to read its output from, if they were
+/// captured. If the child couldn’t be started, the error is a [`SpawnError`].
+fn spawn(
+    params: &Params,
+    cgroup: Option<&Cgroup>,
+) -> anyhow::Result<(process::Child, Option<File>, Option<File>)> {
+    let (mut command, name) = build_command(params, cgroup)?;
+    configure_stdin(&mut command, params)?;
+    let mut terminals = None;
+    if uses_pty(params) {
+        if !params.pty && params.verbose > 0 {
+            notice!("libstdbuf.so not found; using --pty for --unbuffer");
+        }
+        let (out, err) = (pty::open()?, pty::open()?);
+        command.stdout(out.slave).stderr(err.slave);
+        terminals = Some((File::from(out.master), File::from(err.master)));
+    } else if !params.wait_only || !params.ready_pattern.is_empty() {
+        // With --ready-pattern, --wait-only reads the output until the
+        // command is ready.
+        command
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped());
+    }
+
+    let mut child = command
+        .spawn()
+        .map_err(|error| SpawnError { name, error })?;
+    if let Some(score) = params.oom_score_adj {
+        oom::adjust(&mut child, score)?;
+    }
+
+    // Close our copies of the child’s end of the terminals, so that we notice
+    // when the child closes them.
+    drop(command);
+
+    if let Some((out, err)) = terminals {
+        return Ok((child, Some(out), Some(err)));
+    }
+    let out = child
+        .stdout
+        .take()
+        .map(|out| File::from(OwnedFd::from(out)));
+    let err = child
+        .stderr
+        .take()
+        .map(|err| File::from(OwnedFd::from(err)));
+    Ok((child, out, err))
+}
+
+/// The child couldn’t be started.
+#[derive(Debug)]
+pub struct SpawnError {
+    /// The command inside any wrappers (see [`build_command()`]).
+    name: OsString,
+
+    /// Why it couldn’t be started.
+    pub error: io::Error,
+}
+
+impl SpawnError {
+    /// Get the code `rederr` exits with.
+    #[must_use]
+    pub fn exit_code(&self) -> i32 {
+        match self.error.kind() {
+            io::ErrorKind::NotFound => exit_code::NOT_FOUND,
+            _ => exit_code::CANNOT_EXECUTE,
+        }
+    }
+}
+
+impl fmt::Display for SpawnError {
+    #[allow(clippy::unnecessary_debug_formatting)] // Quote the name.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Could not run command {:?}: {}", self.name, self.error)
+    }
+}
+
+impl std::error::Error for SpawnError {}
+
+/// Prepare a child output stream to be polled, if it was piped.
+fn register<R: AsFd>(
+    poller: &mut dyn Poller<PollKey>,
+    key: PollKey,
+    stream: Option<R>,
+) -> anyhow::Result<Option<R>> {
+    let Some(stream) = stream else {
+        return Ok(None);
+    };
+    set_nonblocking(&stream.as_fd(), true)
+        .expect("child output cannot be set to non-blocking");
+    poller
+        .register(key, stream.as_fd(), Interest::Read)
+        .context("watching child output")?;
+    Ok(Some(stream))
+}
+
+/// Handle an event on our stdin or the child’s stdin (see `--stdin=pipe`).
+fn forward_stdin(
+    poller: &mut dyn Poller<PollKey>,
+    stdin: &mut StdinPump,
+    key: &PollKey,
+) -> anyhow::Result<()> {
+    let wait = if *key == PollKey::Input {
+        stdin.read().context("reading stdin")?
+    } else {
+        stdin.write().context("writing to command’s stdin")?
+    };
+    watch_stdin(poller, stdin, wait).context("forwarding stdin")
+}
+
+/// Poll whichever end of the stdin forwarding is needed next, if either.
+fn watch_stdin(
+    poller: &mut dyn Poller<PollKey>,
+    stdin: &StdinPump,
+    wait: stdin::Wait,
+) -> io::Result<()> {
+    poller.unregister(&PollKey::Input)?;
+    poller.unregister(&PollKey::ChildInput)?;
+    match (wait, stdin.child()) {
+        (stdin::Wait::Input, _) => {
+            poller.register(PollKey::Input, stdin.input(), Interest::Read)
+        }
+        (stdin::Wait::Child, Some(child)) => {
+            poller.register(PollKey::ChildInput, child, Interest::Write)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// What’s being polled, or not, because of output waiting to be written (see
+/// `--max-buffer`).
+#[derive(Debug, Default)]
+struct BacklogPolls {
+    /// Which of our streams are being polled because output is waiting for
+    /// them.
+    writing: Vec<Stream>,
+
+    /// Which of the child’s output streams aren’t being polled because too
+    /// much output is waiting for where they go.
+    paused: Vec<Stream>,
+}
+
+impl BacklogPolls {
+    /// Poll our streams that output is waiting for, and stop or start polling
+    /// the child’s output `streams` depending on whether too much output is
+    /// waiting for where they go.
+    ///
+    /// Streams are only resumed if they were paused, so streams that were
+    /// closed in the meantime aren’t polled again.
+    fn update(
+        &mut self,
+        poller: &mut dyn Poller<PollKey>,
+        session: &mut Session,
+        streams: [(Stream, &Option<File>); 2],
+    ) -> anyhow::Result<()> {
+        let Some(backlog) = session.output.backlog() else {
+            return Ok(());
+        };
+
+        for destination in [Stream::Out, Stream::Err] {
+            let key = PollKey::Backlog(destination);
+            let writing = self.writing.contains(&destination);
+            if backlog.is_waiting(destination) && !writing {
+                let fd = backlog.fd(destination);
+                poller.register(key, fd, Interest::Write)?;
+                self.writing.push(destination);
+            } else if !backlog.is_waiting(destination) && writing {
+                poller.unregister(&key)?;
+                self.writing.retain(|stream| *stream != destination);
+            }
+        }
+
+        let was_paused = !self.paused.is_empty();
+        for (stream, file) in streams {
+            let (Some(file), key) = (file, PollKey::Output(stream)) else {
+                continue;
+            };
+            let paused = self.paused.contains(&stream);
+            if backlog.is_full(stream) && !paused {
+                poller.unregister(&key)?;
+                self.paused.push(stream);
+            } else if !backlog.is_full(stream) && paused {
+                poller.register(key, file.as_fd(), Interest::Read)?;
+                self.paused.retain(|paused| *paused != stream);
+            }
+        }
+
+        if was_paused == self.paused.is_empty() {
+            session.pause_reading(!self.paused.is_empty());
+        }
+        Ok(())
+    }
+}
+
+/// Which timeout expired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TimeoutKind {
+    /// The child didn’t produce output for too long.
+    Idle,
+
+    /// The child ran for too long.
+    Run,
+
+    /// Delayed output is ready to be written (see `--inject-latency`).
+    Delayed,
+
+    /// The child didn’t exit after being terminated (see `--kill-after`).
+    Kill,
+
+    /// The child has been running long enough to warn about (see
+    /// `--warn-at`).
+    Warn,
+
+    /// The child has been running long enough to warn about (see
+    /// `--run-warn`).
+    RunWarn,
+
+    /// The child hasn’t produced output for long enough to warn about (see
+    /// `--idle-warn`).
+    IdleWarn,
+
+    /// The child hasn’t produced output for long enough to output a status
+    /// line (see `--heartbeat`).
+    Heartbeat,
+
+    /// It’s time to update the status line (see `--status-line`).
+    StatusLine,
+
+    /// It’s time to update the status reported to systemd (see
+    /// `--sd-notify`).
+    Notify,
+
+    /// The child exited a while ago but its output is still open (see
+    /// `--no-linger`).
+    Linger,
+}
+
+/// The state of the child while it runs.
+#[allow(clippy::struct_excessive_bools)] // Independent facts about the child.
+struct Session<'a> {
+    /// Parameters for the run.
+    params: &'a Params,
+
+    /// Timeout for the entire run.
+    run_timeout: Timeout,
+
+    /// Timeout for the child to produce output.
+    ///
+    /// This is restarted every time there is output, and is [`Timeout::Never`]
+    /// while the child is stopped or after its output is closed. Until the
+    /// child produces any output, it’s `--start-timeout` if that was passed.
+    idle_timeout: Timeout,
+
+    /// Timeout for the child to exit after it was sent a termination signal.
+    ///
+    /// This is [`Timeout::Never`] unless `--kill-after` was passed and the
+    /// child is being terminated.
+    kill_timeout: Timeout,
+
+    /// Warnings that haven’t been issued yet, in order (see `--warn-at`).
+    ///
+    /// Each is the configured duration and the timeout for it.
+    warnings: VecDeque<(Duration, Timeout)>,
+
+    /// The next `--run-warn` warning: how long the child will have been
+    /// running, and the timeout for it.
+    run_warning: Option<(Duration, Timeout)>,
+
+    /// The next `--idle-warn` warning: how long the child will have gone
+    /// without output, and the timeout for it.
+    ///
+    /// This is restarted and stopped along with the idle timeout.
+    idle_warning: Option<(Duration, Timeout)>,
+
+    /// Whether the child has produced any output (see `--start-timeout`).
+    has_output: bool,
+
+    /// When the child last produced output, if it has.
+    last_output: Option<Instant>,
+
+    /// Timeout for the next `--heartbeat` status line.
+    ///
+    /// This is restarted every time there is output, and is `None` after the
+    /// child’s output is closed.
+    heartbeat: Option<Timeout>,
+
+    /// Timeout for the next `--status-line` update, if there is a status
+    /// line.
+    status_tick: Option<Timeout>,
+
+    /// Sends notifications to systemd, if `--sd-notify` was passed and
+    /// `rederr` is running under systemd.
+    notifier: Option<Notifier>,
+
+    /// Timeout for the next update of the status reported to systemd, if
+    /// there’s a notifier.
+    notify_tick: Option<Timeout>,
+
+    /// Whether the child is stopped.
+    stopped: bool,
+
+    /// Whether the child is being terminated.
+    terminating: bool,
+
+    /// Whether a timeout expired, so `rederr` should fail regardless of how
+    /// the child exits.
+    timed_out: bool,
+
+    /// Whether a signal was forwarded to the child, so it shouldn’t be
+    /// retried.
+    interrupted: bool,
+
+    /// Whether the child is being terminated because it’s ready (see
+    /// `--ready-exit=kill`), so `rederr` should succeed regardless of how it
+    /// exits.
+    ready: bool,
+
+    /// The code to exit with right away, leaving the child running (see
+    /// `--ready-exit=leave` and `--on-run-timeout=exit`).
+    leaving: Option<i32>,
+
+    /// Timeout for the child’s output to be closed after it exits.
+    ///
+    /// This is [`Timeout::Never`] unless the child has exited with its output
+    /// still open, and either `--no-linger` or `--verbose` was passed.
+    linger_timeout: Timeout,
+
+    /// Which of the child’s output streams are still open.
+    open_streams: Vec<Stream>,
+
+    /// Where to write the child’s output.
+    output: Output<'a>,
+
+    /// Buffer to read the child’s output into.
+    buffer: ReadBuffer,
+
+    /// Output held back by `--inject-latency`, and when to write it.
+    delayed: VecDeque<(Timeout, Stream, Vec<u8>)>,
+
+    /// When the run started.
+    start: RunTime,
+
+    /// Statistics about the run.
+    stats: Stats,
+
+    /// Failure injection for testing (see `--chaos`).
+    chaos: Chaos,
+
+    /// The child process.
+    child: Child,
+
+    /// Processes the child left behind, if `--subreaper` was passed.
+    orphans: Option<Orphans>,
+
+    /// The cgroup the child runs in, if `--cgroup` was passed.
+    cgroup: Option<Cgroup>,
+
+    /// What children that already exited had used when the child started, to
+    /// subtract from what the child used.
+    rusage_start: Option<Rusage>,
+
+    /// What the child used, once it has exited.
+    rusage: Option<Rusage>,
+}
+
+impl<'a> Session<'a> {
+    /// Start tracking a run of `child`, reading its output from `streams`
+    /// (those that were captured) and writing it to `output`. `cgroup` is the
+    /// cgroup the child runs in, if any (see `--cgroup`), and `notifier` sends
+    /// notifications to systemd, if any (see `--sd-notify`).
+    fn new(
+        params: &'a Params,
+        output: Output<'a>,
+        child: Child,
+        streams: &[Option<BorrowedFd<'_>>],
+        run_timeout: Timeout,
+        cgroup: Option<Cgroup>,
+        notifier: Option<Notifier>,
+    ) -> Self {
+        let mut session = Self {
+            params,
+            run_timeout,
+            idle_timeout: Timeout::Never,
+            kill_timeout: Timeout::Never,
+            linger_timeout: Timeout::Never,
+            warnings: warnings(&params.warn_at),
+            run_warning: params
+                .run_warn
+                .map(|at| (at, Timeout::from(at).start())),
+            idle_warning: None,
+            has_output: false,
+            last_output: None,
+            heartbeat: params
+                .heartbeat
+                .map(|every| Timeout::from(every).start()),
+            status_tick: output
+                .has_status_line()
+                .then(|| Timeout::from(status_line::INTERVAL).start()),
+            notify_tick: notifier
+                .as_ref()
+                .map(|_| Timeout::from(sd_notify::INTERVAL).start()),
+            notifier,
+            stopped: false,
+            terminating: false,
+            timed_out: false,
+            interrupted: false,
+            ready: false,
+            leaving: None,
+            open_streams: [Stream::Out, Stream::Err]
+                .into_iter()
+                .zip(streams)
+                .filter_map(|(stream, fd)| fd.map(|_| stream))
+                .collect(),
+            output,
+            buffer: ReadBuffer::new(params.buffer_size, streams),
+            delayed: VecDeque::new(),
+            start: RunTime::start(),
+            stats: Stats::default(),
+            chaos: Chaos::new(params.chaos_seed()),
+            child,
+            orphans: params.subreaper.then(Orphans::new),
+            cgroup,
+            rusage_start: Rusage::children().ok(),
+            rusage: None,
+        };
+        session.restart_idle_timeout();
+        if params.ready_pattern.is_empty() {
+            session.notify(|notifier| notifier.ready());
+        }
+        session.notify_status();
+        session
+    }
+
+    /// Are all output streams closed and written, and have the child and any
+    /// orphans it left (see `--subreaper`) exited?
+    fn is_done(&self) -> bool {
+        self.leaving.is_some()
+            || self.open_streams.is_empty()
+                && self.delayed.is_empty()
+                && self.output.backlog().is_none_or(Backlog::is_empty)
+                && self.child.has_exited()
+                && !self.orphans.as_ref().is_some_and(Orphans::is_remaining)
+    }
+
+    /// Record that one of the child’s output streams was closed.
+    fn close_stream(&mut self, stream: Stream) -> io::Result<()> {
+        if !self.open_streams.contains(&stream) {
+            // We stopped reading it after the child exited (see
+            // `--no-linger`).
+            return Ok(());
+        }
+        self.output.finish(stream)?;
+        self.check_output()?;
+        self.open_streams.retain(|open| *open != stream);
+        if self.open_streams.is_empty() {
+            // There’s no more output to wait for.
+            self.stop_idle_timeout();
+            self.heartbeat = None;
+            self.linger_timeout = Timeout::Never;
+        }
+        Ok(())
+    }
+
+    /// Print statistics about how the child’s output was read, what it used
+    /// (see `--stats`), and what its cgroup used (see `--cgroup`), if
+    /// requested.
+    fn report_stats(&mut self) {
+        if self.output.debug.is_some() {
+            self.output.debug(format_args!("{}", self.stats));
+            self.output.debug(format_args!("{}", self.start.elapsed()));
+        } else if self.params.verbose >= 2 {
+            self.output.notice(&self.stats.poll_summary());
+        }
+
+        if let (true, Some(rusage)) = (self.params.stats, self.rusage) {
+            self.output.notice(&format!("Command used {rusage}"));
+        }
+        let usage = self.cgroup.as_ref().map(Cgroup::usage);
+        if let Some(summary) = usage.and_then(|usage| usage.summary()) {
+            if self.params.verbose > 0 {
+                self.output.notice(&format!("Command {summary}"));
+            }
+        }
+    }
+
+    /// Should the stream `event` is for be closed?
+    ///
+    /// It shouldn’t if it hasn’t hung up, or if it has but there might still
+    /// be output waiting in the pipe because too much output is waiting to be
+    /// written to read it (see `--max-buffer`). It should if we stopped
+    /// reading it after the child exited (see `--no-linger`).
+    fn should_close(&mut self, event: &Event<PollKey>) -> bool {
+        if matches!(event.key, PollKey::Output(stream)
+            if !self.open_streams.contains(&stream))
+        {
+            return true;
+        }
+        event.is_hangup()
+            && !self.chaos.delay_hangup()
+            && !self.is_backed_up(&event.key)
+    }
+
+    /// Is too much output waiting to be written to read more from `key`?
+    fn is_backed_up(&self, key: &PollKey) -> bool {
+        let Some(backlog) = self.output.backlog() else {
+            return false;
+        };
+        matches!(key, PollKey::Output(stream) if backlog.is_full(*stream))
+    }
+
+    /// Record whether reading the child’s output is paused because too much
+    /// is waiting to be written (see `--max-buffer`).
+    ///
+    /// The child can’t produce output while reading is paused, so the idle
+    /// timeout is stopped.
+    fn pause_reading(&mut self, paused: bool) {
+        if paused {
+            self.stop_idle_timeout();
+        } else {
+            self.restart_idle_timeout();
+        }
+    }
+
+    /// Record that the child produced output.
+    fn record_output(&mut self) {
+        self.has_output = true;
+        self.last_output = Some(Instant::now());
+        self.restart_idle_timeout();
+        self.draw_status(false);
+        if self.heartbeat.is_some() {
+            self.heartbeat = self
+                .params
+                .heartbeat
+                .map(|every| Timeout::from(every).start());
+        }
+    }
+
+    /// Restart the idle timeout, unless there’s no output to wait for.
+    ///
+    /// Before the child produces any output, this uses `--start-timeout`
+    /// instead, if it was passed.
+    fn restart_idle_timeout(&mut self) {
+        if !self.open_streams.is_empty() && !self.terminating {
+            let timeout = if self.is_starting() {
+                self.params.start_timeout
+            } else {
+                self.params.idle_timeout
+            };
+            self.idle_timeout = Timeout::from(timeout).start();
+            self.idle_warning = self
+                .params
+                .idle_warn
+                .map(|at| (at, Timeout::from(at).start()));
+        }
+    }
+
+    /// Stop the idle timeout, e.g. because the child can’t produce output.
+    const fn stop_idle_timeout(&mut self) {
+        self.idle_timeout = Timeout::Never;
+        self.idle_warning = None;
+    }
+
+    /// Is the idle timeout `--start-timeout`, because the child hasn’t
+    /// produced any output yet?
+    const fn is_starting(&self) -> bool {
+        !self.has_output && self.params.start_timeout.is_some()
+    }
+
+    /// Get the timeout that will expire first.
+    fn timeout(&self) -> (TimeoutKind, Timeout) {
+        let mut first = (TimeoutKind::Run, &self.run_timeout);
+        let others = [
+            (TimeoutKind::Idle, &self.idle_timeout),
+            (TimeoutKind::Kill, &self.kill_timeout),
+            (TimeoutKind::Linger, &self.linger_timeout),
+        ];
+        let delayed = self
+            .delayed
+            .front()
+            .map(|(delay, _, _)| (TimeoutKind::Delayed, delay));
+        let warning = self
+            .warnings
+            .front()
+            .map(|(_, timeout)| (TimeoutKind::Warn, timeout));
+        let run_warning = self
+            .run_warning
+            .as_ref()
+            .map(|(_, timeout)| (TimeoutKind::RunWarn, timeout));
+        let idle_warning = self
+            .idle_warning
+            .as_ref()
+            .map(|(_, timeout)| (TimeoutKind::IdleWarn, timeout));
+        let heartbeat = self
+            .heartbeat
+            .as_ref()
+            .map(|timeout| (TimeoutKind::Heartbeat, timeout));
+        let status_tick = self
+            .status_tick
+            .as_ref()
+            .map(|timeout| (TimeoutKind::StatusLine, timeout));
+        let notify_tick = self
+            .notify_tick
+            .as_ref()
+            .map(|timeout| (TimeoutKind::Notify, timeout));
+
+        for (kind, timeout) in others
+            .into_iter()
+            .chain(delayed)
+            .chain(warning)
+            .chain(run_warning)
+            .chain(idle_warning)
+            .chain(heartbeat)
+            .chain(status_tick)
+            .chain(notify_tick)
+        {
+            if timeout < first.1 {
+                first = (kind, timeout);
+            }
+        }
+
+        (first.0, first.1.clone())
+    }
+
+    /// Handle a timeout expiring.
+    ///
+    /// If `--kill-after` wasn’t passed, this exits when a run or idle timeout
+    /// expires.
+    fn expired(
+        &mut self,
+        kind: TimeoutKind,
+        expired: &Timeout,
+    ) -> anyhow::Result<()> {
+        match kind {
+            TimeoutKind::Idle | TimeoutKind::Run => {
+                self.timed_out(kind, expired)?;
+            }
+            TimeoutKind::Kill => {
+                self.output.notice(&format!(
+                    "Command still running {:?} after being terminated; \
+                    killing it",
+                    expired.elapsed_rounded()
+                ));
+                self.kill_timeout = Timeout::Never;
+                self.child.signal(Signal::SIGKILL)?;
+            }
+            TimeoutKind::Warn
+            | TimeoutKind::RunWarn
+            | TimeoutKind::IdleWarn => self.expired_warning(kind),
+            TimeoutKind::Heartbeat => self.heartbeat(),
+            TimeoutKind::StatusLine => {
+                self.draw_status(true);
+                self.status_tick =
+                    Some(Timeout::from(status_line::INTERVAL).start());
+            }
+            TimeoutKind::Notify => {
+                self.notify_status();
+                self.notify_tick = self
+                    .notifier
+                    .as_ref()
+                    .map(|_| Timeout::from(sd_notify::INTERVAL).start());
+            }
+            TimeoutKind::Linger => {
+                self.linger_timeout = Timeout::Never;
+                if !self.params.no_linger {
+                    self.output.notice(&format!(
+                        "Command exited {:?} ago but its output is still \
+                        open; waiting for it to close (see --no-linger)",
+                        expired.elapsed_rounded()
+                    ));
+                    return Ok(());
+                }
+
+                if self.params.verbose > 0 {
+                    self.output.notice(&format!(
+                        "Command exited {:?} ago but its output is still \
+                        open; not waiting for it to close",
+                        expired.elapsed_rounded()
+                    ));
+                }
+                for stream in self.open_streams.clone() {
+                    self.close_stream(stream)?;
+                }
+            }
+            TimeoutKind::Delayed => {
+                while let Some((delay, stream, bytes)) =
+                    self.delayed.pop_front()
+                {
+                    if delay.check_expired().is_none() {
+                        self.delayed.push_front((delay, stream, bytes));
+                        break;
+                    }
+                    self.output.write(stream, &bytes)?;
+                    self.check_output()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle a run or idle timeout expiring by doing what
+    /// `--on-run-timeout` or `--on-idle-timeout` says to.
+    fn timed_out(
+        &mut self,
+        kind: TimeoutKind,
+        expired: &Timeout,
+    ) -> anyhow::Result<()> {
+        let (option, message) = self.describe_timeout(kind, expired);
+        let action = self.params.timeout_action(match kind {
+            TimeoutKind::Idle => self.params.on_idle_timeout,
+            _ => self.params.on_run_timeout,
+        });
+
+        if action == TimeoutAction::Warn {
+            let mut warning = String::from("Warning: ");
+            let mut chars = message.chars();
+            warning.extend(chars.next().map(|c| c.to_ascii_lowercase()));
+            warning.push_str(chars.as_str());
+            warning.push_str("; leaving the command running");
+            self.output.notice(&warning);
+            if kind == TimeoutKind::Idle {
+                self.restart_idle_timeout();
+            } else {
+                self.run_timeout = Timeout::Never;
+            }
+            return Ok(());
+        }
+
+        self.stats.timed_out = Some(option);
+        self.timed_out = true;
+        self.output.timed_out(match kind {
+            TimeoutKind::Idle => runner::TimeoutKind::Idle,
+            _ => runner::TimeoutKind::Run,
+        });
+        self.output.release()?;
+        self.output.notice(&message);
+        let signal = match action {
+            TimeoutAction::Term => Signal::SIGTERM,
+            TimeoutAction::Kill => Signal::SIGKILL,
+            TimeoutAction::Warn | TimeoutAction::Exit => {
+                self.leaving = Some(i32::from(self.params.timeout_exit_code));
+                return Ok(());
+            }
+        };
+
+        self.run_timeout = Timeout::Never;
+        self.stop_idle_timeout();
+        self.child.signal(signal)?;
+        self.start_terminating();
+        if signal == Signal::SIGKILL {
+            // There’s nothing left to escalate to.
+            self.kill_timeout = Timeout::Never;
+        }
+        Ok(())
+    }
+
+    /// Get the option that set a run or idle timeout that expired, and the
+    /// message to display about it.
+    fn describe_timeout(
+        &self,
+        kind: TimeoutKind,
+        expired: &Timeout,
+    ) -> (&'static str, String) {
+        let elapsed = expired.elapsed_rounded();
+        match kind {
+            TimeoutKind::Run
+                if matches!(self.run_timeout, Timeout::At { .. }) =>
+            {
+                ("--deadline", format!("Deadline reached after {elapsed:?}"))
+            }
+            TimeoutKind::Idle if self.is_starting() => (
+                "--start-timeout",
+                format!(
+                    "Timed out waiting for the first output after {elapsed:?}"
+                ),
+            ),
+            TimeoutKind::Idle => {
+                ("--idle-timeout", timeout_message(kind, expired))
+            }
+            _ => ("--run-timeout", timeout_message(kind, expired)),
+        }
+    }
+
+    /// Start waiting for the child to exit after sending it a termination
+    /// signal.
+    ///
+    /// If `--kill-after` was passed, this starts the timeout to kill the
+    /// child. It does nothing if the child is already being terminated.
+    fn start_terminating(&mut self) {
+        if !self.terminating {
+            self.terminating = true;
+            self.stop_idle_timeout();
+            self.warnings.clear();
+            self.run_warning = None;
+            self.kill_timeout = Timeout::from(self.params.kill_after).start();
+        }
+    }
+
+    /// Handle the child’s output matching `--kill-on-pattern` or
+    /// `--ready-pattern`, or going over `--max-output-bytes` or
+    /// `--max-output-lines`.
+    fn check_output(&mut self) -> io::Result<()> {
+        self.kill_on_pattern()?;
+        self.check_limit()?;
+        self.check_ready()
+    }
+
+    /// Note that output was truncated if it went over a limit, and terminate
+    /// the child if `--max-output-kill` was passed.
+    fn check_limit(&mut self) -> nix::Result<()> {
+        while let Some(message) = self.output.take_limit_exceeded() {
+            if self.params.max_output_kill && !self.terminating {
+                self.output.notice(&format!("{message}; terminating it"));
+                self.child.signal(Signal::SIGTERM)?;
+                self.start_terminating();
+            } else {
+                self.output.notice(&message);
+            }
+        }
+        Ok(())
+    }
+
+    /// Terminate the child if its output matched `--kill-on-pattern`.
+    ///
+    /// If `--kill-after` was passed, the child is killed if it doesn’t exit in
+    /// time.
+    fn kill_on_pattern(&mut self) -> nix::Result<()> {
+        let Some(pattern) = self.output.take_kill_pattern() else {
+            return Ok(());
+        };
+        if !self.terminating {
+            let signal = self.params.pattern_signal;
+            self.output.notice(&format!(
+                "Output matched --kill-on-pattern {pattern:?}; sending {signal}"
+            ));
+            self.child.signal(signal)?;
+            self.start_terminating();
+        }
+        Ok(())
+    }
+
+    /// Note that the child is ready if its output matched `--ready-pattern`,
+    /// and exit or terminate it if `--ready-exit` was passed, or stop
+    /// processing its output if `--wait-only` was passed.
+    fn check_ready(&mut self) -> io::Result<()> {
+        let Some(pattern) = self.output.take_ready_pattern() else {
+            return Ok(());
+        };
+        let message = format!(
+            "Command is ready: output matched --ready-pattern {pattern:?}"
+        );
+        self.notify(|notifier| notifier.ready());
+        match self.params.ready_exit {
+            None => {
+                self.output.notice(&message);
+                if self.params.wait_only {
+                    self.output.pass_through()?;
+                }
+            }
+            Some(ReadyExit::Leave) => {
+                self.output
+                    .notice(&format!("{message}; leaving it running"));
+                self.leaving = Some(0);
+            }
+            Some(ReadyExit::Kill) => {
+                self.output.notice(&format!("{message}; terminating it"));
+                self.ready = true;
+                if !self.terminating {
+                    self.child.signal(Signal::SIGTERM)?;
+                    self.start_terminating();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a `--warn-at`, `--run-warn`, or `--idle-warn` warning being
+    /// due.
+    fn expired_warning(&mut self, kind: TimeoutKind) {
+        match kind {
+            TimeoutKind::Warn => {
+                if let Some((at, _)) = self.warnings.pop_front() {
+                    self.warn(&self.run_warning_message(at), at);
+                }
+            }
+            TimeoutKind::RunWarn => {
+                if let Some(warning) = self.run_warning.take() {
+                    self.warn(&self.run_warning_message(warning.0), warning.0);
+                    self.run_warning =
+                        self.repeat_warning(warning, self.params.run_warn);
+                }
+            }
+            TimeoutKind::IdleWarn => {
+                if let Some(warning) = self.idle_warning.take() {
+                    let elapsed = self.start.elapsed().monotonic;
+                    let at = warning.0;
+                    self.warn(
+                        &format!("Warning: no output for {at:?}"),
+                        elapsed,
+                    );
+                    self.idle_warning =
+                        self.repeat_warning(warning, self.params.idle_warn);
+                }
+            }
+            _ => unreachable!("{kind:?} isn’t a warning"),
+        }
+    }
+
+    /// Update the `--status-line`, if there is one.
+    ///
+    /// Unless `force` is set, this doesn’t redraw it if it was drawn very
+    /// recently.
+    fn draw_status(&mut self, force: bool) {
+        if self.status_tick.is_none() {
+            return;
+        }
+        let text = status_line::text(
+            self.start.elapsed().monotonic,
+            self.stats.out_bytes.saturating_add(self.stats.err_bytes),
+            self.last_output.map(|last| last.elapsed()),
+        );
+        self.output.draw_status(&text, force);
+    }
+
+    /// Send a notification to systemd, if `--sd-notify` was passed.
+    ///
+    /// If it fails, this warns and stops sending notifications, since the
+    /// child shouldn’t be affected by systemd going away.
+    fn notify<F>(&mut self, send: F)
+    where
+        F: FnOnce(&mut Notifier) -> io::Result<()>,
+    {
+        let Some(notifier) = &mut self.notifier else {
+            return;
+        };
+        if let Err(error) = send(notifier) {
+            self.notify_failed(&error);
+        }
+    }
+
+    /// Tell systemd about `count` bytes of output from the child’s `stream`
+    /// that were read into the buffer, if `--sd-notify` was passed.
+    fn notify_output(&mut self, stream: Stream, count: usize) {
+        let Some(notifier) = &mut self.notifier else {
+            return;
+        };
+        if let Err(error) = notifier.output(stream, &self.buffer[..count]) {
+            self.notify_failed(&error);
+        }
+    }
+
+    /// Warn that a notification to systemd failed, and stop sending them.
+    fn notify_failed(&mut self, error: &io::Error) {
+        self.output.notice(&format!(
+            "Warning: could not notify systemd: {error}; not trying again"
+        ));
+        self.notifier = None;
+        self.notify_tick = None;
+    }
+
+    /// Update the status reported to systemd, if `--sd-notify` was passed.
+    fn notify_status(&mut self) {
+        let elapsed = self.start.elapsed().monotonic;
+        self.notify(|notifier| notifier.update_status(elapsed));
+    }
+
+    /// Output a `--heartbeat` status line, and start waiting for the next one.
+    fn heartbeat(&mut self) {
+        let elapsed = gap::describe(self.start.elapsed().monotonic);
+        let last_output = self.last_output.map_or_else(
+            || "no output yet".to_owned(),
+            |last| format!("last output {} ago", gap::describe(last.elapsed())),
+        );
+        self.output.heartbeat(&format!(
+            "still running, {elapsed} elapsed, {last_output}"
+        ));
+        self.heartbeat = self
+            .params
+            .heartbeat
+            .map(|every| Timeout::from(every).start());
+    }
+
+    /// Get the warning that the child is still running after `at`.
+    fn run_warning_message(&self, at: Duration) -> String {
+        match self.params.run_timeout {
+            Some(run_timeout) => format!(
+                "Warning: still running after {at:?} (run timeout is \
+                {run_timeout:?})"
+            ),
+            None => format!("Warning: still running after {at:?}"),
+        }
+    }
+
+    /// Get the warning after `warning` if `--warn-repeat` was passed. `every`
+    /// is how often to repeat it.
+    fn repeat_warning(
+        &self,
+        (at, timeout): (Duration, Timeout),
+        every: Option<Duration>,
+    ) -> Option<(Duration, Timeout)> {
+        let every = every
+            .filter(|every| self.params.warn_repeat && !every.is_zero())?;
+        Some((at.saturating_add(every), timeout.extend(every)))
+    }
+
+    /// Issue a warning, and run `--warn-command` if it was passed. `elapsed`
+    /// is how long the child has been running.
+    fn warn(&mut self, message: &str, elapsed: Duration) {
+        self.output.notice(message);
+
+        if let Some(command) = &self.params.warn_command {
+            // The hook is never waited for; if it’s still running when we
+            // exit, it will be reaped by init.
+            let result = process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .env("REDERR_WARNING", message)
+                .env("REDERR_ELAPSED_MS", elapsed.as_millis().to_string())
+                .stdin(process::Stdio::null())
+                .spawn();
+            if let Err(error) = result {
+                self.output
+                    .notice(&format!("Could not run --warn-command: {error}"));
+            }
+        }
+    }
+
+    /// Wait for the child to exit and get the code `rederr` should exit with.
+    ///
+    /// If the child succeeded but its output didn’t pass `--fail-pattern` or
+    /// `--success-pattern`, this says why.
+    fn exit_code(&mut self) -> nix::Result<i32> {
+        if let Some(code) = self.leaving {
+            return Ok(code);
+        }
+        let code = self.child.wait()?;
+        let pattern_failure = self.output.pattern_failure();
+        Ok(match self.params.fail_on_stderr {
+            _ if self.timed_out => i32::from(self.params.timeout_exit_code),
+            _ if self.ready => 0,
+            Some(stderr_code) if code == 0 && self.stats.err_bytes > 0 => {
+                i32::from(stderr_code)
+            }
+            _ if code == 0 => pattern_failure.map_or(0, |reason| {
+                self.output.notice(&reason);
+                1
+            }),
+            _ => code,
+        })
+    }
+
+    /// Should the run be retried, given that `rederr` would exit with `code`?
+    ///
+    /// This doesn’t check whether there are retries left.
+    fn should_retry(&self, code: i32) -> bool {
+        if self.leaving.is_some() {
+            false
+        } else if self.timed_out {
+            self.params.retry_on_timeout
+        } else {
+            code != 0
+                && !self.interrupted
+                && (self.params.retry_on_codes.is_empty()
+                    || self
+                        .params
+                        .retry_on_codes
+                        .iter()
+                        .any(|retry_code| i32::from(*retry_code) == code))
+        }
+    }
+
+    /// Stop tracking the run and get the output back, e.g. to retry.
+    fn into_output(self) -> Output<'a> {
+        self.output
+    }
+
+    /// Print the `--summary`, finish the `--log-dir` log, and write the
+    /// `--status-file`, given that `rederr` is exiting with `code`.
+    fn finish(mut self, code: i32) {
+        self.output.clear_status();
+        if self.params.summary {
+            self.output
+                .notice(&self.stats.summary(&self.start.elapsed()));
+        }
+        if let Err(error) = self.output.drain_backlog() {
+            notice!("Error: {error:#}");
+        }
+        self.send_notifications(code);
+        self.output.log_exit(code);
+
+        if let Some(path) = &self.params.status_file {
+            let time_zone = self.params.time_zone();
+            let killed_by = self.child.killed_by();
+            let status = Status {
+                command: self.params.program().unwrap_or_default(),
+                args: self.params.program_args(),
+                start: Timestamp::wall(self.start.wall(), time_zone),
+                end: Timestamp::wall(SystemTime::now(), time_zone),
+                duration: self.start.elapsed().monotonic,
+                exit_code: code,
+                child_exit_code: self
+                    .child
+                    .exit_code()
+                    .filter(|_| killed_by.is_none()),
+                signal: killed_by,
+                timed_out: self.timed_out,
+                stats: &self.stats,
+                cgroup: self.cgroup.as_ref().map(Cgroup::usage),
+                rusage: self.rusage,
+            };
+            if let Err(error) = status.write(path) {
+                notice!("Error: {error:#}");
+            }
+        }
+    }
+
+    /// Tell `--ping-url`, `--webhook`, and `--mail-to` how the run went,
+    /// given that `rederr` is exiting with `code`.
+    ///
+    /// This must be called before the `--status-file` is written, since
+    /// `--mail-on=change` reads the last run’s status from it. Failures are
+    /// reported but otherwise ignored.
+    fn send_notifications(&mut self, code: i32) {
+        if !self.params.keeps_tail() {
+            return;
+        }
+        let (output, truncated) = self.output.tail();
+        let command = command_line(self.params);
+        let report = Report {
+            command: &command,
+            host: report::hostname(),
+            exit_code: code,
+            signal: self.child.killed_by(),
+            timed_out: self.timed_out,
+            duration: self.start.elapsed().monotonic,
+            output: String::from_utf8_lossy(&output).into_owned(),
+            truncated,
+        };
+
+        let mut errors = Vec::new();
+        if let Some(ping) = Ping::new(self.params) {
+            errors.extend(
+                if report.succeeded() {
+                    ping.success()
+                } else {
+                    ping.fail(&output)
+                }
+                .err(),
+            );
+        }
+        if let Some(url) = &self.params.webhook {
+            if self.params.webhook_always || !report.succeeded() {
+                let template = self.params.webhook_template.as_ref();
+                errors.extend(webhook::send(url, &report, template).err());
+            }
+        }
+        if let Some(mail) = Mail::new(self.params) {
+            let previous = self
+                .params
+                .status_file
+                .as_deref()
+                .and_then(status::read_succeeded);
+            if mail.wanted(&report, previous) {
+                errors.extend(mail.send(&report).err());
+            }
+        }
+        for error in errors {
+            self.output.notice(&format!("Warning: {error:#}"));
+        }
+    }
+
+    /// Read whatever is available from a child output stream and output it.
+    fn read<R: Read + AsFd>(
+        &mut self,
+        stream: Stream,
+        reader: &mut R,
+    ) -> anyhow::Result<()> {
+        if !self.open_streams.contains(&stream) {
+            // We stopped reading it after the child exited (see
+            // `--no-linger`).
+            return Ok(());
+        }
+        if self.splice(stream, reader.as_fd())? {
+            return Ok(());
+        }
+
+        loop {
+            let limit = self.chaos.read_len(self.buffer.len());
+            let count = match reader.read(&mut self.buffer[..limit]) {
+                Ok(count) => count,
+                // Reading from a terminal whose other end was closed fails
+                // with EIO rather than returning EOF.
+                Err(err) if err.raw_os_error() == Some(Errno::EIO as i32) => 0,
+                Err(err) => {
+                    if err.kind() == io::ErrorKind::WouldBlock {
+                        // Done reading.
+                        self.output
+                            .debug(format_args!("io::ErrorKind::WouldBlock"));
+
+                        return Ok(());
+                    }
+
+                    return Err(err.into());
+                }
+            };
+
+            // Writes block, so if our output is backed up we stop reading from
+            // the child and the kernel will block it when the pipe fills up. If
+            // --backpressure was passed, don’t count that time against the
+            // child.
+            let write_start = Instant::now();
+            if self.output.debug.is_some() {
+                self.output.debug(format_args!(
+                    "read {} bytes {:?}",
+                    count,
+                    self.buffer[..count].as_bstr()
+                ));
+            } else if count > 0 {
+                // Only output if there’s something to output.
+                self.notify_output(stream, count);
+                match self.params.inject_latency {
+                    Some((delayed, delay)) if delayed == stream => {
+                        self.delayed.push_back((
+                            Timeout::from(delay).start(),
+                            stream,
+                            self.buffer[..count].to_vec(),
+                        ));
+                    }
+                    _ => self.output.write(stream, &self.buffer[..count])?,
+                }
+                self.check_output()?;
+            }
+
+            let write_time = write_start.elapsed();
+            self.stats.record_read(stream, count, limit);
+            if self.params.summary {
+                self.stats.record_lines(stream, &self.buffer[..count]);
+            }
+            self.buffer.record(count, limit);
+            self.stats.record_write(write_time, count == limit);
+            if self.params.backpressure {
+                self.run_timeout = self.run_timeout.extend(write_time);
+                for (_, warning) in &mut self.warnings {
+                    *warning = warning.extend(write_time);
+                }
+                if let Some((_, warning)) = &mut self.run_warning {
+                    *warning = warning.extend(write_time);
+                }
+            }
+
+            if count > 0 {
+                self.record_output();
+            }
+
+            if count < limit
+                || self.is_backed_up(&PollKey::Output(stream))
+                || self.leaving.is_some()
+            {
+                // We could read again and get either 0 bytes or
+                // io::ErrorKind::WouldBlock, but I think this check makes it
+                // more likely the output ordering is correct. A partial read
+                // indicates that the stream had stopped, so we should check to
+                // see if another stream is ready.
+                //
+                // If too much output is waiting to be written, reading stops
+                // until some of it is (see `--max-buffer`). Once we’re
+                // leaving the child running, nothing more is read.
+                return Ok(());
+            }
+        }
+    }
+
+    /// Move whatever is available from a child output stream straight to our
+    /// output with `splice()`, if the stream can be spliced.
+    ///
+    /// Returns `false` if the rest of the stream should be read as usual. That
+    /// includes when `splice()` would block, which might be because our
+    /// output is full rather than because the child’s pipe is empty. A normal
+    /// read and write handles both.
+    fn splice(
+        &mut self,
+        stream: Stream,
+        from: BorrowedFd<'_>,
+    ) -> anyhow::Result<bool> {
+        let Some(to) = self.output.splice.target(stream) else {
+            return Ok(false);
+        };
+
+        loop {
+            let limit = self.buffer.len();
+            let write_start = Instant::now();
+            let count = match splice::splice(from, to, limit) {
+                Ok(count) => count,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(false);
+                }
+                Err(error) if error.kind() == io::ErrorKind::Unsupported => {
+                    self.output.splice.disable(stream);
+                    return Ok(false);
+                }
+                Err(error) => return Err(error.into()),
+            };
+            self.check_output()?;
+
+            let write_time = write_start.elapsed();
+            self.stats.record_read(stream, count, limit);
+            self.buffer.record(count, limit);
+            self.stats.record_write(write_time, count == limit);
+            if self.params.backpressure {
+                self.run_timeout = self.run_timeout.extend(write_time);
+                for (_, warning) in &mut self.warnings {
+                    *warning = warning.extend(write_time);
+                }
+                if let Some((_, warning)) = &mut self.run_warning {
+                    *warning = warning.extend(write_time);
+                }
+            }
+
+            if count > 0 {
+                self.record_output();
+            }
+
+            if count < limit {
+                // See the comment at the end of `read()`.
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Handle a signal received by `rederr`. `streams` are the child’s output
+    /// streams, which are the masters of its terminals if we’re resizing them
+    /// (see `--pty`).
+    fn handle_signal(
+        &mut self,
+        signal: Signal,
+        streams: [&Option<File>; 2],
+    ) -> anyhow::Result<()> {
+        self.output.debug(format_args!("received {signal}"));
+
+        if signal == Signal::SIGCHLD {
+            while let Some(change) = self.child.check()? {
+                self.handle_child_change(change)?;
+            }
+            self.reap_orphans()?;
+        } else if signals::FORWARD.contains(&signal) {
+            self.interrupted = true;
+            self.child.signal(signal)?;
+            self.start_terminating();
+        } else if signal == Signal::SIGWINCH {
+            // Only caught with --pty.
+            for master in streams.into_iter().flatten() {
+                pty::resize(master).context("resizing terminal")?;
+            }
+            // The terminals aren’t the child’s controlling terminal, so it
+            // won’t get this from the kernel.
+            self.child.signal(signal)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reap orphans that have exited once the child has exited, and report how
+    /// many there were once they all have (see `--subreaper`).
+    fn reap_orphans(&mut self) -> nix::Result<()> {
+        let Some(orphans) = &mut self.orphans else {
+            return Ok(());
+        };
+        if !self.child.has_exited() || !orphans.is_remaining() {
+            return Ok(());
+        }
+
+        orphans.reap()?;
+        match orphans.reaped() {
+            _ if orphans.is_remaining() => {}
+            0 => {}
+            1 => self.output.notice("Reaped 1 process left by the command"),
+            count => self.output.notice(&format!(
+                "Reaped {count} processes left by the command"
+            )),
+        }
+        Ok(())
+    }
+
+    /// Warn about invalid UTF-8 in the output with `--utf8=strict`.
+    fn warn_invalid_utf8(&mut self) {
+        if self.params.utf8_mode() != Some(Utf8Mode::Strict) {
+            return;
+        }
+        if let Some(count @ 1..) = self.output.invalid_utf8_count() {
+            self.output.notice(&format!(
+                "Warning: output contained {count} invalid UTF-8 sequence{}",
+                if count == 1 { "" } else { "s" },
+            ));
+        }
+    }
+
+    /// Handle the child being stopped, continued, or exiting.
+    fn handle_child_change(&mut self, change: Change) -> anyhow::Result<()> {
+        self.output.debug(format_args!("child changed: {change:?}"));
+
+        match change {
+            Change::Stopped(signal) => {
+                // The child can’t produce output while it’s stopped.
+                self.stop_idle_timeout();
+                self.stopped = true;
+                if self.params.forward_stop {
+                    self.output.notice(&format!(
+                        "Child stopped by {signal}; stopping"
+                    ));
+                    signal::raise(Signal::SIGSTOP)?;
+                    // We’ve been continued. The child might exit before we
+                    // can see that it was continued, so don’t wait for that.
+                    self.child.signal(Signal::SIGCONT)?;
+                    self.handle_child_change(Change::Continued)?;
+                } else {
+                    self.output.notice(&format!(
+                        "Child stopped by {signal}; waiting for it to continue"
+                    ));
+                }
+            }
+            Change::Continued => {
+                if self.stopped {
+                    self.output.notice("Child continued");
+                    self.stopped = false;
+                    self.restart_idle_timeout();
+                }
+            }
+            Change::Exited(_) => {
+                // The child has been reaped, so its usage is included now.
+                self.rusage = self.rusage_start.and_then(|start| {
+                    Some(Rusage::children().ok()?.since(&start))
+                });
+                self.report_limit();
+
+                // Wait for output to be closed. Something the child started
+                // might keep it open, so stop waiting or report that after a
+                // while.
+                let linger = self.params.no_linger || self.params.verbose > 0;
+                if linger && !self.open_streams.is_empty() {
+                    self.linger_timeout =
+                        Timeout::from(self.params.linger_timeout).start();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Report if the child was killed for exceeding a `--limit-*` option.
+    fn report_limit(&mut self) {
+        let Some(signal) = self.child.killed_by() else {
+            return;
+        };
+        let option = match signal {
+            Signal::SIGXCPU if self.params.limit_cpu.is_some() => "--limit-cpu",
+            Signal::SIGXFSZ if self.params.limit_fsize.is_some() => {
+                "--limit-fsize"
+            }
+            _ => return,
+        };
+        self.output
+            .notice(&format!("Child killed by {signal}; it exceeded {option}"));
+    }
+}
+
+/// Where to write the child’s output.
+struct Output<'a> {
+    /// Formats and writes output.
+    writer: Writer<'a>,
+
+    /// Checks for invalid UTF-8 (see `--utf8`).
+    utf8: Option<Utf8Filter>,
+
+    /// Partial lines held back by `--line-buffered`.
+    lines: Option<LineBuffer>,
+
+    /// Watches lines for patterns (see `--fail-pattern` and
+    /// `--success-pattern`).
+    patterns: Option<Patterns>,
+
+    /// Drops lines (see `--grep` and `--exclude`).
+    filter: Option<Filter>,
+
+    /// Notices long silences (see `--gap-marker`).
+    gaps: Option<GapMarker>,
+
+    /// Watches for writes that take too long (see `--watchdog`).
+    watchdog: Watchdog,
+
+    /// Where to move output without copying it (see `--no-splice`).
+    splice: Splice,
+
+    /// Whether to pass output through untouched, because the command is
+    /// ready and `--wait-only` was passed.
+    untouched: bool,
+
+    /// What happened, if it’s being handed back to [`crate::Runner`] rather
+    /// than written.
+    events: Option<VecDeque<runner::Event>>,
+
+    /// Where to write debugging information, if `--debug` was passed.
+    debug: Option<Box<dyn Write>>,
+}
+
+impl<'a> Output<'a> {
+    /// Set up output streams based on `params`.
+    fn new(params: &'a Params) -> anyhow::Result<Self> {
+        let mut colors = Colors {
+            out: params.out_color(),
+            err: ColorSpec::new(),
+            highlights: params.highlight.clone(),
+            slow: ColorSpec::new(),
+        };
+        colors.err.set_fg(Some(Color::Red)).set_intense(true);
+        colors.slow.set_fg(Some(Color::Yellow)).set_bold(true);
+
+        let (out, err) = (params.out_stream(), params.err_stream());
+        let capture = params.quiet_on_success.then(|| Capture::new(&out, &err));
+        let queued = params
+            .max_buffer
+            .map(|max| Queued::new(max, params.separate, &out, &err))
+            .transpose()
+            .context("setting up --max-buffer")?;
+        let mut formatter = Formatter::new(
+            params.tags(),
+            params.prefix_seq,
+            Clock::new(params.timestamps, params.time_zone().clone()),
+        );
+        if params.timing {
+            formatter = formatter.with_timing(params.slow_threshold);
+        }
+        let mut output = Self {
+            writer: Writer {
+                sink: Sink {
+                    out,
+                    err,
+                    colors,
+                    capture,
+                    queued,
+                    log: Log::open(params)?,
+                    record: create_recording(params)?,
+                    status: params
+                        .shows_status_line()
+                        .then(StatusLine::default),
+                    tail: params.keeps_tail().then(Tail::default),
+                },
+                formatter,
+                dedup: params.dedup.then(Dedup::default),
+                deduped: Vec::new(),
+                head_tail: HeadTail::new(params.head, params.tail),
+                limit: Limit::new(
+                    params.max_output_bytes,
+                    params.max_output_lines,
+                ),
+                sink_filters: SinkFilters::new(
+                    &params.sink_grep,
+                    &params.sink_exclude,
+                    params.filter_stream,
+                ),
+                transform: if params.hex {
+                    Some(Transform::Hex(HexDump::default()))
+                } else {
+                    params.sanitize.then_some(Transform::Sanitize)
+                },
+                transformed: Vec::new(),
+            },
+            utf8: params.utf8_mode().map(Utf8Filter::new),
+            patterns: Patterns::new(
+                &params.fail_pattern,
+                &params.success_pattern,
+                &params.kill_on_pattern,
+                &params.ready_pattern,
+            ),
+            filter: Filter::new(
+                &params.grep,
+                &params.exclude,
+                params.filter_stream,
+                &params.discarded_streams(),
+            ),
+            lines: params
+                .line_buffered()
+                .then(|| LineBuffer::new(line_buffer::MAX_PARTIAL_LINE)),
+            gaps: params.gap_marker.map(GapMarker::new),
+            watchdog: Watchdog::new(params.watchdog),
+            splice: Splice::default(),
+            untouched: false,
+            events: None,
+            debug: None,
+        };
+        if params.passthrough() && output.passes_through() {
+            let sink = &output.writer.sink;
+            output.splice = Splice::new(
+                params.separate,
+                (&sink.out, &sink.colors.out),
+                (&sink.err, &sink.colors.err),
+            );
+        }
+        Ok(output)
+    }
+
+    /// Would the child’s output be written unchanged, with nothing else written
+    /// to the same streams, and not copied anywhere else? If so, it can be
+    /// spliced (see `--no-splice`).
+    const fn passes_through(&self) -> bool {
+        let writer = &self.writer;
+        let sink = &writer.sink;
+        writer.formatter.is_identity()
+            && !writer.formatter.includes_notices()
+            && writer.dedup.is_none()
+            && writer.head_tail.is_none()
+            && writer.limit.is_none()
+            && writer.sink_filters.is_none()
+            && writer.transform.is_none()
+            && sink.capture.is_none()
+            && sink.queued.is_none()
+            && sink.log.is_none()
+            && sink.record.is_none()
+            && sink.status.is_none()
+            && sink.tail.is_none()
+            && self.utf8.is_none()
+            && self.lines.is_none()
+            && self.patterns.is_none()
+            && self.filter.is_none()
+            && self.gaps.is_none()
+    }
+
+    /// Forget everything about the last run before retrying: output held back
+    /// by `--quiet-on-success`, how much output counts against the limits,
+    /// lines held back by `--dedup`, `--head`, and `--tail`, and the offsets
+    /// for `--hex`.
+    ///
+    /// Logs, the recording, and the tail for notifications are kept, since
+    /// they cover every run.
+    fn reset(&mut self, params: &Params) {
+        let writer = &mut self.writer;
+        let sink = &mut writer.sink;
+        sink.capture = params
+            .quiet_on_success
+            .then(|| Capture::new(&sink.out, &sink.err));
+        writer.dedup = params.dedup.then(Dedup::default);
+        writer.head_tail = HeadTail::new(params.head, params.tail);
+        writer.limit =
+            Limit::new(params.max_output_bytes, params.max_output_lines);
+        if let Some(Transform::Hex(hex)) = &mut writer.transform {
+            *hex = HexDump::default();
+        }
+    }
+
+    /// Write a chunk of output from the child’s `stream`.
+    fn write(&mut self, stream: Stream, bytes: &[u8]) -> io::Result<()> {
+        if let Some(events) = &mut self.events {
+            events.push_back(match stream {
+                Stream::Out => runner::Event::Stdout(bytes.to_vec()),
+                Stream::Err => runner::Event::Stderr(bytes.to_vec()),
+            });
+            return Ok(());
+        }
+        if self.untouched {
+            let (mut stdout, mut stderr) = (io::stdout(), io::stderr());
+            let to: &mut dyn Write = match stream {
+                Stream::Out => &mut stdout,
+                Stream::Err => &mut stderr,
+            };
+            to.write_all(bytes)?;
+            return to.flush();
+        }
+        let _busy = self.watchdog.busy();
+        if let Some(silence) = self.gaps.as_mut().and_then(GapMarker::output) {
+            self.writer.marker(gap::message(silence).as_bytes())?;
+        }
+        match &mut self.utf8 {
+            Some(utf8) => utf8.push(stream, bytes, |bytes| {
+                write_lines(
+                    &mut self.lines,
+                    self.patterns.as_mut(),
+                    self.filter.as_ref(),
+                    &mut self.writer,
+                    stream,
+                    bytes,
+                )
+            }),
+            None => write_lines(
+                &mut self.lines,
+                self.patterns.as_mut(),
+                self.filter.as_ref(),
+                &mut self.writer,
+                stream,
+                bytes,
+            ),
+        }
+    }
+
+    /// Write anything held back from `stream`, which has been closed.
+    fn finish(&mut self, stream: Stream) -> io::Result<()> {
+        if let Some(utf8) = &mut self.utf8 {
+            let _busy = self.watchdog.busy();
+            utf8.finish(stream, |bytes| {
+                write_lines(
+                    &mut self.lines,
+                    self.patterns.as_mut(),
+                    self.filter.as_ref(),
+                    &mut self.writer,
+                    stream,
+                    bytes,
+                )
+            })?;
+        }
+        self.flush_lines(stream)?;
+        let _busy = self.watchdog.busy();
+        self.writer.finish(stream)
+    }
+
+    /// Write the partial line held back from `stream` by `--line-buffered`.
+    fn flush_lines(&mut self, stream: Stream) -> io::Result<()> {
+        let _busy = self.watchdog.busy();
+        match &mut self.lines {
+            Some(lines) => lines.flush(stream, |bytes| {
+                write_filtered(
+                    self.patterns.as_mut(),
+                    self.filter.as_ref(),
+                    &mut self.writer,
+                    stream,
+                    bytes,
+                )
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Stop processing output, and pass it through untouched to our stdout
+    /// and stderr (see `--wait-only`). Partial lines that were held back are
+    /// written first.
+    fn pass_through(&mut self) -> io::Result<()> {
+        self.flush_lines(Stream::Out)?;
+        self.flush_lines(Stream::Err)?;
+        self.writer.sink.out.flush()?;
+        self.writer.sink.err.flush()?;
+        self.lines = None;
+        self.patterns = None;
+        self.untouched = true;
+        let plain = ColorSpec::new();
+        let sink = &self.writer.sink;
+        self.splice =
+            Splice::new(true, (&sink.out, &plain), (&sink.err, &plain));
+        Ok(())
+    }
+
+    /// Get the `--kill-on-pattern` that matched, if one did and it hasn’t
+    /// been handled yet.
+    fn take_kill_pattern(&mut self) -> Option<String> {
+        self.patterns
+            .as_mut()
+            .and_then(Patterns::take_kill)
+            .map(str::to_owned)
+    }
+
+    /// Get a message about a stream that went over `--max-output-bytes` or
+    /// `--max-output-lines`, if one did and it hasn’t been handled yet.
+    fn take_limit_exceeded(&mut self) -> Option<String> {
+        self.writer.limit.as_mut().and_then(Limit::take_exceeded)
+    }
+
+    /// Get the `--ready-pattern` that matched, if one did and it hasn’t been
+    /// handled yet.
+    fn take_ready_pattern(&mut self) -> Option<String> {
+        self.patterns
+            .as_mut()
+            .and_then(Patterns::take_ready)
+            .map(str::to_owned)
+    }
+
+    /// Get why the run failed based on `--fail-pattern` or `--success-pattern`,
+    /// if it did, and start watching a new run.
+    fn pattern_failure(&mut self) -> Option<String> {
+        self.patterns.as_mut().and_then(Patterns::finish)
+    }
+
+    /// Number of invalid UTF-8 sequences seen, if `--utf8` was passed.
+    fn invalid_utf8_count(&self) -> Option<u64> {
+        self.utf8.as_ref().map(Utf8Filter::invalid_count)
+    }
+
+    /// Write output held back by `--quiet-on-success`, and stop holding back
+    /// output.
+    fn release(&mut self) -> io::Result<()> {
+        let _busy = self.watchdog.busy();
+        self.writer.sink.release()
+    }
+
+    /// Record how `rederr` is exiting in the `--log-dir` log, if there is one.
+    fn log_exit(&mut self, code: i32) {
+        self.writer.sink.log_exit(code);
+    }
+
+    /// Get the end of the output, if it was kept for notifications (see
+    /// `--ping-url` and `--webhook`), and whether its start was dropped.
+    fn tail(&self) -> (Vec<u8>, bool) {
+        self.writer
+            .sink
+            .tail
+            .as_ref()
+            .map(|tail| (tail.contents(), tail.is_truncated()))
+            .unwrap_or_default()
+    }
+
+    /// Output waiting to be written, if `--max-buffer` was passed.
+    fn backlog(&self) -> Option<&Backlog> {
+        self.writer
+            .sink
+            .queued
+            .as_ref()
+            .map(|queued| &queued.backlog)
+    }
+
+    /// Write what we can of the output waiting for our `destination`, which
+    /// is writable (see `--max-buffer`).
+    fn write_backlog(&mut self, destination: Stream) -> io::Result<()> {
+        match &mut self.writer.sink.queued {
+            Some(queued) => queued.backlog.write(destination),
+            None => Ok(()),
+        }
+    }
+
+    /// Write all the output waiting to be written, blocking if necessary (see
+    /// `--max-buffer`).
+    fn drain_backlog(&mut self) -> io::Result<()> {
+        let _busy = self.watchdog.busy();
+        match &mut self.writer.sink.queued {
+            Some(queued) => queued.backlog.drain(),
+            None => Ok(()),
+        }
+    }
+
+    /// Write debugging information, if `--debug` was passed.
+    ///
+    /// This ignores errors, like [`notice!`].
+    fn debug(&mut self, message: fmt::Arguments<'_>) {
+        if let Some(debug) = &mut self.debug {
+            let _ = writeln!(debug, "{message}");
+        }
+    }
+
+    /// Display a message from `rederr` itself.
+    ///
+    /// With `--tag`, this goes in the output along with the child’s stderr, so
+    /// that it shows up in the right place relative to the child’s output.
+    /// Otherwise, it goes to stderr.
+    ///
+    /// With `--quiet-on-success`, the message is held back with the output.
+    /// It’s always written to the `--log-file` and the `--record` recording, if
+    /// there are any.
+    ///
+    /// Partial lines held back by `--line-buffered` are written first, since
+    /// we might be about to exit.
+    ///
+    /// This ignores errors writing the message, like [`notice!`]. It does
+    /// nothing if the output is going to [`crate::Runner`].
+    fn notice(&mut self, message: &str) {
+        if self.events.is_some() {
+            return;
+        }
+        for stream in [Stream::Out, Stream::Err] {
+            let _ = self.flush_lines(stream);
+        }
+
+        let _busy = self.watchdog.busy();
+        let writer = &mut self.writer;
+        if writer.formatter.includes_notices() {
+            for segment in writer.formatter.notice(message.as_bytes()) {
+                let _ = writer.sink.render(&segment);
+            }
+            return;
+        }
+
+        writer.sink.log_notice(message);
+        if let Some(capture) = &mut writer.sink.capture {
+            // This is replayed along with the child’s stderr, which goes to
+            // stdout unless --separate was passed.
+            let _ = capture.spool.push(Stream::Err, message.as_bytes());
+            let _ = capture.spool.push(Stream::Err, b"\n");
+        } else {
+            let _ = writer.sink.clear_status();
+            notice!("{message}");
+        }
+    }
+
+    /// Record that a run or idle timeout expired, if the output is going to
+    /// [`crate::Runner`].
+    fn timed_out(&mut self, kind: runner::TimeoutKind) {
+        if let Some(events) = &mut self.events {
+            events.push_back(runner::Event::TimedOut(kind));
+        }
+    }
+
+    /// Display a status line from `rederr` itself (see `--heartbeat`).
+    ///
+    /// This ignores errors writing the line, like [`Output::notice()`].
+    fn heartbeat(&mut self, message: &str) {
+        let _busy = self.watchdog.busy();
+        let _ = self.writer.heartbeat(message.as_bytes());
+    }
+
+    /// Is there a status line (see `--status-line`)?
+    const fn has_status_line(&self) -> bool {
+        self.writer.sink.status.is_some()
+    }
+
+    /// Draw `text` as the status line, if there is one (see `--status-line`).
+    ///
+    /// It isn’t drawn if the output is in the middle of a line. Unless `force`
+    /// is set, it isn’t redrawn if it was drawn very recently. This ignores
+    /// errors, like [`Output::notice()`].
+    fn draw_status(&mut self, text: &str, force: bool) {
+        let sink = &mut self.writer.sink;
+        if let (Some(status), true) =
+            (&mut sink.status, self.writer.formatter.at_line_start())
+        {
+            let _ = status.draw(&mut sink.out, text, force);
+        }
+    }
+
+    /// Erase the status line, if it’s drawn (see `--status-line`).
+    ///
+    /// This ignores errors, like [`Output::notice()`].
+    fn clear_status(&mut self) {
+        let _ = self.writer.sink.clear_status();
+    }
+}
+
+/// Write a chunk of output from the child’s `stream`, holding back partial
+/// lines if `lines` is set.
+fn write_lines(
+    lines: &mut Option<LineBuffer>,
+    mut patterns: Option<&mut Patterns>,
+    filter: Option<&Filter>,
+    writer: &mut Writer,
+    stream: Stream,
+    bytes: &[u8],
+) -> io::Result<()> {
+    match lines {
+        Some(lines) => lines.push(stream, bytes, |bytes| {
+            write_filtered(
+                patterns.as_deref_mut(),
+                filter,
+                writer,
+                stream,
+                bytes,
+            )
+        }),
+        None => write_filtered(patterns, filter, writer, stream, bytes),
+    }
+}
+
+/// Write complete lines from the child’s `stream`, checking them against
+/// `patterns` and dropping any that `filter` rejects.
+fn write_filtered(
+    patterns: Option<&mut Patterns>,
+    filter: Option<&Filter>,
+    writer: &mut Writer,
+    stream: Stream,
+    bytes: &[u8],
+) -> io::Result<()> {
+    if let Some(patterns) = patterns {
+        patterns.check(bytes);
+    }
+    match filter {
+        Some(filter) => {
+            filter.write(stream, bytes, |line| writer.write(stream, line))
+        }
+        None => writer.write(stream, bytes),
+    }
+}
+
+/// Formats output and writes it to stdout and stderr.
+struct Writer<'a> {
+    /// Where formatted output goes.
+    sink: Sink,
+
+    /// Formats output (see `--tag`, `--prefix-seq`, `--timestamps`, and
+    /// `--timing`).
+    formatter: Formatter<'a>,
+
+    /// Collapses repeated lines (see `--dedup`).
+    dedup: Option<Dedup>,
+
+    /// Buffer for deduplicated output.
+    deduped: Vec<u8>,
+
+    /// Holds back the last lines of each stream (see `--head` and `--tail`).
+    head_tail: Option<HeadTail>,
+
+    /// Limits on how much output to write (see `--max-output-bytes` and
+    /// `--max-output-lines`).
+    limit: Option<Limit>,
+
+    /// Decides which sinks get each line (see `--sink-grep` and
+    /// `--sink-exclude`).
+    sink_filters: Option<SinkFilters>,
+
+    /// How to transform output before formatting it, if at all.
+    transform: Option<Transform>,
+
+    /// Buffer for transformed output.
+    transformed: Vec<u8>,
+}
+
+/// A way to transform the child’s output before formatting it.
+enum Transform {
+    /// Escape control characters (see `--sanitize`).
+    Sanitize,
+
+    /// Render as a hex dump (see `--hex`).
+    Hex(HexDump),
+}
+
+impl Writer<'_> {
+    /// Format and write a chunk of output from the child’s `stream`.
+    fn write(&mut self, stream: Stream, bytes: &[u8]) -> io::Result<()> {
+        let Some(dedup) = &mut self.dedup else {
+            return self.write_held(stream, bytes);
+        };
+        let mut deduped = mem::take(&mut self.deduped);
+        dedup.push(stream, bytes, &mut deduped);
+        let result = self.write_held(stream, &deduped);
+        self.deduped = deduped;
+        result
+    }
+
+    /// Format and write a chunk of output from the child’s `stream`, holding
+    /// back lines for `--tail`.
+    fn write_held(&mut self, stream: Stream, bytes: &[u8]) -> io::Result<()> {
+        let bytes = match &mut self.head_tail {
+            Some(head_tail) => head_tail.push(stream, bytes),
+            None => bytes,
+        };
+        self.write_now(stream, bytes)
+    }
+
+    /// Write what was held back from `stream`, since it’s been closed: the
+    /// count of repeats for `--dedup`, and the lines held back by `--tail`
+    /// after a marker if any lines were omitted.
+    fn finish(&mut self, stream: Stream) -> io::Result<()> {
+        if let Some(dedup) = &mut self.dedup {
+            let mut deduped = mem::take(&mut self.deduped);
+            dedup.finish(stream, &mut deduped);
+            let result = self.write_held(stream, &deduped);
+            self.deduped = deduped;
+            result?;
+        }
+
+        let Some(head_tail) = &mut self.head_tail else {
+            return Ok(());
+        };
+        let rest = head_tail.finish(stream);
+        if rest.omitted > 0 {
+            self.marker(head_tail::message(stream, rest.omitted).as_bytes())?;
+        }
+        for line in rest.tail {
+            self.write_now(stream, &line)?;
+        }
+        Ok(())
+    }
+
+    /// Format and write a chunk of output from the child’s `stream` without
+    /// holding any of it back.
+    fn write_now(&mut self, stream: Stream, bytes: &[u8]) -> io::Result<()> {
+        let bytes = match &mut self.limit {
+            Some(limit) => limit.allow(stream, bytes),
+            None => bytes,
+        };
+        let Some(filters) = self.sink_filters.take() else {
+            return self.write_to(stream, bytes, Sinks::ALL);
+        };
+        let result = filters.write(stream, bytes, |sinks, bytes| {
+            self.write_to(stream, bytes, sinks)
+        });
+        self.sink_filters = Some(filters);
+        result
+    }
+
+    /// Format and write a chunk of output from the child’s `stream` to
+    /// `sinks`.
+    fn write_to(
+        &mut self,
+        stream: Stream,
+        bytes: &[u8],
+        sinks: Sinks,
+    ) -> io::Result<()> {
+        let bytes = match &mut self.transform {
+            Some(Transform::Sanitize) => {
+                sanitize::escape(bytes, &mut self.transformed);
+                self.transformed.as_slice()
+            }
+            Some(Transform::Hex(hex)) => {
+                hex.dump(stream, bytes, &mut self.transformed);
+                self.transformed.as_slice()
+            }
+            None => bytes,
+        };
+        for segment in self.formatter.format(stream, bytes) {
+            self.sink.render_to(&segment, sinks)?;
+        }
+        Ok(())
+    }
+
+    /// Format and write a status line from `rederr` itself (see
+    /// `--heartbeat`).
+    fn heartbeat(&mut self, message: &[u8]) -> io::Result<()> {
+        for segment in self.formatter.heartbeat(message) {
+            self.sink.render(&segment)?;
+        }
+        Ok(())
+    }
+
+    /// Format and write a marker from `rederr` itself (see `--gap-marker`,
+    /// `--head`, and `--tail`).
+    fn marker(&mut self, message: &[u8]) -> io::Result<()> {
+        for segment in self.formatter.marker(message) {
+            self.sink.render(&segment)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes formatted output to stdout and stderr.
+struct Sink {
+    /// Stream for the child’s stdout.
+    out: StandardStream,
+
+    /// Stream for the child’s stderr.
+    err: StandardStream,
+
+    /// Colors for the child’s output.
+    colors: Colors,
+
+    /// Output held back by `--quiet-on-success`, if it’s still being held.
+    capture: Option<Capture>,
+
+    /// Output waiting to be written (see `--max-buffer`).
+    queued: Option<Queued>,
+
+    /// Uncolored copy of the output (see `--log-file`).
+    log: Option<Log>,
+
+    /// Recording of the output (see `--record`).
+    record: Option<Recorder>,
+
+    /// The status line on the terminal, if there is one (see
+    /// `--status-line`).
+    status: Option<StatusLine>,
+
+    /// The end of the output, if it’s needed for notifications (see
+    /// `--ping-url` and `--webhook`).
+    tail: Option<Tail>,
+}
+
+impl Sink {
+    /// Write a segment of formatted output to every sink, or hold it back.
+    fn render(&mut self, segment: &Segment) -> io::Result<()> {
+        self.render_to(segment, Sinks::ALL)
+    }
+
+    /// Write a segment of formatted output to `sinks`, or hold it back.
+    ///
+    /// The segment is written to the log, the recording, and the tail right
+    /// away, if there are any and they’re in `sinks`.
+    fn render_to(&mut self, segment: &Segment, sinks: Sinks) -> io::Result<()> {
+        if let (Some(log), true) = (&mut self.log, sinks.log) {
+            log.render(segment, &self.colors)?;
+        }
+        if let (Some(record), true) = (&mut self.record, sinks.record) {
+            record.render(segment, &self.colors)?;
+        }
+        if let (Some(tail), true) = (&mut self.tail, sinks.notify) {
+            tail.render(segment, &self.colors)?;
+        }
+        if !sinks.terminal {
+            return Ok(());
+        }
+
+        if let (None, Some(queued)) = (&self.capture, &mut self.queued) {
+            let buffer = match segment.stream {
+                Stream::Out => &mut queued.out,
+                Stream::Err => &mut queued.err,
+            };
+            buffer.clear();
+            format::render(buffer, segment, &self.colors)?;
+            queued.backlog.push(segment.stream, buffer.as_slice());
+            return Ok(());
+        }
+
+        let Some(capture) = &mut self.capture else {
+            self.clear_status()?;
+            let out = match segment.stream {
+                Stream::Out => &mut self.out,
+                Stream::Err => &mut self.err,
+            };
+            return format::render(out, segment, &self.colors);
+        };
+
+        // Render now so that colors and timestamps are the same as if the
+        // output weren’t held back.
+        let buffer = match segment.stream {
+            Stream::Out => &mut capture.out,
+            Stream::Err => &mut capture.err,
+        };
+        buffer.clear();
+        format::render(buffer, segment, &self.colors)?;
+        capture.spool.push(segment.stream, buffer.as_slice())
+    }
+
+    /// Erase the status line, if it’s drawn (see `--status-line`).
+    fn clear_status(&mut self) -> io::Result<()> {
+        match &mut self.status {
+            Some(status) => status.clear(&mut self.out),
+            None => Ok(()),
+        }
+    }
+
+    /// Write a message from `rederr` itself to the log, the recording, and
+    /// the tail, if there are any.
+    ///
+    /// This is only needed for messages that aren’t rendered as output (see
+    /// [`Output::notice()`]). It ignores errors, like [`notice!`].
+    fn log_notice(&mut self, message: &str) {
+        if let Some(tail) = &mut self.tail {
+            tail.notice(message);
+        }
+        if let Some(record) = &mut self.record {
+            let _ = record.notice(message);
+        }
+        if let Some(log) = &mut self.log {
+            let _ = log.flush_collapsed();
+            let _ = writeln!(log.file(Stream::Err), "{message}");
+            if log.err.is_none() {
+                log.mid_line = false;
+            }
+        }
+    }
+
+    /// Finish the `--log-dir` log, if there is one.
+    ///
+    /// This is called just before exiting, so errors are reported but
+    /// otherwise ignored.
+    fn log_exit(&mut self, code: i32) {
+        if let Some(log) = &mut self.log {
+            if let Err(error) = log.finish(code) {
+                notice!("Error: {error:#}");
+            }
+        }
+    }
+
+    /// Write output held back by `--quiet-on-success`, and stop holding back
+    /// output.
+    fn release(&mut self) -> io::Result<()> {
+        let Some(capture) = self.capture.take() else {
+            return Ok(());
+        };
+        self.clear_status()?;
+        capture.spool.replay(|stream, bytes| {
+            // Go through --max-buffer like live output, but don’t let it grow
+            // past the limit, since the whole spool is pushed at once.
+            if let Some(queued) = &mut self.queued {
+                queued.backlog.push(stream, bytes);
+                return queued.backlog.make_room(stream);
+            }
+            let out = match stream {
+                Stream::Out => &mut self.out,
+                Stream::Err => &mut self.err,
+            };
+            out.write_all(bytes)?;
+            out.flush()
+        })
+    }
+}
+
+/// Uncolored copies of the output (see `--log-file` and `--log-dir`).
+struct Log {
+    /// Log for the child’s stdout, and its stderr if there’s no separate log.
+    out: NoColor<File>,
+
+    /// Separate log for the child’s stderr (see `--log-file-stderr`).
+    err: Option<NoColor<File>>,
+
+    /// Whether `out` ends in the middle of a line.
+    mid_line: bool,
+
+    /// Unfinished lines held back by `--collapse-cr`, and a buffer to render
+    /// segments into before adding them.
+    collapse: Option<(Collapse, Vec<u8>)>,
+
+    /// More about the log, if it was created by `--log-dir`.
+    run: Option<RunLog>,
+}
+
+/// A log file created by `--log-dir`.
+struct RunLog {
+    /// Where the log is.
+    path: PathBuf,
+
+    /// Time zone for the footer.
+    time_zone: TimeZone,
+
+    /// How to compress the log once it’s finished (see `--log-compress`).
+    compression: Option<Compression>,
+}
+
+impl Log {
+    /// Create the log files requested in `params`, if any.
+    fn open(params: &Params) -> anyhow::Result<Option<Self>> {
+        if let Some(dir) = &params.log_dir {
+            return Self::open_in(dir, params).map(Some);
+        }
+        let Some(out) = &params.log_file else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            out: create_log(out)?,
+            err: params
+                .log_file_stderr
+                .as_deref()
+                .map(create_log)
+                .transpose()?,
+            mid_line: false,
+            collapse: params.collapse_cr.then(Default::default),
+            run: None,
+        }))
+    }
+
+    /// Create a new log file for this run in `dir`, and write its header.
+    fn open_in(dir: &Path, params: &Params) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir).with_context(|| {
+            format!("creating log directory {}", dir.display())
+        })?;
+
+        let time_zone = params.time_zone().clone();
+        let started = Timestamp::wall(SystemTime::now(), &time_zone);
+        let command = params.program().unwrap_or_default();
+        let name = Path::new(command)
+            .file_name()
+            .unwrap_or(command)
+            .to_string_lossy();
+        // Colons aren’t allowed in file names on some systems.
+        let name = format!("{}-{name}", started.to_string().replace(':', ""));
+
+        let (path, mut out) = create_new_log(dir, &name)?;
+        writeln!(out, "# Command: {}", command_line(params))
+            .and_then(|()| writeln!(out, "# Started: {started}"))
+            .with_context(|| format!("writing log file {}", path.display()))?;
+
+        Ok(Self {
+            out,
+            err: None,
+            mid_line: false,
+            collapse: params.collapse_cr.then(Default::default),
+            run: Some(RunLog {
+                path,
+                time_zone,
+                compression: params.log_compress,
+            }),
+        })
+    }
+
+    /// Get the log file for the child’s `stream`.
+    const fn file(&mut self, stream: Stream) -> &mut NoColor<File> {
+        match (stream, &mut self.err) {
+            (Stream::Err, Some(err)) => err,
+            _ => &mut self.out,
+        }
+    }
+
+    /// Write a segment of formatted output to the log for its stream.
+    fn render(&mut self, segment: &Segment, colors: &Colors) -> io::Result<()> {
+        let Self {
+            out, err, collapse, ..
+        } = self;
+        if let Some((collapse, rendered)) = collapse {
+            let file = match (segment.stream, err) {
+                (Stream::Err, Some(err)) => err,
+                _ => out,
+            };
+            rendered.clear();
+            format::render(&mut NoColor::new(&mut *rendered), segment, colors)?;
+            collapse.push(
+                segment.stream,
+                rendered,
+                segment.prefix,
+                |line| file.write_all(line),
+            )?;
+        } else {
+            format::render(self.file(segment.stream), segment, colors)?;
+        }
+        if segment.stream == Stream::Out || self.err.is_none() {
+            if let Some(last) = segment.bytes.last() {
+                self.mid_line = *last != b'\n';
+            }
+        }
+        Ok(())
+    }
+
+    /// Write unfinished lines held back by `--collapse-cr`.
+    fn flush_collapsed(&mut self) -> io::Result<()> {
+        let Some((collapse, _)) = &mut self.collapse else {
+            return Ok(());
+        };
+        collapse.flush(Stream::Out, |line| self.out.write_all(line))?;
+        let err = self.err.as_mut().unwrap_or(&mut self.out);
+        collapse.flush(Stream::Err, |line| err.write_all(line))
+    }
+
+    /// Write the footer and compress the log, if it was created by
+    /// `--log-dir`.
+    ///
+    /// Unfinished lines held back by `--collapse-cr` are written first.
+    fn finish(&mut self, code: i32) -> anyhow::Result<()> {
+        self.flush_collapsed().context("writing log file")?;
+        let Some(run) = &self.run else {
+            return Ok(());
+        };
+        let finished = Timestamp::wall(SystemTime::now(), &run.time_zone);
+        let mid_line = if self.mid_line { "\n" } else { "" };
+        write!(
+            self.out,
+            "{mid_line}# Finished: {finished}\n# Exit status: {code}\n"
+        )
+        .with_context(|| format!("writing log file {}", run.path.display()))?;
+
+        if let Some(compression) = run.compression {
+            compress(&run.path, compression)?;
+        }
+        Ok(())
+    }
+}
+
+/// Create (or truncate) a log file.
+fn create_log(path: &Path) -> anyhow::Result<NoColor<File>> {
+    File::create(path)
+        .map(NoColor::new)
+        .with_context(|| format!("creating log file {}", path.display()))
+}
+
+/// Create a new log file in `dir` named `name` plus ".log".
+///
+/// If that file already exists, a number is added to the name.
+fn create_new_log(
+    dir: &Path,
+    name: &str,
+) -> anyhow::Result<(PathBuf, NoColor<File>)> {
+    let mut path = dir.join(format!("{name}.log"));
+    for number in 2..=100 {
+        match File::options().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, NoColor::new(file))),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                path = dir.join(format!("{name}-{number}.log"));
+            }
+            Err(error) => {
+                return Err(error).with_context(|| {
+                    format!("creating log file {}", path.display())
+                });
+            }
+        }
+    }
+    anyhow::bail!("creating log file {}: too many runs", path.display())
+}
+
+/// Compress a finished log file in place (see `--log-compress`).
+fn compress(path: &Path, compression: Compression) -> anyhow::Result<()> {
+    let program = match compression {
+        Compression::Gzip => "gzip",
+        Compression::Zstd => "zstd",
+    };
+    let mut command = process::Command::new(program);
+    if compression == Compression::Zstd {
+        // gzip does both of these by default.
+        command.args(["--quiet", "--rm"]);
+    }
+    let status = command
+        .arg("--force")
+        .arg("--")
+        .arg(path)
+        .stdin(process::Stdio::null())
+        .status()
+        .with_context(|| format!("running {program}"))?;
+    if !status.success() {
+        anyhow::bail!(
+            "{program} failed to compress {}: {status}",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Start the `--record` recording, if there is one.
+fn create_recording(params: &Params) -> anyhow::Result<Option<Recorder>> {
+    params
+        .record
+        .as_deref()
+        .map(|path| {
+            Recorder::create(
+                path,
+                pty::window_size()
+                    .map_or((80, 24), |size| (size.ws_col, size.ws_row)),
+                &command_line(params),
+            )
+        })
+        .transpose()
+}
+
+/// Get the command line as it might be typed into a shell.
+fn command_line(params: &Params) -> String {
+    params
+        .program()
+        .into_iter()
+        .chain(params.program_args().iter().map(OsString::as_os_str))
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            let plain = !arg.is_empty()
+                && arg.bytes().all(|byte| {
+                    byte.is_ascii_alphanumeric()
+                        || b"%+,-./:=@_".contains(&byte)
+                });
+            if plain {
+                arg.into_owned()
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Output held back by `--quiet-on-success`.
+struct Capture {
+    /// Rendered output from both streams, in order.
+    spool: Spool,
+
+    /// Buffer to render the child’s stdout into.
+    out: Buffer,
+
+    /// Buffer to render the child’s stderr into.
+    err: Buffer,
+}
+
+impl Capture {
+    /// Start holding back output that would go to `out` and `err`.
+    fn new(out: &StandardStream, err: &StandardStream) -> Self {
+        Self {
+            spool: Spool::new(spool::MEMORY_LIMIT),
+            out: buffer_like(out),
+            err: buffer_like(err),
+        }
+    }
+}
+
+/// Output waiting to be written (see `--max-buffer`).
+struct Queued {
+    /// The output.
+    backlog: Backlog,
+
+    /// Buffer to render the child’s stdout into.
+    out: Buffer,
+
+    /// Buffer to render the child’s stderr into.
+    err: Buffer,
+}
+
+impl Queued {
+    /// Start queuing output that would go to `out` and `err`, up to `max`
+    /// bytes for each of our streams.
+    fn new(
+        max: u64,
+        separate: bool,
+        out: &StandardStream,
+        err: &StandardStream,
+    ) -> io::Result<Self> {
+        let max = usize::try_from(max).unwrap_or(usize::MAX);
+        Ok(Self {
+            backlog: Backlog::new(max, separate)?,
+            out: buffer_like(out),
+            err: buffer_like(err),
+        })
+    }
+}
+
+/// Create a buffer that renders color the same way as `stream`.
+fn buffer_like(stream: &StandardStream) -> Buffer {
+    if stream.supports_color() {
+        Buffer::ansi()
+    } else {
+        Buffer::no_color()
+    }
+}
+
+/// Start the timeouts for `--warn-at`, in the order they will expire.
+fn warnings(warn_at: &[Duration]) -> VecDeque<(Duration, Timeout)> {
+    let mut warn_at = warn_at.to_vec();
+    warn_at.sort_unstable();
+    warn_at.dedup();
+    warn_at
+        .into_iter()
+        .map(|at| (at, Timeout::from(at).start()))
+        .collect()
+}
+
+/// Get a message about a run or idle timeout expiring.
+///
+/// `expired` is the timeout object after it expired.
+fn timeout_message(kind: TimeoutKind, expired: &Timeout) -> String {
+    let elapsed = expired.elapsed_rounded();
+    match kind {
+        TimeoutKind::Idle => {
+            format!("Timed out waiting for input after {elapsed:?}")
+        }
+        TimeoutKind::Run => format!("Run timed out after {elapsed:?}"),
+        TimeoutKind::Delayed
+        | TimeoutKind::Kill
+        | TimeoutKind::Warn
+        | TimeoutKind::RunWarn
+        | TimeoutKind::IdleWarn
+        | TimeoutKind::Heartbeat
+        | TimeoutKind::StatusLine
+        | TimeoutKind::Notify
+        | TimeoutKind::Linger => {
+            unreachable!("{kind:?} isn’t a failure")
+        }
+    }
+}
+
+/// Wait for input.
+///
+/// Returns:
+///  * `Ok(None)`: got input.
+///  * `Ok(Some(Timeout::Expired { .. })`: timeout expired without input.
+///  * `Err(error)`: an error occurred.
+fn poll(
+    poller: &mut dyn Poller<PollKey>,
+    events: &mut VecDeque<Event<PollKey>>,
+    timeout: &Timeout,
+    chaos: &mut Chaos,
+) -> anyhow::Result<Option<Timeout>> {
+    let timeout = timeout.start();
+    while events.is_empty() {
+        if let Some(expired) = timeout.check_expired() {
+            return Ok(Some(expired));
+        }
+
+        // The poller may return early without events; the timeout is checked
+        // again on the next loop.
+        let result = if chaos.interrupt() {
+            Err(io::ErrorKind::Interrupted.into())
+        } else {
+            poller.poll(events, timeout.wait())
+        };
+        if let Err(error) = result {
+            // A signal was received. It will be handled through the signal
+            // pipe, which will be readable on the next loop.
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+
+            // Invalid timeout or other error.
+            return Err(error.into());
+        }
+    }
+
+    Ok(None)
+}
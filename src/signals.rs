@@ -0,0 +1,125 @@
+//! Receive signals in the `poll()` loop.
+//!
+//! Signal handlers can’t do much safely, so the handlers installed here just
+//! set a flag and write a byte to a pipe (the self-pipe trick). The read end of
+//! the pipe is registered with `poll()`, and when it becomes readable the main
+//! loop calls [`SignalPipe::take()`] to find out which signals were received.
+
+use nix::sys::signal::Signal;
+use signal_hook::SigId;
+use std::io::{self, Read};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Signals that are forwarded to the child when `rederr` receives them.
+pub const FORWARD: &[Signal] = &[
+    Signal::SIGHUP,
+    Signal::SIGINT,
+    Signal::SIGQUIT,
+    Signal::SIGTERM,
+];
+
+/// A pipe that receives notifications of signals.
+#[derive(Debug)]
+pub struct SignalPipe {
+    /// The end of the pipe we poll and read.
+    read: UnixStream,
+
+    /// The end of the pipe the signal handlers write to.
+    write: UnixStream,
+
+    /// Flags set when a signal is received.
+    received: Vec<(Signal, Arc<AtomicBool>)>,
+
+    /// Registered signal handlers, so we can remove them when dropped.
+    ids: Vec<SigId>,
+}
+
+impl SignalPipe {
+    /// Create a new pipe that doesn’t receive any signals yet.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if the pipe couldn’t be created.
+    pub fn new() -> io::Result<Self> {
+        let (read, write) = UnixStream::pair()?;
+        read.set_nonblocking(true)?;
+        write.set_nonblocking(true)?;
+        Ok(Self {
+            read,
+            write,
+            received: Vec::new(),
+            ids: Vec::new(),
+        })
+    }
+
+    /// Start receiving `signal`.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if the signal handler couldn’t be installed.
+    pub fn add(&mut self, signal: Signal) -> io::Result<()> {
+        let flag = Arc::new(AtomicBool::new(false));
+        let signal_number = signal as i32;
+
+        // The flag must be set before the pipe is written to, so that it’s set
+        // by the time we wake up. Handlers run in the order registered.
+        self.ids
+            .push(signal_hook::flag::register(signal_number, flag.clone())?);
+        self.ids.push(signal_hook::low_level::pipe::register(
+            signal_number,
+            self.write.try_clone()?,
+        )?);
+        self.received.push((signal, flag));
+        Ok(())
+    }
+
+    /// Drain the pipe and return the signals received since the last call.
+    ///
+    /// # Errors
+    ///
+    /// This returns an error if the pipe couldn’t be read.
+    pub fn take(&mut self) -> io::Result<Vec<Signal>> {
+        let mut buffer = [0; 64];
+        loop {
+            match self.read.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    break;
+                }
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(self
+            .received
+            .iter()
+            .filter(|(_, flag)| flag.swap(false, Ordering::SeqCst))
+            .map(|(signal, _)| *signal)
+            .collect())
+    }
+}
+
+impl AsFd for SignalPipe {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.read.as_fd()
+    }
+}
+
+impl AsRawFd for SignalPipe {
+    fn as_raw_fd(&self) -> RawFd {
+        self.read.as_raw_fd()
+    }
+}
+
+impl Drop for SignalPipe {
+    fn drop(&mut self) {
+        for id in self.ids.drain(..) {
+            signal_hook::low_level::unregister(id);
+        }
+    }
+}
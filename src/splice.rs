@@ -0,0 +1,112 @@
+//! Move the child’s output straight to our output (see `--no-splice`).
+//!
+//! When nothing needs to color or change a stream, its output can be moved
+//! from the child’s pipe to our stdout or stderr with `splice()`, which
+//! doesn’t copy it through a buffer in `rederr`. This is only available on
+//! Linux.
+//!
+//! The kernel refuses to splice to some destinations, such as files opened
+//! for appending, and older kernels can’t splice to terminals. If that
+//! happens, splicing is turned off for the stream and its output is copied as
+//! usual.
+
+use crate::params::Stream;
+use std::io;
+use std::os::fd::BorrowedFd;
+use termcolor::{ColorSpec, StandardStream, WriteColor};
+
+/// Which of our output streams to splice each of the child’s output streams
+/// to, if any.
+#[derive(Debug, Default)]
+pub struct Splice {
+    /// Where to splice the child’s stdout.
+    out: Option<Stream>,
+
+    /// Where to splice the child’s stderr.
+    err: Option<Stream>,
+}
+
+impl Splice {
+    /// Decide which streams to splice, given the streams and colors output
+    /// would be written with, and whether the child’s stderr goes to our
+    /// stderr (`--separate`).
+    ///
+    /// This should only be called if nothing else needs to see or change the
+    /// output. A stream is only spliced if it wouldn’t be colored.
+    pub fn new(
+        separate: bool,
+        out: (&StandardStream, &ColorSpec),
+        err: (&StandardStream, &ColorSpec),
+    ) -> Self {
+        if !cfg!(any(target_os = "linux", target_os = "android")) {
+            return Self::default();
+        }
+
+        let plain = |(stream, color): (&StandardStream, &ColorSpec)| {
+            color.is_none() || !stream.supports_color()
+        };
+        let err_to = if separate { Stream::Err } else { Stream::Out };
+        Self {
+            out: plain(out).then_some(Stream::Out),
+            err: plain(err).then_some(err_to),
+        }
+    }
+
+    /// Which of our streams to splice output from the child’s `stream` to, if
+    /// any.
+    pub const fn target(&self, stream: Stream) -> Option<Stream> {
+        match stream {
+            Stream::Out => self.out,
+            Stream::Err => self.err,
+        }
+    }
+
+    /// Stop splicing `stream`, because the kernel refused.
+    pub const fn disable(&mut self, stream: Stream) {
+        match stream {
+            Stream::Out => self.out = None,
+            Stream::Err => self.err = None,
+        }
+    }
+}
+
+/// Move up to `len` bytes from `from`, which must be a pipe, to our stdout or
+/// stderr.
+///
+/// Returns an error of kind [`io::ErrorKind::Unsupported`] if the kernel
+/// can’t splice between them, in which case nothing was moved.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn splice(
+    from: BorrowedFd<'_>,
+    to: Stream,
+    len: usize,
+) -> io::Result<usize> {
+    use nix::errno::Errno;
+    use nix::fcntl::SpliceFFlags;
+    use std::os::fd::AsFd;
+
+    let (stdout, stderr) = (io::stdout(), io::stderr());
+    let to = match to {
+        Stream::Out => stdout.as_fd(),
+        Stream::Err => stderr.as_fd(),
+    };
+    let result =
+        nix::fcntl::splice(from, None, to, None, len, SpliceFFlags::empty());
+    match result {
+        Ok(count) => Ok(count),
+        Err(Errno::EINVAL | Errno::ENOSYS) => {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// `splice()` is only available on Linux.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn splice(
+    _from: BorrowedFd<'_>,
+    _to: Stream,
+    _len: usize,
+) -> io::Result<usize> {
+    Err(io::ErrorKind::Unsupported.into())
+}
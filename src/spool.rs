@@ -0,0 +1,215 @@
+//! Hold on to the child’s output so that it can be written later.
+//!
+//! Capture modes need to keep all of the child’s output until they know what
+//! to do with it. A [`Spool`] keeps output in memory up to a limit, then
+//! transparently spills the rest to a temporary file, so that a surprisingly
+//! large run neither runs out of memory nor loses output.
+//!
+//! Output is stored as records: a byte for the stream, the length of the chunk
+//! as a little-endian `u32`, then the chunk itself. The temporary file is
+//! deleted as soon as it’s created, so it’s cleaned up even if `rederr` is
+//! killed.
+
+use crate::params::Stream;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, Write};
+use std::process;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Default maximum number of bytes to keep in memory before spilling to a
+/// temporary file.
+pub const MEMORY_LIMIT: usize = 8 * 1024 * 1024;
+
+/// Length of a record header: a stream byte and a `u32` length.
+const HEADER_LEN: usize = 5;
+
+/// Used to give each temporary file a unique name.
+static FILE_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Output from the child, in memory or in a temporary file.
+#[derive(Debug)]
+pub struct Spool {
+    /// Records kept in memory. These always come before records in the file.
+    memory: Vec<u8>,
+
+    /// Maximum number of bytes to keep in memory.
+    memory_limit: usize,
+
+    /// Records that didn’t fit in memory.
+    file: Option<File>,
+}
+
+impl Spool {
+    /// Create an empty spool that keeps up to `memory_limit` bytes in memory.
+    pub const fn new(memory_limit: usize) -> Self {
+        Self {
+            memory: Vec::new(),
+            memory_limit,
+            file: None,
+        }
+    }
+
+    /// Store a chunk of output from the child’s `stream`.
+    pub fn push(&mut self, stream: Stream, chunk: &[u8]) -> io::Result<()> {
+        let header = header(stream, chunk)?;
+        let record_len = chunk.len().saturating_add(HEADER_LEN);
+
+        if self.file.is_none()
+            && self.memory.len().saturating_add(record_len) <= self.memory_limit
+        {
+            self.memory.extend_from_slice(&header);
+            self.memory.extend_from_slice(chunk);
+        } else {
+            let file = match &mut self.file {
+                Some(file) => file,
+                None => self.file.insert(temp_file()?),
+            };
+            file.write_all(&header)?;
+            file.write_all(chunk)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pass every stored chunk, in order, to `write`.
+    pub fn replay<F>(self, mut write: F) -> io::Result<()>
+    where
+        F: FnMut(Stream, &[u8]) -> io::Result<()>,
+    {
+        let mut memory = &self.memory[..];
+        while let Some((stream, chunk)) = read_record(&mut memory)? {
+            write(stream, &chunk)?;
+        }
+
+        if let Some(mut file) = self.file {
+            file.rewind()?;
+            let mut reader = BufReader::new(file);
+            while let Some((stream, chunk)) = read_record(&mut reader)? {
+                write(stream, &chunk)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Build the header for a record.
+fn header(stream: Stream, chunk: &[u8]) -> io::Result<[u8; HEADER_LEN]> {
+    let len = u32::try_from(chunk.len()).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, "chunk too large to spool")
+    })?;
+    let stream = match stream {
+        Stream::Out => 0,
+        Stream::Err => 1,
+    };
+
+    let mut header = [stream, 0, 0, 0, 0];
+    header[1..].copy_from_slice(&len.to_le_bytes());
+    Ok(header)
+}
+
+/// Read a record, or return `None` at the end of the input.
+fn read_record(input: &mut dyn Read) -> io::Result<Option<(Stream, Vec<u8>)>> {
+    let mut header = [0; HEADER_LEN];
+    match input.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+            return Ok(None);
+        }
+        Err(error) => return Err(error),
+    }
+
+    let stream = match header[0] {
+        0 => Stream::Out,
+        1 => Stream::Err,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "corrupt spool record",
+            ))
+        }
+    };
+    let mut len = [0; 4];
+    len.copy_from_slice(&header[1..]);
+
+    let mut chunk = vec![0; u32::from_le_bytes(len) as usize];
+    input.read_exact(&mut chunk)?;
+    Ok(Some((stream, chunk)))
+}
+
+/// Create an anonymous temporary file.
+///
+/// The file is deleted immediately, so it only exists as long as it’s open.
+fn temp_file() -> io::Result<File> {
+    let path = env::temp_dir().join(format!(
+        "rederr-spool-{}-{}",
+        process::id(),
+        FILE_COUNTER.fetch_add(1, Ordering::Relaxed),
+    ));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    fs::remove_file(&path)?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::{check, let_assert};
+
+    /// Replay a spool into a list of chunks.
+    fn replay_all(spool: Spool) -> Vec<(Stream, Vec<u8>)> {
+        let mut chunks = Vec::new();
+        let_assert!(
+            Ok(()) = spool.replay(|stream, chunk| {
+                chunks.push((stream, chunk.to_vec()));
+                Ok(())
+            })
+        );
+        chunks
+    }
+
+    #[test]
+    fn in_memory() {
+        let mut spool = Spool::new(1024);
+        spool.push(Stream::Out, b"out\n").unwrap();
+        spool.push(Stream::Err, b"err\n").unwrap();
+
+        check!(spool.file.is_none());
+        check!(
+            replay_all(spool)
+                == [
+                    (Stream::Out, b"out\n".to_vec()),
+                    (Stream::Err, b"err\n".to_vec()),
+                ]
+        );
+    }
+
+    #[test]
+    fn spills_to_file() {
+        // Room for exactly one record in memory.
+        let mut spool = Spool::new(HEADER_LEN + 4);
+        spool.push(Stream::Out, b"one\n").unwrap();
+        check!(spool.file.is_none());
+        spool.push(Stream::Err, b"two\n").unwrap();
+        check!(spool.file.is_some());
+
+        // Once spilled, everything goes to the file to keep the order.
+        spool.push(Stream::Out, b"").unwrap();
+        spool.push(Stream::Out, b"three\n").unwrap();
+
+        check!(
+            replay_all(spool)
+                == [
+                    (Stream::Out, b"one\n".to_vec()),
+                    (Stream::Err, b"two\n".to_vec()),
+                    (Stream::Out, b"".to_vec()),
+                    (Stream::Out, b"three\n".to_vec()),
+                ]
+        );
+    }
+}
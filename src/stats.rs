@@ -0,0 +1,383 @@
+//! Statistics about a run.
+
+use crate::params::Stream;
+use bstr::ByteSlice;
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Minimum difference between the monotonic and wall clock durations of a run
+/// to report.
+const MIN_DISCREPANCY: Duration = Duration::from_secs(1);
+
+/// Statistics about a run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Total time spent writing the child’s output.
+    pub write_time: Duration,
+
+    /// Estimated time the child spent blocked writing to a full pipe.
+    ///
+    /// We can’t see when the child is blocked, but if a read fills our buffer
+    /// then there is probably more data waiting in the pipe. If the pipe is
+    /// full, the child can’t write to it until we read again, which we won’t
+    /// do until we finish writing what we just read. This is an upper bound,
+    /// since the pipe might have room left.
+    pub child_blocked_time: Duration,
+
+    /// Bytes read from the child’s stdout.
+    pub out_bytes: u64,
+
+    /// Bytes read from the child’s stderr.
+    pub err_bytes: u64,
+
+    /// Number of times `poll()` returned.
+    pub wakeups: u64,
+
+    /// Number of successful reads from the child’s output.
+    pub reads: u64,
+
+    /// Number of reads that returned less than was asked for, including EOF.
+    pub short_reads: u64,
+
+    /// Lines read from the child’s stdout, including an unfinished last line.
+    pub out_lines: u64,
+
+    /// Lines read from the child’s stderr, including an unfinished last line.
+    pub err_lines: u64,
+
+    /// Whether the last byte read from the child’s stdout wasn’t a newline.
+    pub out_mid_line: bool,
+
+    /// Whether the last byte read from the child’s stderr wasn’t a newline.
+    pub err_mid_line: bool,
+
+    /// The option for the timeout that expired, if one did.
+    pub timed_out: Option<&'static str>,
+}
+
+impl Stats {
+    /// Record `poll()` returning.
+    pub const fn record_wakeup(&mut self) {
+        self.wakeups = self.wakeups.saturating_add(1);
+    }
+
+    /// Record reading `count` bytes from the child’s `stream`, when the read
+    /// asked for `limit` bytes.
+    pub const fn record_read(
+        &mut self,
+        stream: Stream,
+        count: usize,
+        limit: usize,
+    ) {
+        let bytes = match stream {
+            Stream::Out => &mut self.out_bytes,
+            Stream::Err => &mut self.err_bytes,
+        };
+        *bytes = bytes.saturating_add(count as u64);
+        self.reads = self.reads.saturating_add(1);
+        if count < limit {
+            self.short_reads = self.short_reads.saturating_add(1);
+        }
+    }
+
+    /// Record the lines in `bytes`, which were read from the child’s `stream`.
+    ///
+    /// This isn’t done by [`Self::record_read()`], since output moved with
+    /// `splice()` is never seen (see `--summary`).
+    pub fn record_lines(&mut self, stream: Stream, bytes: &[u8]) {
+        let Some(&last) = bytes.last() else {
+            return;
+        };
+        let (lines, mid_line) = match stream {
+            Stream::Out => (&mut self.out_lines, &mut self.out_mid_line),
+            Stream::Err => (&mut self.err_lines, &mut self.err_mid_line),
+        };
+        // Count the lines that start in `bytes`.
+        let newlines = bytes.find_iter(b"\n").count();
+        let starts = (newlines as u64)
+            .saturating_add(u64::from(!*mid_line))
+            .saturating_sub(u64::from(last == b'\n'));
+        *lines = lines.saturating_add(starts);
+        *mid_line = last != b'\n';
+    }
+
+    /// Summarize the run for `--summary`.
+    pub fn summary(&self, elapsed: &Elapsed) -> String {
+        format!(
+            "Command {elapsed}; stdout: {} bytes in {} lines; stderr: {} \
+            bytes in {} lines; {} reads; {}",
+            self.out_bytes,
+            self.out_lines,
+            self.err_bytes,
+            self.err_lines,
+            self.reads,
+            self.timed_out.map_or_else(
+                || "no timeout expired".to_owned(),
+                |option| format!("{option} expired"),
+            ),
+        )
+    }
+
+    /// Summarize how `poll()` and reads went (see `-vv`).
+    pub fn poll_summary(&self) -> String {
+        let bytes = self.out_bytes.saturating_add(self.err_bytes);
+        format!(
+            "poll() woke up {} times; {} reads ({} short); {} bytes per wakeup",
+            self.wakeups,
+            self.reads,
+            self.short_reads,
+            bytes.checked_div(self.wakeups).unwrap_or(0),
+        )
+    }
+
+    /// Record writing output that took `elapsed`.
+    ///
+    /// `read_filled_buffer` is whether the read that produced the output
+    /// filled the buffer, which indicates the pipe may be full.
+    pub const fn record_write(
+        &mut self,
+        elapsed: Duration,
+        read_filled_buffer: bool,
+    ) {
+        self.write_time = self.write_time.saturating_add(elapsed);
+        if read_filled_buffer {
+            self.child_blocked_time =
+                self.child_blocked_time.saturating_add(elapsed);
+        }
+    }
+}
+
+/// When a run started, according to both the monotonic and wall clocks.
+///
+/// The monotonic clock doesn’t advance while the system is suspended and isn’t
+/// affected by changes to the system clock, so comparing the two explains runs
+/// that seem to have taken much longer than they did (“the job took 9 hours”
+/// when really the laptop slept).
+#[derive(Clone, Copy, Debug)]
+pub struct RunTime {
+    /// Start time by the monotonic clock.
+    monotonic: Instant,
+
+    /// Start time by the wall clock.
+    wall: SystemTime,
+}
+
+impl RunTime {
+    /// Record that a run is starting now.
+    pub fn start() -> Self {
+        Self {
+            monotonic: Instant::now(),
+            wall: SystemTime::now(),
+        }
+    }
+
+    /// Get when the run started by the wall clock.
+    pub const fn wall(&self) -> SystemTime {
+        self.wall
+    }
+
+    /// Get how long the run has taken so far.
+    pub fn elapsed(&self) -> Elapsed {
+        Elapsed {
+            monotonic: self.monotonic.elapsed(),
+            wall: self.wall.elapsed().ok(),
+        }
+    }
+}
+
+/// How long a run took, according to both the monotonic and wall clocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Elapsed {
+    /// Time elapsed by the monotonic clock. This is what timeouts use.
+    pub monotonic: Duration,
+
+    /// Time elapsed by the wall clock, or `None` if the wall clock went
+    /// backward.
+    pub wall: Option<Duration>,
+}
+
+impl Elapsed {
+    /// Do the clocks disagree enough to mention it?
+    ///
+    /// They’re allowed to differ by 1% (or [`MIN_DISCREPANCY`] for short runs)
+    /// to allow for NTP gradually adjusting the wall clock.
+    pub fn clocks_disagree(&self) -> bool {
+        let Some(wall) = self.wall else {
+            return true;
+        };
+        let allowed = MIN_DISCREPANCY
+            .max(self.monotonic.checked_div(100).unwrap_or_default());
+        wall.abs_diff(self.monotonic) > allowed
+    }
+}
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ran for {:?}", self.monotonic)?;
+        if self.clocks_disagree() {
+            match self.wall {
+                Some(wall) => write!(f, ", but {wall:?} by the wall clock")?,
+                None => write!(f, ", but the wall clock went backward")?,
+            }
+            write!(
+                f,
+                " (the system may have been suspended, or its clock changed)"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "read {} bytes from stdout and {} bytes from stderr\n\
+            spent {:?} writing output; child blocked on full pipe for up to \
+            {:?}\n{}",
+            self.out_bytes,
+            self.err_bytes,
+            self.write_time,
+            self.child_blocked_time,
+            self.poll_summary(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    #[test]
+    fn record_write_partial_read() {
+        let mut stats = Stats::default();
+        stats.record_write(Duration::from_millis(5), false);
+        check!(stats.write_time == Duration::from_millis(5));
+        check!(stats.child_blocked_time == Duration::ZERO);
+    }
+
+    #[test]
+    fn record_lines() {
+        let mut stats = Stats::default();
+        stats.record_lines(Stream::Out, b"one\ntw");
+        check!(stats.out_lines == 2);
+        stats.record_lines(Stream::Out, b"o\n");
+        check!(stats.out_lines == 2);
+        stats.record_lines(Stream::Out, b"");
+        stats.record_lines(Stream::Out, b"\nfour");
+        check!(stats.out_lines == 4);
+        check!(stats.err_lines == 0);
+
+        stats.record_lines(Stream::Err, b"\n");
+        check!(stats.err_lines == 1);
+    }
+
+    #[test]
+    fn summary() {
+        let mut stats = Stats {
+            out_bytes: 10,
+            out_lines: 2,
+            err_bytes: 3,
+            err_lines: 1,
+            reads: 4,
+            ..Stats::default()
+        };
+        let elapsed = Elapsed {
+            monotonic: Duration::from_millis(1_500),
+            wall: Some(Duration::from_millis(1_500)),
+        };
+        check!(
+            stats.summary(&elapsed)
+                == "Command ran for 1.5s; stdout: 10 bytes in 2 lines; \
+                stderr: 3 bytes in 1 lines; 4 reads; no timeout expired"
+        );
+
+        stats.timed_out = Some("--idle-timeout");
+        check!(stats
+            .summary(&elapsed)
+            .ends_with("; 4 reads; --idle-timeout expired"));
+    }
+
+    #[test]
+    fn elapsed_clocks_agree() {
+        let elapsed = Elapsed {
+            monotonic: Duration::from_hours(9),
+            wall: Some(Duration::from_hours(9) + Duration::from_secs(30)),
+        };
+        check!(!elapsed.clocks_disagree());
+        check!(elapsed.to_string() == "ran for 32400s");
+    }
+
+    #[test]
+    fn elapsed_suspended() {
+        let elapsed = Elapsed {
+            monotonic: Duration::from_mins(5),
+            wall: Some(Duration::from_hours(9)),
+        };
+        check!(elapsed.clocks_disagree());
+        check!(
+            elapsed.to_string()
+                == "ran for 300s, but 32400s by the wall clock (the system may \
+                have been suspended, or its clock changed)"
+        );
+    }
+
+    #[test]
+    fn elapsed_wall_clock_backward() {
+        let elapsed = Elapsed {
+            monotonic: Duration::from_millis(10),
+            wall: None,
+        };
+        check!(elapsed.clocks_disagree());
+        check!(
+            elapsed.to_string()
+                == "ran for 10ms, but the wall clock went backward (the \
+                system may have been suspended, or its clock changed)"
+        );
+    }
+
+    #[test]
+    fn record_read() {
+        let mut stats = Stats::default();
+        stats.record_read(Stream::Out, 5, 5);
+        stats.record_read(Stream::Err, 2, 5);
+        stats.record_read(Stream::Out, 0, 5);
+        stats.record_read(Stream::Err, 3, 3);
+        check!(stats.out_bytes == 5);
+        check!(stats.err_bytes == 5);
+        check!(stats.reads == 4);
+        check!(stats.short_reads == 2);
+    }
+
+    #[test]
+    fn poll_summary() {
+        let mut stats = Stats::default();
+        check!(
+            stats.poll_summary()
+                == "poll() woke up 0 times; 0 reads (0 short); 0 bytes per \
+                wakeup"
+        );
+
+        stats.record_wakeup();
+        stats.record_wakeup();
+        stats.record_wakeup();
+        stats.record_read(Stream::Out, 10, 10);
+        stats.record_read(Stream::Out, 1, 10);
+        check!(
+            stats.poll_summary()
+                == "poll() woke up 3 times; 2 reads (1 short); 3 bytes per \
+                wakeup"
+        );
+    }
+
+    #[test]
+    fn record_write_full_read() {
+        let mut stats = Stats::default();
+        stats.record_write(Duration::from_millis(5), true);
+        stats.record_write(Duration::from_millis(2), false);
+        stats.record_write(Duration::from_millis(3), true);
+        check!(stats.write_time == Duration::from_millis(10));
+        check!(stats.child_blocked_time == Duration::from_millis(8));
+    }
+}
@@ -0,0 +1,240 @@
+//! Write a machine-readable summary of the run (see `--status-file`).
+//!
+//! The summary is a single JSON object, so that monitoring scripts can check
+//! on the last run without parsing `rederr`’s output. It’s written to a
+//! temporary file and renamed into place, so readers never see a partial
+//! summary.
+
+use crate::cgroup::Usage;
+use crate::rusage::Rusage;
+use crate::stats::Stats;
+use crate::timestamp::Timestamp;
+use anyhow::Context;
+use nix::sys::signal::Signal;
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Summary of a run.
+#[derive(Debug)]
+pub struct Status<'a> {
+    /// The executable that was run.
+    pub command: &'a OsStr,
+
+    /// The arguments passed to the executable.
+    pub args: &'a [OsString],
+
+    /// When the run started.
+    pub start: Timestamp,
+
+    /// When the run ended.
+    pub end: Timestamp,
+
+    /// How long the run took by the monotonic clock.
+    pub duration: Duration,
+
+    /// The code `rederr` is exiting with.
+    pub exit_code: i32,
+
+    /// The child’s exit code, if it exited normally.
+    pub child_exit_code: Option<i32>,
+
+    /// The signal that killed the child, if any.
+    pub signal: Option<Signal>,
+
+    /// Whether a timeout expired.
+    pub timed_out: bool,
+
+    /// Statistics about the run.
+    pub stats: &'a Stats,
+
+    /// What the child’s cgroup used, if `--cgroup` was passed.
+    pub cgroup: Option<Usage>,
+
+    /// What the child used, if it has exited.
+    pub rusage: Option<Rusage>,
+}
+
+impl Status<'_> {
+    /// Render the summary as a JSON object, followed by a newline.
+    pub fn to_json(&self) -> String {
+        let args: Vec<_> = self
+            .args
+            .iter()
+            .map(|arg| string(&arg.to_string_lossy()))
+            .collect();
+        let optional =
+            |value: Option<String>| value.unwrap_or_else(|| "null".to_owned());
+
+        let mut json = String::new();
+        // Writing to a String can’t fail.
+        let _ = writeln!(
+            json,
+            "{{\"command\":{},\"args\":[{}],\"start\":{},\"end\":{},\
+            \"duration_ms\":{},\"exit_code\":{},\"child_exit_code\":{},\
+            \"signal\":{},\"timed_out\":{},\"stdout_bytes\":{},\
+            \"stderr_bytes\":{},\"poll\":{{\"wakeups\":{},\"reads\":{},\
+            \"short_reads\":{}}},\"cgroup\":{},\"rusage\":{}}}",
+            string(&self.command.to_string_lossy()),
+            args.join(","),
+            string(&self.start.to_string()),
+            string(&self.end.to_string()),
+            self.duration.as_millis(),
+            self.exit_code,
+            optional(self.child_exit_code.map(|code| code.to_string())),
+            optional(self.signal.map(|signal| string(signal.as_str()))),
+            self.timed_out,
+            self.stats.out_bytes,
+            self.stats.err_bytes,
+            self.stats.wakeups,
+            self.stats.reads,
+            self.stats.short_reads,
+            optional(self.cgroup.map(|usage| format!(
+                "{{\"cpu_usec\":{},\"memory_peak_bytes\":{}}}",
+                optional(
+                    usage.cpu_time.map(|time| time.as_micros().to_string())
+                ),
+                optional(usage.memory_peak.map(|peak| peak.to_string())),
+            ))),
+            optional(self.rusage.map(|usage| format!(
+                "{{\"user_usec\":{},\"system_usec\":{},\"max_rss_bytes\":{}}}",
+                usage.user.as_micros(),
+                usage.system.as_micros(),
+                usage.max_rss,
+            ))),
+        );
+        json
+    }
+
+    /// Write the summary to `path`, replacing it if it exists.
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, self.to_json())
+            .and_then(|()| fs::rename(&temporary, path))
+            .with_context(|| format!("writing status file {}", path.display()))
+    }
+}
+
+/// Read whether the run recorded in the summary at `path` succeeded, or
+/// `None` if it can’t be read.
+///
+/// This only understands summaries written by [`Status::write()`].
+pub fn read_succeeded(path: &Path) -> Option<bool> {
+    let json = fs::read_to_string(path).ok()?;
+    let (_, rest) = json.split_once("\"exit_code\":")?;
+    let end = rest.find([',', '}']).unwrap_or(rest.len());
+    let code: i32 = rest[..end].trim().parse().ok()?;
+    Some(code == 0)
+}
+
+/// Encode `value` as a JSON string.
+pub fn string(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len().saturating_add(2));
+    encoded.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => encoded.push_str("\\\""),
+            '\\' => encoded.push_str("\\\\"),
+            '\n' => encoded.push_str("\\n"),
+            '\r' => encoded.push_str("\\r"),
+            '\t' => encoded.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(encoded, "\\u{:04x}", u32::from(c));
+            }
+            c => encoded.push(c),
+        }
+    }
+    encoded.push('"');
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+    use std::time::SystemTime;
+
+    #[test]
+    fn string_escapes() {
+        check!(string("plain") == r#""plain""#);
+        check!(string("a \"b\" \\c") == r#""a \"b\" \\c""#);
+        check!(string("1\n2\t3\u{1}") == r#""1\n2\t3\u0001""#);
+        check!(string("é") == "\"é\"");
+    }
+
+    #[test]
+    fn to_json() {
+        let stats = Stats {
+            out_bytes: 4,
+            err_bytes: 2,
+            wakeups: 3,
+            reads: 5,
+            short_reads: 1,
+            ..Stats::default()
+        };
+        let time = Timestamp::Wall {
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(1_709_210_096),
+            offset: 0,
+        };
+        let args = [OsString::from("a b"), OsString::from("\"c\"")];
+        let summary = Status {
+            command: OsStr::new("cmd"),
+            args: &args,
+            start: time,
+            end: time,
+            duration: Duration::from_millis(1_500),
+            exit_code: 143,
+            child_exit_code: None,
+            signal: Some(Signal::SIGTERM),
+            timed_out: true,
+            stats: &stats,
+            cgroup: None,
+            rusage: Some(Rusage {
+                user: Duration::from_millis(20),
+                system: Duration::from_micros(1_500),
+                max_rss: 4_096,
+            }),
+        };
+        check!(
+            summary.to_json()
+                == "{\"command\":\"cmd\",\"args\":[\"a b\",\"\\\"c\\\"\"],\
+                \"start\":\"2024-02-29T12:34:56.000Z\",\
+                \"end\":\"2024-02-29T12:34:56.000Z\",\"duration_ms\":1500,\
+                \"exit_code\":143,\"child_exit_code\":null,\
+                \"signal\":\"SIGTERM\",\"timed_out\":true,\"stdout_bytes\":4,\
+                \"stderr_bytes\":2,\"poll\":{\"wakeups\":3,\"reads\":5,\
+                \"short_reads\":1},\"cgroup\":null,\"rusage\":{\"user_usec\":20000,\
+                \"system_usec\":1500,\"max_rss_bytes\":4096}}\n"
+        );
+    }
+
+    #[test]
+    fn to_json_cgroup() {
+        let stats = Stats::default();
+        let time = Timestamp::Offset(Duration::ZERO);
+        let summary = Status {
+            command: OsStr::new("cmd"),
+            args: &[],
+            start: time,
+            end: time,
+            duration: Duration::ZERO,
+            exit_code: 0,
+            child_exit_code: Some(0),
+            signal: None,
+            timed_out: false,
+            stats: &stats,
+            cgroup: Some(Usage {
+                memory_peak: None,
+                cpu_time: Some(Duration::from_micros(1_234)),
+            }),
+            rusage: None,
+        };
+        check!(summary.to_json().ends_with(
+            ",\"cgroup\":{\"cpu_usec\":1234,\"memory_peak_bytes\":null},\
+            \"rusage\":null}\n"
+        ));
+    }
+}
@@ -0,0 +1,180 @@
+//! A status line at the bottom of the terminal (see `--status-line`).
+//!
+//! The status line is drawn after the last complete line of output without a
+//! newline, so it stays at the bottom of the terminal as output scrolls past.
+//! It’s erased before anything else is written, and drawn again afterward.
+
+use crate::gap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use termcolor::{ColorSpec, WriteColor};
+
+/// How often to update the status line while the child is quiet.
+pub const INTERVAL: Duration = Duration::from_secs(1);
+
+/// The shortest time between redraws after output, so that it doesn’t
+/// flicker when there’s a lot of output.
+const MIN_REDRAW: Duration = Duration::from_millis(100);
+
+/// Go to the start of the line and erase it.
+const ERASE: &[u8] = b"\r\x1b[K";
+
+/// Tracks whether the status line is on the terminal.
+#[derive(Debug, Default)]
+pub struct StatusLine {
+    /// Whether the status line is on the terminal now.
+    drawn: bool,
+
+    /// When the status line was last drawn, if it has been.
+    drawn_at: Option<Instant>,
+}
+
+impl StatusLine {
+    /// Draw `text` as the status line on `out`, which must be at the start of
+    /// a line.
+    ///
+    /// Unless `force` is set, this does nothing if the status line was drawn
+    /// very recently.
+    pub fn draw(
+        &mut self,
+        out: &mut dyn WriteColor,
+        text: &str,
+        force: bool,
+    ) -> io::Result<()> {
+        if !force && self.drawn_at.is_some_and(|at| at.elapsed() < MIN_REDRAW) {
+            return Ok(());
+        }
+
+        out.write_all(ERASE)?;
+        out.set_color(ColorSpec::new().set_dimmed(true))?;
+        out.write_all(text.as_bytes())?;
+        out.reset()?;
+        out.flush()?;
+        self.drawn = true;
+        self.drawn_at = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Erase the status line from `out`, if it’s there.
+    pub fn clear(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        if self.drawn {
+            self.drawn = false;
+            out.write_all(ERASE)?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the text of the status line, e.g. "12s elapsed, 3.4 KiB of output,
+/// last output 2s ago".
+pub fn text(
+    elapsed: Duration,
+    bytes: u64,
+    last_output: Option<Duration>,
+) -> String {
+    let last_output = last_output.map_or_else(
+        || "no output yet".to_owned(),
+        |ago| format!("last output {} ago", seconds(ago)),
+    );
+    format!(
+        "{} elapsed, {} of output, {last_output}",
+        seconds(elapsed),
+        size(bytes),
+    )
+}
+
+/// Describe a duration in whole seconds, e.g. "2m 14s" or "0s".
+pub fn seconds(duration: Duration) -> String {
+    if duration.as_secs() == 0 {
+        "0s".to_owned()
+    } else {
+        gap::describe(duration)
+    }
+}
+
+/// Describe a number of bytes like "3.4 KiB".
+// Precision loss doesn’t matter with one decimal place.
+#[allow(clippy::cast_precision_loss)]
+fn size(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    for unit in ["KiB", "MiB", "GiB"] {
+        if size < 1024.0 {
+            return format!("{size:.1} {unit}");
+        }
+        size /= 1024.0;
+    }
+    format!("{size:.1} TiB")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+    use termcolor::Ansi;
+
+    #[test]
+    fn text_no_output() {
+        check!(
+            text(Duration::from_millis(1_500), 0, None)
+                == "1s elapsed, 0 B of output, no output yet"
+        );
+    }
+
+    #[test]
+    fn text_start() {
+        check!(
+            text(Duration::from_millis(5), 2, Some(Duration::ZERO))
+                == "0s elapsed, 2 B of output, last output 0s ago"
+        );
+    }
+
+    #[test]
+    fn text_output() {
+        check!(
+            text(
+                Duration::from_secs(134),
+                3_500,
+                Some(Duration::from_secs(2))
+            ) == "2m 14s elapsed, 3.4 KiB of output, last output 2s ago"
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        check!(size(1_023) == "1023 B");
+        check!(size(1_024) == "1.0 KiB");
+        check!(size(5 * 1024 * 1024) == "5.0 MiB");
+        check!(size(3 << 40) == "3.0 TiB");
+    }
+
+    #[test]
+    fn draw_and_clear() {
+        let mut line = StatusLine::default();
+        let mut out = Ansi::new(Vec::new());
+        line.clear(&mut out).unwrap();
+        line.draw(&mut out, "status", false).unwrap();
+        line.clear(&mut out).unwrap();
+        line.clear(&mut out).unwrap();
+        check!(
+            out.into_inner() == b"\r\x1b[K\x1b[0m\x1b[2mstatus\x1b[0m\r\x1b[K"
+        );
+    }
+
+    #[test]
+    fn draw_throttled() {
+        let mut line = StatusLine::default();
+        let mut out = Ansi::new(Vec::new());
+        line.draw(&mut out, "a", false).unwrap();
+        line.draw(&mut out, "b", false).unwrap();
+        line.draw(&mut out, "c", true).unwrap();
+        check!(
+            out.into_inner()
+                == b"\r\x1b[K\x1b[0m\x1b[2ma\x1b[0m\r\x1b[K\x1b[0m\x1b[2mc\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Forward our stdin to the child (see `--stdin=pipe`).
+//!
+//! Our stdin is only read when `poll()` says it’s readable, and it’s left
+//! blocking since the file description may be shared with other processes.
+//! The child’s end of the pipe is non-blocking, so if the child isn’t reading
+//! we stop reading our stdin until there’s room in the pipe again.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::process::ChildStdin;
+
+/// What the pump needs before it can make progress.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wait {
+    /// Our stdin to be readable.
+    Input,
+
+    /// The child’s stdin to be writable.
+    Child,
+
+    /// Nothing; forwarding is finished.
+    Done,
+}
+
+/// Copies our stdin to the child’s stdin.
+#[derive(Debug)]
+pub struct StdinPump {
+    /// Our stdin. This is a duplicate of fd 0 so that reads aren’t buffered.
+    input: File,
+
+    /// The child’s stdin, or `None` once it’s been closed.
+    child: Option<ChildStdin>,
+
+    /// Data read from our stdin.
+    buffer: Vec<u8>,
+
+    /// Where the data not yet written to the child starts in `buffer`.
+    start: usize,
+
+    /// Where the data not yet written to the child ends in `buffer`.
+    end: usize,
+}
+
+impl StdinPump {
+    /// Start forwarding our stdin to `child`, reading up to `buffer_size`
+    /// bytes at a time.
+    pub fn new(child: ChildStdin, buffer_size: usize) -> io::Result<Self> {
+        popol::set_nonblocking(&child, true)?;
+        Ok(Self {
+            input: File::from(io::stdin().as_fd().try_clone_to_owned()?),
+            child: Some(child),
+            buffer: vec![0; buffer_size.max(1)],
+            start: 0,
+            end: 0,
+        })
+    }
+
+    /// Our stdin, to poll for [`Wait::Input`].
+    pub fn input(&self) -> BorrowedFd<'_> {
+        self.input.as_fd()
+    }
+
+    /// The child’s stdin, to poll for [`Wait::Child`].
+    pub fn child(&self) -> Option<BorrowedFd<'_>> {
+        self.child.as_ref().map(AsFd::as_fd)
+    }
+
+    /// Read from our stdin, which is readable, and forward it to the child.
+    ///
+    /// Closes the child’s stdin at EOF.
+    pub fn read(&mut self) -> io::Result<Wait> {
+        let count = match self.input.read(&mut self.buffer) {
+            Ok(count) => count,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {
+                return Ok(Wait::Input);
+            }
+            Err(error) => return Err(error),
+        };
+
+        if count == 0 {
+            self.child = None;
+            return Ok(Wait::Done);
+        }
+
+        self.start = 0;
+        self.end = count;
+        self.write()
+    }
+
+    /// Write pending data to the child’s stdin, which is writable.
+    ///
+    /// If the child has closed its stdin, forwarding stops.
+    pub fn write(&mut self) -> io::Result<Wait> {
+        let Some(child) = &mut self.child else {
+            return Ok(Wait::Done);
+        };
+
+        while self.start < self.end {
+            match child.write(&self.buffer[self.start..self.end]) {
+                Ok(count) => self.start = self.start.saturating_add(count),
+                Err(error) => match error.kind() {
+                    io::ErrorKind::WouldBlock => return Ok(Wait::Child),
+                    io::ErrorKind::Interrupted => {}
+                    io::ErrorKind::BrokenPipe => {
+                        // The child doesn’t want any more input.
+                        self.child = None;
+                        return Ok(Wait::Done);
+                    }
+                    _ => return Err(error),
+                },
+            }
+        }
+
+        Ok(Wait::Input)
+    }
+}
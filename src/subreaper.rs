@@ -0,0 +1,78 @@
+//! Wait for processes the child leaves behind (see `--subreaper`).
+//!
+//! When a process exits, its children are normally reparented to init, so
+//! background processes started by the child escape `rederr` entirely. On
+//! Linux, `rederr` can make itself a child subreaper, so that they’re
+//! reparented to it instead. Then it can wait for them to exit.
+//!
+//! Orphans are only reaped after the child has exited, so that waiting for
+//! any child can’t reap the child itself. This also reaps `--warn-command`
+//! hooks that are still running.
+
+use nix::errno::Errno;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+
+/// Make orphaned descendants our children.
+#[cfg(target_os = "linux")]
+pub fn enable() -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    nix::sys::prctl::set_child_subreaper(true)
+        .context("becoming a child subreaper")
+}
+
+/// Child subreapers are only supported on Linux.
+#[cfg(not(target_os = "linux"))]
+pub fn enable() -> anyhow::Result<()> {
+    anyhow::bail!("--subreaper is only supported on Linux")
+}
+
+/// Orphaned processes that were reparented to us.
+#[derive(Debug)]
+pub struct Orphans {
+    /// How many have exited and been reaped.
+    reaped: usize,
+
+    /// Whether any might still be running.
+    remaining: bool,
+}
+
+impl Orphans {
+    /// Start tracking orphans. Until [`Self::reap()`] is called, some might be
+    /// running.
+    pub const fn new() -> Self {
+        Self {
+            reaped: 0,
+            remaining: true,
+        }
+    }
+
+    /// How many orphans have been reaped.
+    pub const fn reaped(&self) -> usize {
+        self.reaped
+    }
+
+    /// Might any orphans still be running?
+    pub const fn is_remaining(&self) -> bool {
+        self.remaining
+    }
+
+    /// Reap orphans that have exited without blocking.
+    ///
+    /// Only call this after the child has been reaped, since this reaps any of
+    /// our children.
+    pub fn reap(&mut self) -> nix::Result<()> {
+        loop {
+            match waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::StillAlive) => return Ok(()),
+                Ok(_) => self.reaped = self.reaped.saturating_add(1),
+                Err(Errno::ECHILD) => {
+                    self.remaining = false;
+                    return Ok(());
+                }
+                Err(Errno::EINTR) => {}
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Keep the end of the output to send in notifications (see `--ping-url`,
+//! `--webhook`, and `--mail-to`).
+//!
+//! The output is kept uncolored, with both streams mixed together the way they
+//! would be on a terminal. Only the last [`MAX_SIZE`] bytes are kept.
+
+use crate::format::{self, Colors, Segment};
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+use termcolor::NoColor;
+
+/// The most output to keep, in KiB.
+pub const MAX_SIZE_KIB: usize = 10;
+
+/// The most output to keep, in bytes.
+pub const MAX_SIZE: usize = MAX_SIZE_KIB * 1024;
+
+/// The end of the output.
+#[derive(Debug, Default)]
+pub struct Tail {
+    /// The last [`MAX_SIZE`] bytes of output.
+    bytes: VecDeque<u8>,
+
+    /// Whether output was dropped from the start.
+    truncated: bool,
+
+    /// Buffer to render segments into.
+    rendered: Vec<u8>,
+}
+
+impl Tail {
+    /// Add a segment of formatted output.
+    pub fn render(
+        &mut self,
+        segment: &Segment,
+        colors: &Colors,
+    ) -> io::Result<()> {
+        self.rendered.clear();
+        format::render(&mut NoColor::new(&mut self.rendered), segment, colors)?;
+        let rendered = mem::take(&mut self.rendered);
+        self.push(&rendered);
+        self.rendered = rendered;
+        Ok(())
+    }
+
+    /// Add a message from `rederr` itself.
+    pub fn notice(&mut self, message: &str) {
+        if self.bytes.back().is_some_and(|last| *last != b'\n') {
+            self.push(b"\n");
+        }
+        self.push(message.as_bytes());
+        self.push(b"\n");
+    }
+
+    /// Get the output that was kept.
+    ///
+    /// If the start was dropped, this starts with the first complete line.
+    pub fn contents(&self) -> Vec<u8> {
+        let mut contents: Vec<u8> = self.bytes.iter().copied().collect();
+        if self.truncated {
+            let start = contents
+                .iter()
+                .position(|byte| *byte == b'\n')
+                .map_or(0, |newline| newline.saturating_add(1));
+            contents.drain(..start);
+        }
+        contents
+    }
+
+    /// Was the start of the output dropped?
+    pub const fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Add `bytes`, dropping output from the start to make room.
+    fn push(&mut self, bytes: &[u8]) {
+        let bytes = &bytes[bytes.len().saturating_sub(MAX_SIZE)..];
+        let excess = self
+            .bytes
+            .len()
+            .saturating_add(bytes.len())
+            .saturating_sub(MAX_SIZE);
+        if excess > 0 {
+            self.bytes.drain(..excess.min(self.bytes.len()));
+            self.truncated = true;
+        }
+        self.bytes.extend(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+    use bstr::ByteSlice;
+
+    #[test]
+    fn short() {
+        let mut tail = Tail::default();
+        tail.push(b"one\ntw");
+        tail.notice("Timed out");
+        check!(tail.contents().as_bstr() == "one\ntw\nTimed out\n");
+        check!(!tail.is_truncated());
+    }
+
+    #[test]
+    fn truncated() {
+        let mut tail = Tail::default();
+        tail.push(b"first\n");
+        tail.push(&[b'a'; MAX_SIZE]);
+        tail.push(b"\nlast\n");
+        check!(tail.is_truncated());
+        check!(tail.contents().as_bstr() == "last\n");
+    }
+
+    #[test]
+    fn truncated_one_line() {
+        let mut tail = Tail::default();
+        tail.push(&[b'a'; MAX_SIZE]);
+        tail.push(b"b");
+        check!(tail.is_truncated());
+        check!(tail.contents().len() == MAX_SIZE);
+        check!(tail.contents().ends_with(b"ab"));
+    }
+}
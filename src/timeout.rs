@@ -9,14 +9,28 @@
 //! timeouts, such as reading from a [`std::net::TcpStream`], you will need to
 //! set the timeout for the read correctly so that you don’t exceed the overall
 //! timeout.
+//!
+//! Timeouts are measured with [`Instant`], which uses a monotonic clock. It
+//! isn’t affected by changes to the system clock, and on Linux and macOS it
+//! doesn’t advance while the system is suspended, so time spent suspended
+//! doesn’t count against a timeout.
+//!
+//! The exception is [`Timeout::At`], which expires at a wall clock time. It’s
+//! measured with [`SystemTime`], so changes to the system clock and time spent
+//! suspended do count. Use [`Timeout::wait()`] to find out how long to wait
+//! before checking it again, so that changes to the clock are noticed.
 
 use std::cmp::Ordering;
 use std::fmt;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
 /// Minimum valid timeout that `poll()` respects.
 const TIMEOUT_RESOLUTION: Duration = Duration::from_millis(1);
 
+/// Longest time to wait before checking a [`Timeout::At`] again, in case the
+/// system clock changed or the system was suspended.
+const WALL_CLOCK_INTERVAL: Duration = Duration::from_secs(10);
+
 /// A stateful timeout.
 ///
 /// Create a `Timeout::Future` to represent a planned timeout. Run
@@ -35,7 +49,7 @@ pub enum Timeout {
     ///
     /// ```rust
     /// use assert2::let_assert;
-    /// use cron_wrapper::timeout::Timeout;
+    /// use rederr::timeout::Timeout;
     /// use std::time::Duration;
     ///
     /// let_assert!(
@@ -58,6 +72,18 @@ pub enum Timeout {
         start: Instant,
     },
 
+    /// Time out at a wall clock time.
+    ///
+    /// Produced by [`Timeout::at()`]. This is already counting down, so
+    /// [`Timeout::start()`] doesn’t change it.
+    At {
+        /// When the timeout expires.
+        deadline: SystemTime,
+
+        /// When the timeout was created.
+        start: Instant,
+    },
+
     /// A timeout that has expired.
     ///
     /// Produced by [`Timeout::check_expired()`].
@@ -71,6 +97,24 @@ pub enum Timeout {
 }
 
 impl Timeout {
+    /// Create a timeout that expires at the wall clock time `deadline`.
+    ///
+    /// ```rust
+    /// use assert2::check;
+    /// use rederr::timeout::Timeout;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let deadline = SystemTime::now() + Duration::from_secs(60);
+    /// check!(Timeout::at(deadline) < Timeout::from(Duration::from_secs(61)));
+    /// ```
+    #[must_use]
+    pub fn at(deadline: SystemTime) -> Self {
+        Self::At {
+            deadline,
+            start: Instant::now(),
+        }
+    }
+
     /// Get the remaining timeout if available.
     ///
     /// Returns `Some(Duration::ZERO)` if the timeout has already expired.
@@ -82,14 +126,33 @@ impl Timeout {
             Self::Pending { timeout, start } => {
                 Some(timeout.saturating_sub(start.elapsed()))
             }
+            Self::At { deadline, .. } => Some(
+                deadline
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default(),
+            ),
             Self::Expired { .. } => Some(Duration::ZERO),
         }
     }
 
+    /// Get how long to wait before checking whether the timeout has expired.
+    ///
+    /// This is the same as [`Timeout::timeout()`], except that it’s limited
+    /// for [`Timeout::At`] so that changes to the system clock are noticed.
+    #[must_use]
+    pub fn wait(&self) -> Option<Duration> {
+        let timeout = self.timeout();
+        if let Self::At { .. } = self {
+            timeout.map(|timeout| timeout.min(WALL_CLOCK_INTERVAL))
+        } else {
+            timeout
+        }
+    }
+
     /// Return a pending version of this `Timeout`.
     ///
-    /// If the timeout is `Never`, `Pending`, or `Expired`, then it returns a
-    /// clone of `self`.
+    /// If the timeout is `Never`, `Pending`, `At`, or `Expired`, then it
+    /// returns a clone of `self`.
     #[must_use]
     pub fn start(&self) -> Self {
         if let Self::Future { timeout } = self {
@@ -102,6 +165,26 @@ impl Timeout {
         }
     }
 
+    /// Return a version of this `Timeout` that expires `extra` later.
+    ///
+    /// If the timeout is `Never`, `At`, or `Expired`, then it returns a clone
+    /// of `self`. A deadline is fixed, so it isn’t extended.
+    #[must_use]
+    pub fn extend(&self, extra: Duration) -> Self {
+        match self {
+            Self::Future { timeout } => Self::Future {
+                timeout: timeout.saturating_add(extra),
+            },
+            Self::Pending { timeout, start } => Self::Pending {
+                timeout: timeout.saturating_add(extra),
+                start: *start,
+            },
+            Self::Never | Self::At { .. } | Self::Expired { .. } => {
+                self.clone()
+            }
+        }
+    }
+
     /// Has the timeout expired?
     ///
     /// Returns:
@@ -121,6 +204,16 @@ impl Timeout {
                     None
                 }
             }
+            Self::At { start, .. } => {
+                let remaining = self.timeout().unwrap_or_default();
+                (remaining < TIMEOUT_RESOLUTION).then(|| {
+                    let elapsed = start.elapsed();
+                    Self::Expired {
+                        requested: elapsed.saturating_add(remaining),
+                        actual: elapsed,
+                    }
+                })
+            }
             // FIXME better way of doing this?
             Self::Expired { .. } => Some(self.clone()),
             _ => None,
@@ -138,7 +231,9 @@ impl Timeout {
     pub fn elapsed(&self) -> Duration {
         match &self {
             Self::Never | Self::Future { .. } => Duration::ZERO,
-            Self::Pending { start, .. } => start.elapsed(),
+            Self::Pending { start, .. } | Self::At { start, .. } => {
+                start.elapsed()
+            }
             Self::Expired { actual, .. } => *actual,
         }
     }
@@ -185,6 +280,13 @@ impl fmt::Display for Timeout {
                     timeout.saturating_sub(start.elapsed()),
                 )
             }
+            Self::At { deadline, .. } => {
+                write!(
+                    f,
+                    "At({deadline:?}, {:?} remaining)",
+                    self.timeout().unwrap_or_default(),
+                )
+            }
             Self::Expired { requested, actual } => {
                 write!(f, "Expired({requested:?} requested, {actual:?} actual)")
             }
@@ -235,7 +337,7 @@ mod tests {
 
     use super::*;
     use assert2::{check, let_assert};
-    use std::time::Duration;
+    use std::time::{Duration, SystemTime};
 
     const fn future_timeout(microseconds: u64) -> Timeout {
         Timeout::Future {
@@ -259,6 +361,57 @@ mod tests {
         }
     }
 
+    #[allow(clippy::arithmetic_side_effects)]
+    fn at_timeout(milliseconds: u64) -> Timeout {
+        Timeout::at(SystemTime::now() + Duration::from_millis(milliseconds))
+    }
+
+    #[test]
+    fn at_timeout_remaining() {
+        let timeout = at_timeout(60_000);
+        let_assert!(Some(remaining) = timeout.timeout());
+        check!(remaining > Duration::from_secs(59));
+        check!(remaining <= Duration::from_mins(1));
+        check!(timeout.wait() == Some(WALL_CLOCK_INTERVAL));
+        check!(matches!(timeout.start(), Timeout::At { .. }));
+        check!(timeout.check_expired() == None);
+    }
+
+    #[test]
+    fn at_timeout_past() {
+        let timeout = Timeout::at(SystemTime::now() - Duration::from_mins(1));
+        check!(timeout.timeout() == Some(Duration::ZERO));
+        check!(timeout.wait() == Some(Duration::ZERO));
+        let_assert!(Some(Timeout::Expired { .. }) = timeout.check_expired());
+    }
+
+    #[test]
+    fn compare_timeout_at() {
+        let timeout = at_timeout(5_000);
+
+        check!(Timeout::Never > timeout);
+        check!(future_timeout(6_000_000) > timeout);
+        check!(future_timeout(4_000_000) < timeout);
+        check!(pending_timeout(6_000_000, 500) > timeout);
+        check!(expired_timeout(5_000) < timeout);
+    }
+
+    #[test]
+    fn extend_timeout_at() {
+        let timeout = at_timeout(5_000);
+        let_assert!(
+            Timeout::At { deadline, .. } =
+                timeout.extend(Duration::from_secs(5))
+        );
+        let_assert!(
+            Timeout::At {
+                deadline: original,
+                ..
+            } = timeout
+        );
+        check!(deadline == original);
+    }
+
     #[test]
     fn elapsed_rounded_up() {
         check!(expired_timeout(1_500).elapsed_rounded().as_micros() == 2_000);
@@ -369,6 +522,36 @@ mod tests {
         check!(timeout == expired_timeout(5_000));
     }
 
+    #[test]
+    fn extend_timeout_never() {
+        check!(Timeout::Never.extend(Duration::from_secs(1)) == Timeout::Never);
+    }
+
+    #[test]
+    fn extend_timeout_future() {
+        let_assert!(
+            Timeout::Future { timeout } =
+                future_timeout(1_000).extend(Duration::from_millis(1))
+        );
+        check!(timeout.as_micros() == 2_000);
+    }
+
+    #[test]
+    fn extend_timeout_pending_overtime() {
+        let timeout = pending_timeout(5_000, 6_000);
+        check!(timeout.check_expired().is_some());
+
+        let timeout = timeout.extend(Duration::from_secs(5));
+        check!(timeout.check_expired() == None);
+        check!(timeout.elapsed().as_micros() >= 6_000);
+    }
+
+    #[test]
+    fn extend_timeout_expired() {
+        let timeout = expired_timeout(5_000);
+        check!(timeout.extend(Duration::from_secs(5)) == timeout);
+    }
+
     #[test]
     fn check_expired_timeout_never() {
         check!(Timeout::Never.check_expired() == None);
@@ -0,0 +1,375 @@
+//! Timestamps for the start of each line of output (see `--timestamps` and
+//! `--timing`).
+
+use crate::params::TimestampFormat;
+use crate::tz::TimeZone;
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+/// When a line of output started.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timestamp {
+    /// Wall clock time, displayed in ISO 8601 format with `offset` (in seconds
+    /// east of UTC) applied.
+    Wall {
+        /// The time.
+        time: SystemTime,
+
+        /// The UTC offset in the time zone it’s displayed in.
+        offset: i32,
+    },
+
+    /// Time relative to some earlier event, displayed as `HH:MM:SS.mmm`.
+    Offset(Duration),
+}
+
+impl Timestamp {
+    /// Get a wall clock timestamp for `time` in `time_zone`.
+    pub fn wall(time: SystemTime, time_zone: &TimeZone) -> Self {
+        let secs = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| {
+                i64::try_from(since.as_secs()).unwrap_or(i64::MAX)
+            });
+        Self::Wall {
+            time,
+            offset: time_zone.offset_at(secs),
+        }
+    }
+}
+
+impl fmt::Display for Timestamp {
+    // Truncating division is how we split out the units.
+    #[allow(clippy::integer_division)]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Wall { time, offset } => {
+                // Times before the epoch are clamped to it.
+                let since_epoch = time
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default();
+                let secs = since_epoch
+                    .as_secs()
+                    .saturating_add_signed(i64::from(*offset));
+                let (year, month, day) = civil_from_days(secs / 86_400);
+                write!(
+                    f,
+                    "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}",
+                    secs % 86_400 / 3_600,
+                    secs % 3_600 / 60,
+                    secs % 60,
+                    since_epoch.subsec_millis(),
+                )?;
+                if *offset == 0 {
+                    return write!(f, "Z");
+                }
+                let sign = if *offset < 0 { '-' } else { '+' };
+                let offset = offset.unsigned_abs();
+                write!(
+                    f,
+                    "{sign}{:02}:{:02}",
+                    offset / 3_600,
+                    offset % 3_600 / 60
+                )
+            }
+            Self::Offset(offset) => {
+                let secs = offset.as_secs();
+                write!(
+                    f,
+                    "{:02}:{:02}:{:02}.{:03}",
+                    secs / 3_600,
+                    secs % 3_600 / 60,
+                    secs % 60,
+                    offset.subsec_millis(),
+                )
+            }
+        }
+    }
+}
+
+/// Produces timestamps in the format requested with `--timestamps`.
+#[derive(Debug)]
+pub struct Clock {
+    /// The format to use, or `None` for no timestamps.
+    format: Option<TimestampFormat>,
+
+    /// When the clock was created, for [`TimestampFormat::Elapsed`].
+    start: Instant,
+
+    /// When the last timestamp was produced, for [`TimestampFormat::Delta`].
+    last: Instant,
+
+    /// Time zone for [`TimestampFormat::Iso`].
+    time_zone: TimeZone,
+}
+
+impl Clock {
+    /// Create a clock that produces timestamps in `format`, if any.
+    ///
+    /// Wall clock times are displayed in `time_zone`.
+    pub fn new(format: Option<TimestampFormat>, time_zone: TimeZone) -> Self {
+        let now = Instant::now();
+        Self {
+            format,
+            start: now,
+            last: now,
+            time_zone,
+        }
+    }
+
+    /// Does this clock produce timestamps?
+    pub const fn is_enabled(&self) -> bool {
+        self.format.is_some()
+    }
+
+    /// Get a timestamp for now, if timestamps are enabled.
+    pub fn stamp(&mut self) -> Option<Timestamp> {
+        let now = Instant::now();
+        let previous = self.last;
+        self.last = now;
+
+        Some(match self.format? {
+            TimestampFormat::Iso => {
+                Timestamp::wall(SystemTime::now(), &self.time_zone)
+            }
+            TimestampFormat::Elapsed => {
+                Timestamp::Offset(now.saturating_duration_since(self.start))
+            }
+            TimestampFormat::Delta => {
+                Timestamp::Offset(now.saturating_duration_since(previous))
+            }
+        })
+    }
+}
+
+/// How long it was between the start of one line of output and the start of
+/// the previous line (see `--timing`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Delay {
+    /// The time since the previous line started, or since the command started
+    /// for the first line.
+    pub elapsed: Duration,
+
+    /// Whether `elapsed` is longer than `--slow-threshold`.
+    pub slow: bool,
+}
+
+impl fmt::Display for Delay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>8.3}s", self.elapsed.as_secs_f64())
+    }
+}
+
+/// Measures the time between the starts of lines (see `--timing`).
+#[derive(Debug)]
+pub struct Timing {
+    /// When the previous line started.
+    last: Instant,
+
+    /// Lines that took longer than this are slow (see `--slow-threshold`).
+    slow: Option<Duration>,
+}
+
+impl Timing {
+    /// Start measuring now. Delays longer than `slow` are marked as slow.
+    pub fn new(slow: Option<Duration>) -> Self {
+        Self {
+            last: Instant::now(),
+            slow,
+        }
+    }
+
+    /// Record that a line started at `now`, and get the delay since the
+    /// previous line.
+    pub fn delay(&mut self, now: Instant) -> Delay {
+        let elapsed = now.saturating_duration_since(self.last);
+        self.last = now;
+        Delay {
+            elapsed,
+            slow: self.slow.is_some_and(|slow| elapsed > slow),
+        }
+    }
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) date.
+///
+/// This is Howard Hinnant’s `civil_from_days()` algorithm, simplified since
+/// `days` can’t be negative.
+// The intermediate values are all small and non-negative, and truncating
+// division is part of the algorithm.
+#[allow(clippy::arithmetic_side_effects, clippy::integer_division)]
+const fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Shift the epoch to 0000-03-01, so leap days are at the end of years.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Convert a (year, month, day) date to days since 1970-01-01.
+///
+/// This is Howard Hinnant’s `days_from_civil()` algorithm.
+// The intermediate values are all small for reasonable years, and truncating
+// division is part of the algorithm.
+#[allow(clippy::arithmetic_side_effects, clippy::integer_division)]
+pub const fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Shift the epoch to 0000-03-01, so leap days are at the end of years.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_from_march = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::{check, let_assert};
+    use std::thread;
+
+    #[test]
+    fn display_delay() {
+        let delay = Delay {
+            elapsed: Duration::from_millis(1_234),
+            slow: false,
+        };
+        check!(delay.to_string() == "   1.234s");
+    }
+
+    #[test]
+    #[allow(clippy::arithmetic_side_effects)]
+    fn timing_slow() {
+        let start = Instant::now();
+        let mut timing = Timing {
+            last: start,
+            slow: Some(Duration::from_secs(1)),
+        };
+        check!(
+            timing.delay(start + Duration::from_millis(500))
+                == Delay {
+                    elapsed: Duration::from_millis(500),
+                    slow: false,
+                }
+        );
+        check!(
+            timing.delay(start + Duration::from_secs(2))
+                == Delay {
+                    elapsed: Duration::from_millis(1_500),
+                    slow: true,
+                }
+        );
+    }
+
+    #[test]
+    #[allow(clippy::arithmetic_side_effects)]
+    fn timing_without_threshold() {
+        let start = Instant::now();
+        let mut timing = Timing::new(None);
+        let delay = timing.delay(start + Duration::from_hours(1));
+        check!(!delay.slow);
+    }
+
+    #[test]
+    fn display_wall() {
+        let time = SystemTime::UNIX_EPOCH
+            + Duration::from_secs(1_709_210_096)
+            + Duration::from_millis(7);
+        check!(
+            Timestamp::Wall { time, offset: 0 }.to_string()
+                == "2024-02-29T12:34:56.007Z"
+        );
+    }
+
+    #[test]
+    fn display_wall_offset() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        check!(
+            Timestamp::Wall {
+                time,
+                offset: 5 * 3_600 + 30 * 60
+            }
+            .to_string()
+                == "2024-02-29T18:04:56.000+05:30"
+        );
+        check!(
+            Timestamp::Wall {
+                time,
+                offset: -13 * 3_600
+            }
+            .to_string()
+                == "2024-02-28T23:34:56.000-13:00"
+        );
+    }
+
+    #[test]
+    fn display_wall_epoch() {
+        check!(
+            Timestamp::Wall {
+                time: SystemTime::UNIX_EPOCH,
+                offset: 0
+            }
+            .to_string()
+                == "1970-01-01T00:00:00.000Z"
+        );
+    }
+
+    #[test]
+    fn display_offset() {
+        let offset =
+            Duration::from_millis(((25 * 60 + 3) * 60 + 4) * 1_000 + 5);
+        check!(Timestamp::Offset(offset).to_string() == "25:03:04.005");
+    }
+
+    #[test]
+    fn civil_from_days_boundaries() {
+        check!(civil_from_days(0) == (1970, 1, 1));
+        check!(civil_from_days(59) == (1970, 3, 1));
+        check!(civil_from_days(11_016) == (2000, 2, 29));
+        check!(civil_from_days(11_017) == (2000, 3, 1));
+        check!(civil_from_days(20_818) == (2026, 12, 31));
+    }
+
+    #[test]
+    fn days_from_civil_boundaries() {
+        check!(days_from_civil(1970, 1, 1) == 0);
+        check!(days_from_civil(1969, 12, 31) == -1);
+        check!(days_from_civil(2000, 2, 29) == 11_016);
+        check!(days_from_civil(2000, 3, 1) == 11_017);
+        check!(days_from_civil(2026, 12, 31) == 20_818);
+    }
+
+    #[test]
+    fn clock_disabled() {
+        let mut clock = Clock::new(None, TimeZone::default());
+        check!(!clock.is_enabled());
+        check!(clock.stamp() == None);
+    }
+
+    #[test]
+    fn clock_elapsed() {
+        let mut clock =
+            Clock::new(Some(TimestampFormat::Elapsed), TimeZone::default());
+        let first = clock.stamp();
+        thread::sleep(Duration::from_millis(10));
+        let second = clock.stamp();
+        let_assert!(Some(Timestamp::Offset(first)) = first);
+        let_assert!(Some(Timestamp::Offset(second)) = second);
+        check!(second >= first + Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,684 @@
+//! Time zones for timestamps and deadlines (see `--tz` and `--deadline`).
+//!
+//! Zones are loaded from the system’s time zone database (`TZif` files, as
+//! described in tzfile(5)), which is usually in `/usr/share/zoneinfo`. Times
+//! after the last transition in a file, and zones that aren’t in the database,
+//! use a POSIX TZ string like `EST5EDT,M3.2.0,M11.1.0`.
+//!
+//! Leap seconds are ignored, since [`SystemTime`](std::time::SystemTime)
+//! ignores them too.
+
+use crate::timestamp::days_from_civil;
+use anyhow::{anyhow, bail, Context};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where to find the time zone database if `TZDIR` isn’t set.
+const DEFAULT_TZDIR: &str = "/usr/share/zoneinfo";
+
+/// Seconds in a day.
+const DAY: i64 = 86_400;
+
+/// A time zone: a name and a way to find the UTC offset at any time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TimeZone {
+    /// The name the zone was loaded with, e.g. "Europe/Paris".
+    name: String,
+
+    /// Times (in seconds since the epoch) when the offset changes, and the
+    /// offset (in seconds east of UTC) from then on.
+    transitions: Vec<(i64, i32)>,
+
+    /// The offset before the first transition.
+    initial: i32,
+
+    /// Rule for times after the last transition.
+    rule: Option<Rule>,
+}
+
+impl TimeZone {
+    /// Load a zone by name, e.g. "Europe/Paris", "UTC", or "EST5EDT".
+    pub fn load(name: &str) -> anyhow::Result<Self> {
+        // A leading ":" means the rest is implementation defined, which for
+        // us is always a file.
+        let file = name.strip_prefix(':').unwrap_or(name);
+        let path = if Path::new(file).is_absolute() {
+            PathBuf::from(file)
+        } else {
+            env::var_os("TZDIR")
+                .map_or_else(|| PathBuf::from(DEFAULT_TZDIR), PathBuf::from)
+                .join(file)
+        };
+
+        let mut zone = match fs::read(&path) {
+            Ok(data) => parse_tzif(&data)
+                .with_context(|| format!("reading {}", path.display()))?,
+            Err(_) if name == "UTC" => Self::default(),
+            Err(_) if file == name => Self {
+                rule: Some(
+                    Rule::parse(name).ok_or_else(|| anyhow!("unknown zone"))?,
+                ),
+                ..Self::default()
+            },
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("reading {}", path.display()))
+            }
+        };
+        name.clone_into(&mut zone.name);
+        Ok(zone)
+    }
+
+    /// Load the local zone from `$TZ`, or from `/etc/localtime` if `TZ` isn’t
+    /// set. Falls back to UTC if the zone can’t be loaded.
+    pub fn local() -> Self {
+        env::var("TZ")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .map_or_else(
+                || Self::load("/etc/localtime"),
+                |name| Self::load(&name),
+            )
+            .unwrap_or_default()
+    }
+
+    /// The name the zone was loaded with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the UTC offset, in seconds east of UTC, at `time` seconds since the
+    /// epoch.
+    pub fn offset_at(&self, time: i64) -> i32 {
+        let index = self.transitions.partition_point(|(at, _)| *at <= time);
+        if index == self.transitions.len() {
+            if let Some(rule) = &self.rule {
+                return rule.offset_at(time);
+            }
+        }
+        match index.checked_sub(1) {
+            Some(last) => self.transitions[last].1,
+            None => self.initial,
+        }
+    }
+}
+
+/// Parse the contents of a `TZif` file.
+fn parse_tzif(data: &[u8]) -> anyhow::Result<TimeZone> {
+    let mut reader = Reader(data);
+    let header = Header::read(&mut reader)?;
+    if header.version == 0 {
+        return header.read_data(&mut reader, 4);
+    }
+
+    // Version 2 and later repeat the data with 64-bit times, followed by a
+    // POSIX TZ string for times after the last transition.
+    reader.skip(header.data_len(4)?)?;
+    let header = Header::read(&mut reader)?;
+    let mut zone = header.read_data(&mut reader, 8)?;
+
+    let footer = reader.0.strip_prefix(b"\n").unwrap_or_default();
+    let end = footer.iter().position(|&b| b == b'\n').unwrap_or_default();
+    zone.rule = std::str::from_utf8(&footer[..end])
+        .ok()
+        .filter(|rule| !rule.is_empty())
+        .and_then(Rule::parse);
+    Ok(zone)
+}
+
+/// Reads big-endian values from the start of a slice.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Take `len` bytes.
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if self.0.len() < len {
+            bail!("file is truncated");
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    /// Skip `len` bytes.
+    fn skip(&mut self, len: usize) -> anyhow::Result<()> {
+        self.take(len).map(|_| ())
+    }
+
+    /// Read a signed integer that’s `size` bytes long.
+    fn int(&mut self, size: usize) -> anyhow::Result<i64> {
+        let bytes = self.take(size)?;
+        let mut buffer = if bytes[0] & 0x80 == 0 {
+            [0; 8]
+        } else {
+            [0xff; 8]
+        };
+        buffer[8_usize.saturating_sub(size)..].copy_from_slice(bytes);
+        Ok(i64::from_be_bytes(buffer))
+    }
+
+    /// Read a count from a header.
+    fn count(&mut self) -> anyhow::Result<usize> {
+        usize::try_from(self.int(4)?).context("invalid count")
+    }
+}
+
+/// A `TZif` header.
+struct Header {
+    /// The format version, or 0 for the original format.
+    version: u8,
+
+    /// The number of UT/local indicators.
+    isutcnt: usize,
+
+    /// The number of standard/wall indicators.
+    isstdcnt: usize,
+
+    /// The number of leap second records.
+    leapcnt: usize,
+
+    /// The number of transition times.
+    timecnt: usize,
+
+    /// The number of local time types.
+    typecnt: usize,
+
+    /// The length of the time zone abbreviation strings.
+    charcnt: usize,
+}
+
+impl Header {
+    /// Read a header.
+    fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        if reader.take(4)? != b"TZif" {
+            bail!("not a time zone file");
+        }
+        let version = match reader.take(1)?[0] {
+            0 => 0,
+            version => version.saturating_sub(b'0'),
+        };
+        reader.skip(15)?;
+        Ok(Self {
+            version,
+            isutcnt: reader.count()?,
+            isstdcnt: reader.count()?,
+            leapcnt: reader.count()?,
+            timecnt: reader.count()?,
+            typecnt: reader.count()?,
+            charcnt: reader.count()?,
+        })
+    }
+
+    /// The length of the data block when times are `time_size` bytes long.
+    fn data_len(&self, time_size: usize) -> anyhow::Result<usize> {
+        [
+            self.timecnt.checked_mul(time_size),
+            Some(self.timecnt),
+            self.typecnt.checked_mul(6),
+            Some(self.charcnt),
+            self.leapcnt.checked_mul(time_size.saturating_add(4)),
+            Some(self.isstdcnt),
+            Some(self.isutcnt),
+        ]
+        .into_iter()
+        .try_fold(0_usize, |total, len| total.checked_add(len?))
+        .context("file is too large")
+    }
+
+    /// Read the data block that follows this header.
+    fn read_data(
+        &self,
+        reader: &mut Reader,
+        time_size: usize,
+    ) -> anyhow::Result<TimeZone> {
+        if reader.0.len() < self.data_len(time_size)? {
+            bail!("file is truncated");
+        }
+
+        let mut times = Vec::with_capacity(self.timecnt);
+        for _ in 0..self.timecnt {
+            times.push(reader.int(time_size)?);
+        }
+        let indexes = reader.take(self.timecnt)?;
+
+        let mut offsets = Vec::with_capacity(self.typecnt);
+        for _ in 0..self.typecnt {
+            offsets
+                .push(i32::try_from(reader.int(4)?).context("invalid offset")?);
+            reader.skip(2)?; // isdst and abbreviation index.
+        }
+        reader.skip(
+            self.charcnt
+                .saturating_add(
+                    self.leapcnt.saturating_mul(time_size.saturating_add(4)),
+                )
+                .saturating_add(self.isstdcnt)
+                .saturating_add(self.isutcnt),
+        )?;
+
+        let transitions = times
+            .into_iter()
+            .zip(indexes)
+            .map(|(time, &index)| {
+                offsets
+                    .get(usize::from(index))
+                    .map(|&offset| (time, offset))
+                    .context("invalid local time type")
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(TimeZone {
+            name: String::new(),
+            transitions,
+            initial: offsets.first().copied().unwrap_or_default(),
+            rule: None,
+        })
+    }
+}
+
+/// A POSIX TZ rule, e.g. `CET-1CEST,M3.5.0,M10.5.0/3`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Rule {
+    /// Offset during standard time, in seconds east of UTC.
+    std_offset: i32,
+
+    /// Daylight saving time, if the zone has it.
+    dst: Option<Dst>,
+}
+
+/// When daylight saving time applies in a [`Rule`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Dst {
+    /// Offset during daylight saving time, in seconds east of UTC.
+    offset: i32,
+
+    /// When daylight saving time starts, in local standard time.
+    start: (RuleDate, i32),
+
+    /// When daylight saving time ends, in local daylight saving time.
+    end: (RuleDate, i32),
+}
+
+/// A day of the year in a [`Rule`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RuleDate {
+    /// `Jn`: day 1 to 365, not counting February 29.
+    Julian(u16),
+
+    /// `n`: day 0 to 365, counting February 29.
+    Day(u16),
+
+    /// `Mm.w.d`: day `d` (0 is Sunday) of week `w` (5 is the last) of month
+    /// `m`.
+    Month(u8, u8, u8),
+}
+
+impl Rule {
+    /// Parse a POSIX TZ string.
+    fn parse(input: &str) -> Option<Self> {
+        let mut parser = Parser(input.as_bytes());
+        parser.name()?;
+        let std_offset = parser.offset()?.checked_neg()?;
+        if parser.0.is_empty() {
+            return Some(Self {
+                std_offset,
+                dst: None,
+            });
+        }
+
+        parser.name()?;
+        let offset = if parser.0.first() == Some(&b',') {
+            std_offset.checked_add(3_600)?
+        } else {
+            parser.offset()?.checked_neg()?
+        };
+        // Without rules, POSIX leaves the dates up to the implementation.
+        // Use the current US rules, like glibc.
+        let (start, end) = if parser.0.is_empty() {
+            (
+                (RuleDate::Month(3, 2, 0), 7_200),
+                (RuleDate::Month(11, 1, 0), 7_200),
+            )
+        } else {
+            parser.expect(b',')?;
+            let start = parser.transition()?;
+            parser.expect(b',')?;
+            (start, parser.transition()?)
+        };
+        parser.0.is_empty().then_some(Self {
+            std_offset,
+            dst: Some(Dst { offset, start, end }),
+        })
+    }
+
+    /// Get the UTC offset at `time` seconds since the epoch.
+    fn offset_at(&self, time: i64) -> i32 {
+        let Some(dst) = &self.dst else {
+            return self.std_offset;
+        };
+
+        let local = time.saturating_add(i64::from(self.std_offset));
+        let year = year_of(local);
+        let start = dst
+            .start
+            .0
+            .day(year)
+            .saturating_mul(DAY)
+            .saturating_add(i64::from(dst.start.1))
+            .saturating_sub(i64::from(self.std_offset));
+        let end = dst
+            .end
+            .0
+            .day(year)
+            .saturating_mul(DAY)
+            .saturating_add(i64::from(dst.end.1))
+            .saturating_sub(i64::from(dst.offset));
+
+        let in_dst = if start <= end {
+            start <= time && time < end
+        } else {
+            // Southern hemisphere: DST spans the new year.
+            time < end || start <= time
+        };
+        if in_dst {
+            dst.offset
+        } else {
+            self.std_offset
+        }
+    }
+}
+
+impl RuleDate {
+    /// Get the day, in days since the epoch, in `year`.
+    // Values are bounded by parsing, and the year by `year_of()`.
+    #[allow(clippy::arithmetic_side_effects)]
+    fn day(self, year: i64) -> i64 {
+        let jan1 = days_from_civil(year, 1, 1);
+        match self {
+            Self::Julian(day) => {
+                let leap = is_leap(year) && day >= 60;
+                jan1 + i64::from(day) - 1 + i64::from(leap)
+            }
+            Self::Day(day) => jan1 + i64::from(day),
+            Self::Month(month, week, weekday) => {
+                let first = days_from_civil(year, month.into(), 1);
+                let next = if month == 12 {
+                    days_from_civil(year + 1, 1, 1)
+                } else {
+                    days_from_civil(year, i64::from(month) + 1, 1)
+                };
+                // 1970-01-01 was a Thursday.
+                let first_weekday = (first + 4).rem_euclid(7);
+                let mut day = first
+                    + (i64::from(weekday) - first_weekday).rem_euclid(7)
+                    + (i64::from(week) - 1) * 7;
+                while day >= next {
+                    day -= 7;
+                }
+                day
+            }
+        }
+    }
+}
+
+/// Get the year containing `time` seconds since the epoch.
+///
+/// Years outside of 1..=9999 are clamped.
+// Values are small after clamping.
+#[allow(clippy::arithmetic_side_effects, clippy::integer_division)]
+fn year_of(time: i64) -> i64 {
+    let days = time.div_euclid(DAY);
+    let mut year = (1970 + days * 400 / 146_097).clamp(1, 9_999);
+    while year > 1 && days_from_civil(year, 1, 1) > days {
+        year -= 1;
+    }
+    while year < 9_999 && days_from_civil(year + 1, 1, 1) <= days {
+        year += 1;
+    }
+    year
+}
+
+/// Is `year` a leap year?
+const fn is_leap(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Parses a POSIX TZ string.
+struct Parser<'a>(&'a [u8]);
+
+impl Parser<'_> {
+    /// Consume `byte`.
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        let (&first, rest) = self.0.split_first()?;
+        (first == byte).then(|| self.0 = rest)
+    }
+
+    /// Consume a zone abbreviation, e.g. "EST" or "<+03>".
+    fn name(&mut self) -> Option<()> {
+        let len = if self.expect(b'<').is_some() {
+            let len = self.0.iter().position(|&b| b == b'>')?;
+            self.0 = &self.0[len.saturating_add(1)..];
+            len
+        } else {
+            let len = self
+                .0
+                .iter()
+                .position(|b| !b.is_ascii_alphabetic())
+                .unwrap_or(self.0.len());
+            self.0 = &self.0[len..];
+            len
+        };
+        (len >= 3).then_some(())
+    }
+
+    /// Consume an offset or time, e.g. "-5" or "2:30", in seconds.
+    fn offset(&mut self) -> Option<i32> {
+        let negative = match self.0.first() {
+            Some(b'-') => true,
+            Some(b'+') => false,
+            _ => {
+                return self.unsigned_offset();
+            }
+        };
+        self.0 = &self.0[1..];
+        let offset = self.unsigned_offset()?;
+        if negative {
+            offset.checked_neg()
+        } else {
+            Some(offset)
+        }
+    }
+
+    /// Consume an offset without a sign, e.g. "2:30", in seconds.
+    fn unsigned_offset(&mut self) -> Option<i32> {
+        let mut seconds = self.number()?.checked_mul(3_600)?;
+        for scale in [60, 1] {
+            if self.expect(b':').is_none() {
+                break;
+            }
+            seconds =
+                seconds.checked_add(self.number()?.checked_mul(scale)?)?;
+        }
+        i32::try_from(seconds).ok()
+    }
+
+    /// Consume a decimal number.
+    fn number(&mut self) -> Option<u32> {
+        let len = self
+            .0
+            .iter()
+            .position(|b| !b.is_ascii_digit())
+            .unwrap_or(self.0.len());
+        let (digits, rest) = self.0.split_at(len);
+        self.0 = rest;
+        std::str::from_utf8(digits).ok()?.parse().ok()
+    }
+
+    /// Consume a transition date and optional time, e.g. "M3.2.0/2".
+    fn transition(&mut self) -> Option<(RuleDate, i32)> {
+        let date = if self.expect(b'M').is_some() {
+            let month = u8::try_from(self.number()?).ok()?;
+            self.expect(b'.')?;
+            let week = u8::try_from(self.number()?).ok()?;
+            self.expect(b'.')?;
+            let weekday = u8::try_from(self.number()?).ok()?;
+            let valid = (1..=12).contains(&month)
+                && (1..=5).contains(&week)
+                && weekday <= 6;
+            valid.then_some(RuleDate::Month(month, week, weekday))?
+        } else if self.expect(b'J').is_some() {
+            let day = u16::try_from(self.number()?).ok()?;
+            (1..=365).contains(&day).then_some(RuleDate::Julian(day))?
+        } else {
+            let day = u16::try_from(self.number()?).ok()?;
+            (day <= 365).then_some(RuleDate::Day(day))?
+        };
+
+        let time = if self.expect(b'/').is_some() {
+            self.offset()?
+        } else {
+            7_200
+        };
+        Some((date, time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::{check, let_assert};
+
+    /// 2024-01-15T12:00:00Z.
+    const WINTER: i64 = 1_705_320_000;
+
+    /// 2024-07-15T12:00:00Z.
+    const SUMMER: i64 = 1_721_044_800;
+
+    #[test]
+    fn rule_fixed() {
+        let_assert!(Some(rule) = Rule::parse("<+03>-3"));
+        check!(rule.offset_at(WINTER) == 3 * 3_600);
+        check!(rule.offset_at(SUMMER) == 3 * 3_600);
+    }
+
+    #[test]
+    fn rule_northern() {
+        let_assert!(Some(rule) = Rule::parse("EST5EDT,M3.2.0,M11.1.0"));
+        check!(rule.offset_at(WINTER) == -5 * 3_600);
+        check!(rule.offset_at(SUMMER) == -4 * 3_600);
+
+        // 2024-03-10T07:00:00Z is 2:00 EST, when DST starts.
+        check!(rule.offset_at(1_710_053_999) == -5 * 3_600);
+        check!(rule.offset_at(1_710_054_000) == -4 * 3_600);
+
+        // 2024-11-03T06:00:00Z is 2:00 EDT, when DST ends.
+        check!(rule.offset_at(1_730_613_599) == -4 * 3_600);
+        check!(rule.offset_at(1_730_613_600) == -5 * 3_600);
+    }
+
+    #[test]
+    fn rule_southern() {
+        let_assert!(Some(rule) = Rule::parse("AEST-10AEDT,M10.1.0,M4.1.0/3"));
+        check!(rule.offset_at(WINTER) == 11 * 3_600);
+        check!(rule.offset_at(SUMMER) == 10 * 3_600);
+    }
+
+    #[test]
+    fn rule_julian_and_default_dst_offset() {
+        let_assert!(Some(rule) = Rule::parse("XXX0YYY,J60/0,300"));
+        // March 1, 2024 (day 61 counting from 1) is after J60 in a leap year.
+        check!(rule.offset_at(1_709_251_200) == 3_600);
+        check!(rule.offset_at(WINTER) == 0);
+    }
+
+    #[test]
+    fn rule_invalid() {
+        check!(Rule::parse("") == None);
+        check!(Rule::parse("EST") == None);
+        check!(Rule::parse("EST5EDT,M13.1.0,M11.1.0") == None);
+        check!(Rule::parse("EST5EDT,M3.2.0") == None);
+    }
+
+    #[test]
+    fn year_of_boundaries() {
+        check!(year_of(0) == 1970);
+        check!(year_of(-1) == 1969);
+        check!(year_of(WINTER) == 2024);
+        check!(year_of(1_735_689_599) == 2024);
+        check!(year_of(1_735_689_600) == 2025);
+    }
+
+    /// Build a minimal version 2 `TZif` file.
+    fn tzif(
+        transitions: &[(i64, u8)],
+        offsets: &[i32],
+        footer: &str,
+    ) -> Vec<u8> {
+        let header = |data: &mut Vec<u8>| {
+            data.extend(b"TZif2");
+            data.extend([0; 15]);
+            for count in [0, 0, 0, transitions.len(), offsets.len(), 0] {
+                data.extend(u32::try_from(count).unwrap().to_be_bytes());
+            }
+        };
+
+        let mut data = Vec::new();
+        header(&mut data);
+        for (time, _) in transitions {
+            data.extend(i32::try_from(*time).unwrap().to_be_bytes());
+        }
+        data.extend(transitions.iter().map(|(_, index)| index));
+        for offset in offsets {
+            data.extend(offset.to_be_bytes());
+            data.extend([0, 0]);
+        }
+
+        header(&mut data);
+        for (time, _) in transitions {
+            data.extend(time.to_be_bytes());
+        }
+        data.extend(transitions.iter().map(|(_, index)| index));
+        for offset in offsets {
+            data.extend(offset.to_be_bytes());
+            data.extend([0, 0]);
+        }
+        data.extend(format!("\n{footer}\n").as_bytes());
+        data
+    }
+
+    #[test]
+    fn tzif_transitions_and_footer() {
+        let data = tzif(
+            &[(1_000, 1), (2_000, 0)],
+            &[3_600, 7_200],
+            "CET-1CEST,M3.5.0,M10.5.0/3",
+        );
+        let_assert!(Ok(zone) = parse_tzif(&data));
+        check!(zone.offset_at(0) == 3_600);
+        check!(zone.offset_at(1_500) == 7_200);
+        check!(zone.offset_at(2_000) == 3_600);
+        check!(zone.offset_at(WINTER) == 3_600);
+        check!(zone.offset_at(SUMMER) == 7_200);
+    }
+
+    #[test]
+    fn tzif_invalid() {
+        check!(parse_tzif(b"TZjf").is_err());
+        let data = tzif(&[(1_000, 2)], &[0], "");
+        check!(parse_tzif(&data).is_err());
+        check!(parse_tzif(&data[..30]).is_err());
+    }
+
+    #[test]
+    fn load_posix_string() {
+        let_assert!(Ok(zone) = TimeZone::load("JST-9"));
+        check!(zone.name() == "JST-9");
+        check!(zone.offset_at(WINTER) == 9 * 3_600);
+    }
+
+    #[test]
+    fn load_unknown() {
+        check!(TimeZone::load("Nowhere/Special").is_err());
+    }
+}
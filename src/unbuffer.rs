@@ -0,0 +1,59 @@
+//! Make the child line buffer its output.
+//!
+//! Programs that use C stdio buffer their output in large blocks when it isn’t
+//! going to a terminal, so output shows up late and idle timeouts fire even
+//! though the child is working. This does the same thing as `stdbuf -oL`: it
+//! preloads `libstdbuf.so` from coreutils into the child, which configures
+//! stdio buffering from environment variables.
+//!
+//! This has no effect on programs that don’t use C stdio, on statically linked
+//! programs, or on setuid programs. It also only works where the dynamic linker
+//! honors `LD_PRELOAD`, which macOS’s dyld does not.
+//!
+//! If `libstdbuf.so` can’t be found, the child is connected to pseudo-terminals
+//! instead (as with `--pty`), since stdio line buffers output to a terminal.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Environment variable that can be set to the path to `libstdbuf.so`.
+pub const LIBSTDBUF_VAR: &str = "REDERR_LIBSTDBUF";
+
+/// Where `libstdbuf.so` is installed on various systems.
+const LIBSTDBUF_PATHS: &[&str] = &[
+    "/usr/libexec/coreutils/libstdbuf.so",
+    "/usr/lib/coreutils/libstdbuf.so",
+    "/usr/lib/x86_64-linux-gnu/coreutils/libstdbuf.so",
+    "/usr/lib/aarch64-linux-gnu/coreutils/libstdbuf.so",
+    "/usr/lib64/coreutils/libstdbuf.so",
+    "/usr/local/libexec/coreutils/libstdbuf.so",
+];
+
+/// Configure `command` to line buffer stdout using `library` (see
+/// [`library()`]).
+pub fn configure(command: &mut Command, library: &Path) {
+    // Keep anything that was already being preloaded.
+    let mut preload = OsString::from(library);
+    if let Some(existing) = env::var_os("LD_PRELOAD") {
+        if !existing.is_empty() {
+            preload.push(":");
+            preload.push(existing);
+        }
+    }
+
+    command.env("LD_PRELOAD", preload).env("_STDBUF_O", "L");
+}
+
+/// Find `libstdbuf.so`, either at the path in [`LIBSTDBUF_VAR`] or in one of
+/// the usual places.
+pub fn library() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(LIBSTDBUF_VAR) {
+        return Some(PathBuf::from(path)).filter(|path| path.exists());
+    }
+    LIBSTDBUF_PATHS
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+}
@@ -0,0 +1,255 @@
+//! Check the child’s output for invalid UTF-8 (see `--utf8`).
+//!
+//! A multibyte character may be split across reads, so an incomplete sequence
+//! at the end of a chunk is held back until the next chunk from the same
+//! stream shows whether it’s valid.
+
+use crate::params::{Stream, Utf8Mode};
+use std::io;
+use std::str;
+
+/// UTF-8 encoding of U+FFFD REPLACEMENT CHARACTER.
+const REPLACEMENT: &[u8] = "\u{FFFD}".as_bytes();
+
+/// Checks each of the child’s streams for invalid UTF-8.
+#[derive(Debug)]
+pub struct Utf8Filter {
+    /// What to do with invalid sequences.
+    mode: Utf8Mode,
+
+    /// Incomplete sequence at the end of the last chunk from stdout.
+    out: Partial,
+
+    /// Incomplete sequence at the end of the last chunk from stderr.
+    err: Partial,
+
+    /// Number of invalid sequences seen.
+    invalid: u64,
+}
+
+/// An incomplete UTF-8 sequence.
+#[derive(Debug, Default)]
+struct Partial {
+    /// The bytes of the sequence. Only the first `len` are used.
+    bytes: [u8; 4],
+
+    /// The number of bytes in the sequence.
+    len: usize,
+}
+
+impl Utf8Filter {
+    /// Create a filter that handles invalid UTF-8 according to `mode`.
+    pub fn new(mode: Utf8Mode) -> Self {
+        Self {
+            mode,
+            out: Partial::default(),
+            err: Partial::default(),
+            invalid: 0,
+        }
+    }
+
+    /// Number of invalid sequences seen so far.
+    pub const fn invalid_count(&self) -> u64 {
+        self.invalid
+    }
+
+    /// Check a chunk of output from the child’s `stream`, and pass it on to
+    /// `write` in pieces.
+    pub fn push<F>(
+        &mut self,
+        stream: Stream,
+        chunk: &[u8],
+        mut write: F,
+    ) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        let mut invalid = Invalid {
+            mode: self.mode,
+            count: &mut self.invalid,
+        };
+        let partial = match stream {
+            Stream::Out => &mut self.out,
+            Stream::Err => &mut self.err,
+        };
+
+        let mut rest = chunk;
+        if partial.len > 0 {
+            // Try to finish the incomplete sequence from the last chunk.
+            let start = partial.len;
+            let needed = sequence_len(partial.bytes[0]).saturating_sub(start);
+            let take = needed.min(chunk.len());
+            let end = start.saturating_add(take);
+            partial.bytes[start..end].copy_from_slice(&chunk[..take]);
+
+            match str::from_utf8(&partial.bytes[..end]) {
+                Ok(_) => {
+                    write(&partial.bytes[..end])?;
+                    rest = &chunk[take..];
+                }
+                Err(error) => match error.error_len() {
+                    None => {
+                        // Still incomplete, so the chunk was too short.
+                        partial.len = end;
+                        return Ok(());
+                    }
+                    Some(len) => {
+                        invalid.handle(&partial.bytes[..len], &mut write)?;
+                        // Check the bytes after the invalid sequence again.
+                        rest = &chunk[len.saturating_sub(start)..];
+                    }
+                },
+            }
+            partial.len = 0;
+        }
+
+        loop {
+            match str::from_utf8(rest) {
+                Ok(_) => {
+                    if !rest.is_empty() {
+                        write(rest)?;
+                    }
+                    return Ok(());
+                }
+                Err(error) => {
+                    let (valid, after) = rest.split_at(error.valid_up_to());
+                    if !valid.is_empty() {
+                        write(valid)?;
+                    }
+                    let Some(len) = error.error_len() else {
+                        // Incomplete sequence at the end of the chunk.
+                        partial.bytes[..after.len()].copy_from_slice(after);
+                        partial.len = after.len();
+                        return Ok(());
+                    };
+                    invalid.handle(&after[..len], &mut write)?;
+                    rest = &after[len..];
+                }
+            }
+        }
+    }
+
+    /// Handle the end of `stream`. An incomplete sequence left over from the
+    /// last chunk is invalid.
+    pub fn finish<F>(&mut self, stream: Stream, mut write: F) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        let partial = match stream {
+            Stream::Out => &mut self.out,
+            Stream::Err => &mut self.err,
+        };
+        let len = partial.len;
+        partial.len = 0;
+        if len > 0 {
+            Invalid {
+                mode: self.mode,
+                count: &mut self.invalid,
+            }
+            .handle(&partial.bytes[..len], &mut write)?;
+        }
+        Ok(())
+    }
+}
+
+/// Handles invalid sequences.
+struct Invalid<'a> {
+    /// What to do with invalid sequences.
+    mode: Utf8Mode,
+
+    /// Number of invalid sequences seen.
+    count: &'a mut u64,
+}
+
+impl Invalid<'_> {
+    /// Count an invalid sequence and pass it, or its replacement, to `write`.
+    fn handle<F>(&mut self, bytes: &[u8], write: &mut F) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        *self.count = self.count.saturating_add(1);
+        match self.mode {
+            Utf8Mode::Replace => write(REPLACEMENT),
+            Utf8Mode::Strict => write(bytes),
+        }
+    }
+}
+
+/// Get the length of the sequence that starts with `first`.
+///
+/// This is only called on bytes that start a valid incomplete sequence.
+const fn sequence_len(first: u8) -> usize {
+    match first.leading_ones() {
+        2 => 2,
+        3 => 3,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::check;
+
+    /// Push chunks through a filter and return the output and invalid count.
+    fn filter(mode: Utf8Mode, chunks: &[&[u8]]) -> (Vec<u8>, u64) {
+        let mut filter = Utf8Filter::new(mode);
+        let mut output = Vec::new();
+        let mut write = |bytes: &[u8]| {
+            output.extend_from_slice(bytes);
+            Ok(())
+        };
+        for chunk in chunks {
+            filter.push(Stream::Out, chunk, &mut write).unwrap();
+        }
+        filter.finish(Stream::Out, &mut write).unwrap();
+        (output, filter.invalid_count())
+    }
+
+    #[test]
+    fn valid() {
+        let (output, invalid) = filter(Utf8Mode::Replace, &[b"a\xc3\xa9b"]);
+        check!(output == "aéb".as_bytes());
+        check!(invalid == 0);
+    }
+
+    #[test]
+    fn split_character() {
+        let (output, invalid) =
+            filter(Utf8Mode::Replace, &[b"a\xf0\x9f", b"\x98", b"\x80b"]);
+        check!(output == "a😀b".as_bytes());
+        check!(invalid == 0);
+    }
+
+    #[test]
+    fn replace_invalid() {
+        let (output, invalid) =
+            filter(Utf8Mode::Replace, &[b"a\xe2\x28\xa1b\xff"]);
+        check!(output == "a\u{FFFD}(\u{FFFD}b\u{FFFD}".as_bytes());
+        check!(invalid == 3);
+    }
+
+    #[test]
+    fn replace_split_invalid() {
+        // An incomplete sequence followed by a byte that can’t continue it.
+        let (output, invalid) =
+            filter(Utf8Mode::Replace, &[b"a\xf0\x9f", b"b"]);
+        check!(output == "a\u{FFFD}b".as_bytes());
+        check!(invalid == 1);
+    }
+
+    #[test]
+    fn replace_incomplete_at_end() {
+        let (output, invalid) = filter(Utf8Mode::Replace, &[b"a\xf0\x9f"]);
+        check!(output == "a\u{FFFD}".as_bytes());
+        check!(invalid == 1);
+    }
+
+    #[test]
+    fn strict_passes_bytes() {
+        let input: &[u8] = b"a\xe2\x28\xa1b\xf0\x9f";
+        let (output, invalid) = filter(Utf8Mode::Strict, &[input]);
+        check!(output == input);
+        check!(invalid == 3);
+    }
+}
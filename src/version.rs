@@ -0,0 +1,26 @@
+//! Output version information.
+
+use std::io::{self, Write};
+
+/// Write version information to `out`.
+///
+/// If `verbose` is set, this includes information about the build, which is
+/// useful for bug reports.
+pub fn render(out: &mut dyn Write, verbose: bool) -> io::Result<()> {
+    writeln!(out, "rederr {}", env!("CARGO_PKG_VERSION"))?;
+    if verbose {
+        let features = env!("REDERR_FEATURES");
+        writeln!(out, "commit: {}", env!("REDERR_GIT_COMMIT"))?;
+        writeln!(out, "target: {}", env!("REDERR_TARGET"))?;
+        writeln!(
+            out,
+            "features: {}",
+            if features.is_empty() {
+                "none"
+            } else {
+                features
+            }
+        )?;
+    }
+    Ok(())
+}
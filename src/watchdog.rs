@@ -0,0 +1,115 @@
+//! Make sure `rederr` itself doesn’t hang.
+//!
+//! `rederr` exists to keep commands from hanging silently, so it would be bad
+//! if it hung itself, e.g. because it’s blocked writing to a log file on a
+//! stuck NFS mount. With `--watchdog`, a thread checks that operations which
+//! should finish promptly do, and exits the process if one doesn’t.
+//!
+//! Waiting for the child is not watched, since that’s handled by timeouts.
+
+use crate::exit_code;
+use std::io::{self, Write};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for the diagnostic to be written before exiting anyway.
+///
+/// Writing it could block too, if it’s stderr that’s stuck.
+const REPORT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Value of [`Watchdog::busy_since`] when nothing is being watched.
+const IDLE: u64 = 0;
+
+/// Watches for operations taking too long.
+#[derive(Debug)]
+pub struct Watchdog {
+    /// When the watchdog was started.
+    start: Instant,
+
+    /// When the current operation started, in milliseconds since `start` plus
+    /// one, or [`IDLE`] if nothing is being watched.
+    busy_since: Arc<AtomicU64>,
+}
+
+impl Watchdog {
+    /// Start a watchdog that exits if an operation takes longer than
+    /// `threshold`, or do nothing if `threshold` is `None`.
+    pub fn new(threshold: Option<Duration>) -> Self {
+        let watchdog = Self {
+            start: Instant::now(),
+            busy_since: Arc::new(AtomicU64::new(IDLE)),
+        };
+
+        if let Some(threshold) = threshold {
+            let start = watchdog.start;
+            let busy_since = watchdog.busy_since.clone();
+            thread::spawn(move || watch(start, &busy_since, threshold));
+        }
+
+        watchdog
+    }
+
+    /// Mark the start of an operation that should finish promptly.
+    ///
+    /// The operation is over when the returned guard is dropped.
+    pub fn busy(&self) -> Busy<'_> {
+        let since = millis(self.start.elapsed()).saturating_add(1);
+        self.busy_since.store(since, Ordering::SeqCst);
+        Busy {
+            busy_since: &self.busy_since,
+        }
+    }
+}
+
+/// Guard for an operation watched by [`Watchdog`].
+#[derive(Debug)]
+pub struct Busy<'a> {
+    /// Reference to [`Watchdog::busy_since`].
+    busy_since: &'a AtomicU64,
+}
+
+impl Drop for Busy<'_> {
+    fn drop(&mut self) {
+        self.busy_since.store(IDLE, Ordering::SeqCst);
+    }
+}
+
+/// Check on operations forever, and exit if one takes too long.
+fn watch(start: Instant, busy_since: &AtomicU64, threshold: Duration) {
+    let interval = threshold.checked_div(4).unwrap_or(threshold);
+    loop {
+        thread::sleep(interval);
+        let since = busy_since.load(Ordering::SeqCst);
+        if since == IDLE {
+            continue;
+        }
+
+        let elapsed =
+            millis(start.elapsed()).saturating_sub(since.saturating_sub(1));
+        if Duration::from_millis(elapsed) >= threshold {
+            report(Duration::from_millis(elapsed));
+        }
+    }
+}
+
+/// Report that `rederr` is stuck and exit.
+fn report(elapsed: Duration) -> ! {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = writeln!(
+            io::stderr(),
+            "Watchdog: rederr blocked writing output for {elapsed:?}; exiting"
+        );
+        let _ = sender.send(());
+    });
+    let _ = receiver.recv_timeout(REPORT_TIMEOUT);
+    process::exit(exit_code::ERROR);
+}
+
+/// Get a [`Duration`] as milliseconds.
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
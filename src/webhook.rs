@@ -0,0 +1,145 @@
+//! POST a report to a webhook when the command finishes (see `--webhook`).
+//!
+//! By default the body is the report as a JSON object. `--webhook-template`
+//! replaces it with a template in which `{{name}}` is replaced by the value of
+//! a variable. Values are escaped for JSON but not quoted, so that they can go
+//! inside JSON strings. That’s enough for the incoming webhooks of chat
+//! services, e.g. `{"text": "{{command}} {{outcome}} on {{host}}"}`.
+
+use crate::curl::Request;
+use crate::report::{self, Report};
+use crate::status;
+use anyhow::{anyhow, bail, Context};
+use std::time::Duration;
+
+/// How long to wait for each request.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many times to retry a request that fails in a way that might be
+/// temporary.
+const RETRIES: u32 = 2;
+
+/// A template for the body of the request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    /// The pieces of the template, in order.
+    parts: Vec<Part>,
+}
+
+/// A piece of a [`Template`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    /// Text to include as is.
+    Text(String),
+
+    /// The name of a variable to replace with its value.
+    Variable(String),
+}
+
+impl Template {
+    /// Parse a template, making sure that all its variables exist.
+    pub fn parse(input: &str) -> anyhow::Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = input;
+        while let Some((text, after)) = rest.split_once("{{") {
+            let (name, after) = after
+                .split_once("}}")
+                .ok_or_else(|| anyhow!("unclosed {{{{ in template"))?;
+            let name = name.trim();
+            if !report::VARIABLES.contains(&name) {
+                bail!(
+                    "unknown variable {{{{{name}}}}}; expected one of {}",
+                    report::VARIABLES.join(", ")
+                );
+            }
+            parts.push(Part::Text(text.to_owned()));
+            parts.push(Part::Variable(name.to_owned()));
+            rest = after;
+        }
+        parts.push(Part::Text(rest.to_owned()));
+        parts.retain(|part| *part != Part::Text(String::new()));
+        Ok(Self { parts })
+    }
+
+    /// Render the template with the values from `report`.
+    pub fn render(&self, report: &Report) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Variable(name) => {
+                    let value = report.variable(name).unwrap_or_default();
+                    let quoted = status::string(&value);
+                    rendered.push_str(
+                        quoted
+                            .strip_prefix('"')
+                            .and_then(|value| value.strip_suffix('"'))
+                            .unwrap_or(&quoted),
+                    );
+                }
+            }
+        }
+        rendered
+    }
+}
+
+/// POST `report` to `url`, rendered with `template` if there is one.
+pub fn send(
+    url: &str,
+    report: &Report,
+    template: Option<&Template>,
+) -> anyhow::Result<()> {
+    let body = template
+        .map_or_else(|| report.to_json(), |template| template.render(report));
+    Request {
+        url,
+        body: Some(("application/json", body.as_bytes())),
+        timeout: TIMEOUT,
+        retries: RETRIES,
+    }
+    .send()
+    .with_context(|| format!("posting to --webhook {url}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::{check, let_assert};
+
+    #[test]
+    fn render() {
+        let report = Report {
+            command: "backup",
+            host: "web1".to_owned(),
+            exit_code: 1,
+            signal: None,
+            timed_out: false,
+            duration: Duration::from_secs(3),
+            output: "error: \"disk\" full\n".to_owned(),
+            truncated: false,
+        };
+        let_assert!(
+            Ok(template) = Template::parse(
+                r#"{"text": "{{command}} {{ outcome }} on {{host}}:\n{{output}}"}"#
+            )
+        );
+        check!(
+            template.render(&report)
+                == r#"{"text": "backup failed with exit code 1 on web1:\nerror: \"disk\" full\n"}"#
+        );
+    }
+
+    #[test]
+    fn parse_plain() {
+        let_assert!(Ok(template) = Template::parse("plain"));
+        check!(template.parts == [Part::Text("plain".to_owned())]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let_assert!(Err(error) = Template::parse("{{nope}}"));
+        check!(error.to_string().starts_with("unknown variable {{nope}}; "));
+        let_assert!(Err(error) = Template::parse("{{host"));
+        check!(error.to_string() == "unclosed {{ in template");
+    }
+}
@@ -0,0 +1,88 @@
+//! Test the async library interface.
+#![cfg(feature = "tokio")]
+use assert2::check;
+use bstr::ByteSlice;
+use futures_core::Stream;
+use rederr::{AsyncRunner, Event, Runner, TimeoutKind};
+use std::future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+/// Run `runner` and collect its events, joining consecutive chunks of output
+/// from the same stream.
+async fn events(runner: &Runner) -> Vec<Event> {
+    let mut stream = runner.run_async().unwrap();
+    let mut events: Vec<Event> = Vec::new();
+    while let Some(event) = next(&mut stream).await {
+        match (events.last_mut(), event.unwrap()) {
+            (Some(Event::Stdout(last)), Event::Stdout(bytes))
+            | (Some(Event::Stderr(last)), Event::Stderr(bytes)) => {
+                last.extend(bytes);
+            }
+            (_, event) => events.push(event),
+        }
+    }
+    events
+}
+
+/// Get the next item from `stream`.
+async fn next(
+    stream: &mut AsyncRunner,
+) -> Option<<AsyncRunner as Stream>::Item> {
+    future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+}
+
+#[tokio::test]
+async fn output_and_exit_code() {
+    let events = events(&Runner::new("tests/fixtures/fail.sh")).await;
+
+    let stdout: Vec<u8> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Stdout(bytes) => Some(bytes.as_slice()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .concat();
+    check!(stdout.as_bstr() == "out\n");
+    check!(events.contains(&Event::Stderr(b"err\n".to_vec())));
+    check!(events.last() == Some(&Event::Exit(3)));
+}
+
+#[tokio::test]
+async fn not_found() {
+    check!(Runner::new("tests/fixtures/missing").run_async().is_err());
+}
+
+#[tokio::test]
+async fn idle_timeout() {
+    let runner = Runner::new("tests/fixtures/midline_sleep.sh")
+        .idle_timeout(Duration::from_millis(50));
+
+    check!(
+        events(&runner).await
+            == [
+                Event::Stdout(b"111".to_vec()),
+                Event::TimedOut(TimeoutKind::Idle),
+                Event::Exit(128 + 15),
+            ]
+    );
+}
+
+#[tokio::test]
+async fn run_timeout_kill_after() {
+    let start = Instant::now();
+    let runner = Runner::new("tests/fixtures/ignore_term.sh")
+        .run_timeout(Duration::from_millis(200))
+        .kill_after(Duration::from_millis(200));
+
+    check!(
+        events(&runner).await
+            == [
+                Event::Stdout(b"started\n".to_vec()),
+                Event::TimedOut(TimeoutKind::Run),
+                Event::Exit(128 + 9),
+            ]
+    );
+    check!(start.elapsed() < Duration::from_secs(5));
+}
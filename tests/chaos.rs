@@ -0,0 +1,81 @@
+//! Test that runs complete correctly with failures injected by `--chaos`.
+//!
+//! These only run when the `chaos` feature is enabled, e.g. with
+//! `cargo test --features chaos`.
+#![cfg(feature = "chaos")]
+
+use assert2::check;
+use bstr::ByteSlice;
+use std::fmt::Write;
+
+mod helpers;
+
+/// Seeds to try.
+const SEEDS: &[&str] = &["0", "1", "42", "1234567", "18446744073709551615"];
+
+/// Run `rederr generate-output` with chaos.
+fn generate_output(seed: &str, args: &[&str]) -> std::process::Output {
+    let mut command = vec![
+        "--chaos",
+        seed,
+        "--buffer-size",
+        "16",
+        env!("CARGO_BIN_EXE_rederr"),
+        "generate-output",
+        "--lines=200",
+        "--stderr-ratio=0.25",
+    ];
+    command.splice(4..4, args.iter().copied());
+    helpers::rederr(command).output().unwrap()
+}
+
+/// Get the expected output of `generate-output`.
+fn expected(name: &str, numbers: impl Iterator<Item = u32>) -> String {
+    numbers.fold(String::new(), |mut output, n| {
+        writeln!(output, "{name} {n}").unwrap();
+        output
+    })
+}
+
+#[test]
+fn chaos_separate() {
+    for seed in SEEDS {
+        let output = generate_output(seed, &["--separate"]);
+
+        check!(output.status.success(), "seed {seed}");
+        check!(
+            output.stdout.as_bstr()
+                == expected("out", (1..=200).filter(|n| n % 4 != 0)),
+            "seed {seed}"
+        );
+        check!(
+            output.stderr.as_bstr()
+                == expected("err", (1..=200).filter(|n| n % 4 == 0)),
+            "seed {seed}"
+        );
+    }
+}
+
+#[test]
+fn chaos_combined_byte_count() {
+    for seed in SEEDS {
+        let output = generate_output(seed, &[]);
+
+        check!(output.status.success(), "seed {seed}");
+        check!(output.stdout.lines().count() == 200, "seed {seed}");
+        check!(output.stderr.as_bstr() == "", "seed {seed}");
+    }
+}
+
+#[test]
+fn chaos_exit_code() {
+    for seed in SEEDS {
+        let output = helpers::rederr(["--chaos", seed, "false"])
+            .output()
+            .unwrap();
+
+        check!(output.status.code() == Some(1), "seed {seed}");
+        check!(output.stdout.as_bstr() == "", "seed {seed}");
+        check!(output.stderr.as_bstr() == "", "seed {seed}");
+    }
+}
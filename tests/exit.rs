@@ -1,9 +1,14 @@
 //! Test handling of child processes exiting various ways.
-use assert2::check;
+use assert2::{check, let_assert};
 use bstr::ByteSlice;
 use nix::sys::signal::{kill, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
+use std::env;
+use std::fs;
 use std::os::unix::process::ExitStatusExt;
+use std::process::{self, Stdio};
+use std::thread;
 use std::time::{Duration, Instant};
 
 mod helpers;
@@ -31,6 +36,512 @@ fn child_failure() {
     check!(output.stderr.as_bstr() == "");
 }
 
+/// Get a path for `test` to use as a marker, e.g. for `fail_once.sh`.
+fn marker_path(test: &str) -> String {
+    env::temp_dir()
+        .join(format!("rederr-{test}-{}", process::id()))
+        .display()
+        .to_string()
+}
+
+#[test]
+fn retries_exhausted() {
+    let output = helpers::rederr([
+        "--retries=2",
+        "--retry-delay=10ms",
+        "tests/fixtures/fail.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(3));
+    check!(output.stdout.as_bstr() == "out\nerr\nout\nerr\nout\nerr\n");
+    check!(
+        output.stderr.as_bstr()
+            == "Command failed with exit code 3; retrying in 10ms (retry 1 \
+                of 2)\n\
+                Command failed with exit code 3; retrying in 10ms (retry 2 \
+                of 2)\n"
+    );
+}
+
+#[test]
+fn retries_success() {
+    let marker = marker_path("retries_success");
+    let output = helpers::rederr([
+        "--retries=3",
+        "--retry-delay=1ms",
+        "tests/fixtures/fail_once.sh",
+        &marker,
+    ])
+    .output()
+    .unwrap();
+    fs::remove_file(&marker).unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "failed\nsucceeded\n");
+    check!(
+        output.stderr.as_bstr()
+            == "Command failed with exit code 2; retrying in 1ms (retry 1 \
+                of 3)\n"
+    );
+}
+
+#[test]
+fn retries_quiet_on_success() {
+    let marker = marker_path("retries_quiet_on_success");
+    let output = helpers::rederr([
+        "--quiet-on-success",
+        "--retries=1",
+        "--retry-delay=1ms",
+        "tests/fixtures/fail_once.sh",
+        &marker,
+    ])
+    .output()
+    .unwrap();
+    fs::remove_file(&marker).unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn retries_reset_output() {
+    let output = helpers::rederr([
+        "--quiet-on-success",
+        "--max-output-lines=1",
+        "--retries=1",
+        "--retry-delay=1ms",
+        "tests/fixtures/fail.sh",
+    ])
+    .output()
+    .unwrap();
+
+    // Only the last run is shown, and it gets its own limit.
+    check!(output.status.code() == Some(3));
+    check!(output.stdout.as_bstr() == "out\nerr\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn retries_backoff() {
+    let output = helpers::rederr([
+        "--retries=2",
+        "--retry-delay=10ms",
+        "--retry-backoff",
+        "false",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(1));
+    check!(output
+        .stderr
+        .contains_str("retrying in 10ms (retry 1 of 2)\n"));
+    check!(output
+        .stderr
+        .contains_str("retrying in 20ms (retry 2 of 2)\n"));
+}
+
+#[test]
+fn retries_not_after_timeout() {
+    let output = helpers::rederr([
+        "--retries=2",
+        "--run-timeout=50ms",
+        "--kill-after=1s",
+        "sleep",
+        "1",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(!output.stderr.contains_str("retrying"));
+}
+
+#[test]
+fn retry_on_codes_match() {
+    let output = helpers::rederr([
+        "--retries=1",
+        "--retry-delay=1ms",
+        "--retry-on-codes=1,3",
+        "tests/fixtures/fail.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(3));
+    check!(output.stdout.as_bstr() == "out\nerr\nout\nerr\n");
+    check!(output
+        .stderr
+        .contains_str("retrying in 1ms (retry 1 of 1)\n"));
+}
+
+#[test]
+fn retry_on_codes_no_match() {
+    let output = helpers::rederr([
+        "--retries=1",
+        "--retry-delay=1ms",
+        "--retry-on-codes=1,75",
+        "tests/fixtures/fail.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(3));
+    check!(output.stdout.as_bstr() == "out\nerr\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn retry_on_timeout() {
+    let output = helpers::rederr([
+        "--retries=1",
+        "--retry-delay=1ms",
+        "--retry-on-timeout",
+        "--run-timeout=50ms",
+        "--kill-after=1s",
+        "sleep",
+        "1",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(124));
+    let lines: Vec<_> = output.stderr.lines().collect();
+    let_assert!([first, retrying, second] = lines.as_slice());
+    check!(first.starts_with(b"Run timed out after "));
+    check!(
+        retrying.as_bstr()
+            == "Command timed out; retrying in 1ms (retry 1 of 1)"
+    );
+    check!(second.starts_with(b"Run timed out after "));
+}
+
+#[test]
+fn fail_on_stderr() {
+    let output =
+        helpers::rederr(["--fail-on-stderr", "tests/fixtures/simple.sh"])
+            .output()
+            .unwrap();
+
+    check!(output.status.code() == Some(1));
+    check!(output.stdout.as_bstr() == "out\nerr\n");
+}
+
+#[test]
+fn fail_on_stderr_code() {
+    let output =
+        helpers::rederr(["--fail-on-stderr=9", "tests/fixtures/simple.sh"])
+            .output()
+            .unwrap();
+
+    check!(output.status.code() == Some(9));
+}
+
+#[test]
+fn fail_on_stderr_no_stderr() {
+    let output = helpers::rederr([
+        "--fail-on-stderr=9",
+        env!("CARGO_BIN_EXE_rederr"),
+        "generate-output",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+}
+
+#[test]
+fn fail_on_stderr_child_failure() {
+    let output =
+        helpers::rederr(["--fail-on-stderr=9", "tests/fixtures/fail.sh"])
+            .output()
+            .unwrap();
+
+    check!(output.status.code() == Some(3));
+}
+
+#[test]
+fn fail_pattern() {
+    let output = helpers::rederr([
+        "--fail-pattern=^nope$",
+        "--fail-pattern=^e",
+        "tests/fixtures/simple.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(1));
+    check!(output.stdout.as_bstr() == "out\nerr\n");
+    check!(output.stderr.as_bstr() == "Output matched --fail-pattern \"^e\"\n");
+}
+
+#[test]
+fn fail_pattern_child_failure() {
+    let output =
+        helpers::rederr(["--fail-pattern=err", "tests/fixtures/fail.sh"])
+            .output()
+            .unwrap();
+
+    check!(output.status.code() == Some(3));
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn success_pattern() {
+    let output = helpers::rederr([
+        "--success-pattern=^out$",
+        "tests/fixtures/simple.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn success_pattern_no_match() {
+    let output =
+        helpers::rederr(["--success-pattern=done", "tests/fixtures/simple.sh"])
+            .output()
+            .unwrap();
+
+    check!(output.status.code() == Some(1));
+    check!(
+        output.stderr.as_bstr()
+            == "Output did not match --success-pattern \"done\"\n"
+    );
+}
+
+#[test]
+fn kill_on_pattern() {
+    let output = helpers::rederr([
+        "--kill-on-pattern=^started$",
+        "tests/fixtures/trap_term.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(3));
+    check!(output.stdout.as_bstr() == "started\nterminating\n");
+    check!(
+        output.stderr.as_bstr()
+            == "Output matched --kill-on-pattern \"^started$\"; sending \
+                SIGTERM\n"
+    );
+}
+
+#[test]
+fn kill_on_pattern_kill_after() {
+    let start = Instant::now();
+    let output = helpers::rederr([
+        "--kill-on-pattern=started",
+        "--kill-after=200ms",
+        "tests/fixtures/ignore_term.sh",
+    ])
+    .output()
+    .unwrap();
+
+    // The command ignores SIGTERM, so it was killed by SIGKILL.
+    check!(output.status.code() == Some(137));
+    check!(output.stdout.as_bstr() == "started\n");
+    check!(output
+        .stderr
+        .ends_with(b" after being terminated; killing it\n"));
+    check!(start.elapsed() > Duration::from_millis(190));
+    check!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn kill_on_pattern_signal() {
+    let output = helpers::rederr([
+        "--kill-on-pattern=started",
+        "--pattern-signal=INT",
+        "tests/fixtures/ignore_term.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(130));
+    check!(output.stderr.ends_with(b"; sending SIGINT\n"));
+}
+
+#[test]
+fn ready_pattern() {
+    let output = helpers::rederr([
+        "--ready-pattern=^listening$",
+        "tests/fixtures/server.sh",
+        "/dev/null",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stdout.as_bstr()
+            == "starting\nlistening\nstill running\nstill running\n"
+    );
+    check!(
+        output.stderr.as_bstr()
+            == "Command is ready: output matched --ready-pattern \
+                \"^listening$\"\n"
+    );
+}
+
+#[test]
+fn ready_exit_leave() {
+    let marker = marker_path("ready_exit_leave");
+    let start = Instant::now();
+    let output = helpers::rederr([
+        "--ready-pattern=listening",
+        "--ready-exit",
+        "tests/fixtures/server.sh",
+        &marker,
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(start.elapsed() < Duration::from_millis(300));
+    check!(output.stdout.as_bstr() == "starting\nlistening\n");
+    check!(output.stderr.ends_with(b"; leaving it running\n"));
+
+    // The command should survive writing to its closed output.
+    thread::sleep(Duration::from_millis(600));
+    check!(fs::remove_file(&marker).is_ok());
+}
+
+#[test]
+fn ready_exit_kill() {
+    let output = helpers::rederr([
+        "--ready-pattern=started",
+        "--ready-exit=kill",
+        "tests/fixtures/trap_term.sh",
+    ])
+    .output()
+    .unwrap();
+
+    // trap_term.sh exits with 3 when it’s terminated.
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "started\nterminating\n");
+    check!(output.stderr.ends_with(b"; terminating it\n"));
+}
+
+#[test]
+fn ready_exit_never_ready() {
+    let output = helpers::rederr([
+        "--ready-pattern=never",
+        "--ready-exit",
+        "tests/fixtures/fail.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(3));
+}
+
+#[test]
+fn max_output_kill() {
+    let output = helpers::rederr([
+        "--max-output-lines=2",
+        "--max-output-kill",
+        env!("CARGO_BIN_EXE_rederr"),
+        "generate-output",
+        "--lines=1000",
+        "--rate=100/s",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(143));
+    check!(output.stdout.as_bstr() == "out 1\nout 2\n");
+    check!(
+        output.stderr.as_bstr()
+            == "Command stdout truncated after 12 bytes and 2 lines; \
+                terminating it\n"
+    );
+}
+
+/// Read a line from the stdout of `child`.
+fn read_line(child: &mut std::process::Child) -> String {
+    use std::io::{BufRead, BufReader};
+
+    let mut line = String::new();
+    let stdout = child.stdout.as_mut().unwrap();
+    BufReader::new(stdout).read_line(&mut line).unwrap();
+    line
+}
+
+/// Start `rederr` running a command that waits to be killed, and wait until
+/// it’s ready to receive signals.
+fn spawn_ready() -> (std::process::Child, Pid) {
+    let mut child = helpers::rederr([
+        env!("CARGO_BIN_EXE_rederr"),
+        "generate-output",
+        "--lines=2",
+        "--rate=1/60s",
+    ])
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .unwrap();
+    let pid = to_pid(child.id());
+
+    check!(read_line(&mut child) == "out 1\n");
+
+    (child, pid)
+}
+
+#[test]
+fn child_not_found() {
+    let output = helpers::rederr(["/nonexistent/command"]).output().unwrap();
+
+    check!(output.status.code() == Some(127));
+    check!(output.stdout.as_bstr() == "");
+    check!(output.stderr.starts_with(b"Could not run command "));
+}
+
+#[test]
+fn child_not_executable() {
+    let output = helpers::rederr(["./README.md"]).output().unwrap();
+
+    check!(output.status.code() == Some(126));
+    check!(output.stdout.as_bstr() == "");
+    check!(output.stderr.starts_with(b"Could not run command "));
+}
+
+#[test]
+fn run_timeout_exit_code() {
+    let output = helpers::rederr(["--run-timeout", "50ms", "sleep", "1"])
+        .output()
+        .unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(output.stdout.as_bstr() == "");
+    check!(output.stderr.starts_with(b"Run timed out "));
+}
+
+#[test]
+fn run_timeout_custom_exit_code() {
+    let output = helpers::rederr([
+        "--run-timeout",
+        "50ms",
+        "--timeout-exit-code",
+        "3",
+        "sleep",
+        "1",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(3));
+    check!(output.stdout.as_bstr() == "");
+    check!(output.stderr.starts_with(b"Run timed out "));
+}
+
 #[test]
 fn child_sigterm() {
     let start = Instant::now();
@@ -38,8 +549,154 @@ fn child_sigterm() {
     kill(to_pid(child.id()), Signal::SIGTERM).unwrap();
     let output = child.wait_with_output().unwrap();
 
-    check!(output.status.signal() == Some(15), "Expected SIGTERM (15)");
+    // rederr might be killed before it can set up signal handling, in which
+    // case it won’t forward the signal.
+    check!(
+        output.status.signal() == Some(15) || output.status.code() == Some(143),
+        "Expected SIGTERM (15)"
+    );
     check!(output.stdout.as_bstr() == "");
     check!(output.stderr.as_bstr() == "");
     check!(start.elapsed() < Duration::from_secs(1));
 }
+
+#[test]
+fn forward_signals() {
+    for signal in [
+        Signal::SIGHUP,
+        Signal::SIGINT,
+        Signal::SIGQUIT,
+        Signal::SIGTERM,
+    ] {
+        let start = Instant::now();
+        let (child, pid) = spawn_ready();
+        kill(pid, signal).unwrap();
+        let output = child.wait_with_output().unwrap();
+
+        // The child is killed by the signal, and rederr reports that.
+        check!(
+            output.status.code() == Some(128 + signal as i32),
+            "{signal}"
+        );
+        check!(output.stdout.as_bstr() == "", "{signal}");
+        check!(output.stderr.as_bstr() == "", "{signal}");
+        check!(start.elapsed() < Duration::from_secs(1), "{signal}");
+    }
+}
+
+#[test]
+fn child_stopped() {
+    // The idle timeout shouldn’t expire while the child is stopped.
+    let output = helpers::rederr([
+        "--idle-timeout",
+        "200ms",
+        "tests/fixtures/stop_self.sh",
+        "0.4",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "stopping\ncontinued\n");
+
+    // The child might exit before rederr notices that it was continued.
+    let stopped = "Child stopped by SIGSTOP; waiting for it to continue\n";
+    check!(
+        output.stderr.as_bstr() == stopped
+            || output.stderr.as_bstr() == format!("{stopped}Child continued\n")
+    );
+}
+
+#[test]
+fn child_stopped_forward_stop() {
+    let child =
+        helpers::rederr(["--forward-stop", "tests/fixtures/stop_self.sh"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+    let pid = to_pid(child.id());
+
+    let status = waitpid(pid, Some(WaitPidFlag::WUNTRACED)).unwrap();
+    check!(status == WaitStatus::Stopped(pid, Signal::SIGSTOP));
+    kill(pid, Signal::SIGCONT).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "stopping\ncontinued\n");
+    check!(
+        output.stderr.as_bstr()
+            == "Child stopped by SIGSTOP; stopping\nChild continued\n"
+    );
+}
+
+#[test]
+fn kill_after_timeout_drains_output() {
+    let start = Instant::now();
+    let output = helpers::rederr([
+        "--run-timeout",
+        "150ms",
+        "--kill-after",
+        "5s",
+        "tests/fixtures/trap_term.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(output.stdout.as_bstr() == "started\nterminating\n");
+    check!(output.stderr.starts_with(b"Run timed out "));
+    check!(output.stderr.lines().count() == 1);
+    check!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn kill_after_timeout_kills() {
+    let start = Instant::now();
+    let output = helpers::rederr([
+        "--run-timeout",
+        "150ms",
+        "--kill-after",
+        "200ms",
+        "tests/fixtures/ignore_term.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(output.stdout.as_bstr() == "started\n");
+    let_assert!(
+        [timed_out, killing] = &output.stderr.lines().collect::<Vec<_>>()[..]
+    );
+    check!(timed_out.starts_with(b"Run timed out "));
+    check!(killing.starts_with(b"Command still running "));
+    check!(killing.ends_with(b" after being terminated; killing it"));
+    check!(start.elapsed() > Duration::from_millis(340));
+    check!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn kill_after_signal_kills() {
+    let mut child = helpers::rederr([
+        "--kill-after",
+        "200ms",
+        "tests/fixtures/ignore_term.sh",
+    ])
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .unwrap();
+
+    check!(read_line(&mut child) == "started\n");
+
+    let start = Instant::now();
+    kill(to_pid(child.id()), Signal::SIGTERM).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    // The command was killed by SIGKILL.
+    check!(output.status.code() == Some(137));
+    check!(output.stderr.starts_with(b"Command still running "));
+    check!(output.stderr.lines().count() == 1);
+    check!(start.elapsed() > Duration::from_millis(190));
+    check!(start.elapsed() < Duration::from_secs(1));
+}
@@ -0,0 +1,8 @@
+#!/bin/bash
+# sed uses C stdio, so it buffers output when it isn’t going to a terminal.
+
+{
+  echo 111
+  sleep 0.3
+  echo 222
+} | sed s/^/x/
@@ -0,0 +1,6 @@
+#!/bin/bash
+# Close stdout and stderr, then keep running.
+
+echo out
+exec >&- 2>&-
+sleep 1
@@ -0,0 +1,4 @@
+#!/bin/bash
+
+printf 'a\033[31mb\r\tc\n'
+printf '\007\n' >&2
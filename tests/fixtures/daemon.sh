@@ -0,0 +1,6 @@
+#!/bin/sh
+# Start a process that keeps stdout and stderr open after this exits.
+
+sleep 1 &
+echo started
+exit 3
@@ -0,0 +1,5 @@
+#!/bin/bash
+
+echo out
+echo err >&2
+exit 3
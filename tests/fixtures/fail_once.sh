@@ -0,0 +1,10 @@
+#!/bin/bash
+# Fail the first time, when "$1" doesn’t exist yet, then succeed.
+
+if [ -e "$1" ] ; then
+  echo succeeded
+else
+  touch "$1"
+  echo failed
+  exit 2
+fi
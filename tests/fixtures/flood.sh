@@ -0,0 +1,5 @@
+#!/bin/sh
+# Output more than a pipe holds to stdout, then a line to stderr.
+
+head -c 200000 /dev/zero
+echo done >&2
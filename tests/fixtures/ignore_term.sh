@@ -0,0 +1,6 @@
+#!/bin/bash
+# Ignore SIGTERM so that only SIGKILL will work.
+
+trap '' TERM
+echo started
+exec sleep 60
@@ -1,3 +0,0 @@
-#!/bin/bash
-
-echo bad $'\xe2\x28\xa1' bad
@@ -0,0 +1,7 @@
+#!/bin/bash
+# Report which output streams are terminals, and the terminal size.
+
+[ -t 1 ] && echo "stdout is a terminal"
+[ -t 2 ] && echo "stderr is a terminal" >&2
+[ -t 1 ] && stty size <&1
+exit 0
@@ -0,0 +1,3 @@
+#!/bin/sh
+
+echo "TZ=$TZ LC_ALL=$LC_ALL"
@@ -0,0 +1,5 @@
+#!/bin/sh
+# Start a process that outlives this, without keeping stdout or stderr open.
+
+sleep 0.3 >/dev/null 2>&1 &
+echo started
@@ -0,0 +1,5 @@
+#!/bin/sh
+# Output our PID, then keep running.
+
+echo $$
+exec sleep 30
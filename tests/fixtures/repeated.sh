@@ -0,0 +1,9 @@
+#!/bin/bash
+
+for _ in 1 2 3 ; do
+	echo "retrying"
+	echo "connection refused" >&2
+done
+echo "connected"
+echo "connected" >&2
+echo "connected"
@@ -0,0 +1,11 @@
+#!/bin/bash
+# Pretend to be a server that takes a moment to start, then keeps outputting.
+# Touches the file passed as the first argument before exiting.
+
+echo starting
+sleep 0.1
+echo listening
+sleep 0.3
+echo still running
+echo still running >&2
+touch "$1"
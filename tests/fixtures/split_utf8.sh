@@ -0,0 +1,6 @@
+#!/bin/bash
+
+# Output "é" split across two writes.
+printf 'a\xff\nb\xc3'
+sleep 0.1
+printf '\xa9\n'
@@ -0,0 +1,10 @@
+#!/bin/bash
+# Stop, then continue after a delay if $1 is set.
+
+echo stopping
+if [[ "$1" ]] ; then
+  ( sleep "$1" ; kill -CONT $$ ) &
+fi
+kill -STOP $$
+echo continued
+wait
@@ -0,0 +1,8 @@
+#!/bin/bash
+# Output something and exit when terminated.
+
+trap 'echo terminating ; exit 3' TERM
+echo started
+while true ; do
+  sleep 0.05
+done
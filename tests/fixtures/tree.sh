@@ -0,0 +1,6 @@
+#!/bin/sh
+# Start a process that keeps stdout and stderr open, then wait for it.
+
+sleep 30 &
+echo started
+wait
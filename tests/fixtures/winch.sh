@@ -0,0 +1,6 @@
+#!/bin/bash
+# Report the terminal size, and again when it changes.
+
+trap 'stty size <&1; exit 0' WINCH
+stty size <&1
+while true; do sleep 0.05; done
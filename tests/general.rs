@@ -1,10 +1,31 @@
 //! General tests.
-use assert2::check;
+use assert2::{check, let_assert};
 use bstr::{ByteSlice, B};
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::process;
 use std::time::{Duration, Instant};
 
 mod helpers;
 
+/// Get a path for a temporary file named after `test`.
+fn temp_path(test: &str) -> PathBuf {
+    env::temp_dir().join(format!("rederr-{test}-{}", process::id()))
+}
+
+/// Get arguments to run `rederr generate-output` as the command.
+fn generate_output<'a>(args: &[&'a str]) -> Vec<&'a str> {
+    let mut command = vec![env!("CARGO_BIN_EXE_rederr"), "generate-output"];
+    command.extend(args);
+    command
+}
+
 #[test]
 fn simple_separate() {
     // This seems to work without --separate, but I don’t think we can rely on
@@ -18,9 +39,57 @@ fn simple_separate() {
     check!(output.stderr.as_bstr() == "err\n");
 }
 
+#[test]
+fn splice_to_file() {
+    let path = temp_path("splice_to_file");
+    let status = helpers::rederr(["--separate", "tests/fixtures/simple.sh"])
+        .stdout(File::create(&path).unwrap())
+        .stderr(process::Stdio::null())
+        .status()
+        .unwrap();
+    let written = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(status.success());
+    check!(written.as_bstr() == "out\n");
+}
+
+#[test]
+fn splice_to_appended_file_falls_back() {
+    // splice() refuses to write to a file opened with O_APPEND.
+    let path = temp_path("splice_to_appended_file");
+    fs::write(&path, "before\n").unwrap();
+    let stdout = File::options().append(true).open(&path).unwrap();
+    let status = helpers::rederr(["--separate", "tests/fixtures/simple.sh"])
+        .stdout(stdout)
+        .stderr(process::Stdio::null())
+        .status()
+        .unwrap();
+    let written = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(status.success());
+    check!(written.as_bstr() == "before\nout\n");
+}
+
+#[test]
+fn no_splice() {
+    let output = helpers::rederr([
+        "--separate",
+        "--no-splice",
+        "tests/fixtures/simple.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out\n");
+    check!(output.stderr.as_bstr() == "err\n");
+}
+
 #[test]
 fn simple_separate_long_idle_timeout() {
-    // The maximum timeout for `poll()` is around 27 days.
+    // The maximum timeout for `poll()` and epoll is around 25 days.
     let start = Instant::now();
     let output = helpers::rederr([
         "--separate",
@@ -63,6 +132,56 @@ fn midline_sleep_idle_timeout() {
     check!(output.stderr[..28].as_bstr() == "Timed out waiting for input ");
 }
 
+#[test]
+fn start_timeout() {
+    let start = Instant::now();
+    let output = helpers::rederr([
+        "--start-timeout=100ms",
+        "--idle-timeout=10s",
+        "sleep",
+        "5",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(output.stdout.as_bstr() == "");
+    check!(
+        output.stderr[..39].as_bstr()
+            == "Timed out waiting for the first output "
+    );
+    check!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn start_timeout_only_until_output() {
+    let output = helpers::rederr([
+        "--start-timeout=50ms",
+        "tests/fixtures/midline_sleep.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "111222333\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn start_timeout_then_idle_timeout() {
+    let output = helpers::rederr([
+        "--start-timeout=5s",
+        "--idle-timeout=50ms",
+        "tests/fixtures/midline_sleep.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(output.stdout.as_bstr() == "111");
+    check!(output.stderr[..28].as_bstr() == "Timed out waiting for input ");
+}
+
 #[test]
 fn midline_sleep_run_timeout() {
     let start = Instant::now();
@@ -102,69 +221,2915 @@ fn midline_sleep_unused_timeouts() {
 }
 
 #[test]
-fn mixed_output_no_color_combined() {
-    let output = helpers::rederr(["tests/fixtures/mixed_output.sh"])
+fn no_linger() {
+    let start = Instant::now();
+    let output = helpers::rederr(["--no-linger", "tests/fixtures/daemon.sh"])
+        .output()
+        .unwrap();
+
+    check!(output.status.code() == Some(3));
+    check!(output.stdout.as_bstr() == "started\n");
+    check!(output.stderr.as_bstr() == "");
+    check!(start.elapsed() < Duration::from_millis(800));
+}
+
+#[test]
+fn no_linger_verbose() {
+    let output = helpers::rederr([
+        "-v",
+        "--no-linger",
+        "--linger-timeout=50ms",
+        "tests/fixtures/daemon.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(3));
+    check!(output.stdout.as_bstr() == "started\n");
+    check!(output.stderr.contains_str(
+        "but its output is still open; not waiting for it to close"
+    ));
+}
+
+#[test]
+fn linger_verbose() {
+    let output = helpers::rederr([
+        "-v",
+        "--linger-timeout=50ms",
+        "tests/fixtures/daemon.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(3));
+    check!(output.stdout.as_bstr() == "started\n");
+    check!(output
+        .stderr
+        .contains_str("but its output is still open; waiting for it to close"));
+}
+
+/// Time out `tests/fixtures/tree.sh` with `arg`, which should kill its child
+/// too so that its output is closed.
+fn kill_descendants(arg: &str) {
+    let start = Instant::now();
+    let output = helpers::rederr([
+        arg,
+        "--run-timeout=100ms",
+        "--kill-after=1s",
+        "tests/fixtures/tree.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(!output.status.success());
+    check!(output.stdout.as_bstr() == "started\n");
+    check!(output.stderr[..14].as_bstr() == "Run timed out ");
+    check!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn process_group() {
+    kill_descendants("--process-group");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn kill_tree() {
+    kill_descendants("--kill-tree");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn parent_death_signal() {
+    check_parent_death_signal(&[]);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn parent_death_signal_user() {
+    // Changing the user clears the parent death signal, so it must be set
+    // afterward.
+    if is_root() {
+        check_parent_death_signal(&["--user=nobody"]);
+    }
+}
+
+/// Check that the command is killed when `rederr` is, when it’s run with
+/// `--parent-death-signal=KILL` and `args`.
+#[cfg(target_os = "linux")]
+fn check_parent_death_signal(args: &[&str]) {
+    use std::io::{BufRead, BufReader};
+
+    let mut all_args = vec!["--parent-death-signal=KILL"];
+    all_args.extend(args);
+    all_args.extend(["-S", "echo $$; exec sleep 30"]);
+    let mut child = helpers::rederr(all_args)
+        .env("SHELL", "/bin/sh")
+        .stdout(process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    // The command is reparented, so it might be left as a zombie.
+    let path = format!("/proc/{}/stat", line.trim());
+    let start = Instant::now();
+    while fs::read_to_string(&path).is_ok_and(|stat| !stat.contains(") Z ")) {
+        check!(start.elapsed() < Duration::from_secs(2));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn subreaper() {
+    let start = Instant::now();
+    let output = helpers::rederr(["--subreaper", "tests/fixtures/orphan.sh"])
         .output()
         .unwrap();
 
     check!(output.status.success());
-    check!(output.stdout.as_bstr() == "111aaa333\nbbb\n");
+    check!(output.stdout.as_bstr() == "started\n");
+    check!(output.stderr.as_bstr() == "Reaped 1 process left by the command\n");
+    check!(start.elapsed() >= Duration::from_millis(300));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn subreaper_run_timeout() {
+    let output = helpers::rederr([
+        "--subreaper",
+        "--run-timeout=100ms",
+        "tests/fixtures/orphan.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(output.stdout.as_bstr() == "started\n");
+    check!(output.stderr[..14].as_bstr() == "Run timed out ");
+}
+
+#[test]
+fn clean_env() {
+    let path = temp_path("clean_env");
+    fs::write(&path, "# comment\nA=file\nexport B='from file'\n").unwrap();
+    let output = helpers::rederr([
+        "--clean-env",
+        "--env-file",
+        path.to_str().unwrap(),
+        "--env",
+        "A=arg",
+        "env",
+    ])
+    .output()
+    .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "A=arg\nB=from file\n");
     check!(output.stderr.as_bstr() == "");
 }
 
 #[test]
-fn mixed_output_no_color_separate() {
-    let output = helpers::rederr(["-s", "tests/fixtures/mixed_output.sh"])
+fn unset_env() {
+    let output = helpers::rederr(["--unset-env", "HOME", "env"])
+        .env("HOME", "/home")
         .output()
         .unwrap();
 
     check!(output.status.success());
-    check!(output.stdout.as_bstr() == "111333\n");
-    check!(output.stderr.as_bstr() == "aaabbb\n");
+    check!(!output.stdout.lines().any(|line| line.starts_with(b"HOME=")));
+    check!(output.stdout.contains_str("PATH="));
 }
 
 #[test]
-fn mixed_output_color_combined() {
-    let output = helpers::rederr(["-c", "tests/fixtures/mixed_output.sh"])
+fn env_file_invalid() {
+    let path = temp_path("env_file_invalid");
+    fs::write(&path, "A=1\nB\n").unwrap();
+    let output =
+        helpers::rederr(["--env-file", path.to_str().unwrap(), "true"])
+            .output()
+            .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(output.status.code() == Some(125));
+    check!(output.stderr.contains_str("line 2: expected KEY=VALUE"));
+}
+
+#[test]
+fn shell() {
+    let output = helpers::rederr(["-S", "echo a | tr a b"])
+        .env("SHELL", "/bin/sh")
         .output()
         .unwrap();
 
     check!(output.status.success());
-    check!(output.stdout.as_bstr() ==
-        "111\u{1b}[0m\u{1b}[38;5;9maaa\u{1b}[0m333\n\u{1b}[0m\u{1b}[38;5;9mbbb\n\u{1b}[0m");
+    check!(output.stdout.as_bstr() == "b\n");
     check!(output.stderr.as_bstr() == "");
 }
 
 #[test]
-fn mixed_output_color_separate() {
-    let output = helpers::rederr(["-cs", "tests/fixtures/mixed_output.sh"])
+fn shell_path_args() {
+    let output = helpers::rederr([
+        "--shell",
+        "--shell-path=/bin/sh",
+        r#"echo "$0 $1""#,
+        "x",
+        "y",
+    ])
+    .env("SHELL", "/nonexistent")
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "x y\n");
+}
+
+/// Are the tests running as root, so that `--user` and `--group` work?
+fn is_root() -> bool {
+    let output = process::Command::new("id").arg("-u").output().unwrap();
+    output.stdout.as_bstr() == "0\n"
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn user() {
+    let output = helpers::rederr(["--user=nobody", "-S", "id -un; id -G"])
+        .env("SHELL", "/bin/sh")
+        .output()
+        .unwrap();
+
+    if is_root() {
+        check!(output.status.success());
+        check!(output.stdout.starts_with(b"nobody\n"));
+        check!(!output.stdout.contains_str("\n0"));
+    } else {
+        check!(output.status.code() == Some(125));
+        check!(output.stderr.contains_str("require running rederr as root"));
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn group_without_user() {
+    let output = helpers::rederr(["--group=65534", "-S", "id -u; id -G"])
+        .env("SHELL", "/bin/sh")
+        .output()
+        .unwrap();
+
+    if is_root() {
+        check!(output.status.success());
+        check!(output.stdout.as_bstr() == "0\n65534\n");
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn user_unknown() {
+    let output = helpers::rederr(["--user=no-such-user", "true"])
         .output()
         .unwrap();
 
+    check!(output.status.code() == Some(125));
+    if is_root() {
+        check!(
+            output.stderr.as_bstr() == "Error: no such user \"no-such-user\"\n"
+        );
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn limit_cpu() {
+    let output =
+        helpers::rederr(["--limit-cpu=1s", "-S", "while :; do :; done"])
+            .env("SHELL", "/bin/sh")
+            .output()
+            .unwrap();
+
+    check!(output.status.code() == Some(152));
+    check!(output
+        .stderr
+        .contains_str("Child killed by SIGXCPU; it exceeded --limit-cpu"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn limit_fsize() {
+    let path = temp_path("limit_fsize");
+    let output = helpers::rederr([
+        "--limit-fsize=1k",
+        "dd",
+        "if=/dev/zero",
+        &format!("of={}", path.display()),
+        "bs=4096",
+        "count=1",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(153));
+    check!(output
+        .stderr
+        .contains_str("Child killed by SIGXFSZ; it exceeded --limit-fsize"));
+    check!(fs::metadata(&path).unwrap().len() == 1024);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn limit_nofile_and_mem() {
+    let output = helpers::rederr([
+        "--limit-nofile=17",
+        "--limit-mem=1G",
+        "-S",
+        "ulimit -n; ulimit -v",
+    ])
+    .env("SHELL", "/bin/sh")
+    .output()
+    .unwrap();
+
     check!(output.status.success());
-    check!(output.stdout.as_bstr() == "111333\n");
-    check!(output.stderr.as_bstr() ==
-        "\u{1b}[0m\u{1b}[38;5;9maaa\u{1b}[0m\u{1b}[0m\u{1b}[38;5;9mbbb\n\u{1b}[0m");
+    check!(output.stdout.as_bstr() == "17\n1048576\n");
 }
 
 #[test]
-fn invalid_utf8() {
-    let output = helpers::rederr(["tests/fixtures/invalid_utf8.sh"])
+fn nice() {
+    let output = helpers::rederr(["--nice=5", "nice"]).output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "5\n");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn ionice() {
+    let output = helpers::rederr(["--ionice=best-effort:6", "ionice"])
         .output()
         .unwrap();
 
     check!(output.status.success());
-    check!(output.stdout.as_bstr() == B(b"bad \xE2(\xA1 bad\n"));
-    check!(output.stderr.as_bstr() == "");
+    check!(output.stdout.as_bstr() == "best-effort: prio 6\n");
 }
 
 #[test]
-fn invalid_utf8_debug() {
-    let output = helpers::rederr(["--debug", "tests/fixtures/invalid_utf8.sh"])
+#[cfg(target_os = "linux")]
+fn ionice_default_level() {
+    let output = helpers::rederr(["--ionice=best-effort", "ionice"])
         .output()
         .unwrap();
 
     check!(output.status.success());
-    check!(output.stdout.contains_str("\"bad \\xe2(\\xa1 bad\\n\""));
-    check!(output.stderr.as_bstr() == "");
+    check!(output.stdout.as_bstr() == "best-effort: prio 4\n");
+}
+
+#[test]
+#[cfg(not(target_os = "linux"))]
+fn ionice_unsupported() {
+    let output = helpers::rederr(["--ionice=idle", "true"]).output().unwrap();
+
+    check!(output.status.code() == Some(125));
+    check!(output
+        .stderr
+        .contains_str("--ionice is only supported on Linux"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn oom_score_adj() {
+    let output = helpers::rederr([
+        "--oom-score-adj=500",
+        "-S",
+        "sleep 0.1; cat /proc/$$/oom_score_adj",
+    ])
+    .env("SHELL", "/bin/sh")
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "500\n");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn oom_score_adj_lower() {
+    let output = helpers::rederr([
+        "--oom-score-adj=-500",
+        "-S",
+        "sleep 0.1; cat /proc/$$/oom_score_adj",
+    ])
+    .env("SHELL", "/bin/sh")
+    .output()
+    .unwrap();
+
+    // Lowering it requires CAP_SYS_RESOURCE, which even root might not have
+    // in a container.
+    if output.status.success() {
+        check!(output.stdout.as_bstr() == "-500\n");
+    } else {
+        check!(output.status.code() == Some(125));
+        check!(output.stdout.as_bstr() == "");
+        check!(output
+            .stderr
+            .starts_with(b"Error: --oom-score-adj: writing /proc/"));
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn cgroup() {
+    let output =
+        helpers::rederr(["--cgroup", "-v", "-S", "cat /proc/self/cgroup"])
+            .env("SHELL", "/bin/sh")
+            .output()
+            .unwrap();
+
+    // cgroup v2 might not be mounted or writable.
+    if output.status.success() {
+        let_assert!(
+            Some(line) = output
+                .stdout
+                .lines()
+                .find(|line| { line.starts_with(b"0::") })
+        );
+        check!(line.contains_str("/rederr-"));
+        check!(output.stderr.contains_str("Command used "));
+    } else {
+        check!(output.status.code() == Some(125));
+        check!(output.stderr.starts_with(b"Error: "));
+    }
+}
+
+#[test]
+fn chdir() {
+    let output = helpers::rederr([
+        "--separate",
+        "--chdir",
+        "tests/fixtures",
+        "./simple.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out\n");
+    check!(output.stderr.as_bstr() == "err\n");
+}
+
+#[test]
+fn chdir_missing() {
+    let output = helpers::rederr(["--chdir", "missing", "true"])
+        .output()
+        .unwrap();
+
+    check!(output.status.code() == Some(125));
+    check!(output.stderr.contains_str("--chdir missing: "));
+}
+
+#[test]
+fn chdir_not_directory() {
+    let output =
+        helpers::rederr(["--chdir", "tests/fixtures/simple.sh", "true"])
+            .output()
+            .unwrap();
+
+    check!(output.status.code() == Some(125));
+    check!(output.stderr.contains_str("not a directory"));
+}
+
+#[test]
+fn mixed_output_no_color_combined() {
+    let output = helpers::rederr(["tests/fixtures/mixed_output.sh"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "111aaa333\nbbb\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn mixed_output_no_color_separate() {
+    let output = helpers::rederr(["-s", "tests/fixtures/mixed_output.sh"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "111333\n");
+    check!(output.stderr.as_bstr() == "aaabbb\n");
+}
+
+#[test]
+fn mixed_output_line_buffered() {
+    let output =
+        helpers::rederr(["--line-buffered", "tests/fixtures/mixed_output.sh"])
+            .output()
+            .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "111333\naaabbb\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn midline_sleep_line_buffered_run_timeout() {
+    let output = helpers::rederr([
+        "--line-buffered",
+        "--run-timeout=150ms",
+        "tests/fixtures/midline_sleep.sh",
+    ])
+    .output()
+    .unwrap();
+
+    // The partial line is output before exiting.
+    check!(output.status.code() == Some(124));
+    check!(output.stdout.as_bstr() == "111222");
+    check!(output.stderr.starts_with(b"Run timed out "));
+}
+
+#[test]
+fn mixed_output_color_combined() {
+    let output = helpers::rederr(["-c", "tests/fixtures/mixed_output.sh"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() ==
+        "111\u{1b}[0m\u{1b}[38;5;9maaa\u{1b}[0m333\n\u{1b}[0m\u{1b}[38;5;9mbbb\n\u{1b}[0m");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn mixed_output_color_separate() {
+    let output = helpers::rederr(["-cs", "tests/fixtures/mixed_output.sh"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "111333\n");
+    check!(output.stderr.as_bstr() ==
+        "\u{1b}[0m\u{1b}[38;5;9maaa\u{1b}[0m\u{1b}[0m\u{1b}[38;5;9mbbb\n\u{1b}[0m");
+}
+
+#[test]
+fn mixed_output_assume_tty() {
+    let output =
+        helpers::rederr(["--assume-tty", "tests/fixtures/mixed_output.sh"])
+            .env("TERM", "xterm")
+            .env_remove("NO_COLOR")
+            .output()
+            .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() ==
+        "111\u{1b}[0m\u{1b}[38;5;9maaa\u{1b}[0m333\n\u{1b}[0m\u{1b}[38;5;9mbbb\n\u{1b}[0m");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn mixed_output_assume_tty_no_color() {
+    let output =
+        helpers::rederr(["--assume-tty", "tests/fixtures/mixed_output.sh"])
+            .env("TERM", "xterm")
+            .env("NO_COLOR", "1")
+            .output()
+            .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "111aaa333\nbbb\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn mixed_output_color_always_no_color() {
+    let output =
+        helpers::rederr(["--color=always", "tests/fixtures/mixed_output.sh"])
+            .env("NO_COLOR", "1")
+            .output()
+            .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() ==
+        "111\u{1b}[0m\u{1b}[38;5;9maaa\u{1b}[0m333\n\u{1b}[0m\u{1b}[38;5;9mbbb\n\u{1b}[0m");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn mixed_output_clicolor_force() {
+    let output = helpers::rederr(["tests/fixtures/mixed_output.sh"])
+        .env("CLICOLOR_FORCE", "1")
+        .env_remove("NO_COLOR")
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() ==
+        "111\u{1b}[0m\u{1b}[38;5;9maaa\u{1b}[0m333\n\u{1b}[0m\u{1b}[38;5;9mbbb\n\u{1b}[0m");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn mixed_output_clicolor_force_no_color() {
+    let output = helpers::rederr(["tests/fixtures/mixed_output.sh"])
+        .env("CLICOLOR_FORCE", "1")
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "111aaa333\nbbb\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn mixed_output_clicolor_force_color_never() {
+    let output =
+        helpers::rederr(["--color=never", "tests/fixtures/mixed_output.sh"])
+            .env("CLICOLOR_FORCE", "1")
+            .env_remove("NO_COLOR")
+            .output()
+            .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "111aaa333\nbbb\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn invalid_utf8() {
+    let output =
+        helpers::rederr(generate_output(&["--lines=1", "--invalid-utf8"]))
+            .output()
+            .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == B(b"out 1 \xE2(\xA1\n"));
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn invalid_utf8_replace() {
+    let mut args = vec!["--utf8=replace"];
+    args.extend(generate_output(&["--lines=1", "--invalid-utf8"]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out 1 \u{FFFD}(\u{FFFD}\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn invalid_utf8_lossy_split() {
+    let output =
+        helpers::rederr(["--utf8-lossy", "tests/fixtures/split_utf8.sh"])
+            .output()
+            .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "a\u{FFFD}\nbé\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn invalid_utf8_strict() {
+    let mut args = vec!["--utf8=strict", "--separate"];
+    args.extend(generate_output(&["--lines=2", "--invalid-utf8"]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == B(b"out 1 \xE2(\xA1\nout 2 \xE2(\xA1\n"));
+    check!(
+        output.stderr.as_bstr()
+            == "Warning: output contained 4 invalid UTF-8 sequences\n"
+    );
+}
+
+#[test]
+fn invalid_utf8_debug() {
+    let mut args = vec!["--debug"];
+    args.extend(generate_output(&["--lines=1", "--invalid-utf8"]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.contains_str("\"out 1 \\xe2(\\xa1\\n\""));
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn generated_separate() {
+    let mut args = vec!["--separate"];
+    args.extend(generate_output(&[
+        "--lines=6",
+        "--stderr-ratio=0.5",
+        "--rate=100/s",
+    ]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out 1\nout 3\nout 5\n");
+    check!(output.stderr.as_bstr() == "err 2\nerr 4\nerr 6\n");
+}
+
+#[test]
+fn generated_combined() {
+    // The rate keeps the streams from racing each other.
+    let output = helpers::rederr(generate_output(&[
+        "--lines=4",
+        "--stderr-ratio=0.5",
+        "--rate=20/s",
+    ]))
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out 1\nerr 2\nout 3\nerr 4\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn tag_combined() {
+    let mut args = vec!["--tag"];
+    args.extend(generate_output(&[
+        "--lines=4",
+        "--stderr-ratio=0.5",
+        "--rate=20/s",
+    ]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stdout.as_bstr()
+            == "[out] out 1\n[err] err 2\n[out] out 3\n[err] err 4\n"
+    );
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn tag_custom() {
+    let output = helpers::rederr([
+        "--separate",
+        "--out-tag=O: ",
+        "tests/fixtures/simple.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "O: out\n");
+    check!(output.stderr.as_bstr() == "[err] err\n");
+}
+
+#[test]
+fn tag_notices() {
+    let mut args = vec!["--tag", "--run-timeout=200ms"];
+    args.extend(generate_output(&["--lines=2", "--rate=1/s"]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(output
+        .stdout
+        .starts_with(b"[out] out 1\n[rederr] Run timed out "));
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn timestamps_default() {
+    let output = helpers::rederr([
+        "--timestamps",
+        "--separate",
+        "tests/fixtures/simple.sh",
+    ])
+    .env("TZ", "UTC")
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    // e.g. "2024-02-29T12:34:56.007Z out\n"
+    check!(output.stdout.len() == 29);
+    check!(output.stdout[4] == b'-');
+    check!(output.stdout.ends_with(b"Z out\n"));
+}
+
+#[test]
+fn timestamps_local_zone() {
+    let path = temp_path("timestamps_local_zone.json");
+    let output = helpers::rederr([
+        "--timestamps",
+        &format!("--status-file={}", path.display()),
+        "echo",
+        "out",
+    ])
+    .env("TZ", "JST-9")
+    .output()
+    .unwrap();
+    let status = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.ends_with(b"+09:00 out\n"));
+    check!(status.contains("+09:00\",\"end\":\""));
+}
+
+#[test]
+fn timestamps_elapsed_tag() {
+    let output = helpers::rederr([
+        "--timestamps=elapsed",
+        "--tag",
+        "--separate",
+        "tests/fixtures/simple.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.starts_with(b"00:00:0"));
+    check!(output.stdout.ends_with(b" [out] out\n"));
+    check!(output.stderr.starts_with(b"00:00:0"));
+    check!(output.stderr.ends_with(b" [err] err\n"));
+}
+
+#[test]
+fn timing() {
+    let output =
+        helpers::rederr(["--timing", "sh", "-c", "echo a; sleep 0.3; echo b"])
+            .output()
+            .unwrap();
+
+    check!(output.status.success());
+    let lines = output.stdout.lines().collect::<Vec<_>>();
+    let_assert!([first, second] = &lines[..]);
+    // e.g. "   0.004s a"
+    check!(first.starts_with(b"   0.0"));
+    check!(first.ends_with(b"s a"));
+    check!(second.starts_with(b"   0.3"));
+    check!(second.ends_with(b"s b"));
+}
+
+#[test]
+fn timing_slow_threshold() {
+    let output = helpers::rederr([
+        "--timing",
+        "--slow-threshold=200ms",
+        "--always-color",
+        "sh",
+        "-c",
+        "echo a; sleep 0.3; echo b",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    let lines = output.stdout.lines().collect::<Vec<_>>();
+    let_assert!([first, second] = &lines[..]);
+    check!(first.starts_with(b"   0.0"));
+    check!(second.starts_with(b"\x1b[0m\x1b[1m\x1b[33m   0.3"));
+    check!(second.ends_with(b"s\x1b[0m b"));
+}
+
+#[test]
+fn gap_marker() {
+    let mut args = vec!["--gap-marker=300ms"];
+    args.extend(generate_output(&["--lines=3", "--rate=2/1s"]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    let lines = output.stdout.lines().collect::<Vec<_>>();
+    let_assert!([b"out 1", gap1, b"out 2", gap2, b"out 3"] = &lines[..]);
+    for gap in [gap1, gap2] {
+        check!(gap.starts_with("‹ ".as_bytes()));
+        check!(gap.ends_with("ms of silence ›".as_bytes()));
+    }
+}
+
+#[test]
+fn heartbeat() {
+    let output = helpers::rederr([
+        "--separate",
+        "--heartbeat=300ms",
+        "sh",
+        "-c",
+        "echo out; sleep 0.4; echo err >&2; sleep 0.2",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out\n");
+    let lines = output.stderr.lines().collect::<Vec<_>>();
+    let_assert!([heartbeat, b"err"] = &lines[..]);
+    check!(heartbeat.starts_with(b"still running, "));
+    check!(heartbeat.ends_with(b"ms ago"));
+}
+
+#[test]
+fn heartbeat_after_partial_line() {
+    let output = helpers::rederr([
+        "--separate",
+        "--heartbeat=300ms",
+        "sh",
+        "-c",
+        "printf partial >&2; sleep 0.4; echo end >&2",
+    ])
+    .output()
+    .unwrap();
+
+    // The heartbeat starts its own line.
+    check!(output.status.success());
+    let lines = output.stderr.lines().collect::<Vec<_>>();
+    let_assert!([b"partial", heartbeat, b"end"] = &lines[..]);
+    check!(heartbeat.starts_with(b"still running, "));
+}
+
+#[test]
+fn heartbeat_no_output() {
+    let log = temp_path("heartbeat_no_output");
+    let output = helpers::rederr([
+        "--heartbeat=200ms".as_ref(),
+        "--log-file".as_ref(),
+        log.as_os_str(),
+        "sleep".as_ref(),
+        "0.5".as_ref(),
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    let lines = output.stdout.lines().collect::<Vec<_>>();
+    check!(lines.len() == 2);
+    for line in lines {
+        check!(line.starts_with(b"still running, "));
+        check!(line.ends_with(b"elapsed, no output yet"));
+    }
+    check!(fs::read(&log).unwrap() == output.stdout);
+    fs::remove_file(&log).unwrap();
+}
+
+#[test]
+fn status_line_not_terminal() {
+    // The status line is only shown when stdout is a terminal.
+    let output = helpers::rederr([
+        "--status-line",
+        "sh",
+        "-c",
+        "echo out; sleep 1.2; echo err >&2",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out\nerr\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn timestamps_tz() {
+    let output = helpers::rederr([
+        "--timestamps",
+        "--tz=JST-9",
+        "--locale=C",
+        "tests/fixtures/locale.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    let_assert!(Some((timestamp, line)) = output.stdout.split_once_str(" "));
+    check!(timestamp.ends_with(b"+09:00"));
+    check!(line.as_bstr() == "TZ=JST-9 LC_ALL=C\n");
+}
+
+#[test]
+fn prefix_seq() {
+    let mut args = vec!["--prefix-seq"];
+    args.extend(generate_output(&[
+        "--lines=3",
+        "--stderr-ratio=0.5",
+        "--rate=20/s",
+    ]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stdout.as_bstr() == "     1 out 1\n     2 err 2\n     3 out 3\n"
+    );
+}
+
+#[test]
+fn pty() {
+    let output =
+        helpers::rederr(["--pty", "--separate", "tests/fixtures/isatty.sh"])
+            .env("COLUMNS", "100")
+            .env("LINES", "24")
+            .output()
+            .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "stdout is a terminal\n24 100\n");
+    check!(output.stderr.as_bstr() == "stderr is a terminal\n");
+}
+
+#[test]
+fn pty_resize() {
+    use nix::pty::{openpty, Winsize};
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+    use rustix::termios::{tcgetwinsize, tcsetwinsize};
+    use std::io::{BufRead, BufReader};
+
+    // Our output is piped, so the size comes from the terminal on our stdin.
+    let size = Winsize {
+        ws_row: 24,
+        ws_col: 80,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let terminal = openpty(Some(&size), None).unwrap();
+    let mut child = helpers::rederr([
+        "--pty",
+        "--run-timeout=5s",
+        "tests/fixtures/winch.sh",
+    ])
+    .stdin(File::from(terminal.slave))
+    .stdout(process::Stdio::piped())
+    .spawn()
+    .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    check!(line == "24 80\n");
+
+    let mut size = tcgetwinsize(&terminal.master).unwrap();
+    size.ws_row = 30;
+    size.ws_col = 120;
+    tcsetwinsize(&terminal.master, size).unwrap();
+    let pid = Pid::from_raw(child.id().try_into().unwrap());
+    kill(pid, Signal::SIGWINCH).unwrap();
+
+    line.clear();
+    stdout.read_to_string(&mut line).unwrap();
+    check!(child.wait().unwrap().success());
+    check!(line == "30 120\n");
+}
+
+#[test]
+fn no_pty() {
+    let output = helpers::rederr(["--separate", "tests/fixtures/isatty.sh"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn quiet_on_success_success() {
+    let output = helpers::rederr([
+        "--quiet-on-success",
+        "--separate",
+        "tests/fixtures/simple.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn quiet_on_success_failure() {
+    let output = helpers::rederr([
+        "--quiet-on-success",
+        "--separate",
+        "tests/fixtures/fail.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(3));
+    check!(output.stdout.as_bstr() == "out\n");
+    check!(output.stderr.as_bstr() == "err\n");
+}
+
+#[test]
+fn quiet_on_success_timeout() {
+    let mut args = vec!["--quiet-on-success", "--run-timeout=200ms", "--tag"];
+    args.extend(generate_output(&["--lines=2", "--rate=1/10s"]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(output
+        .stdout
+        .starts_with(b"[out] out 1\n[rederr] Run timed out after "));
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn log_file() {
+    let log = temp_path("log_file");
+    let mut args = vec![
+        "--always-color".to_owned(),
+        format!("--log-file={}", log.display()),
+    ];
+    args.extend(
+        generate_output(&["--lines=2", "--stderr-ratio=0.5", "--rate=20/s"])
+            .into_iter()
+            .map(str::to_owned),
+    );
+    let output = helpers::rederr(args).output().unwrap();
+    let logged = fs::read(&log).unwrap();
+    fs::remove_file(&log).unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stdout.as_bstr() == "out 1\n\x1b[0m\x1b[38;5;9merr 2\n\x1b[0m"
+    );
+    check!(logged.as_bstr() == "out 1\nerr 2\n");
+}
+
+#[test]
+fn sink_grep_terminal() {
+    let log = temp_path("sink_grep_terminal");
+    let output = helpers::rederr([
+        "--sink-grep=terminal:^err".as_ref(),
+        "--log-file".as_ref(),
+        log.as_os_str(),
+        "sh".as_ref(),
+        "-c".as_ref(),
+        "echo out; echo err 1; echo err 2; printf out".as_ref(),
+    ])
+    .output()
+    .unwrap();
+    let logged = fs::read(&log).unwrap();
+    fs::remove_file(&log).unwrap();
+
+    // The log gets everything.
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "err 1\nerr 2\n");
+    check!(logged.as_bstr() == "out\nerr 1\nerr 2\nout");
+}
+
+#[test]
+fn record() {
+    let cast = temp_path("record");
+    let mut args = vec![format!("--record={}", cast.display())];
+    args.extend(
+        generate_output(&["--lines=2", "--stderr-ratio=0.5", "--rate=20/s"])
+            .into_iter()
+            .map(str::to_owned),
+    );
+    let output = helpers::rederr(args)
+        .env("COLUMNS", "100")
+        .env("LINES", "30")
+        .output()
+        .unwrap();
+    let recorded = fs::read_to_string(&cast).unwrap();
+    fs::remove_file(&cast).unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out 1\nerr 2\n");
+    let lines = recorded.lines().collect::<Vec<_>>();
+    let_assert!([header, events @ ..] = &lines[..]);
+    check!(header.starts_with(
+        "{\"version\": 2, \"width\": 100, \"height\": 30, \"timestamp\": "
+    ));
+    check!(header.contains("generate-output --lines=2 "));
+
+    let events = events
+        .iter()
+        .map(|event| {
+            let_assert!(Some((time, text)) = event.split_once(", \"o\", "));
+            check!(time.starts_with('['));
+            check!(time[1..].parse::<f64>().is_ok());
+            text
+        })
+        .collect::<Vec<_>>();
+    check!(
+        events
+            == [
+                "\"out 1\\r\\n\"]",
+                "\"\\u001b[0m\\u001b[38;5;9merr 2\\r\\n\\u001b[0m\"]",
+            ]
+    );
+}
+
+/// Get the messages sent to a systemd notification socket.
+fn notifications(socket: &UnixDatagram) -> Vec<String> {
+    socket.set_nonblocking(true).unwrap();
+    let mut messages = Vec::new();
+    let mut buffer = [0; 1024];
+    while let Ok(count) = socket.recv(&mut buffer) {
+        messages.push(String::from_utf8_lossy(&buffer[..count]).into_owned());
+    }
+    messages
+}
+
+#[test]
+fn sd_notify() {
+    let path = temp_path("sd-notify");
+    let socket = UnixDatagram::bind(&path).unwrap();
+    let output = helpers::rederr([
+        "--sd-notify",
+        "sh",
+        "-c",
+        "echo \"${NOTIFY_SOCKET:-unset}\"; sleep 0.1; echo >&2 done",
+    ])
+    .env("NOTIFY_SOCKET", &path)
+    .env("WATCHDOG_USEC", "1000")
+    .output()
+    .unwrap();
+    let messages = notifications(&socket);
+    fs::remove_file(&path).unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "unset\ndone\n");
+    let_assert!([ready, status, rest @ ..] = &messages[..]);
+    check!(ready == "READY=1");
+    check!(status == "STATUS=0s elapsed, no output yet");
+    check!(rest == ["WATCHDOG=1", "WATCHDOG=1"]);
+}
+
+#[test]
+fn sd_notify_ready_pattern() {
+    let path = temp_path("sd-notify-ready");
+    let socket = UnixDatagram::bind(&path).unwrap();
+    let output = helpers::rederr([
+        "--sd-notify",
+        "--ready-pattern=^listening$",
+        "sh",
+        "-c",
+        "echo starting; sleep 1.5; echo listening",
+    ])
+    .env("NOTIFY_SOCKET", &path)
+    .output()
+    .unwrap();
+    let messages = notifications(&socket);
+    fs::remove_file(&path).unwrap();
+
+    check!(output.status.success());
+    let_assert!(Some(ready) = messages.iter().position(|m| m == "READY=1"));
+    check!(messages[..ready].iter().any(|message| {
+        message.starts_with("STATUS=") && message.contains("starting")
+    }));
+}
+
+#[test]
+fn sd_notify_without_socket() {
+    let output = helpers::rederr(["--sd-notify", "echo", "hi"])
+        .env_remove("NOTIFY_SOCKET")
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "hi\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn log_file_stderr() {
+    let (out_log, err_log) = (
+        temp_path("log_file_stderr.out"),
+        temp_path("log_file_stderr.err"),
+    );
+    let output = helpers::rederr([
+        "--log-file".as_ref(),
+        out_log.as_os_str(),
+        "--log-file-stderr".as_ref(),
+        err_log.as_os_str(),
+        "tests/fixtures/simple.sh".as_ref(),
+    ])
+    .output()
+    .unwrap();
+    let out_logged = fs::read(&out_log).unwrap();
+    let err_logged = fs::read(&err_log).unwrap();
+    fs::remove_file(&out_log).unwrap();
+    fs::remove_file(&err_log).unwrap();
+
+    check!(output.status.success());
+    check!(out_logged.as_bstr() == "out\n");
+    check!(err_logged.as_bstr() == "err\n");
+}
+
+#[test]
+fn log_file_notice() {
+    let log = temp_path("log_file_notice");
+    let output = helpers::rederr([
+        "--log-file".as_ref(),
+        log.as_os_str(),
+        "--run-timeout=100ms".as_ref(),
+        "sleep".as_ref(),
+        "1".as_ref(),
+    ])
+    .output()
+    .unwrap();
+    let logged = fs::read(&log).unwrap();
+    fs::remove_file(&log).unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(logged.starts_with(b"Run timed out after "));
+    check!(output.stderr == logged);
+}
+
+#[test]
+fn log_file_cannot_create() {
+    let output = helpers::rederr([
+        "--log-file=tests/fixtures/missing/log",
+        "tests/fixtures/simple.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(125));
+    check!(output.stdout.as_bstr() == "");
+    check!(output
+        .stderr
+        .starts_with(b"Error: creating log file tests/fixtures/missing/log: "));
+}
+
+/// Read the only file in `dir` and remove `dir`.
+fn read_only_file(dir: &PathBuf) -> (String, String) {
+    let entries: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    let_assert!([path] = entries.as_slice());
+    let contents = fs::read_to_string(path).unwrap();
+    let name = path.file_name().unwrap().to_string_lossy().into_owned();
+    fs::remove_dir_all(dir).unwrap();
+    (name, contents)
+}
+
+#[test]
+fn log_dir() {
+    let dir = temp_path("log_dir");
+    let output = helpers::rederr([
+        &format!("--log-dir={}", dir.display()),
+        "tests/fixtures/fail.sh",
+    ])
+    .env("TZ", "UTC")
+    .output()
+    .unwrap();
+    let (name, logged) = read_only_file(&dir);
+
+    check!(output.status.code() == Some(3));
+    check!(output.stdout.as_bstr() == "out\nerr\n");
+    check!(name.ends_with("Z-fail.sh.log"));
+    check!(!name.contains(':'));
+
+    let lines: Vec<_> = logged.lines().collect();
+    let_assert!(
+        [command, started, "out", "err", finished, status] = lines.as_slice()
+    );
+    check!(*command == "# Command: tests/fixtures/fail.sh");
+    check!(started.starts_with("# Started: 20"));
+    check!(finished.starts_with("# Finished: 20"));
+    check!(*status == "# Exit status: 3");
+}
+
+#[test]
+fn log_dir_partial_line() {
+    let dir = temp_path("log_dir_partial_line");
+    let output = helpers::rederr([
+        &format!("--log-dir={}", dir.display()),
+        "--tz=UTC-2",
+        "printf",
+        "a b",
+    ])
+    .output()
+    .unwrap();
+    let (name, logged) = read_only_file(&dir);
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "a b");
+    check!(name.ends_with("+0200-printf.log"));
+    check!(logged.starts_with("# Command: printf 'a b'\n# Started: "));
+    check!(logged.contains("+02:00\na b\n# Finished: "));
+    check!(logged.ends_with("+02:00\n# Exit status: 0\n"));
+}
+
+#[test]
+fn log_dir_timeout() {
+    let dir = temp_path("log_dir_timeout");
+    let output = helpers::rederr([
+        &format!("--log-dir={}", dir.display()),
+        "--run-timeout=100ms",
+        "sleep",
+        "1",
+    ])
+    .output()
+    .unwrap();
+    let (_, logged) = read_only_file(&dir);
+
+    check!(output.status.code() == Some(124));
+    check!(logged.contains("\nRun timed out after "));
+    check!(logged.ends_with("\n# Exit status: 124\n"));
+}
+
+#[test]
+fn log_dir_compress_gzip() {
+    let dir = temp_path("log_dir_compress_gzip");
+    let output = helpers::rederr([
+        &format!("--log-dir={}", dir.display()),
+        "--log-compress=gzip",
+        "tests/fixtures/simple.sh",
+    ])
+    .output()
+    .unwrap();
+    let entries: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    let_assert!([path] = entries.as_slice());
+    let unzipped = process::Command::new("gzip")
+        .arg("--decompress")
+        .arg("--stdout")
+        .arg(path)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    check!(output.status.success());
+    check!(output.stderr.as_bstr() == "");
+    check!(path.to_string_lossy().ends_with("-simple.sh.log.gz"));
+    check!(unzipped.status.success());
+    check!(unzipped.stdout.contains_str("\nout\nerr\n# Finished: "));
+    check!(unzipped.stdout.ends_with(b"\n# Exit status: 0\n"));
+}
+
+/// Run `rederr` with `--status-file` and return the output and the status.
+fn run_with_status_file(
+    test: &str,
+    args: &[&str],
+) -> (process::Output, String) {
+    let path = temp_path(test);
+    let mut all_args = vec![format!("--status-file={}", path.display())];
+    all_args.extend(args.iter().map(|arg| (*arg).to_owned()));
+    let output = helpers::rederr(all_args).output().unwrap();
+    let status = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    (output, status)
+}
+
+#[test]
+fn status_file() {
+    let (output, status) =
+        run_with_status_file("status_file", &["tests/fixtures/fail.sh"]);
+
+    check!(output.status.code() == Some(3));
+    check!(status.starts_with(
+        "{\"command\":\"tests/fixtures/fail.sh\",\"args\":[],\"start\":\"20"
+    ));
+    check!(status.contains(
+        ",\"exit_code\":3,\"child_exit_code\":3,\"signal\":null,\
+        \"timed_out\":false,\"stdout_bytes\":4,\"stderr_bytes\":4,"
+    ));
+    check!(status.contains(",\"rusage\":{\"user_usec\":"));
+    check!(status.ends_with("}\n"));
+}
+
+#[test]
+fn status_file_wait_only() {
+    let (output, status) = run_with_status_file(
+        "status_file_wait_only",
+        &["--wait-only", "tests/fixtures/fail.sh"],
+    );
+
+    // The output isn’t read, so it isn’t counted.
+    check!(output.status.code() == Some(3));
+    check!(output.stderr.as_bstr() == "err\n");
+    check!(status.contains(
+        ",\"exit_code\":3,\"child_exit_code\":3,\"signal\":null,\
+        \"timed_out\":false,\"stdout_bytes\":0,\"stderr_bytes\":0,"
+    ));
+}
+
+#[test]
+fn stats() {
+    let output = helpers::rederr(["--stats", "--separate", "true"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "");
+    let_assert!(Some(line) = output.stderr.strip_prefix(b"Command used "));
+    check!(line.contains_str("s user, "));
+    check!(line.ends_with(b" KiB max RSS\n"));
+}
+
+#[test]
+fn status_file_timeout() {
+    let (output, status) = run_with_status_file(
+        "status_file_timeout",
+        &["--run-timeout=100ms", "sleep", "1"],
+    );
+
+    check!(output.status.code() == Some(124));
+    check!(status.contains(",\"args\":[\"1\"],"));
+    check!(status.contains(
+        ",\"exit_code\":124,\"child_exit_code\":null,\"signal\":null,\
+        \"timed_out\":true,"
+    ));
+}
+
+#[test]
+fn status_file_killed() {
+    let (output, status) = run_with_status_file(
+        "status_file_killed",
+        &["--run-timeout=100ms", "--kill-after=1s", "sleep", "1"],
+    );
+
+    check!(output.status.code() == Some(124));
+    check!(status.contains(
+        ",\"exit_code\":124,\"child_exit_code\":null,\
+        \"signal\":\"SIGTERM\",\"timed_out\":true,"
+    ));
+}
+
+#[test]
+fn on_run_timeout_term() {
+    let (output, status) = run_with_status_file(
+        "on_run_timeout_term",
+        &[
+            "--run-timeout=200ms",
+            "--on-run-timeout=term",
+            "tests/fixtures/trap_term.sh",
+        ],
+    );
+
+    check!(output.status.code() == Some(124));
+    check!(output.stdout.as_bstr() == "started\nterminating\n");
+    check!(output.stderr[..14].as_bstr() == "Run timed out ");
+    check!(status.contains("\"signal\":null,\"timed_out\":true,"));
+}
+
+#[test]
+fn on_run_timeout_kill() {
+    let start = Instant::now();
+    let (output, status) = run_with_status_file(
+        "on_run_timeout_kill",
+        &[
+            "--run-timeout=200ms",
+            "--kill-after=10s",
+            "--on-run-timeout=kill",
+            "tests/fixtures/ignore_term.sh",
+        ],
+    );
+
+    check!(output.status.code() == Some(124));
+    check!(output.stdout.as_bstr() == "started\n");
+    check!(output.stderr[..14].as_bstr() == "Run timed out ");
+    check!(status.contains("\"signal\":\"SIGKILL\",\"timed_out\":true,"));
+    check!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn on_run_timeout_warn() {
+    let output = helpers::rederr([
+        "--run-timeout=100ms",
+        "--on-run-timeout=warn",
+        "sleep",
+        "0.3",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    let_assert!(
+        Some(rest) =
+            output.stderr.strip_prefix(b"Warning: run timed out after ")
+    );
+    check!(rest.ends_with(b"; leaving the command running\n"));
+}
+
+#[test]
+fn on_idle_timeout_warn() {
+    // The warning is repeated each time the idle timeout expires.
+    let output = helpers::rederr([
+        "--idle-timeout=150ms",
+        "--on-idle-timeout=warn",
+        "sleep",
+        "0.4",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    let lines: Vec<_> = output.stderr.lines().collect();
+    check!(lines.len() == 2);
+    for line in lines {
+        check!(line.starts_with(b"Warning: timed out waiting for input after "));
+    }
+}
+
+#[test]
+fn on_idle_timeout_exit() {
+    // Without --on-idle-timeout, this would wait for the command to exit.
+    let start = Instant::now();
+    let output = helpers::rederr([
+        "--idle-timeout=100ms",
+        "--kill-after=10s",
+        "--on-idle-timeout=exit",
+        "sleep",
+        "1",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(output.stderr[..28].as_bstr() == "Timed out waiting for input ");
+    check!(start.elapsed() < Duration::from_millis(900));
+}
+
+#[test]
+fn summary() {
+    let output = helpers::rederr([
+        "--summary",
+        "--separate",
+        "--idle-timeout=500ms",
+        "tests/fixtures/simple.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    let_assert!(Some(summary) = output.stderr.lines().next_back());
+    check!(summary.starts_with(b"Command ran for "));
+    check!(summary.ends_with(
+        b"; stdout: 4 bytes in 1 lines; stderr: 4 bytes in 1 lines; 2 reads; \
+        no timeout expired"
+    ));
+}
+
+#[test]
+fn summary_timeout() {
+    let output =
+        helpers::rederr(["--summary", "--run-timeout=100ms", "sleep", "1"])
+            .output()
+            .unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(output
+        .stderr
+        .ends_with(b"; 0 reads; --run-timeout expired\n"));
+}
+
+#[test]
+fn command_option() {
+    let output = helpers::rederr([
+        "--command",
+        "echo",
+        "--arg=-n",
+        "--arg",
+        "--separate",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "--separate");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn warn_at() {
+    let output = helpers::rederr([
+        "--warn-at=300ms,100ms",
+        "--run-timeout=5s",
+        "sleep",
+        "0.5",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "");
+    check!(
+        output.stderr.as_bstr()
+            == "Warning: still running after 100ms (run timeout is 5s)\n\
+                Warning: still running after 300ms (run timeout is 5s)\n"
+    );
+}
+
+#[test]
+fn warn_at_after_exit() {
+    let output = helpers::rederr(["--warn-at=1s", "tests/fixtures/simple.sh"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn run_warn() {
+    let output = helpers::rederr(["--run-warn=100ms", "sleep", "0.5"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stderr.as_bstr() == "Warning: still running after 100ms\n");
+}
+
+#[test]
+fn run_warn_repeat() {
+    let output = helpers::rederr([
+        "--run-warn=200ms",
+        "--warn-repeat",
+        "--run-timeout=5s",
+        "sleep",
+        "0.5",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stderr.as_bstr()
+            == "Warning: still running after 200ms (run timeout is 5s)\n\
+                Warning: still running after 400ms (run timeout is 5s)\n"
+    );
+}
+
+#[test]
+fn idle_warn() {
+    // The warning is issued again after the output.
+    let output = helpers::rederr([
+        "--separate",
+        "--idle-warn=200ms",
+        "sh",
+        "-c",
+        "sleep 0.3; echo out; sleep 0.3",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out\n");
+    check!(
+        output.stderr.as_bstr()
+            == "Warning: no output for 200ms\nWarning: no output for 200ms\n"
+    );
+}
+
+#[test]
+fn idle_warn_repeat() {
+    let output =
+        helpers::rederr(["--idle-warn=150ms", "--warn-repeat", "sleep", "0.4"])
+            .output()
+            .unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stderr.as_bstr()
+            == "Warning: no output for 150ms\nWarning: no output for 300ms\n"
+    );
+}
+
+#[test]
+fn warn_command() {
+    let output = helpers::rederr([
+        "--warn-at=100ms",
+        "--warn-command",
+        "echo \"hook $REDERR_ELAPSED_MS: $REDERR_WARNING\"",
+        "sleep",
+        "0.5",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stdout.as_bstr()
+            == "hook 100: Warning: still running after 100ms\n"
+    );
+    check!(output.stderr.as_bstr() == "Warning: still running after 100ms\n");
+}
+
+#[test]
+fn stdin_pipe() {
+    // Large enough to fill the pipe to the child.
+    let input = env!("CARGO_BIN_EXE_rederr");
+    let output = helpers::rederr(["--stdin=pipe", "wc", "--bytes"])
+        .stdin(File::open(input).unwrap())
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    let expected = fs::metadata(input).unwrap().len();
+    check!(output.stdout.trim().as_bstr() == expected.to_string());
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn stdin_pipe_child_stops_reading() {
+    let output = helpers::rederr(["--stdin=pipe", "head", "--bytes=3"])
+        .stdin(File::open(env!("CARGO_BIN_EXE_rederr")).unwrap())
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "\x7fEL");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn stdin_null() {
+    let output = helpers::rederr(["--stdin=null", "cat"])
+        .stdin(File::open("tests/fixtures/simple.sh").unwrap())
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "");
+}
+
+#[test]
+fn stdin_file() {
+    let output =
+        helpers::rederr(["--stdin", "tests/fixtures/simple.sh", "cat"])
+            .output()
+            .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout == fs::read("tests/fixtures/simple.sh").unwrap());
+}
+
+#[test]
+fn stdin_file_missing() {
+    let output = helpers::rederr(["--stdin", "tests/fixtures/missing", "cat"])
+        .output()
+        .unwrap();
+
+    check!(!output.status.success());
+    check!(output.stdout.as_bstr() == "");
+    check!(output
+        .stderr
+        .starts_with(b"Error: opening tests/fixtures/missing for stdin: "));
+}
+
+#[test]
+fn version() {
+    let output = helpers::rederr(["--version"]).output().unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stdout.as_bstr()
+            == format!("rederr {}\n", env!("CARGO_PKG_VERSION"))
+    );
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn version_verbose() {
+    let output = helpers::rederr(["--version", "--verbose"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.contains_str("\ncommit: "));
+    check!(output.stdout.contains_str("\ntarget: "));
+    check!(output.stdout.contains_str("\nfeatures: "));
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn buffered_unbuffer() {
+    let output = helpers::rederr([
+        "--unbuffer",
+        "--run-timeout",
+        "150ms",
+        "tests/fixtures/buffered.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(!output.status.success());
+    check!(output.stdout.as_bstr() == "x111\n");
+    check!(output.stderr[..14].as_bstr() == "Run timed out ");
+}
+
+#[test]
+fn buffered_without_unbuffer() {
+    let output = helpers::rederr([
+        "--run-timeout",
+        "150ms",
+        "tests/fixtures/buffered.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(!output.status.success());
+    check!(output.stdout.as_bstr() == "");
+    check!(output.stderr[..14].as_bstr() == "Run timed out ");
+}
+
+#[test]
+fn unbuffer_missing_libstdbuf() {
+    let output = helpers::rederr([
+        "--unbuffer",
+        "--verbose",
+        "--run-timeout",
+        "150ms",
+        "tests/fixtures/buffered.sh",
+    ])
+    .env("REDERR_LIBSTDBUF", "/nonexistent/libstdbuf.so")
+    .output()
+    .unwrap();
+
+    // Falls back to --pty, which also makes sed line buffer.
+    check!(!output.status.success());
+    check!(output.stdout.as_bstr() == "x111\n");
+    check!(output.stderr.contains_str("using --pty for --unbuffer"));
+    check!(output.stderr.contains_str("Run timed out "));
+}
+
+/// Run `rederr` with a consumer that doesn’t read for a while.
+fn slow_consumer(args: &[&str]) -> std::process::Output {
+    let child = helpers::rederr(args)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(400));
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn slow_consumer_run_timeout() {
+    let output = slow_consumer(&[
+        "--run-timeout",
+        "200ms",
+        "head",
+        "--bytes=200000",
+        "/dev/zero",
+    ]);
+
+    check!(!output.status.success());
+    check!(output.stderr[..14].as_bstr() == "Run timed out ");
+}
+
+#[test]
+fn slow_consumer_run_timeout_backpressure() {
+    let output = slow_consumer(&[
+        "--backpressure",
+        "--run-timeout",
+        "200ms",
+        "head",
+        "--bytes=200000",
+        "/dev/zero",
+    ]);
+
+    check!(output.status.success());
+    check!(output.stdout.len() == 200_000);
+    check!(output.stderr.as_bstr() == "");
+}
+
+/// Run `rederr` with `args` with its stdout going to a pipe that isn’t read
+/// for 400ms, and return its stderr as of then along with its output.
+fn stalled_stdout(args: &[&str]) -> (Vec<u8>, std::process::Output) {
+    let path = temp_path(args.join("_").replace('/', "_").as_str());
+    let mut child = helpers::rederr(args)
+        .stdout(process::Stdio::piped())
+        .stderr(File::create(&path).unwrap())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(400));
+    let stalled = fs::read(&path).unwrap();
+
+    let mut stdout = Vec::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_end(&mut stdout)
+        .unwrap();
+    let status = child.wait().unwrap();
+    let stderr = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let output = std::process::Output {
+        status,
+        stdout,
+        stderr,
+    };
+    (stalled, output)
+}
+
+#[test]
+fn max_buffer_stderr_flows() {
+    let (stalled, output) = stalled_stdout(&[
+        "--separate",
+        "--max-buffer",
+        "1000000",
+        "tests/fixtures/flood.sh",
+    ]);
+
+    check!(stalled.as_bstr() == "done\n");
+    check!(output.status.success());
+    check!(output.stdout.len() == 200_000);
+}
+
+#[test]
+fn max_buffer_full_run_timeout() {
+    let (stalled, output) = stalled_stdout(&[
+        "--max-buffer",
+        "1024",
+        "--run-timeout",
+        "200ms",
+        "tests/fixtures/flood.sh",
+    ]);
+
+    check!(stalled[..14].as_bstr() == "Run timed out ");
+    check!(!output.status.success());
+}
+
+#[test]
+fn max_buffer_full_stops_reading() {
+    let (stalled, output) = stalled_stdout(&[
+        "--separate",
+        "--max-buffer",
+        "1024",
+        "tests/fixtures/flood.sh",
+    ]);
+
+    // The child is blocked writing to stdout, so it hasn’t written to stderr.
+    check!(stalled.as_bstr() == "");
+    check!(output.status.success());
+    check!(output.stdout.len() == 200_000);
+    check!(output.stderr.as_bstr() == "done\n");
+}
+
+#[test]
+fn max_buffer_quiet_on_success() {
+    let output = helpers::rederr([
+        "--quiet-on-success",
+        "--max-buffer",
+        "1024",
+        "sh",
+        "-c",
+        "seq 2000; exit 1",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(1));
+    check!(output.stdout.lines().count() == 2000);
+    check!(output.stdout.starts_with(b"1\n2\n"));
+    check!(output.stdout.ends_with(b"1999\n2000\n"));
+}
+
+#[test]
+fn debug_blocked_time() {
+    let output = helpers::rederr(["--debug", "tests/fixtures/simple.sh"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.contains_str("\nspent "));
+    check!(output.stdout.contains_str(
+        " writing output; child blocked on full pipe for up to "
+    ));
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn debug_run_time() {
+    let output = helpers::rederr(["--debug", "tests/fixtures/simple.sh"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.contains_str("\nran for "));
+    check!(!output.stdout.contains_str("wall clock"));
+}
+
+#[test]
+fn verbose_poll_stats() {
+    let output =
+        helpers::rederr(["-vv", "--separate", "tests/fixtures/simple.sh"])
+            .output()
+            .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out\n");
+    let_assert!(
+        Some(stats) = output.stderr.strip_prefix(b"err\npoll() woke up ")
+    );
+    check!(stats.contains_str(" reads ("));
+    check!(stats.ends_with(b" bytes per wakeup\n"));
+}
+
+#[test]
+fn verbose_once_no_poll_stats() {
+    let output = helpers::rederr(["-v", "tests/fixtures/simple.sh"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out\nerr\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn wait_only() {
+    let output = helpers::rederr([
+        "--always-color",
+        "--wait-only",
+        "tests/fixtures/simple.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out\n");
+    check!(output.stderr.as_bstr() == "err\n");
+}
+
+#[test]
+fn wait_only_ready_pattern() {
+    let output = helpers::rederr([
+        "--always-color",
+        "--wait-only",
+        "--ready-pattern=^listening$",
+        "tests/fixtures/server.sh",
+        "/dev/null",
+    ])
+    .output()
+    .unwrap();
+
+    // Output is read until the command is ready, then passed through.
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "starting\nlistening\nstill running\n");
+    check!(
+        output.stderr.as_bstr()
+            == "Command is ready: output matched --ready-pattern \
+                \"^listening$\"\nstill running\n"
+    );
+}
+
+#[test]
+fn wait_only_run_timeout() {
+    let output = helpers::rederr([
+        "--wait-only",
+        "--run-timeout",
+        "150ms",
+        "tests/fixtures/midline_sleep.sh",
+    ])
+    .output()
+    .unwrap();
+
+    // The child keeps running and writing to the pipe after rederr exits.
+    check!(!output.status.success());
+    check!(output.stdout.as_bstr() == "111222333\n");
+    check!(output.stderr[..14].as_bstr() == "Run timed out ");
+}
+
+#[test]
+fn close_output_run_timeout() {
+    let start = Instant::now();
+    let output = helpers::rederr([
+        "--run-timeout",
+        "150ms",
+        "tests/fixtures/close_output.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(!output.status.success());
+    check!(output.stdout.as_bstr() == "out\n");
+    check!(output.stderr[..14].as_bstr() == "Run timed out ");
+    check!(start.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn simple_inject_latency_out() {
+    let output = helpers::rederr([
+        "--inject-latency",
+        "out=100ms",
+        "tests/fixtures/simple.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "err\nout\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn simple_inject_latency_err() {
+    let output = helpers::rederr([
+        "--inject-latency",
+        "err=100ms",
+        "tests/fixtures/simple.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out\nerr\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn mixed_output_inject_latency() {
+    // Delaying stderr by more than the sleeps in the fixture moves all of it to
+    // the end.
+    let output = helpers::rederr([
+        "--always-color",
+        "--inject-latency",
+        "err=500ms",
+        "tests/fixtures/mixed_output.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() ==
+        "111333\n\u{1b}[0m\u{1b}[38;5;9maaa\u{1b}[0m\u{1b}[0m\u{1b}[38;5;9mbbb\n\u{1b}[0m");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn watchdog_stuck_output() {
+    let start = Instant::now();
+    let mut child = helpers::rederr([
+        "--watchdog",
+        "200ms",
+        "head",
+        "--bytes=1000000",
+        "/dev/zero",
+    ])
+    .stdout(std::process::Stdio::piped())
+    .stderr(std::process::Stdio::piped())
+    .spawn()
+    .unwrap();
+
+    // Never read stdout, so rederr blocks writing to it.
+    let _stdout = child.stdout.take();
+    let status = child.wait().unwrap();
+    let mut stderr = Vec::new();
+    child.stderr.unwrap().read_to_end(&mut stderr).unwrap();
+
+    check!(status.code() == Some(125));
+    check!(stderr.starts_with(b"Watchdog: rederr blocked writing output for "));
+    check!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn jitter() {
+    let start = Instant::now();
+    let output = helpers::rederr(["--jitter=50ms", "echo", "hi"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "hi\n");
+    check!(output.stderr.as_bstr() == "");
+    check!(start.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn jitter_counts_against_run_timeout() {
+    let start = Instant::now();
+    let output =
+        helpers::rederr(["--jitter=900ms", "--run-timeout=1s", "sleep", "5"])
+            .output()
+            .unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(output.stderr[..14].as_bstr() == "Run timed out ");
+    check!(start.elapsed() < Duration::from_millis(1_300));
+}
+
+#[test]
+fn jitter_not_shorter_than_run_timeout() {
+    let output =
+        helpers::rederr(["--jitter=1s", "--run-timeout=1s", "echo", "hi"])
+            .output()
+            .unwrap();
+
+    check!(output.status.code() == Some(125));
+    check!(output.stdout.as_bstr() == "");
+    check!(
+        output.stderr.as_bstr()
+            == "Error: --jitter must be shorter than --run-timeout\n"
+    );
+}
+
+/// Get the time of day in UTC `seconds` from now, as "HH:MM:SS".
+#[allow(clippy::arithmetic_side_effects, clippy::integer_division)]
+fn utc_time_from_now(seconds: u64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap();
+    // Round up so that the deadline is at least `seconds` away.
+    let time = (now.as_secs() + 1 + seconds) % 86_400;
+    format!("{:02}:{:02}:{:02}", time / 3_600, time / 60 % 60, time % 60)
+}
+
+#[test]
+fn deadline() {
+    let deadline = utc_time_from_now(1);
+    let start = Instant::now();
+    let output = helpers::rederr([
+        "--tz=UTC",
+        "--deadline",
+        &deadline,
+        "--run-timeout=10s",
+        "sleep",
+        "5",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(output.stderr[..17].as_bstr() == "Deadline reached ");
+    check!(start.elapsed() < Duration::from_secs(4));
+}
+
+#[test]
+fn deadline_after_run_timeout() {
+    let deadline = utc_time_from_now(60);
+    let output = helpers::rederr([
+        "--tz=UTC",
+        "--deadline",
+        &deadline,
+        "--run-timeout=100ms",
+        "sleep",
+        "5",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(124));
+    check!(output.stderr[..14].as_bstr() == "Run timed out ");
+}
+
+#[test]
+fn deadline_passed() {
+    let output = helpers::rederr([
+        "--tz=UTC",
+        "--deadline=2020-01-01T00:00",
+        "echo",
+        "hi",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.code() == Some(125));
+    check!(output.stdout.as_bstr() == "");
+    check!(
+        output.stderr.as_bstr()
+            == "Error: --deadline 2020-01-01T00:00:00.000Z has already passed\n"
+    );
+}
+
+#[test]
+fn deadline_invalid() {
+    let output = helpers::rederr(["--deadline=4:30pm", "echo", "hi"])
+        .output()
+        .unwrap();
+
+    check!(output.status.code() == Some(2));
+    check!(output.stdout.as_bstr() == "");
+}
+
+#[test]
+fn stdout_color() {
+    let log = temp_path("stdout_color");
+    let mut args = vec![
+        "--always-color".to_owned(),
+        "--stdout-color=blue".to_owned(),
+        "--stdout-style=dim".to_owned(),
+        "--separate".to_owned(),
+        format!("--log-file={}", log.display()),
+    ];
+    args.extend(
+        generate_output(&["--lines=2", "--stderr-ratio=0.5", "--rate=20/s"])
+            .into_iter()
+            .map(str::to_owned),
+    );
+    let output = helpers::rederr(args).output().unwrap();
+    let logged = fs::read(&log).unwrap();
+    fs::remove_file(&log).unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "\x1b[0m\x1b[2m\x1b[34mout 1\n\x1b[0m");
+    check!(output.stderr.as_bstr() == "\x1b[0m\x1b[38;5;9merr 2\n\x1b[0m");
+    check!(logged.as_bstr() == "out 1\nerr 2\n");
+}
+
+#[test]
+fn sanitize() {
+    let output = helpers::rederr([
+        "--always-color",
+        "--sanitize",
+        "tests/fixtures/control_chars.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stdout.as_bstr()
+            == "a^[[31mb^M\tc\n\x1b[0m\x1b[38;5;9m^G\n\x1b[0m"
+    );
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn hex() {
+    let output = helpers::rederr(["--hex", "-s", "tests/fixtures/simple.sh"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stdout.as_bstr()
+            == "00000000  6f 75 74 0a                                       \
+                |out.|\n"
+    );
+    check!(
+        output.stderr.as_bstr()
+            == "00000000  65 72 72 0a                                       \
+                |err.|\n"
+    );
+}
+
+#[test]
+fn collapse_cr() {
+    let log = temp_path("collapse_cr");
+    let output = helpers::rederr([
+        "--log-file".as_ref(),
+        log.as_os_str(),
+        "--collapse-cr".as_ref(),
+        "--tag".as_ref(),
+        "printf".as_ref(),
+        r"10%%\r50%%\r100%%\nline\r\n50%%\r".as_ref(),
+    ])
+    .output()
+    .unwrap();
+    let logged = fs::read(&log).unwrap();
+    fs::remove_file(&log).unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stdout.as_bstr()
+            == "[out] 10%\r50%\r100%\n[out] line\r\n[out] 50%\r"
+    );
+    check!(logged.as_bstr() == "[out] 100%\n[out] line\r\n[out] 50%");
+}
+
+#[test]
+fn highlight() {
+    let output = helpers::rederr([
+        "--always-color",
+        "--highlight=u=blue",
+        "--highlight=e=bold",
+        "tests/fixtures/simple.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stdout.as_bstr()
+            == "o\x1b[0m\x1b[34mu\x1b[0mt\n\
+                \x1b[0m\x1b[38;5;9m\x1b[0m\x1b[1m\x1b[38;5;9me\
+                \x1b[0m\x1b[38;5;9mrr\n\x1b[0m"
+    );
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn grep_exclude() {
+    let mut args = vec!["--separate", "--grep=[15]$", "--exclude=err"];
+    args.extend(generate_output(&["--lines=6", "--stderr-ratio=0.5"]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out 1\nout 5\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn grep_filter_stream() {
+    let mut args = vec!["--separate", "--filter-stream=err", "--grep=[15]$"];
+    args.extend(generate_output(&["--lines=6", "--stderr-ratio=0.5"]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out 1\nout 3\nout 5\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn no_stdout() {
+    let mut args = vec!["--no-stdout"];
+    args.extend(generate_output(&["--lines=20000", "--stderr-ratio=0.0001"]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "err 10000\nerr 20000\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn no_stderr() {
+    let output = helpers::rederr(["--no-stderr", "tests/fixtures/simple.sh"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn max_output_lines() {
+    let mut args = vec!["--separate", "--max-output-lines=3"];
+    args.extend(generate_output(&["--lines=100000"]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out 1\nout 2\nout 3\n");
+    check!(
+        output.stderr.as_bstr()
+            == "Command stdout truncated after 18 bytes and 3 lines\n"
+    );
+}
+
+#[test]
+fn max_output_bytes() {
+    let mut args = vec!["--separate", "--max-output-bytes=8"];
+    args.extend(generate_output(&["--lines=100000"]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out 1\nou");
+    check!(
+        output.stderr.as_bstr()
+            == "Command stdout truncated after 8 bytes and 1 line\n"
+    );
+}
+
+#[test]
+fn head_tail() {
+    let mut args = vec!["--separate", "--head=2", "--tail=2"];
+    args.extend(generate_output(&["--lines=10", "--stderr-ratio=0.3"]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stdout.as_bstr()
+            == "out 1\nout 2\n‹ 3 lines of stdout omitted ›\nout 8\nout 9\n"
+    );
+    check!(output.stderr.as_bstr() == "err 4\nerr 7\nerr 10\n");
+}
+
+#[test]
+fn tail_only() {
+    let mut args = vec!["--tail=1"];
+    args.extend(generate_output(&["--lines=3"]));
+    let output = helpers::rederr(args).output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "‹ 2 lines of stdout omitted ›\nout 3\n");
+}
+
+#[test]
+fn dedup() {
+    let output = helpers::rederr([
+        "--separate",
+        "--dedup",
+        "tests/fixtures/repeated.sh",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stdout.as_bstr()
+            == "retrying\n… last message repeated 2 times\nconnected\n\
+                … last message repeated 1 time\n"
+    );
+    check!(
+        output.stderr.as_bstr()
+            == "connection refused\n… last message repeated 2 times\n\
+                connected\n"
+    );
+}
+
+/// Write a configuration file for `test` and return its path.
+fn write_config(test: &str, contents: &str) -> PathBuf {
+    let path = temp_path(test);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+const CONFIG: &str = "\
+# Defaults
+stats = true
+
+[profile.quick]
+run-timeout = \"100ms\"
+";
+
+#[test]
+fn config_defaults() {
+    let path = write_config("config_defaults", CONFIG);
+    let output = helpers::rederr(["--config".as_ref(), path.as_os_str()])
+        .arg("true")
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(output.status.success());
+    check!(output.stderr.starts_with(b"Command used "));
+}
+
+#[test]
+fn config_profile() {
+    let path = write_config("config_profile", CONFIG);
+    let output = helpers::rederr(["--config".as_ref(), path.as_os_str()])
+        .args(["--profile", "quick", "sleep", "1"])
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(output.status.code() == Some(124));
+}
+
+#[test]
+fn config_overridden() {
+    let path = write_config("config_overridden", CONFIG);
+    let output = helpers::rederr(["--config".as_ref(), path.as_os_str()])
+        .args(["--profile=quick", "--run-timeout=10s", "sleep", "0.2"])
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(output.status.success());
+}
+
+#[test]
+fn config_profile_sink_grep() {
+    let log = temp_path("config_profile_sink_grep.log");
+    let path = write_config(
+        "config_profile_sink_grep",
+        &format!(
+            "[profile.errors]\n\
+            sink-grep = [\"terminal:^err\", \"terminal:^warn\"]\n\
+            log-file = \"{}\"\n",
+            log.display()
+        ),
+    );
+    let output = helpers::rederr(["--config".as_ref(), path.as_os_str()])
+        .args([
+            "--profile=errors",
+            "sh",
+            "-c",
+            "echo a; echo warn; echo err",
+        ])
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    let logged = fs::read(&log).unwrap();
+    fs::remove_file(&log).unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "warn\nerr\n");
+    check!(logged.as_bstr() == "a\nwarn\nerr\n");
+}
+
+#[test]
+fn config_default_path() {
+    let dir = temp_path("config_default_path");
+    fs::create_dir_all(dir.join("rederr")).unwrap();
+    fs::write(dir.join("rederr/config.toml"), CONFIG).unwrap();
+    let output = helpers::rederr(["true"])
+        .env("XDG_CONFIG_HOME", &dir)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    check!(output.status.success());
+    check!(output.stderr.starts_with(b"Command used "));
+}
+
+#[test]
+fn config_missing() {
+    let output =
+        helpers::rederr(["--config", "/nonexistent/config.toml", "true"])
+            .output()
+            .unwrap();
+
+    check!(output.status.code() == Some(125));
+    check!(output
+        .stderr
+        .starts_with(b"Error: reading config /nonexistent/config.toml: "));
+}
+
+#[test]
+fn config_errors() {
+    let path = write_config("config_errors", "stats = true\ncolour = 1\n");
+    let output = helpers::rederr(["--config".as_ref(), path.as_os_str()])
+        .arg("true")
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let path = write_config("config_errors", CONFIG);
+    let unknown = helpers::rederr(["--config".as_ref(), path.as_os_str()])
+        .args(["--profile", "nope", "true"])
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(output.status.code() == Some(125));
+    check!(
+        output.stderr.as_bstr()
+            == format!(
+                "Error: in config {}: unknown option \"colour\"\n",
+                path.display()
+            )
+    );
+    check!(unknown.status.code() == Some(125));
+    check!(unknown.stderr.ends_with(b": no such profile \"nope\"\n"));
+}
+
+#[test]
+fn env_options() {
+    let output = helpers::rederr(["sleep", "1"])
+        .env("REDERR_RUN_TIMEOUT", "100ms")
+        .output()
+        .unwrap();
+    check!(output.status.code() == Some(124));
+
+    let output = helpers::rederr(["true"])
+        .env("REDERR_STATS", "yes")
+        .env("REDERR_SUMMARY", "0")
+        .output()
+        .unwrap();
+    check!(output.status.success());
+    check!(output.stderr.starts_with(b"Command used "));
+}
+
+#[test]
+fn env_overridden() {
+    let output = helpers::rederr(["--run-timeout=10s", "sleep", "0.2"])
+        .env("REDERR_RUN_TIMEOUT", "100ms")
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+}
+
+#[test]
+fn env_overrides_config() {
+    let path = write_config("env_overrides_config", CONFIG);
+    let output = helpers::rederr(["--config".as_ref(), path.as_os_str()])
+        .args(["--profile", "quick", "sleep", "0.2"])
+        .env("REDERR_RUN_TIMEOUT", "10s")
+        .env("REDERR_STATS", "false")
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(output.status.success());
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn dry_run() {
+    let path = write_config("dry_run", CONFIG);
+    let output = helpers::rederr(["--config".as_ref(), path.as_os_str()])
+        .args(["--profile=quick", "--dry-run", "--chdir=/", "--env=A=b c"])
+        .args(["--retries=2", "--retry-delay=1s", "--retry-backoff"])
+        .args(["-S", "touch 'dry run'"])
+        .env("SHELL", "/bin/sh")
+        .env("REDERR_IDLE_TIMEOUT", "1m")
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(output.status.success());
+    check!(output.stderr.as_bstr() == "");
+    check!(
+        output.stdout.as_bstr()
+            == format!(
+                "Options:\n  \
+                --shell\n  \
+                --run-timeout=100ms (from config)\n  \
+                --idle-timeout=1m (from REDERR_IDLE_TIMEOUT)\n  \
+                --retries=2\n  \
+                --retry-delay=1s\n  \
+                --retry-backoff\n  \
+                --chdir=/\n  \
+                --env='A=b c'\n  \
+                --stats (from config)\n  \
+                --dry-run\n  \
+                --config={}\n  \
+                --profile=quick\n\
+                Command: /bin/sh -c 'touch '\\''dry run'\\'''\n\
+                Directory: /\n\
+                Environment:\n  \
+                A='b c'\n\
+                Timeouts:\n  \
+                --run-timeout: 100ms\n  \
+                --idle-timeout: 60s\n  \
+                --linger-timeout: 100ms\n  \
+                --retry-delay: 1s, 2s\n",
+                path.display()
+            )
+    );
+    check!(!PathBuf::from("/dry run").exists());
+}
+
+#[test]
+fn our_options_after_command() {
+    let output = helpers::rederr([
+        "--separate",
+        "/bin/sh",
+        "-c",
+        "echo \"$@\"",
+        "sh",
+        "-s",
+        "--separate",
+        "--",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "-s --separate --\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn separator() {
+    let output = helpers::rederr(["--separate", "--", "echo", "-s", "--", "a"])
+        .output()
+        .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "-s -- a\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn separator_dash_command() {
+    let dir = temp_path("separator_dash_command");
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("-dash");
+    fs::write(&script, "#!/bin/sh\necho \"dash $*\"\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", dir.display(), env::var("PATH").unwrap());
+    let output = helpers::rederr(["--", "-dash", "-s", "a b"])
+        .env("PATH", path)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "dash -s a b\n");
+}
+
+#[test]
+fn args_with_spaces_and_invalid_utf8() {
+    let invalid = OsStr::from_bytes(b"a\xff b");
+    let output = helpers::rederr([
+        OsStr::new("/bin/sh"),
+        OsStr::new("-c"),
+        OsStr::new("printf '[%s]' \"$@\""),
+        OsStr::new("sh"),
+        OsStr::new("two words"),
+        invalid,
+        OsStr::new(""),
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == b"[two words][a\xff b][]".as_bstr());
+}
+
+#[test]
+fn command_with_invalid_utf8_name() {
+    let dir = temp_path("command_with_invalid_utf8_name");
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join(OsStr::from_bytes(b"run \xff"));
+    fs::write(&script, "#!/bin/sh\necho ran\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let output = helpers::rederr([&script]).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "ran\n");
 }
@@ -0,0 +1,124 @@
+//! Test preventing overlapping runs with `--lockfile`.
+use assert2::check;
+use bstr::ByteSlice;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Child, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod helpers;
+
+/// Get a lock file path for `test`.
+fn lock_path(test: &str) -> PathBuf {
+    env::temp_dir().join(format!("rederr-{test}-{}.lock", process::id()))
+}
+
+/// Start `rederr` holding the lock on `path` while it runs `sleep seconds`,
+/// and wait for it to take the lock.
+fn hold_lock(path: &PathBuf, seconds: &str) -> Child {
+    let holder = helpers::rederr([
+        &format!("--lockfile={}", path.display()),
+        "sleep",
+        seconds,
+    ])
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .spawn()
+    .unwrap();
+
+    let pid = format!("{}\n", holder.id());
+    let start = Instant::now();
+    while fs::read_to_string(path).ok().as_ref() != Some(&pid) {
+        assert!(start.elapsed() < Duration::from_secs(5), "lock not taken");
+        thread::sleep(Duration::from_millis(10));
+    }
+    holder
+}
+
+#[test]
+fn lock_free() {
+    let path = lock_path("lock_free");
+    let output = helpers::rederr([
+        &format!("--lockfile={}", path.display()),
+        "echo",
+        "hi",
+    ])
+    .output()
+    .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "hi\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn lock_held() {
+    let path = lock_path("lock_held");
+    let mut holder = hold_lock(&path, "5");
+    let output = helpers::rederr([
+        &format!("--lockfile={}", path.display()),
+        "echo",
+        "hi",
+    ])
+    .output()
+    .unwrap();
+    holder.kill().unwrap();
+    holder.wait().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(output.status.code() == Some(125));
+    check!(output.stdout.as_bstr() == "");
+    check!(
+        output.stderr.as_bstr()
+            == format!(
+                "Error: lock file {} is held by process {}\n",
+                path.display(),
+                holder.id(),
+            )
+    );
+}
+
+#[test]
+fn lock_timeout() {
+    let path = lock_path("lock_timeout");
+    let mut holder = hold_lock(&path, "0.2");
+    let output = helpers::rederr([
+        &format!("--lockfile={}", path.display()),
+        "--lock-timeout=5s",
+        "echo",
+        "hi",
+    ])
+    .output()
+    .unwrap();
+    let holder_status = holder.wait().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(holder_status.success());
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "hi\n");
+}
+
+#[test]
+fn lock_kill() {
+    let path = lock_path("lock_kill");
+    let mut holder = hold_lock(&path, "5");
+    let start = Instant::now();
+    let output = helpers::rederr([
+        &format!("--lockfile={}", path.display()),
+        "--lock-kill",
+        "echo",
+        "hi",
+    ])
+    .output()
+    .unwrap();
+    let holder_status = holder.wait().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    check!(holder_status.code() == Some(143));
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "hi\n");
+    check!(start.elapsed() < Duration::from_secs(4));
+}
@@ -0,0 +1,185 @@
+//! Test the library interface.
+use assert2::{check, let_assert};
+use bstr::ByteSlice;
+use rederr::{Event, Hooks, Runner, TimeoutKind};
+use std::cell::RefCell;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Run `runner` and collect its events, joining consecutive chunks of output
+/// from the same stream.
+fn events(runner: &Runner) -> Vec<Event> {
+    let mut events: Vec<Event> = Vec::new();
+    for event in runner.run().unwrap() {
+        match (events.last_mut(), event.unwrap()) {
+            (Some(Event::Stdout(last)), Event::Stdout(bytes))
+            | (Some(Event::Stderr(last)), Event::Stderr(bytes)) => {
+                last.extend(bytes);
+            }
+            (_, event) => events.push(event),
+        }
+    }
+    events
+}
+
+/// Get all the output from stdout (or stderr, if `stdout` is false) in
+/// `events`.
+fn output(events: &[Event], stdout: bool) -> Vec<u8> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Stdout(bytes) if stdout => Some(bytes.as_slice()),
+            Event::Stderr(bytes) if !stdout => Some(bytes.as_slice()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .concat()
+}
+
+#[test]
+fn output_and_exit_code() {
+    let events = events(&Runner::new("tests/fixtures/fail.sh"));
+
+    check!(output(&events, true).as_bstr() == "out\n");
+    check!(output(&events, false).as_bstr() == "err\n");
+    check!(events.last() == Some(&Event::Exit(3)));
+}
+
+#[test]
+fn args() {
+    let events =
+        events(&Runner::new("echo").arg("a").args(["b", "c"]).buffer_size(1));
+
+    check!(output(&events, true).as_bstr() == "a b c\n");
+    check!(events.last() == Some(&Event::Exit(0)));
+}
+
+#[test]
+fn not_found() {
+    let runner = Runner::new("tests/fixtures/missing");
+    let_assert!(Err(error) = runner.run());
+    check!(error.kind() == std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn not_executable() {
+    let runner = Runner::new("./README.md");
+    let_assert!(Err(error) = runner.run());
+    check!(error.kind() == std::io::ErrorKind::PermissionDenied);
+}
+
+#[test]
+fn idle_timeout() {
+    let runner = Runner::new("tests/fixtures/midline_sleep.sh")
+        .idle_timeout(Duration::from_millis(50));
+
+    check!(
+        events(&runner)
+            == [
+                Event::Stdout(b"111".to_vec()),
+                Event::TimedOut(TimeoutKind::Idle),
+                Event::Exit(128 + 15),
+            ]
+    );
+}
+
+#[test]
+fn idle_timeout_restarts() {
+    let runner = Runner::new("tests/fixtures/midline_sleep.sh")
+        .idle_timeout(Duration::from_millis(500));
+
+    check!(
+        events(&runner)
+            == [Event::Stdout(b"111222333\n".to_vec()), Event::Exit(0)]
+    );
+}
+
+#[test]
+fn run_timeout_kill_after() {
+    let start = Instant::now();
+    let runner = Runner::new("tests/fixtures/ignore_term.sh")
+        .run_timeout(Duration::from_millis(200))
+        .kill_after(Duration::from_millis(200));
+
+    check!(
+        events(&runner)
+            == [
+                Event::Stdout(b"started\n".to_vec()),
+                Event::TimedOut(TimeoutKind::Run),
+                Event::Exit(128 + 9),
+            ]
+    );
+    check!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn drop_kills() {
+    let start = Instant::now();
+    drop(Runner::new("sleep").arg("10").run().unwrap());
+    check!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn hooks() {
+    let (mut stdout, mut stderr, mut timeouts) = (Vec::new(), Vec::new(), 0);
+    let code = Runner::new("tests/fixtures/fail.sh")
+        .run_with(
+            Hooks::new()
+                .on_stdout(|bytes| {
+                    stdout.extend_from_slice(bytes);
+                    Ok(())
+                })
+                .on_stderr(|bytes| {
+                    stderr.extend_from_slice(bytes);
+                    Ok(())
+                })
+                .on_timeout(|_| {
+                    timeouts += 1;
+                    Ok(())
+                }),
+        )
+        .unwrap();
+
+    check!(code == 3);
+    check!(stdout.as_bstr() == "out\n");
+    check!(stderr.as_bstr() == "err\n");
+    check!(timeouts == 0);
+}
+
+#[test]
+fn hooks_timeout_and_exit() {
+    let events = RefCell::new(Vec::new());
+    let code = Runner::new("sleep")
+        .arg("10")
+        .run_timeout(Duration::from_millis(50))
+        .run_with(
+            Hooks::new()
+                .on_timeout(|kind| {
+                    events.borrow_mut().push(Event::TimedOut(kind));
+                    Ok(())
+                })
+                .on_exit(|code| {
+                    events.borrow_mut().push(Event::Exit(code));
+                    Ok(())
+                }),
+        )
+        .unwrap();
+
+    check!(code == 128 + 15);
+    check!(
+        events.into_inner()
+            == [Event::TimedOut(TimeoutKind::Run), Event::Exit(128 + 15)]
+    );
+}
+
+#[test]
+fn hook_error() {
+    let start = Instant::now();
+    let result = Runner::new("tests/fixtures/ignore_term.sh").run_with(
+        Hooks::new().on_stdout(|_| Err(io::Error::other("sink closed"))),
+    );
+
+    let_assert!(Err(error) = result);
+    check!(error.to_string() == "sink closed");
+    check!(start.elapsed() < Duration::from_secs(5));
+}
@@ -0,0 +1,100 @@
+//! Test tools built into `rederr`.
+use assert2::check;
+use bstr::ByteSlice;
+
+mod helpers;
+
+#[test]
+fn man() {
+    let output = helpers::rederr(["man"]).output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.contains_str("\n.TH rederr 1 "));
+    check!(output.stdout.contains_str("\n.SH OPTIONS\n"));
+    check!(output.stdout.contains_str("\n.SH DURATIONS\n"));
+    check!(output.stdout.contains_str("\n.SH \"EXIT STATUS\"\n"));
+    check!(output.stdout.contains_str("\n.SH SIGNALS\n"));
+    check!(output.stdout.contains_str("\n.SH ENVIRONMENT\n"));
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn generate_man() {
+    let output = helpers::rederr(["generate", "man"]).output().unwrap();
+    let man = helpers::rederr(["man"]).output().unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == man.stdout.as_bstr());
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn generate_completions() {
+    for (shell, expected) in [
+        ("bash", "_rederr()"),
+        ("fish", "complete -c rederr "),
+        ("zsh", "#compdef rederr"),
+    ] {
+        let output = helpers::rederr(["generate", "completions", shell])
+            .output()
+            .unwrap();
+
+        check!(output.status.success());
+        check!(output.stdout.contains_str(expected), "{shell}");
+        check!(output.stdout.contains_str("idle-timeout"), "{shell}");
+        check!(output.stderr.as_bstr() == "");
+    }
+}
+
+#[test]
+fn generate_completions_unknown_shell() {
+    let output = helpers::rederr(["generate", "completions", "sh"])
+        .output()
+        .unwrap();
+
+    check!(output.status.code() == Some(2));
+}
+
+#[test]
+fn replay_instant() {
+    let output = helpers::rederr([
+        "replay",
+        "--instant",
+        "--color=never",
+        "tests/fixtures/simple.cast",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(output.stdout.as_bstr() == "out 1\nerr 2\n");
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn replay_color() {
+    let output = helpers::rederr([
+        "replay",
+        "--speed=10",
+        "--color=always",
+        "tests/fixtures/simple.cast",
+    ])
+    .output()
+    .unwrap();
+
+    check!(output.status.success());
+    check!(
+        output.stdout.as_bstr() == "out 1\n\x1b[0m\x1b[38;5;9merr 2\n\x1b[0m"
+    );
+    check!(output.stderr.as_bstr() == "");
+}
+
+#[test]
+fn replay_missing_file() {
+    let output = helpers::rederr(["replay", "tests/fixtures/missing.cast"])
+        .output()
+        .unwrap();
+
+    check!(!output.status.success());
+    check!(output.stderr.contains_str("tests/fixtures/missing.cast"));
+}